@@ -0,0 +1,415 @@
+use crate::{
+    cfg::{Applier as CfgApplier, Cfg, CfgPatch},
+    external::ExternallyManaged,
+    schema,
+    section::{PartFilter, Section, SectionPatch},
+    symlinks::SymlinkPolicy,
+    ChangeStats,
+    Dirs,
+    PatchRecorder,
+};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+const GROUP: &str = "Desktop Entry";
+
+// desktop entries in the autostart dir by file name without the `.desktop`
+// extension, only the declared ones are touched and null removes one
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Autostart {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    entries: BTreeMap<String, Option<Entry>>,
+    // every entry which isn't declared is removed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    exclusive: bool,
+    // the whole files read, other groups like desktop actions included
+    #[serde(skip)]
+    files: BTreeMap<String, Cfg>,
+    #[serde(skip)]
+    skipped: Vec<PathBuf>,
+}
+
+// the keys of the `[Desktop Entry]` group, like Exec and Hidden
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+struct Entry(
+    #[serde(deserialize_with = "crate::serde::deserialize_unannotated")]
+    BTreeMap<String, String>,
+);
+
+impl Entry {
+    fn from_cfg(cfg: &Cfg) -> Self {
+        Self(cfg.sections.get(GROUP).cloned().unwrap_or_default())
+    }
+
+    fn into_cfg(self) -> Cfg {
+        Cfg {
+            sections: [(GROUP.to_owned(), self.0)].into_iter().collect(),
+            ..Cfg::default()
+        }
+    }
+}
+
+pub(crate) fn autostart_dir(dirs: &Dirs) -> PathBuf {
+    dirs.config_dir.join("autostart")
+}
+
+// comments aren't kept when a file is written
+fn read_entry(path: &Path) -> Result<Cfg> {
+    let content = fs::read_to_string(path).context("error reading file")?;
+    let lines = content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Cfg::read(lines.as_bytes())
+}
+
+impl Autostart {
+    // names of the entries written, and of the ones removed
+    pub(crate) fn declared(&self) -> (Vec<&str>, Vec<&str>) {
+        let mut written = Vec::new();
+        let mut removed = Vec::new();
+        for (name, entry) in &self.entries {
+            match entry {
+                Some(_) => written.push(name.as_str()),
+                None => removed.push(name.as_str()),
+            }
+        }
+        (written, removed)
+    }
+
+    // removes the entry unless it's declared
+    pub(crate) fn remove_undeclared(&mut self, name: &str) {
+        self.entries.entry(name.to_owned()).or_insert(None);
+    }
+
+    // the existing file, or the entry if old was read from a file rather than
+    // the environment
+    fn take_cfg(&mut self, name: &str) -> Option<Cfg> {
+        self.files.remove(name).or_else(|| {
+            self.entries
+                .get(name)
+                .cloned()
+                .flatten()
+                .map(Entry::into_cfg)
+        })
+    }
+}
+
+impl Section for Autostart {
+    type Patch = AutostartPatch;
+
+    fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn schema() -> serde_json::Value {
+        let entry = schema::map(schema::string(), true);
+        schema::object(vec![
+            ("entries", schema::map(schema::nullable(entry), false)),
+            ("exclusive", schema::boolean()),
+        ])
+    }
+
+    fn read(_new: &Self, dirs: &Dirs) -> Result<Self> {
+        let dir = autostart_dir(dirs);
+        let mut autostart = Self::default();
+        let read_dir = match dir.read_dir() {
+            Ok(read_dir) => read_dir,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Ok(autostart);
+            },
+            Err(error) => {
+                return Err(error).context("error reading autostart dir");
+            },
+        };
+        for entry in read_dir {
+            let path =
+                entry.context("error reading autostart dir entry")?.path();
+            if path.extension() != Some("desktop".as_ref()) {
+                continue;
+            }
+            let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) => name.to_owned(),
+                None => {
+                    autostart.skipped.push(path);
+                    continue;
+                },
+            };
+            let cfg = read_entry(&path).with_context(|| {
+                format!("error reading autostart entry {}", path.display())
+            })?;
+            autostart
+                .entries
+                .insert(name.clone(), Some(Entry::from_cfg(&cfg)));
+            autostart.files.insert(name, cfg);
+        }
+        Ok(autostart)
+    }
+
+    fn diff(mut old: Self, new: Self) -> Result<AutostartPatch> {
+        let mut patch = AutostartPatch::default();
+        if new.exclusive {
+            patch.removed.extend(
+                old.entries
+                    .keys()
+                    .filter(|name| !new.entries.contains_key(*name))
+                    .cloned(),
+            );
+        }
+        for (name, entry) in new.entries {
+            if name.is_empty() || name.contains('/') {
+                bail!("invalid autostart entry name {:?}", name);
+            }
+            match (old.take_cfg(&name), entry) {
+                (Some(old_cfg), Some(entry)) => {
+                    let cfg_patch = CfgPatch::diff(old_cfg, entry.into_cfg());
+                    if !cfg_patch.is_empty() {
+                        patch.changed.insert(name, cfg_patch);
+                    }
+                },
+                (None, Some(entry)) => {
+                    patch.added.insert(name, entry.into_cfg());
+                },
+                (Some(_), None) => patch.removed.push(name),
+                (None, None) => {},
+            }
+        }
+        Ok(patch)
+    }
+
+    fn apply_to_old(patch: AutostartPatch, old: &mut Self) {
+        for (name, cfg_patch) in patch.changed {
+            if let Some(mut cfg) = old.take_cfg(&name) {
+                cfg_patch.apply_to_old(&mut cfg);
+                old.entries
+                    .insert(name.clone(), Some(Entry::from_cfg(&cfg)));
+                old.files.insert(name, cfg);
+            }
+        }
+        for (name, cfg) in patch.added {
+            old.entries
+                .insert(name.clone(), Some(Entry::from_cfg(&cfg)));
+            old.files.insert(name, cfg);
+        }
+        for name in patch.removed {
+            old.entries.remove(&name);
+            old.files.remove(&name);
+        }
+    }
+
+    fn retain(&mut self, id: &str) -> Result<()> {
+        if !self.entries.contains_key(id) {
+            bail!("no autostart entry {}", id);
+        }
+        self.entries.retain(|name, _| name == id);
+        self.files.retain(|name, _| name == id);
+        Ok(())
+    }
+
+    fn skipped(&self) -> Vec<PathBuf> {
+        self.skipped.clone()
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AutostartPatch {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    changed: BTreeMap<String, CfgPatch>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    added: BTreeMap<String, Cfg>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    removed: Vec<String>,
+}
+
+pub struct Applier<'a> {
+    dry_run: bool,
+    patch_recorder: &'a mut PatchRecorder,
+    dir: Cow<'a, Path>,
+    symlinks: SymlinkPolicy,
+    external: &'a ExternallyManaged,
+}
+
+impl<'a> Applier<'a> {
+    pub(crate) fn new(
+        dry_run: bool,
+        patch_recorder: &'a mut PatchRecorder,
+        dir: Cow<'a, Path>,
+        symlinks: SymlinkPolicy,
+        external: &'a ExternallyManaged,
+    ) -> Self {
+        Self {
+            dry_run,
+            patch_recorder,
+            dir,
+            symlinks,
+            external,
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.desktop", name))
+    }
+
+    fn cfg_applier(&mut self, name: &str) -> CfgApplier<'_> {
+        CfgApplier::new(
+            self.dry_run,
+            self.patch_recorder,
+            self.path(name).into(),
+            self.symlinks,
+            self.external,
+        )
+    }
+
+    fn ensure_dir(&mut self) -> Result<()> {
+        if !self.dry_run && !self.external.skip_write(&self.dir) {
+            fs::create_dir_all(&self.dir).with_context(|| {
+                format!("error creating autostart dir {}", self.dir.display())
+            })?;
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, name: &str) -> Result<()> {
+        let path = self.path(name);
+        if self.external.skip_write(&path) {
+            return Ok(());
+        }
+        self.patch_recorder
+            .log(&crate::PatchEvent::Autostart(PatchEvent::Remove {
+                path: &path,
+            }))
+            .context("error logging autostart entry removal")?;
+        self.patch_recorder
+            .log_file_inverse(&path)
+            .context("error logging autostart entry inverse")?;
+        if !self.dry_run {
+            self.patch_recorder.back_up(&path)?;
+            fs::remove_file(&path).with_context(|| {
+                format!("error removing {}", path.display())
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum PatchEvent<'a> {
+    #[serde(rename_all = "kebab-case")]
+    Remove { path: &'a Path },
+}
+
+impl AutostartPatch {
+    pub fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
+        if !self.added.is_empty() {
+            applier.ensure_dir()?;
+        }
+        for (name, cfg) in self.added {
+            cfg.apply(&mut applier.cfg_applier(&name))
+                .with_context(|| format!("error writing {}", name))?;
+        }
+        for (name, cfg_patch) in self.changed {
+            cfg_patch
+                .apply(&mut applier.cfg_applier(&name))
+                .with_context(|| format!("error writing {}", name))?;
+        }
+        for name in self.removed {
+            applier.remove(&name)?;
+        }
+        Ok(())
+    }
+}
+
+impl SectionPatch for AutostartPatch {
+    fn change_count(&self) -> ChangeStats {
+        self.changed
+            .values()
+            .map(|cfg_patch| cfg_patch.change_count().in_file())
+            .sum::<ChangeStats>()
+            + ChangeStats::files_written(self.added.len())
+            + ChangeStats::files_removed(self.removed.len())
+    }
+
+    // parts are entries by name
+    fn filter_parts(&mut self, filter: &PartFilter) -> Result<()> {
+        self.changed.retain(|name, _| filter.keeps(name));
+        self.added.retain(|name, _| filter.keeps(name));
+        self.removed.retain(|name| filter.keeps_all(name));
+        Ok(())
+    }
+
+    fn parts(&self) -> Vec<(String, String)> {
+        let written = self.changed.keys().chain(self.added.keys());
+        written
+            .map(|name| (name.clone(), "write".to_owned()))
+            .chain(
+                self.removed
+                    .iter()
+                    .map(|name| (name.clone(), "remove".to_owned())),
+            )
+            .collect()
+    }
+
+    fn apply(self: Box<Self>, applier: &mut crate::Applier<'_>) -> Result<()> {
+        (*self).apply(&mut Applier::new(
+            applier.dry_run,
+            &mut applier.patch_recorder,
+            autostart_dir(applier.dirs).into(),
+            applier.symlinks.autostart,
+            &applier.external,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn simulate() {
+        let old = serde_json::from_value::<Autostart>(json!({
+            "entries": {
+                "redshift": { "Exec": "redshift-gtk", "Name": "Redshift" },
+                "nm-applet": { "Exec": "nm-applet" },
+            },
+        }))
+        .unwrap();
+        let new = serde_json::from_value::<Autostart>(json!({
+            "entries": {
+                "redshift": { "Hidden": "true" },
+                "nm-applet": null,
+                "syncthing": { "Exec": "syncthing serve" },
+            },
+        }))
+        .unwrap();
+        let mut state = old.clone();
+        let patch = Autostart::diff(old, new).unwrap();
+        assert_eq!(patch.change_count().total(), 4);
+        Autostart::apply_to_old(patch, &mut state);
+        assert_eq!(
+            serde_json::to_value(state).unwrap(),
+            json!({
+                "entries": {
+                    "redshift": {
+                        "Exec": "redshift-gtk",
+                        "Hidden": "true",
+                        "Name": "Redshift",
+                    },
+                    "syncthing": { "Exec": "syncthing serve" },
+                },
+            })
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use serde_json::Value;
+use std::path::Path;
+
+// keys identifying the elements of the arrays in the input
+const ID_KEYS: &[&str] = &["name", "id", "plugin"];
+
+// layers the user config over the baseline config, objects are merged key by
+// key, elements of arrays of identified objects with the same id are merged
+// and anything else in the user config replaces the baseline
+pub fn merge(baseline: Value, user: Value) -> Value {
+    match (baseline, user) {
+        (Value::Object(mut baseline), Value::Object(user)) => {
+            for (key, user_value) in user {
+                let value = match baseline.remove(&key) {
+                    Some(baseline_value) => merge(baseline_value, user_value),
+                    None => user_value,
+                };
+                baseline.insert(key, value);
+            }
+            Value::Object(baseline)
+        },
+        (Value::Array(mut baseline), Value::Array(user))
+            if baseline
+                .iter()
+                .chain(&user)
+                .all(|value| element_id(value).is_some()) =>
+        {
+            for user_value in user {
+                let index = element_id(&user_value).and_then(|id| {
+                    baseline.iter().position(|baseline_value| {
+                        element_id(baseline_value) == Some(id)
+                    })
+                });
+                match index {
+                    Some(index) => {
+                        let baseline_value = baseline[index].take();
+                        baseline[index] = merge(baseline_value, user_value);
+                    },
+                    None => baseline.push(user_value),
+                }
+            }
+            Value::Array(baseline)
+        },
+        (_, user) => user,
+    }
+}
+
+fn element_id(value: &Value) -> Option<(&str, &Value)> {
+    let object = value.as_object()?;
+    ID_KEYS
+        .iter()
+        .find_map(|key| object.get(*key).map(|id| (*key, id)))
+}
+
+// makes relative wallpaper files and plugin commands relative to the
+// baseline's dir, since the merged config is resolved relative to the user
+// config
+pub fn resolve_paths(baseline: &mut Value, dir: &Path) {
+    for (section, key) in [("wallpapers", "file"), ("plugins", "command")] {
+        let elements = baseline
+            .get_mut(section)
+            .and_then(Value::as_array_mut)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_object_mut);
+        for element in elements {
+            if let Some(Value::String(path)) = element.get_mut(key) {
+                // bare command names are looked up in PATH instead
+                let is_relative_path = Path::new(path.as_str()).is_relative()
+                    && (key == "file" || path.contains('/'));
+                if is_relative_path {
+                    *path =
+                        dir.join(path.as_str()).to_string_lossy().into_owned();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn merge_user_over_baseline() {
+        let baseline = json!({
+            "xfconf": {
+                "channels": [
+                    {
+                        "name": "xsettings",
+                        "props": {
+                            "Net": {
+                                "props": {
+                                    "ThemeName": "Greybird",
+                                    "IconThemeName": "elementary"
+                                }
+                            }
+                        }
+                    },
+                    { "name": "xfwm4", "props": { "general": {} } }
+                ]
+            }
+        });
+        let user = json!({
+            "xfconf": {
+                "channels": [
+                    {
+                        "name": "xsettings",
+                        "props": {
+                            "Net": { "props": { "ThemeName": "Adwaita" } }
+                        }
+                    },
+                    { "name": "thunar", "props": {} }
+                ]
+            }
+        });
+        assert_eq!(
+            merge(baseline, user),
+            json!({
+                "xfconf": {
+                    "channels": [
+                        {
+                            "name": "xsettings",
+                            "props": {
+                                "Net": {
+                                    "props": {
+                                        "ThemeName": "Adwaita",
+                                        "IconThemeName": "elementary"
+                                    }
+                                }
+                            }
+                        },
+                        { "name": "xfwm4", "props": { "general": {} } },
+                        { "name": "thunar", "props": {} }
+                    ]
+                }
+            })
+        );
+    }
+}
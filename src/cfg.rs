@@ -1,23 +1,104 @@
-use crate::PatchRecorder;
+//! Reading, diffing and writing INI-like files of `key=value` lines grouped
+//! under `[section]` headers, like GTK's settings.ini and XFCE's rc files.
+
+use crate::{
+    external::ExternallyManaged,
+    schema,
+    serde::{deserialize_unannotated, Unannotated},
+    symlinks::{has_content, replace_file, SymlinkPolicy},
+    ChangeStats,
+    PatchRecorder,
+};
 use anyhow::{bail, Context, Result};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::{
     borrow::Cow,
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fs,
-    io::{self, BufRead, Write},
-    path::{Path, PathBuf},
+    io::{BufRead, Write},
+    path::Path,
 };
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+/// The keys of a file, or the keys a file should have when diffed against.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct Cfg {
-    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "deserialize_unannotated"
+    )]
+    /// Keys before the first section.
     pub root: BTreeMap<String, String>,
-    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "deserialize_sections"
+    )]
+    /// Keys of each section by section name.
     pub sections: BTreeMap<String, BTreeMap<String, String>>,
+    /// Match existing section names and keys ignoring ASCII case, the
+    /// declared casing is used when a matched key is written.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub case_insensitive: bool,
+    /// Remove existing keys and sections which aren't declared.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub remove_unmanaged: bool,
+    /// When removing unmanaged keys, keep localized ones like `Name[de]`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keep_localized: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DiffOptions {
+    case_insensitive: bool,
+    remove_unmanaged: bool,
+    keep_localized: bool,
+}
+
+impl DiffOptions {
+    fn removes(self, key: &str) -> bool {
+        self.remove_unmanaged && !(self.keep_localized && is_localized(key))
+    }
+}
+
+fn is_localized(key: &str) -> bool {
+    key.strip_suffix(']')
+        .and_then(|key| key.split_once('['))
+        .map_or(false, |(key, locale)| !key.is_empty() && !locale.is_empty())
+}
+
+fn deserialize_sections<'de, D>(
+    deserializer: D,
+) -> Result<BTreeMap<String, BTreeMap<String, String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_unannotated::<_, Unannotated<String>>(deserializer).map(
+        |sections| {
+            sections
+                .into_iter()
+                .map(|(name, props)| (name, props.0))
+                .collect()
+        },
+    )
 }
 
 impl Cfg {
+    // JSON Schema of the fields, which parts of the input holding a file's
+    // keys take
+    pub(crate) fn schema() -> serde_json::Value {
+        let keys = schema::map(schema::string(), true);
+        schema::object(vec![
+            ("root", keys.clone()),
+            ("sections", schema::map(keys, true)),
+            ("case-insensitive", schema::boolean()),
+            ("remove-unmanaged", schema::boolean()),
+            ("keep-localized", schema::boolean()),
+        ])
+    }
+
+    /// Parses a file, later keys replace earlier ones with the same name.
     pub fn read<R>(reader: R) -> Result<Self>
     where
         R: BufRead,
@@ -47,6 +128,8 @@ impl Cfg {
         Ok(cfg)
     }
 
+    /// Writes the keys sorted by name, each section followed by an empty
+    /// line.
     pub fn write<W>(&self, mut writer: W) -> Result<()>
     where
         W: Write,
@@ -76,27 +159,46 @@ impl Cfg {
     }
 }
 
+/// The changes turning one [`Cfg`] into another, serializable to show them.
 #[derive(Debug, Serialize)]
 pub struct CfgPatch {
     #[serde(skip_serializing_if = "MapPatch::is_empty")]
     root: MapPatch<StrPatch>,
     #[serde(skip_serializing_if = "MapPatch::is_empty")]
     sections: MapPatch<MapPatch<StrPatch>>,
+    // the data the patch was diffed against, so applying it doesn't need to
+    // read the file again
+    #[serde(skip)]
+    old: Cfg,
 }
 
 impl CfgPatch {
+    /// Changes making old match new, using the options declared in new.
     pub fn diff(old: Cfg, new: Cfg) -> Self {
+        let options = DiffOptions {
+            case_insensitive: new.case_insensitive,
+            remove_unmanaged: new.remove_unmanaged,
+            keep_localized: new.keep_localized,
+        };
         Self {
-            root: MapPatch::diff(old.root, new.root),
-            sections: MapPatch::diff(old.sections, new.sections),
+            old: old.clone(),
+            root: MapPatch::diff(old.root, new.root, options),
+            sections: MapPatch::diff(old.sections, new.sections, options),
         }
     }
 
+    /// Changed keys, not counting the file they're written to.
+    pub fn change_count(&self) -> ChangeStats {
+        self.root.change_count() + self.sections.change_count()
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.root.is_empty() && self.sections.is_empty()
+        self.change_count().is_empty()
     }
 
-    fn apply_to_old(self, old: &mut Cfg) {
+    /// Makes the changes to old, which should be what the patch was diffed
+    /// against.
+    pub fn apply_to_old(self, old: &mut Cfg) {
         self.root.apply_to_old(&mut old.root);
         self.sections.apply_to_old(&mut old.sections);
     }
@@ -105,9 +207,16 @@ impl CfgPatch {
 trait Patch {
     type Data;
 
-    fn diff(old: Self::Data, new: Self::Data) -> Self;
+    fn diff(old: Self::Data, new: Self::Data, options: DiffOptions) -> Self;
+
+    fn change_count(&self) -> ChangeStats;
+
+    // number of keys in added data
+    fn added_count(data: &Self::Data) -> usize;
 
-    fn is_empty(&self) -> bool;
+    fn is_empty(&self) -> bool {
+        self.change_count().is_empty()
+    }
 
     fn apply_to_old(self, old: &mut Self::Data);
 }
@@ -122,6 +231,11 @@ where
     changed: BTreeMap<String, T>,
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     added: BTreeMap<String, T::Data>,
+    // declared key -> existing key, for changed keys matched ignoring case
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    renamed: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    removed: BTreeSet<String>,
 }
 
 impl<T> Patch for MapPatch<T>
@@ -130,35 +244,80 @@ where
 {
     type Data = BTreeMap<String, T::Data>;
 
-    fn diff(mut old: Self::Data, new: Self::Data) -> Self {
+    fn diff(
+        mut old: Self::Data,
+        new: Self::Data,
+        options: DiffOptions,
+    ) -> Self {
         let mut changed = BTreeMap::new();
         let mut added = BTreeMap::new();
+        let mut renamed = BTreeMap::new();
         for (key, new_value) in new.into_iter() {
-            if let Some(old_value) = old.remove(&key) {
-                let patch = T::diff(old_value, new_value);
+            let old_key = if options.case_insensitive {
+                old.keys()
+                    .find(|old_key| old_key.eq_ignore_ascii_case(&key))
+                    .cloned()
+            } else {
+                Some(key.clone())
+            };
+            if let Some((old_key, old_value)) =
+                old_key.and_then(|old_key| old.remove_entry(&old_key))
+            {
+                let patch = T::diff(old_value, new_value, options);
                 if !patch.is_empty() {
+                    if old_key != key {
+                        renamed.insert(key.clone(), old_key);
+                    }
                     changed.insert(key, patch);
                 }
             } else {
                 added.insert(key, new_value);
             }
         }
-        Self { changed, added }
+        let removed = old
+            .into_keys()
+            .filter(|key| options.removes(key))
+            .collect::<BTreeSet<_>>();
+        Self {
+            changed,
+            added,
+            renamed,
+            removed,
+        }
     }
 
-    fn is_empty(&self) -> bool {
-        self.changed.is_empty() && self.added.is_empty()
+    fn change_count(&self) -> ChangeStats {
+        self.changed
+            .values()
+            .map(T::change_count)
+            .chain(
+                self.added
+                    .values()
+                    .map(|data| ChangeStats::added(T::added_count(data))),
+            )
+            .sum::<ChangeStats>()
+            + ChangeStats::removed(self.removed.len())
     }
 
-    fn apply_to_old(self, old: &mut Self::Data) {
+    fn added_count(data: &Self::Data) -> usize {
+        data.values().map(T::added_count).sum()
+    }
+
+    fn apply_to_old(mut self, old: &mut Self::Data) {
         for (key, value_patch) in self.changed {
-            if let Some(old_value) = old.get_mut(&key) {
-                value_patch.apply_to_old(old_value);
+            let old_key = self.renamed.remove(&key);
+            let old_key = old_key.as_ref().unwrap_or(&key);
+            if let Some(mut old_value) = old.remove(old_key) {
+                value_patch.apply_to_old(&mut old_value);
+                old.insert(key, old_value);
             }
         }
         for (key, value) in self.added {
             old.insert(key, value);
         }
+        for key in self.removed {
+            old.remove(&key);
+        }
     }
 }
 
@@ -170,14 +329,18 @@ struct StrPatch {
 impl Patch for StrPatch {
     type Data = String;
 
-    fn diff(old: Self::Data, new: Self::Data) -> Self {
+    fn diff(old: Self::Data, new: Self::Data, _options: DiffOptions) -> Self {
         Self {
             value: (old != new).then(|| new),
         }
     }
 
-    fn is_empty(&self) -> bool {
-        self.value.is_none()
+    fn change_count(&self) -> ChangeStats {
+        ChangeStats::changed(usize::from(self.value.is_some()))
+    }
+
+    fn added_count(_data: &Self::Data) -> usize {
+        1
     }
 
     fn apply_to_old(self, old: &mut Self::Data) {
@@ -187,10 +350,12 @@ impl Patch for StrPatch {
     }
 }
 
-pub struct Applier<'a> {
+pub(crate) struct Applier<'a> {
     dry_run: bool,
     patch_recorder: &'a mut PatchRecorder,
     path: Cow<'a, Path>,
+    symlinks: SymlinkPolicy,
+    external: &'a ExternallyManaged,
 }
 
 impl<'a> Applier<'a> {
@@ -198,40 +363,47 @@ impl<'a> Applier<'a> {
         dry_run: bool,
         patch_recorder: &'a mut PatchRecorder,
         path: Cow<'a, Path>,
+        symlinks: SymlinkPolicy,
+        external: &'a ExternallyManaged,
     ) -> Self {
         Self {
             dry_run,
             patch_recorder,
             path,
+            symlinks,
+            external,
         }
     }
 
     fn write_cfg(&mut self, cfg: &Cfg) -> Result<()> {
+        if self.external.skip_write(&self.path) {
+            return Ok(());
+        }
+        let path = self.symlinks.resolve(&self.path)?;
+        let mut content = Vec::new();
+        cfg.write(&mut content)
+            .context("error rendering CFG file")?;
+        if has_content(&path, &content) {
+            return Ok(());
+        }
         self.patch_recorder
             .log(&crate::PatchEvent::Cfg { content: cfg })
             .context("error logging CFG write")?;
+        self.patch_recorder
+            .log_file_inverse(&path)
+            .context("error logging CFG file inverse")?;
         if !self.dry_run {
-            let mut tmp = self.path.clone().into_owned().into_os_string();
-            tmp.push(".new");
-            let tmp = PathBuf::from(tmp);
-            cfg.write(
-                fs::File::create(&tmp)
-                    .context("error creating temporary CFG file")?,
-            )
-            .context("error writing temporary CFG file")?;
-            fs::rename(tmp, &self.path)
-                .context("error moving temporary CFG file")?;
+            self.patch_recorder.back_up(&path)?;
+            replace_file(&path, |tmp| {
+                fs::write(tmp, &content)
+                    .context("error writing temporary CFG file")
+            })?;
         }
         Ok(())
     }
 
-    fn update_cfg(&mut self, cfg_patch: CfgPatch) -> Result<()> {
-        let mut cfg = Cfg::read(
-            fs::File::open(&self.path)
-                .map(io::BufReader::new)
-                .context("error opening existing CFG file")?,
-        )
-        .context("error reading existing CFG file")?;
+    fn update_cfg(&mut self, mut cfg_patch: CfgPatch) -> Result<()> {
+        let mut cfg = std::mem::take(&mut cfg_patch.old);
         cfg_patch.apply_to_old(&mut cfg);
         self.write_cfg(&cfg)?;
         Ok(())
@@ -239,15 +411,122 @@ impl<'a> Applier<'a> {
 }
 
 impl Cfg {
-    pub fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
+    pub(crate) fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
         applier.write_cfg(&self)?;
         Ok(())
     }
 }
 
 impl CfgPatch {
-    pub fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
+    pub(crate) fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
         applier.update_cfg(self)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maplit::btreemap;
+
+    #[test]
+    fn options() {
+        let cfg = serde_json::from_value::<Cfg>(serde_json::json!({
+            "case-insensitive": true,
+            "remove-unmanaged": true,
+            "keep-localized": true,
+        }))
+        .unwrap();
+        assert!(cfg.case_insensitive);
+        assert!(cfg.remove_unmanaged);
+        assert!(cfg.keep_localized);
+    }
+
+    #[test]
+    fn case_insensitive_diff() {
+        let mut old = Cfg {
+            sections: btreemap! {
+                "Settings".into() => btreemap! {
+                    "gtk-theme-name".into() => "Adwaita".into(),
+                    "Gtk-Font-Name".into() => "Sans 10".into(),
+                },
+            },
+            ..Default::default()
+        };
+        let new = Cfg {
+            sections: btreemap! {
+                "settings".into() => btreemap! {
+                    "gtk-theme-name".into() => "Greybird".into(),
+                    "gtk-font-name".into() => "Sans 10".into(),
+                },
+            },
+            case_insensitive: true,
+            ..Default::default()
+        };
+        let patch = CfgPatch::diff(
+            Cfg {
+                sections: old.sections.clone(),
+                ..Default::default()
+            },
+            new,
+        );
+        assert!(patch.sections.added.is_empty());
+        assert_eq!(patch.change_count(), ChangeStats::changed(1));
+        patch.apply_to_old(&mut old);
+        assert_eq!(
+            old.sections,
+            btreemap! {
+                "settings".into() => btreemap! {
+                    "gtk-theme-name".into() => "Greybird".into(),
+                    "Gtk-Font-Name".into() => "Sans 10".into(),
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn remove_unmanaged_keeps_localized() {
+        let mut old = Cfg {
+            sections: btreemap! {
+                "Desktop Entry".into() => btreemap! {
+                    "Name".into() => "Terminal".into(),
+                    "Name[de]".into() => "Terminal".into(),
+                    "Comment".into() => "Use the command line".into(),
+                    "Comment[de]".into() => "Befehlszeile benutzen".into(),
+                },
+                "Desktop Action Window".into() => btreemap! {
+                    "Name".into() => "New Window".into(),
+                },
+            },
+            ..Default::default()
+        };
+        let new = Cfg {
+            sections: btreemap! {
+                "Desktop Entry".into() => btreemap! {
+                    "Name".into() => "Terminal".into(),
+                },
+            },
+            remove_unmanaged: true,
+            keep_localized: true,
+            ..Default::default()
+        };
+        let patch = CfgPatch::diff(
+            Cfg {
+                sections: old.sections.clone(),
+                ..Default::default()
+            },
+            new,
+        );
+        patch.apply_to_old(&mut old);
+        assert_eq!(
+            old.sections,
+            btreemap! {
+                "Desktop Entry".into() => btreemap! {
+                    "Name".into() => "Terminal".into(),
+                    "Name[de]".into() => "Terminal".into(),
+                    "Comment[de]".into() => "Befehlszeile benutzen".into(),
+                },
+            }
+        );
+    }
+}
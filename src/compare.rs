@@ -0,0 +1,207 @@
+use crate::XfceConfig;
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::{fmt, path::Path};
+
+#[derive(Debug, PartialEq)]
+pub enum RunDifference {
+    // only the first run changes this
+    Dropped {
+        path: String,
+        value: Value,
+    },
+    // only the second run changes this
+    Added {
+        path: String,
+        value: Value,
+    },
+    Changed {
+        path: String,
+        first: Value,
+        second: Value,
+    },
+}
+
+impl fmt::Display for RunDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dropped { path, value } => write!(f, "- {}: {}", path, value),
+            Self::Added { path, value } => write!(f, "+ {}: {}", path, value),
+            Self::Changed {
+                path,
+                first,
+                second,
+            } => write!(f, "~ {}: {} -> {}", path, first, second),
+        }
+    }
+}
+
+// differences between the diff.json files of two log dirs
+pub fn compare_runs(first: &Path, second: &Path) -> Result<Vec<RunDifference>> {
+    let read = |dir: &Path| -> Result<Value> {
+        let content = crate::logs::read_log_file(dir, "diff.json")?;
+        serde_json::from_slice(&content).with_context(|| {
+            format!("error reading diff.json in {}", dir.display())
+        })
+    };
+    let mut differences = Vec::new();
+    compare(String::new(), read(first)?, read(second)?, &mut differences);
+    Ok(differences)
+}
+
+// differences between two states, like the environment and the state applying
+// a config would leave it in
+pub fn compare_states(
+    first: &XfceConfig,
+    second: &XfceConfig,
+) -> Result<Vec<RunDifference>> {
+    let mut differences = Vec::new();
+    compare(
+        String::new(),
+        serde_json::to_value(first)?,
+        serde_json::to_value(second)?,
+        &mut differences,
+    );
+    Ok(differences)
+}
+
+// the parts of an array by their ids, like channels by name or general
+// configs by path, if all of them have a distinct one
+fn keyed(array: &[Value]) -> Option<Map<String, Value>> {
+    let mut parts = Map::new();
+    for part in array {
+        let key = part
+            .get("name")
+            .or_else(|| part.get("id").map(|id| id.get("path").unwrap_or(id)))
+            .and_then(Value::as_str)?;
+        if parts.insert(key.to_owned(), part.clone()).is_some() {
+            return None;
+        }
+    }
+    Some(parts)
+}
+
+fn compare(
+    path: String,
+    first: Value,
+    second: Value,
+    differences: &mut Vec<RunDifference>,
+) {
+    // parts with ids are compared by id rather than by index
+    if let (Value::Array(first), Value::Array(second)) = (&first, &second) {
+        if let (Some(first), Some(second)) = (keyed(first), keyed(second)) {
+            return compare(
+                path,
+                Value::Object(first),
+                Value::Object(second),
+                differences,
+            );
+        }
+    }
+    match (first, second) {
+        (Value::Object(mut first), Value::Object(second)) => {
+            for (key, second) in second {
+                let path = join(&path, &key);
+                match first.remove(&key) {
+                    Some(first) => compare(path, first, second, differences),
+                    None => differences.push(RunDifference::Added {
+                        path,
+                        value: second,
+                    }),
+                }
+            }
+            for (key, first) in first {
+                differences.push(RunDifference::Dropped {
+                    path: join(&path, &key),
+                    value: first,
+                });
+            }
+        },
+        (first, second) if first == second => {},
+        (first, second) => differences.push(RunDifference::Changed {
+            path: if path.is_empty() {
+                ".".to_owned()
+            } else {
+                path
+            },
+            first,
+            second,
+        }),
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn compare() {
+        let mut differences = Vec::new();
+        super::compare(
+            String::new(),
+            json!({
+                "gtk": { "changed": { "gtk-theme-name": "Adwaita" } },
+                "xresources": { "added": { "Xft.dpi": "96" } },
+            }),
+            json!({
+                "gtk": { "changed": { "gtk-theme-name": "Arc" } },
+                "wallpapers": { "added": ["sky.png"] },
+            }),
+            &mut differences,
+        );
+        assert_eq!(
+            differences,
+            [
+                RunDifference::Changed {
+                    path: "gtk.changed.gtk-theme-name".into(),
+                    first: json!("Adwaita"),
+                    second: json!("Arc"),
+                },
+                RunDifference::Added {
+                    path: "wallpapers".into(),
+                    value: json!({ "added": ["sky.png"] }),
+                },
+                RunDifference::Dropped {
+                    path: "xresources".into(),
+                    value: json!({ "added": { "Xft.dpi": "96" } }),
+                },
+            ]
+        );
+
+        let mut differences = Vec::new();
+        super::compare(
+            String::new(),
+            json!({ "channels": [{ "name": "xfwm4", "props": { "a": 1 } }] }),
+            json!({
+                "channels": [
+                    { "name": "thunar", "props": {} },
+                    { "name": "xfwm4", "props": { "a": 2 } },
+                ],
+            }),
+            &mut differences,
+        );
+        assert_eq!(
+            differences,
+            [
+                RunDifference::Added {
+                    path: "channels.thunar".into(),
+                    value: json!({ "name": "thunar", "props": {} }),
+                },
+                RunDifference::Changed {
+                    path: "channels.xfwm4.props.a".into(),
+                    first: json!(1),
+                    second: json!(2),
+                },
+            ]
+        );
+    }
+}
@@ -1,5 +1,7 @@
+use crate::deadline::TimedOut;
 use anyhow::{Context, Result};
-use std::borrow::Cow;
+use gio::prelude::CancellableExt;
+use std::{borrow::Cow, convert::TryFrom, sync::mpsc, thread};
 
 pub struct DBus {
     proxy: gio::DBusProxy,
@@ -7,6 +9,20 @@ pub struct DBus {
 
 impl DBus {
     pub fn new(destination: &'static str, path: &'static str) -> Result<Self> {
+        // connecting to the bus has no timeout of its own, so it's cancelled
+        // once the deadline passes
+        let cancellable = gio::Cancellable::new();
+        let (done, finished) = mpsc::channel::<()>();
+        if let Some(remaining) = crate::deadline::remaining()? {
+            let cancellable = cancellable.clone();
+            thread::spawn(move || {
+                if let Err(mpsc::RecvTimeoutError::Timeout) =
+                    finished.recv_timeout(remaining)
+                {
+                    cancellable.cancel();
+                }
+            });
+        }
         let proxy = gio::DBusProxy::for_bus_sync(
             gio::BusType::Session,
             gio::DBusProxyFlags::NONE,
@@ -14,9 +30,15 @@ impl DBus {
             destination,
             path,
             destination,
-            None::<&gio::Cancellable>,
-        )
-        .with_context(|| {
+            Some(&cancellable),
+        );
+        drop(done);
+        if cancellable.is_cancelled() {
+            return Err(TimedOut).with_context(|| {
+                format!("error creating dbus proxy for {}", destination)
+            });
+        }
+        let proxy = proxy.with_context(|| {
             format!("error creating dbus proxy for {}", destination)
         })?;
         Ok(Self { proxy })
@@ -30,6 +52,17 @@ impl DBus {
         self.call_inner(method, Some(args.to_variant()))
     }
 
+    // calls f with the name and args of every signal of the object
+    pub fn connect_signal<F>(&self, f: F)
+    where
+        F: Fn(&str, &glib::Variant) + Send + Sync + 'static,
+    {
+        gio::prelude::DBusProxyExtManual::connect_g_signal(
+            &self.proxy,
+            move |_proxy, _sender, signal, args| f(signal, args),
+        );
+    }
+
     pub fn call_no_args(
         &mut self,
         method: &'static str,
@@ -42,12 +75,16 @@ impl DBus {
         method: &'static str,
         args: Option<glib::Variant>,
     ) -> Result<glib::Variant> {
+        // the default timeout without a deadline
+        let timeout = crate::deadline::remaining()?.map_or(-1, |remaining| {
+            i32::try_from(remaining.as_millis()).unwrap_or(i32::MAX)
+        });
         gio::prelude::DBusProxyExt::call_sync(
             &self.proxy,
             method,
             args.as_ref(),
             gio::DBusCallFlags::NONE,
-            -1,
+            timeout,
             None::<&gio::Cancellable>,
         )
         .with_context(|| {
@@ -0,0 +1,199 @@
+use anyhow::{bail, Context, Result};
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    fmt,
+    io::{self, Read},
+    process,
+    thread,
+    time::{Duration, Instant},
+};
+
+thread_local! {
+    // when what's running has to be done by, blocking backends like D-Bus
+    // calls give up then instead of hanging
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+// a deadline which holds until it's dropped, nested ones can only make it
+// earlier
+#[must_use]
+pub struct Deadline {
+    prior: Option<Instant>,
+}
+
+impl Deadline {
+    pub fn set(timeout: Duration) -> Self {
+        let prior = DEADLINE.with(Cell::get);
+        let deadline = Instant::now() + timeout;
+        DEADLINE.with(|current| {
+            current
+                .set(Some(prior.map_or(deadline, |prior| prior.min(deadline))))
+        });
+        Self { prior }
+    }
+}
+
+impl Drop for Deadline {
+    fn drop(&mut self) {
+        DEADLINE.with(|current| current.set(self.prior));
+    }
+}
+
+#[derive(Debug)]
+pub struct TimedOut;
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "timed out".fmt(f)
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+impl TimedOut {
+    pub fn caused(error: &anyhow::Error) -> bool {
+        error.chain().any(|cause| cause.is::<Self>())
+    }
+}
+
+// time left until the deadline if there is one, failing once it passed
+pub fn remaining() -> Result<Option<Duration>, TimedOut> {
+    match DEADLINE.with(Cell::get) {
+        Some(deadline) => match deadline.checked_duration_since(Instant::now())
+        {
+            Some(remaining) if !remaining.is_zero() => Ok(Some(remaining)),
+            _ => Err(TimedOut),
+        },
+        None => Ok(None),
+    }
+}
+
+// waits for the child to exit, killing it once the deadline passes
+pub(crate) fn wait(child: &mut process::Child) -> Result<process::ExitStatus> {
+    // polled since std can't wait with a timeout
+    let mut interval = Duration::from_millis(1);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        match remaining() {
+            Ok(None) => return Ok(child.wait()?),
+            Ok(Some(remaining)) => {
+                thread::sleep(interval.min(remaining));
+                interval = (interval * 2).min(Duration::from_millis(50));
+            },
+            Err(timed_out) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(timed_out.into());
+            },
+        }
+    }
+}
+
+// like Child::wait_with_output, killing the child once the deadline passes
+pub(crate) fn wait_with_output(
+    mut child: process::Child,
+) -> Result<process::Output> {
+    // read while waiting so the child can't block on a full pipe
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+    let status = wait(&mut child)?;
+    Ok(process::Output {
+        status,
+        stdout: join_pipe(stdout)?,
+        stderr: join_pipe(stderr)?,
+    })
+}
+
+type PipeReader = Option<thread::JoinHandle<io::Result<Vec<u8>>>>;
+
+fn read_pipe(pipe: Option<impl Read + Send + 'static>) -> PipeReader {
+    pipe.map(|mut pipe| {
+        thread::spawn(move || {
+            let mut content = Vec::new();
+            pipe.read_to_end(&mut content).map(|_| content)
+        })
+    })
+}
+
+fn join_pipe(reader: PipeReader) -> Result<Vec<u8>> {
+    match reader {
+        Some(reader) => match reader.join() {
+            Ok(content) => Ok(content.context("error reading child output")?),
+            Err(_) => bail!("child output reader panicked"),
+        },
+        None => Ok(Vec::new()),
+    }
+}
+
+// how long applying each section may take by section name
+#[derive(Debug, Default, Clone)]
+pub struct SectionTimeouts(BTreeMap<String, Duration>);
+
+impl SectionTimeouts {
+    // sets a timeout from `<section>=<seconds>`
+    pub fn set(&mut self, spec: &str) -> Result<()> {
+        let (section, seconds) = spec
+            .split_once('=')
+            .context("section timeout must be <section>=<seconds>")?;
+        let name = section.replace('-', "_");
+        if !crate::SECTION_NAMES.contains(&name.as_str()) {
+            bail!("unknown section {}", section);
+        }
+        let seconds = seconds
+            .parse::<u64>()
+            .with_context(|| format!("invalid timeout {}", seconds))?;
+        self.0.insert(name, Duration::from_secs(seconds));
+        Ok(())
+    }
+
+    pub(crate) fn get(&self, section: &str) -> Option<Duration> {
+        self.0.get(section).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadline() {
+        assert!(matches!(remaining(), Ok(None)));
+        let outer = Deadline::set(Duration::from_secs(60));
+        {
+            let _inner = Deadline::set(Duration::ZERO);
+            assert!(remaining().is_err());
+        }
+        assert!(matches!(remaining(), Ok(Some(_))));
+        drop(outer);
+        assert!(matches!(remaining(), Ok(None)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kills_child_past_deadline() {
+        let _deadline = Deadline::set(Duration::from_millis(100));
+        let child = process::Command::new("sh")
+            .args(["-c", "echo started; exec sleep 10"])
+            .stdout(process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        let error = wait_with_output(child).unwrap_err();
+        assert!(TimedOut::caused(&error));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn section_timeouts() {
+        let mut timeouts = SectionTimeouts::default();
+        timeouts.set("default-applications=5").unwrap();
+        assert_eq!(
+            timeouts.get("default_applications"),
+            Some(Duration::from_secs(5))
+        );
+        assert!(timeouts.set("xfcnf=5").is_err());
+    }
+}
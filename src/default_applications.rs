@@ -0,0 +1,420 @@
+use crate::{
+    cfg::{Applier as CfgApplier, Cfg, CfgPatch},
+    external::ExternallyManaged,
+    open_file,
+    schema,
+    section::{Section, SectionPatch},
+    symlinks::SymlinkPolicy,
+    ChangeStats,
+    Dirs,
+    PatchRecorder,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs,
+    io,
+    iter,
+    path::{Path, PathBuf},
+};
+
+// helpers.rc keys of the preferred application categories
+const CATEGORIES: [&str; 4] = [
+    "WebBrowser",
+    "MailReader",
+    "FileManager",
+    "TerminalEmulator",
+];
+
+const DESKTOP_ENTRY: &str = "Desktop Entry";
+
+// the preferred applications exo-open and xfce4-mime-helper launch
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DefaultApplications {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    web_browser: Option<Helper>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mail_reader: Option<Helper>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file_manager: Option<Helper>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    terminal_emulator: Option<Helper>,
+    // the existing helpers.rc and custom helper desktop files
+    #[serde(skip)]
+    rc: Cfg,
+    #[serde(skip)]
+    custom: BTreeMap<&'static str, Cfg>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Helper {
+    // the name of a helper desktop file in xfce4/helpers, e.g. `firefox`
+    Installed(String),
+    // a command written to the user's own helper desktop file
+    Custom { command: String },
+}
+
+impl Helper {
+    // the name helpers.rc refers to the helper by
+    fn name(&self, category: &str) -> String {
+        match self {
+            Self::Installed(name) => name.clone(),
+            Self::Custom { .. } => custom_name(category),
+        }
+    }
+}
+
+// same as the name exo gives custom helpers
+fn custom_name(category: &str) -> String {
+    format!("custom-{}", category)
+}
+
+fn custom_desktop_file(category: &str, command: &str) -> Cfg {
+    let entry = [
+        ("Type", "X-XFCE-Helper".to_owned()),
+        ("Version", "1.0".to_owned()),
+        ("NoDisplay", "true".to_owned()),
+        ("Name", command.to_owned()),
+        ("X-XFCE-Category", category.to_owned()),
+        ("X-XFCE-Commands", command.to_owned()),
+        (
+            "X-XFCE-CommandsWithParameter",
+            format!("{} \"%s\"", command),
+        ),
+    ];
+    let entry = entry
+        .into_iter()
+        .map(|(key, value)| (key.to_owned(), value))
+        .collect();
+    Cfg {
+        sections: iter::once((DESKTOP_ENTRY.to_owned(), entry)).collect(),
+        ..Cfg::default()
+    }
+}
+
+fn read_cfg(path: &Path) -> Result<Option<Cfg>> {
+    open_file(path)
+        .with_context(|| format!("error opening {}", path.display()))?
+        .map(|file| {
+            Cfg::read(io::BufReader::new(file))
+                .with_context(|| format!("error reading {}", path.display()))
+        })
+        .transpose()
+}
+
+impl DefaultApplications {
+    fn helper(&self, category: &str) -> Option<&Helper> {
+        match category {
+            "WebBrowser" => self.web_browser.as_ref(),
+            "MailReader" => self.mail_reader.as_ref(),
+            "FileManager" => self.file_manager.as_ref(),
+            "TerminalEmulator" => self.terminal_emulator.as_ref(),
+            _ => None,
+        }
+    }
+
+    fn helper_mut(&mut self, category: &str) -> Option<&mut Option<Helper>> {
+        match category {
+            "WebBrowser" => Some(&mut self.web_browser),
+            "MailReader" => Some(&mut self.mail_reader),
+            "FileManager" => Some(&mut self.file_manager),
+            "TerminalEmulator" => Some(&mut self.terminal_emulator),
+            _ => None,
+        }
+    }
+
+    fn helpers(&self) -> impl Iterator<Item = (&'static str, &Helper)> + '_ {
+        CATEGORIES.into_iter().filter_map(move |category| {
+            Some((category, self.helper(category)?))
+        })
+    }
+
+    // installed helpers this config uses which have no desktop file in any
+    // of the XDG data dirs
+    pub fn missing_helpers(&self, dirs: &Dirs) -> Vec<String> {
+        let data_dirs = iter::once(dirs.data_home())
+            .chain(crate::xdg_data_dirs())
+            .collect::<Vec<_>>();
+        self.helpers()
+            .filter_map(|(_, helper)| match helper {
+                Helper::Installed(name) => Some(name),
+                Helper::Custom { .. } => None,
+            })
+            .filter(|name| {
+                !data_dirs.iter().any(|data_dir| {
+                    data_dir
+                        .join("xfce4/helpers")
+                        .join(format!("{}.desktop", name))
+                        .is_file()
+                })
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+impl Section for DefaultApplications {
+    type Patch = DefaultApplicationsPatch;
+
+    fn is_empty(&self) -> bool {
+        self.helpers().next().is_none()
+    }
+
+    fn schema() -> serde_json::Value {
+        // an installed helper's name or a custom command
+        let helper = schema::any_of(vec![
+            schema::string(),
+            schema::required(
+                schema::object(vec![("command", schema::string())]),
+                &["command"],
+            ),
+        ]);
+        schema::object(vec![
+            ("web-browser", helper.clone()),
+            ("mail-reader", helper.clone()),
+            ("file-manager", helper.clone()),
+            ("terminal-emulator", helper),
+        ])
+    }
+
+    fn read(_new: &Self, dirs: &Dirs) -> Result<Self> {
+        let mut default_applications = Self {
+            rc: read_cfg(&dirs.xfce4_config_dir.join("helpers.rc"))?
+                .unwrap_or_default(),
+            ..Self::default()
+        };
+        let helpers_dir = dirs.data_home().join("xfce4/helpers");
+        for category in CATEGORIES {
+            let name = match default_applications.rc.root.get(category) {
+                Some(name) => name.clone(),
+                None => continue,
+            };
+            let mut helper = None;
+            if name == custom_name(category) {
+                let path = helpers_dir.join(format!("{}.desktop", name));
+                if let Some(desktop_file) = read_cfg(&path)? {
+                    helper = desktop_file
+                        .sections
+                        .get(DESKTOP_ENTRY)
+                        .and_then(|entry| entry.get("X-XFCE-Commands"))
+                        .map(|command| Helper::Custom {
+                            command: command.clone(),
+                        });
+                    default_applications.custom.insert(category, desktop_file);
+                }
+            }
+            if let Some(slot) = default_applications.helper_mut(category) {
+                *slot = Some(helper.unwrap_or(Helper::Installed(name)));
+            }
+        }
+        Ok(default_applications)
+    }
+
+    fn diff(mut old: Self, new: Self) -> Result<DefaultApplicationsPatch> {
+        let mut custom = BTreeMap::new();
+        for (category, helper) in new.helpers() {
+            if let Helper::Custom { command } = helper {
+                let patch = CfgPatch::diff(
+                    old.custom.remove(category).unwrap_or_default(),
+                    custom_desktop_file(category, command),
+                );
+                if !patch.is_empty() {
+                    custom.insert(category, patch);
+                }
+            }
+        }
+        let rc = Cfg {
+            root: new
+                .helpers()
+                .map(|(category, helper)| {
+                    (category.to_owned(), helper.name(category))
+                })
+                .collect(),
+            ..Cfg::default()
+        };
+        Ok(DefaultApplicationsPatch {
+            custom,
+            rc: CfgPatch::diff(old.rc, rc),
+        })
+    }
+
+    fn apply_to_old(patch: DefaultApplicationsPatch, old: &mut Self) {
+        for (category, cfg_patch) in patch.custom {
+            cfg_patch.apply_to_old(old.custom.entry(category).or_default());
+        }
+        patch.rc.apply_to_old(&mut old.rc);
+        for category in CATEGORIES {
+            let name = match old.rc.root.get(category) {
+                Some(name) => name.clone(),
+                None => continue,
+            };
+            let command = old
+                .custom
+                .get(category)
+                .filter(|_| name == custom_name(category))
+                .and_then(|desktop_file| {
+                    desktop_file.sections.get(DESKTOP_ENTRY)
+                })
+                .and_then(|entry| entry.get("X-XFCE-Commands"))
+                .cloned();
+            if let Some(slot) = old.helper_mut(category) {
+                *slot = Some(match command {
+                    Some(command) => Helper::Custom { command },
+                    None => Helper::Installed(name),
+                });
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DefaultApplicationsPatch {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    custom: BTreeMap<&'static str, CfgPatch>,
+    #[serde(skip_serializing_if = "CfgPatch::is_empty")]
+    rc: CfgPatch,
+}
+
+pub struct Applier<'a> {
+    dry_run: bool,
+    patch_recorder: &'a mut PatchRecorder,
+    config_dir: Cow<'a, Path>,
+    helpers_dir: PathBuf,
+    symlinks: SymlinkPolicy,
+    external: &'a ExternallyManaged,
+}
+
+impl<'a> Applier<'a> {
+    pub(crate) fn new(
+        dry_run: bool,
+        patch_recorder: &'a mut PatchRecorder,
+        config_dir: Cow<'a, Path>,
+        helpers_dir: PathBuf,
+        symlinks: SymlinkPolicy,
+        external: &'a ExternallyManaged,
+    ) -> Self {
+        Self {
+            dry_run,
+            patch_recorder,
+            config_dir,
+            helpers_dir,
+            symlinks,
+            external,
+        }
+    }
+
+    fn cfg_applier(&mut self, path: PathBuf) -> CfgApplier<'_> {
+        CfgApplier::new(
+            self.dry_run,
+            self.patch_recorder,
+            path.into(),
+            self.symlinks,
+            self.external,
+        )
+    }
+
+    fn ensure_dir(&self, dir: &Path) -> Result<()> {
+        if !self.dry_run && !self.external.skip_write(dir) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("error creating {}", dir.display()))?;
+        }
+        Ok(())
+    }
+}
+
+impl DefaultApplicationsPatch {
+    pub fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
+        if !self.custom.is_empty() {
+            applier.ensure_dir(&applier.helpers_dir)?;
+        }
+        for (category, cfg_patch) in self.custom {
+            let path = applier
+                .helpers_dir
+                .join(format!("{}.desktop", custom_name(category)));
+            cfg_patch.apply(&mut applier.cfg_applier(path))?;
+        }
+        if !self.rc.is_empty() {
+            applier.ensure_dir(&applier.config_dir)?;
+            let path = applier.config_dir.join("helpers.rc");
+            self.rc.apply(&mut applier.cfg_applier(path))?;
+        }
+        Ok(())
+    }
+}
+
+impl SectionPatch for DefaultApplicationsPatch {
+    fn change_count(&self) -> ChangeStats {
+        self.custom
+            .values()
+            .chain(iter::once(&self.rc))
+            .map(|cfg_patch| cfg_patch.change_count().in_file())
+            .sum()
+    }
+
+    fn apply(self: Box<Self>, applier: &mut crate::Applier<'_>) -> Result<()> {
+        (*self).apply(&mut Applier::new(
+            applier.dry_run,
+            &mut applier.patch_recorder,
+            applier.dirs.xfce4_config_dir.as_path().into(),
+            applier.dirs.data_home().join("xfce4/helpers"),
+            applier.symlinks.default_applications,
+            &applier.external,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff() {
+        let old = DefaultApplications {
+            web_browser: Some(Helper::Installed("chromium".to_owned())),
+            rc: Cfg {
+                root: [
+                    ("WebBrowser", "chromium"),
+                    ("MailReader", "thunderbird"),
+                ]
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+                ..Cfg::default()
+            },
+            ..DefaultApplications::default()
+        };
+        let new = serde_json::from_str::<DefaultApplications>(
+            r#"{
+                "web-browser": "firefox",
+                "terminal-emulator": { "command": "alacritty" }
+            }"#,
+        )
+        .unwrap();
+        let mut state = old.clone();
+        Section::apply_to_old(Section::diff(old, new).unwrap(), &mut state);
+        assert_eq!(
+            state.rc.root.get("WebBrowser").map(String::as_str),
+            Some("firefox")
+        );
+        assert_eq!(
+            state.rc.root.get("MailReader").map(String::as_str),
+            Some("thunderbird")
+        );
+        assert_eq!(
+            state.terminal_emulator,
+            Some(Helper::Custom {
+                command: "alacritty".to_owned()
+            })
+        );
+        assert_eq!(
+            state.custom["TerminalEmulator"].sections[DESKTOP_ENTRY]
+                ["X-XFCE-CommandsWithParameter"],
+            "alacritty \"%s\""
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use crate::{monitors::Monitor, schema, xfconf::Xfconf};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+const CHANNEL: &str = "xfce4-desktop";
+
+// backdrops compiled into the per monitor and workspace props of the desktop
+// channel, later backdrops override earlier ones where they overlap
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Desktop {
+    #[serde(default)]
+    backdrops: Vec<Backdrop>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Backdrop {
+    #[serde(default)]
+    monitor: MonitorSelector,
+    // all workspaces of the xfwm4 workspace count if not given
+    #[serde(default)]
+    workspace: Option<i32>,
+    #[serde(default)]
+    image: Option<String>,
+    #[serde(default)]
+    image_style: Option<ImageStyle>,
+    #[serde(default)]
+    color: Option<Color>,
+    #[serde(default)]
+    color_style: Option<ColorStyle>,
+}
+
+// monitors by output name, resolved with xrandr on the machine the config is
+// applied to unless one is named
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+enum MonitorSelector {
+    All,
+    Primary,
+    Name(String),
+}
+
+impl Default for MonitorSelector {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+impl From<String> for MonitorSelector {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "all" => Self::All,
+            "primary" => Self::Primary,
+            _ => Self::Name(s),
+        }
+    }
+}
+
+impl From<MonitorSelector> for String {
+    fn from(selector: MonitorSelector) -> Self {
+        match selector {
+            MonitorSelector::All => "all".to_owned(),
+            MonitorSelector::Primary => "primary".to_owned(),
+            MonitorSelector::Name(name) => name,
+        }
+    }
+}
+
+impl MonitorSelector {
+    fn resolve(
+        &self,
+        monitors: &mut Option<Vec<Monitor>>,
+    ) -> Result<Vec<String>> {
+        if let Self::Name(name) = self {
+            return Ok(vec![name.clone()]);
+        }
+        let monitors = match monitors {
+            Some(monitors) => monitors,
+            None => monitors.insert(
+                crate::monitors::list().context("error listing monitors")?,
+            ),
+        };
+        let names = monitors
+            .iter()
+            .filter(|monitor| *self == Self::All || monitor.primary)
+            .map(|monitor| monitor.name.clone())
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            bail!("no monitor matches {:?}", self);
+        }
+        Ok(names)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ImageStyle {
+    None,
+    Centered,
+    Tiled,
+    Stretched,
+    Scaled,
+    Zoomed,
+    Spanning,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ColorStyle {
+    Solid,
+    HorizontalGradient,
+    VerticalGradient,
+    Transparent,
+}
+
+// parsed from #rrggbb or #rrggbbaa
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+struct Color([f64; 4]);
+
+impl TryFrom<String> for Color {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        let hex = s
+            .strip_prefix('#')
+            .filter(|hex| hex.is_ascii() && (hex.len() == 6 || hex.len() == 8))
+            .with_context(|| {
+                format!("invalid color {}, expected #rrggbb", s)
+            })?;
+        let mut rgba = [1.0; 4];
+        for (i, channel) in rgba.iter_mut().take(hex.len() / 2).enumerate() {
+            let byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                .with_context(|| format!("invalid color {}", s))?;
+            *channel = f64::from(byte) / 255.0;
+        }
+        Ok(Self(rgba))
+    }
+}
+
+impl From<Color> for String {
+    fn from(Color(rgba): Color) -> Self {
+        rgba.iter().fold("#".to_owned(), |hex, channel| {
+            format!("{}{:02x}", hex, (channel * 255.0).round() as u8)
+        })
+    }
+}
+
+impl Desktop {
+    pub fn schema() -> serde_json::Value {
+        let mut color = schema::string();
+        color["pattern"] = "^#([0-9a-fA-F]{6}|[0-9a-fA-F]{8})$".into();
+        let backdrop = schema::strict(schema::object(vec![
+            // all, primary or a monitor's name
+            ("monitor", schema::string()),
+            ("workspace", schema::integer()),
+            ("image", schema::string()),
+            (
+                "image-style",
+                schema::string_enum(&[
+                    "none",
+                    "centered",
+                    "tiled",
+                    "stretched",
+                    "scaled",
+                    "zoomed",
+                    "spanning",
+                ]),
+            ),
+            ("color", color),
+            (
+                "color-style",
+                schema::string_enum(&[
+                    "solid",
+                    "horizontal-gradient",
+                    "vertical-gradient",
+                    "transparent",
+                ]),
+            ),
+        ]));
+        schema::strict(schema::object(vec![(
+            "backdrops",
+            schema::array(backdrop),
+        )]))
+    }
+
+    // backdrops for all or the primary monitor are compiled once the config
+    // reaches the machine it's applied to, whose monitors they're for
+    pub fn compile(self, xfconf: &mut Xfconf) -> Result<()> {
+        let named = self.backdrops.iter().all(|backdrop| {
+            matches!(backdrop.monitor, MonitorSelector::Name(_))
+        });
+        if !named {
+            xfconf.defer_desktop(self);
+            return Ok(());
+        }
+        self.compile_for_monitors(xfconf)
+    }
+
+    pub(crate) fn compile_for_monitors(
+        self,
+        xfconf: &mut Xfconf,
+    ) -> Result<()> {
+        let mut monitors = None;
+        for backdrop in self.backdrops {
+            let workspaces = match backdrop.workspace {
+                Some(workspace) => workspace..workspace + 1,
+                None => {
+                    0..xfconf
+                        .get_int("xfwm4", "/general/workspace_count")
+                        .context(
+                            "backdrop for all workspaces needs the xfwm4 \
+                             workspace count, set workspace or declare it",
+                        )?
+                },
+            };
+            for monitor in backdrop.monitor.resolve(&mut monitors)? {
+                for workspace in workspaces.clone() {
+                    backdrop.set(xfconf, &monitor, workspace);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Backdrop {
+    fn set(&self, xfconf: &mut Xfconf, monitor: &str, workspace: i32) {
+        let path = |prop: &str| {
+            format!(
+                "/backdrop/screen0/monitor{}/workspace{}/{}",
+                monitor, workspace, prop
+            )
+        };
+        if let Some(image) = &self.image {
+            xfconf.set_string(CHANNEL, &path("last-image"), image.clone());
+        }
+        if let Some(image_style) = self.image_style {
+            xfconf.set_int(CHANNEL, &path("image-style"), image_style as i32);
+        }
+        if let Some(Color(rgba)) = self.color {
+            xfconf.set_double_array(CHANNEL, &path("rgba1"), rgba.to_vec());
+        }
+        if let Some(color_style) = self.color_style {
+            xfconf.set_int(CHANNEL, &path("color-style"), color_style as i32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn compile() {
+        let mut xfconf = Xfconf::default();
+        xfconf.set_int("xfwm4", "/general/workspace_count", 2);
+        let desktop = serde_json::from_value::<Desktop>(json!({
+            "backdrops": [
+                {
+                    "monitor": "HDMI-0",
+                    "image": "/usr/share/backgrounds/sky.png",
+                    "image-style": "zoomed",
+                },
+                {
+                    "monitor": "HDMI-0",
+                    "workspace": 1,
+                    "color": "#ff000080",
+                },
+            ],
+        }))
+        .unwrap();
+        desktop.compile(&mut xfconf).unwrap();
+        let path = "/backdrop/screen0/monitorHDMI-0/workspace1";
+        assert_eq!(
+            xfconf.get_string(CHANNEL, &format!("{}/last-image", path)),
+            Some("/usr/share/backgrounds/sky.png")
+        );
+        assert_eq!(
+            xfconf.get_int(CHANNEL, &format!("{}/image-style", path)),
+            Some(5)
+        );
+        assert_eq!(
+            Color::try_from("#ff000080".to_owned()).unwrap(),
+            Color([1.0, 0.0, 0.0, 128.0 / 255.0])
+        );
+        assert!(Color::try_from("red".to_owned()).is_err());
+        assert!(Color::try_from("#ff00é".to_owned()).is_err());
+        assert_eq!(
+            String::from(Color::try_from("#ff000080".to_owned()).unwrap()),
+            "#ff000080"
+        );
+
+        let desktop = serde_json::from_value::<Desktop>(json!({
+            "backdrops": [{ "monitor": "primary", "color": "#000000" }],
+        }))
+        .unwrap();
+        let mut xfconf = Xfconf::default();
+        desktop.compile(&mut xfconf).unwrap();
+        let deferred = serde_json::to_value(&xfconf).unwrap();
+        assert_eq!(
+            deferred["desktop"]["backdrops"][0],
+            json!({
+                "monitor": "primary",
+                "workspace": null,
+                "image": null,
+                "image-style": null,
+                "color": "#000000ff",
+                "color-style": null,
+            })
+        );
+    }
+}
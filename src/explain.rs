@@ -0,0 +1,180 @@
+// settings from the XFCE settings dialogs and where they live in the config
+const KNOWN: &[(&str, &str)] = &[
+    (
+        "window manager theme",
+        "xfconf channel xfwm4 prop /general/theme",
+    ),
+    (
+        "window title font",
+        "xfconf channel xfwm4 prop /general/title_font",
+    ),
+    (
+        "window button layout",
+        "xfconf channel xfwm4 prop /general/button_layout",
+    ),
+    (
+        "window focus model, click to focus",
+        "xfconf channel xfwm4 prop /general/click_to_focus",
+    ),
+    (
+        "window compositing",
+        "xfconf channel xfwm4 prop /general/use_compositing",
+    ),
+    (
+        "number of workspaces",
+        "xfconf channel xfwm4 prop /general/workspace_count",
+    ),
+    (
+        "workspace names",
+        "xfconf channel xfwm4 prop /general/workspace_names",
+    ),
+    (
+        "monospace font",
+        "xfconf channel xsettings prop /Gtk/MonospaceFontName",
+    ),
+    ("font dpi", "xfconf channel xsettings prop /Xft/DPI"),
+    (
+        "prefer dark theme",
+        "gtk settings key gtk-application-prefer-dark-theme",
+    ),
+    (
+        "panel size",
+        "xfconf channel xfce4-panel prop /panels/panel-<n>/size",
+    ),
+    (
+        "panel position",
+        "xfconf channel xfce4-panel prop /panels/panel-<n>/position",
+    ),
+    (
+        "panel length",
+        "xfconf channel xfce4-panel prop /panels/panel-<n>/length",
+    ),
+    (
+        "panel autohide",
+        "xfconf channel xfce4-panel prop /panels/panel-<n>/autohide-behavior",
+    ),
+    (
+        "panel items, plugins",
+        "xfconf channel xfce4-panel props /panels/panel-<n>/plugin-ids and \
+         /plugins/plugin-<id>",
+    ),
+    (
+        "clock format",
+        "xfconf channel xfce4-panel prop \
+         /plugins/plugin-<id>/digital-time-format of a clock plugin",
+    ),
+    (
+        "launcher items",
+        "xfconf channel xfce4-panel prop /plugins/plugin-<id>/items, with the \
+         desktop files in panel plugin config launcher-<id>",
+    ),
+    (
+        "whisker menu",
+        "panel plugin config whiskermenu-<id>, an rc file",
+    ),
+    (
+        "desktop wallpaper, background",
+        "wallpapers section, pointing xfconf channel xfce4-desktop prop \
+         /backdrop/screen0/monitor<name>/workspace<n>/last-image at it",
+    ),
+    (
+        "desktop icons",
+        "xfconf channel xfce4-desktop prop /desktop-icons/style",
+    ),
+    (
+        "keyboard shortcuts, application shortcuts",
+        "xfconf channel xfce4-keyboard-shortcuts prop /commands/custom/<keys>",
+    ),
+    (
+        "window manager shortcuts",
+        "xfconf channel xfce4-keyboard-shortcuts prop /xfwm4/custom/<keys>",
+    ),
+    (
+        "keyboard layout",
+        "xfconf channel keyboard-layout prop /Default/XkbLayout",
+    ),
+    (
+        "key repeat",
+        "xfconf channel keyboards prop /Default/KeyRepeat",
+    ),
+    (
+        "mouse acceleration",
+        "xfconf channel pointers prop /<device>/Acceleration",
+    ),
+    (
+        "lid action",
+        "xfconf channel xfce4-power-manager prop \
+         /xfce4-power-manager/lid-action-on-ac",
+    ),
+    (
+        "terminal font",
+        "xfconf channel xfce4-terminal prop /font-name",
+    ),
+    (
+        "file manager default view",
+        "xfconf channel thunar prop /default-view",
+    ),
+    (
+        "notification theme",
+        "xfconf channel xfce4-notifyd prop /theme",
+    ),
+    ("x resources", "xresources section, resources"),
+    ("autostart applications", "autostart section, entries"),
+];
+
+#[derive(Debug)]
+pub struct Explanation {
+    pub setting: String,
+    pub config: String,
+}
+
+// settings whose name or config contain every word of the query, ignoring
+// case
+pub fn explain(query: &str) -> Vec<Explanation> {
+    let words = query
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    let known = KNOWN.iter().map(|(setting, config)| Explanation {
+        setting: (*setting).to_owned(),
+        config: (*config).to_owned(),
+    });
+    #[cfg(feature = "glib")]
+    let known =
+        known.chain(crate::xsettings::overridden().map(|(key, path)| {
+            Explanation {
+                setting: key.trim_start_matches("gtk-").replace('-', " "),
+                config: format!(
+                    "xfconf channel xsettings prop {}, xfsettingsd sets gtk \
+                     settings key {} from it",
+                    path, key
+                ),
+            }
+        }));
+    known
+        .filter(|explanation| {
+            let setting = explanation.setting.to_lowercase();
+            let config = explanation.config.to_lowercase();
+            words
+                .iter()
+                .all(|word| setting.contains(word) || config.contains(word))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn explain() {
+        let settings = |query| {
+            super::explain(query)
+                .into_iter()
+                .map(|explanation| explanation.setting)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(settings("Clock FORMAT"), ["clock format"]);
+        assert_eq!(settings("panel autohide"), ["panel autohide"]);
+        assert!(settings("xfwm4").len() > 1);
+        assert!(settings("no such setting").is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+// header comments of files other tools generate, lowercased, and the tool
+const MANAGER_HEADERS: &[(&str, &str)] = &[
+    ("home-manager", "home-manager"),
+    ("ansible managed", "ansible"),
+    ("managed by puppet", "puppet"),
+    ("managed by salt", "salt"),
+    ("chezmoi", "chezmoi"),
+];
+// how much of a file is searched for a header
+const HEADER_LEN: u64 = 512;
+
+// paths another tool manages, e.g. read-only dotfiles, which are still diffed
+// but never written, relative paths are relative to the config dir
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ExternallyManaged {
+    paths: Vec<PathBuf>,
+    // files which look like another tool manages them but are written
+    // without a warning, taken from overwrite-managed
+    #[serde(skip)]
+    overwrite: Vec<PathBuf>,
+}
+
+impl ExternallyManaged {
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub(crate) fn with_overwrite(mut self, overwrite: &[PathBuf]) -> Self {
+        self.overwrite = overwrite.to_vec();
+        self
+    }
+
+    pub(crate) fn resolve(&self, config_dir: &Path) -> Self {
+        let resolve = |paths: &[PathBuf]| {
+            paths.iter().map(|path| config_dir.join(path)).collect()
+        };
+        Self {
+            paths: resolve(&self.paths),
+            overwrite: resolve(&self.overwrite),
+        }
+    }
+
+    // whether a write to the path has to be left out, warning about the drift
+    // it leaves in place if so, or about overwriting a file another tool
+    // seems to manage
+    pub(crate) fn skip_write(&self, path: &Path) -> bool {
+        let managed =
+            self.paths.iter().any(|managed| path.starts_with(managed));
+        if managed {
+            crate::warn(format_args!(
+                "not writing externally managed {}",
+                path.display()
+            ));
+        } else if !self
+            .overwrite
+            .iter()
+            .any(|overwrite| path.starts_with(overwrite))
+        {
+            if let Some(manager) = detect_manager(path) {
+                crate::warn(format_args!(
+                    "overwriting {} which {} seems to manage, add it to \
+                     externally-managed to leave it alone or to \
+                     overwrite-managed to silence this",
+                    path.display(),
+                    manager
+                ));
+            }
+        }
+        managed
+    }
+}
+
+// the tool managing the file, going by where it links to and how it starts
+fn detect_manager(path: &Path) -> Option<&'static str> {
+    let is_symlink = path
+        .symlink_metadata()
+        .map_or(false, |metadata| metadata.file_type().is_symlink());
+    if is_symlink {
+        let target = path.canonicalize().ok()?;
+        if target.starts_with("/nix/store") {
+            return Some("nix");
+        }
+        // stow dirs can be marked to protect them from being stowed into
+        let stowed = target
+            .ancestors()
+            .skip(1)
+            .any(|dir| dir.join(".stow").exists());
+        if stowed {
+            return Some("stow");
+        }
+    }
+
+    let mut header = Vec::new();
+    fs::File::open(path)
+        .ok()?
+        .take(HEADER_LEN)
+        .read_to_end(&mut header)
+        .ok()?;
+    let header = String::from_utf8_lossy(&header).to_lowercase();
+    MANAGER_HEADERS
+        .iter()
+        .find(|(marker, _)| header.contains(marker))
+        .map(|(_, manager)| *manager)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skip_write() {
+        let external = ExternallyManaged {
+            paths: vec![
+                PathBuf::from("xfce4/panel"),
+                PathBuf::from("/etc/gtk-3.0/settings.ini"),
+            ],
+            overwrite: Vec::new(),
+        }
+        .resolve(Path::new("/home/user/.config"));
+        assert!(external.skip_write(Path::new(
+            "/home/user/.config/xfce4/panel/whiskermenu-1.rc"
+        )));
+        assert!(
+            external.skip_write(Path::new("/home/user/.config/xfce4/panel"))
+        );
+        assert!(external.skip_write(Path::new("/etc/gtk-3.0/settings.ini")));
+        assert!(!external.skip_write(Path::new(
+            "/home/user/.config/xfce4/panel-old/whiskermenu-1.rc"
+        )));
+        assert!(!external
+            .skip_write(Path::new("/home/user/.config/gtk-3.0/settings.ini")));
+    }
+
+    #[test]
+    fn detect_manager() {
+        let dir = std::env::temp_dir()
+            .join(format!("xfce-config-external-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let generated = dir.join("generated.rc");
+        fs::write(&generated, "# Ansible managed\n[Settings]\n").unwrap();
+        let plain = dir.join("plain.rc");
+        fs::write(&plain, "[Settings]\n").unwrap();
+        assert_eq!(super::detect_manager(&generated), Some("ansible"));
+        assert_eq!(super::detect_manager(&plain), None);
+        assert_eq!(super::detect_manager(&dir.join("missing.rc")), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
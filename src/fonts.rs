@@ -0,0 +1,148 @@
+use anyhow::{bail, Context, Result};
+use std::{collections::BTreeSet, io, process};
+
+// xfconf props holding Pango font descriptions
+#[cfg(feature = "glib")]
+pub const XFCONF_PROPS: &[(&str, &str)] = &[
+    ("xsettings", "/Gtk/FontName"),
+    ("xsettings", "/Gtk/MonospaceFontName"),
+    ("xfwm4", "/general/title_font"),
+    ("xfce4-terminal", "/font-name"),
+];
+
+// fontconfig aliases which always resolve to some installed font
+const GENERIC_FAMILIES: &[&str] = &[
+    "sans",
+    "sans-serif",
+    "serif",
+    "monospace",
+    "cursive",
+    "fantasy",
+    "system-ui",
+];
+
+// words at the end of a Pango font description which aren't part of the
+// family name
+const STYLE_WORDS: &[&str] = &[
+    "normal",
+    "roman",
+    "regular",
+    "book",
+    "oblique",
+    "italic",
+    "small-caps",
+    "thin",
+    "ultra-light",
+    "extra-light",
+    "light",
+    "semi-light",
+    "demi-light",
+    "medium",
+    "semi-bold",
+    "demi-bold",
+    "bold",
+    "ultra-bold",
+    "extra-bold",
+    "heavy",
+    "black",
+    "ultra-heavy",
+    "extra-heavy",
+    "ultra-condensed",
+    "extra-condensed",
+    "condensed",
+    "semi-condensed",
+    "semi-expanded",
+    "expanded",
+    "extra-expanded",
+    "ultra-expanded",
+];
+
+// font families of a Pango font description like `DejaVu Sans Bold 10`
+pub fn families(description: &str) -> Vec<String> {
+    let mut words = description.split_whitespace().collect::<Vec<_>>();
+    if words.last().map_or(false, |size| {
+        size.trim_end_matches("px").parse::<f64>().is_ok()
+    }) {
+        words.pop();
+    }
+    while words.last().map_or(false, |word| {
+        STYLE_WORDS
+            .iter()
+            .any(|style| style.eq_ignore_ascii_case(word))
+    }) {
+        words.pop();
+    }
+    words
+        .join(" ")
+        .split(',')
+        .map(str::trim)
+        .filter(|family| !family.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+// families which fontconfig doesn't know about, or none if fontconfig isn't
+// available to ask
+pub fn missing(families: &BTreeSet<String>) -> Result<Option<Vec<String>>> {
+    let output = match process::Command::new("fc-list")
+        .args(["--format", "%{family}\\n"])
+        .output()
+    {
+        Ok(output) => output,
+        Err(error) if matches!(error.kind(), io::ErrorKind::NotFound) => {
+            return Ok(None);
+        },
+        Err(error) => return Err(error).context("error running fc-list"),
+    };
+    if !output.status.success() {
+        bail!("fc-list exited with {}", output.status);
+    }
+    let installed = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .flat_map(|line| line.split(','))
+        .map(|family| family.trim().to_lowercase())
+        .collect::<BTreeSet<_>>();
+    Ok(Some(
+        families
+            .iter()
+            .filter(|family| {
+                let family = family.to_lowercase();
+                !GENERIC_FAMILIES.contains(&family.as_str())
+                    && !installed.contains(&family)
+            })
+            .cloned()
+            .collect(),
+    ))
+}
+
+// runs the hook through the shell with the missing families as arguments
+pub fn run_install_hook(hook: &str, families: &[String]) -> Result<()> {
+    let mut child = process::Command::new("sh")
+        .arg("-c")
+        .arg(hook)
+        .arg("sh")
+        .args(families)
+        .spawn()
+        .context("error running font install hook")?;
+    let status = crate::deadline::wait(&mut child)
+        .context("error waiting for font install hook")?;
+    if !status.success() {
+        bail!("font install hook exited with {}", status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn description_families() {
+        assert_eq!(families("DejaVu Sans Bold Italic 10"), ["DejaVu Sans"]);
+        assert_eq!(
+            families("Noto Sans Mono,Monospace 11"),
+            ["Noto Sans Mono", "Monospace"]
+        );
+        assert_eq!(families("Cantarell"), ["Cantarell"]);
+    }
+}
@@ -1,34 +1,39 @@
 use crate::{
     cfg::{Applier as CfgApplier, Cfg, CfgPatch},
+    external::ExternallyManaged,
     json::{Applier as JsonApplier, Json, JsonPatch},
     open_file,
+    schema,
+    section::{Section, SectionPatch},
     serde::{IdMap, RelativePathBuf},
+    symlinks::SymlinkPolicy,
+    ChangeStats,
+    Dirs,
     PatchRecorder,
 };
 use anyhow::{bail, Context, Result};
 use serde::{ser, Deserialize, Serialize};
+#[cfg(feature = "glib")]
+use serde_json::{json, Value};
+#[cfg(feature = "glib")]
+use std::time::SystemTime;
 use std::{
     borrow::Cow,
     collections::BTreeMap,
     fmt,
+    fs,
     io,
     path::{Path, PathBuf},
 };
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct General {
     #[serde(default, skip_serializing_if = "Configs::is_empty")]
     configs: Configs,
 }
 
-impl General {
-    pub fn is_empty(&self) -> bool {
-        self.configs.is_empty()
-    }
-}
-
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 struct Configs(IdMap<Config>);
 
@@ -38,7 +43,7 @@ impl Configs {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 struct Config {
     id: ConfigId,
@@ -102,7 +107,7 @@ enum ConfigRoot {
     Config,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "content", rename_all = "kebab-case")]
 enum ConfigContent {
     Cfg(Cfg),
@@ -110,11 +115,74 @@ enum ConfigContent {
 }
 
 impl General {
-    pub fn read(new_general: &Self, config_dir: &Path) -> Result<Self> {
-        let configs = Configs::read(&new_general.configs, config_dir)
+    // the files the configs are written to
+    pub fn files<'a>(
+        &'a self,
+        config_dir: &'a Path,
+    ) -> impl Iterator<Item = PathBuf> + 'a {
+        (self.configs.0)
+            .0
+            .values()
+            .map(move |config| config.id.full_path(config_dir))
+    }
+}
+
+impl Section for General {
+    type Patch = GeneralPatch;
+
+    fn is_empty(&self) -> bool {
+        self.configs.is_empty()
+    }
+
+    fn schema() -> serde_json::Value {
+        let id = schema::required(
+            schema::object(vec![
+                ("root", schema::string_enum(&["config"])),
+                ("path", schema::string()),
+            ]),
+            &["root", "path"],
+        );
+        let config = |content| {
+            schema::required(
+                schema::object(vec![("id", id.clone()), ("content", content)]),
+                &["id", "content"],
+            )
+        };
+        schema::object(vec![(
+            "configs",
+            schema::array(schema::tagged(
+                "type",
+                vec![
+                    ("cfg", config(Cfg::schema())),
+                    ("json", config(schema::any())),
+                ],
+            )),
+        )])
+    }
+
+    fn read(new: &Self, dirs: &Dirs) -> Result<Self> {
+        let configs = Configs::read(&new.configs, &dirs.config_dir)
             .context("error loading configs")?;
         Ok(Self { configs })
     }
+
+    fn diff(old: Self, new: Self) -> Result<GeneralPatch> {
+        GeneralPatch::diff(old, new)
+    }
+
+    fn retain(&mut self, id: &str) -> Result<()> {
+        let id = id
+            .strip_prefix("configs.")
+            .context("general parts are configs.<config id>")?;
+        if !self.configs.0.retain_id(id) {
+            bail!("no config {}", id);
+        }
+        Ok(())
+    }
+
+    fn apply_to_old(patch: GeneralPatch, old: &mut Self) {
+        patch.configs.apply_to_old(&mut old.configs);
+    }
 }
 
 impl Configs {
@@ -156,6 +224,110 @@ impl ConfigContent {
     }
 }
 
+// modification times of the files under the config dir, used to find the
+// files changed while recording
+#[cfg(feature = "glib")]
+pub struct Snapshot(BTreeMap<PathBuf, SystemTime>);
+
+// xfconf writes its own files, changes to them are recorded from xfconfd
+#[cfg(feature = "glib")]
+const SNAPSHOT_EXCLUDED: &[&str] = &["xfce4/xfconf"];
+
+#[cfg(feature = "glib")]
+impl Snapshot {
+    pub fn take(config_dir: &Path) -> Result<Self> {
+        fn walk(
+            config_dir: &Path,
+            dir: &Path,
+            files: &mut BTreeMap<PathBuf, SystemTime>,
+        ) -> Result<()> {
+            for entry in dir.read_dir().context("error reading dir")? {
+                let entry = entry.context("error reading dir entry")?;
+                let path = entry.path();
+                let rel_path = path.strip_prefix(config_dir)?.to_owned();
+                // recorded paths end up in JSON
+                if rel_path.to_str().is_none() {
+                    crate::warn(format_args!(
+                        "skipping non-UTF-8 path {}",
+                        path.display()
+                    ));
+                    continue;
+                }
+                if SNAPSHOT_EXCLUDED
+                    .iter()
+                    .any(|excluded| rel_path.starts_with(excluded))
+                {
+                    continue;
+                }
+                // follows links so configs linked in from elsewhere are seen
+                let metadata = std::fs::metadata(&path)
+                    .context("error getting dir entry metadata")?;
+                if metadata.is_dir() {
+                    walk(config_dir, &path, files)?;
+                } else if metadata.is_file() {
+                    files.insert(
+                        rel_path,
+                        metadata
+                            .modified()
+                            .context("error getting modified time")?,
+                    );
+                }
+            }
+            Ok(())
+        }
+
+        let mut files = BTreeMap::new();
+        // nothing to snapshot before the config dir is first created
+        if !config_dir.is_dir() {
+            return Ok(Self(files));
+        }
+        walk(config_dir, config_dir, &mut files)
+            .context("error walking config dir")?;
+        Ok(Self(files))
+    }
+
+    // input configs for the files which changed since the snapshot
+    pub fn changed_configs(&self, config_dir: &Path) -> Result<Vec<Value>> {
+        let now = Self::take(config_dir)?;
+        let mut configs = Vec::new();
+        for (path, modified) in now.0 {
+            if self.0.get(&path) == Some(&modified) {
+                continue;
+            }
+            let full_path = config_dir.join(&path);
+            let file = match open_file(&full_path)
+                .context("error opening changed file")?
+            {
+                Some(file) => file,
+                None => continue,
+            };
+            let content = if path.extension() == Some("json".as_ref()) {
+                Json::read(file).map(ConfigContent::Json)
+            } else {
+                Cfg::read(io::BufReader::new(file)).map(ConfigContent::Cfg)
+            };
+            let content = match content {
+                Ok(content) => content,
+                Err(error) => {
+                    crate::warn(format_args!(
+                        "not recording changed file {}: {:#}",
+                        full_path.display(),
+                        error
+                    ));
+                    continue;
+                },
+            };
+            let mut config = serde_json::to_value(content)?;
+            config.as_object_mut().unwrap().insert(
+                "id".to_owned(),
+                json!({ "root": ConfigRoot::Config, "path": path }),
+            );
+            configs.push(config);
+        }
+        Ok(configs)
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct GeneralPatch {
@@ -170,10 +342,6 @@ impl GeneralPatch {
                 .context("error diffing configs")?,
         })
     }
-
-    pub fn is_empty(&self) -> bool {
-        self.configs.is_empty()
-    }
 }
 
 #[derive(Debug, Serialize)]
@@ -205,8 +373,16 @@ impl ConfigsPatch {
         Ok(Self { changed, added })
     }
 
+    fn change_count(&self) -> ChangeStats {
+        self.changed
+            .values()
+            .map(ConfigPatch::change_count)
+            .sum::<ChangeStats>()
+            + ChangeStats::files_written(self.added.len())
+    }
+
     fn is_empty(&self) -> bool {
-        self.changed.is_empty() && self.added.is_empty()
+        self.change_count().is_empty()
     }
 }
 
@@ -225,8 +401,12 @@ impl ConfigPatch {
         })
     }
 
+    fn change_count(&self) -> ChangeStats {
+        self.content.change_count().in_file()
+    }
+
     fn is_empty(&self) -> bool {
-        self.content.is_empty()
+        self.change_count().is_empty()
     }
 }
 
@@ -250,10 +430,10 @@ impl ConfigContentPatch {
         }
     }
 
-    fn is_empty(&self) -> bool {
+    fn change_count(&self) -> ChangeStats {
         match self {
-            ConfigContentPatch::Cfg(cfg_patch) => cfg_patch.is_empty(),
-            ConfigContentPatch::Json(json_patch) => json_patch.is_empty(),
+            ConfigContentPatch::Cfg(cfg_patch) => cfg_patch.change_count(),
+            ConfigContentPatch::Json(json_patch) => json_patch.change_count(),
         }
     }
 }
@@ -261,6 +441,8 @@ pub struct Applier<'a> {
     dry_run: bool,
     patch_recorder: &'a mut PatchRecorder,
     config_dir: Cow<'a, Path>,
+    symlinks: SymlinkPolicy,
+    external: &'a ExternallyManaged,
 }
 
 impl<'a> Applier<'a> {
@@ -268,11 +450,15 @@ impl<'a> Applier<'a> {
         dry_run: bool,
         patch_recorder: &'a mut PatchRecorder,
         config_dir: Cow<'a, Path>,
+        symlinks: SymlinkPolicy,
+        external: &'a ExternallyManaged,
     ) -> Self {
         Self {
             dry_run,
             patch_recorder,
             config_dir,
+            symlinks,
+            external,
         }
     }
 
@@ -281,6 +467,8 @@ impl<'a> Applier<'a> {
             self.dry_run,
             self.patch_recorder,
             id.full_path(&self.config_dir).into(),
+            self.symlinks,
+            self.external,
         )
     }
 
@@ -289,8 +477,24 @@ impl<'a> Applier<'a> {
             self.dry_run,
             self.patch_recorder,
             id.full_path(&self.config_dir).into(),
+            self.symlinks,
+            self.external,
         )
     }
+
+    // added configs can be in dirs which don't exist yet, like on first boot
+    fn ensure_parent_dir(&mut self, id: &ConfigId) -> Result<()> {
+        let path = id.full_path(&self.config_dir);
+        if self.dry_run || self.external.skip_write(&path) {
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| {
+                format!("error creating config dir {}", dir.display())
+            })?;
+        }
+        Ok(())
+    }
 }
 
 impl GeneralPatch {
@@ -300,9 +504,48 @@ impl GeneralPatch {
     }
 }
 
+impl SectionPatch for GeneralPatch {
+    fn change_count(&self) -> ChangeStats {
+        self.configs.change_count()
+    }
+
+    fn apply(self: Box<Self>, applier: &mut crate::Applier<'_>) -> Result<()> {
+        (*self).apply(&mut Applier::new(
+            applier.dry_run,
+            &mut applier.patch_recorder,
+            applier.dirs.config_dir.as_path().into(),
+            applier.symlinks.general,
+            &applier.external,
+        ))
+    }
+}
+
 impl ConfigsPatch {
+    fn apply_to_old(self, old: &mut Configs) {
+        for config in self.added {
+            (old.0).0.insert(config.id.clone(), config);
+        }
+        for (id, config_patch) in self.changed {
+            if let Some(config) = (old.0).0.get_mut(&id) {
+                match (config_patch.content, &mut config.content) {
+                    (
+                        ConfigContentPatch::Cfg(patch),
+                        ConfigContent::Cfg(cfg),
+                    ) => patch.apply_to_old(cfg),
+                    (
+                        ConfigContentPatch::Json(patch),
+                        ConfigContent::Json(json),
+                    ) => patch.apply_to_old(json),
+                    // diffing fails on mismatched content types
+                    _ => {},
+                }
+            }
+        }
+    }
+
     fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
         for config in self.added {
+            applier.ensure_parent_dir(&config.id)?;
             config.apply(applier)?;
         }
         for cfg_patch in self.changed.into_values() {
@@ -1,10 +1,17 @@
-use crate::{serde::IdMap, PatchRecorder};
-use anyhow::{Context, Result};
+use crate::{
+    schema,
+    section::{Section, SectionPatch},
+    serde::IdMap,
+    ChangeStats,
+    Dirs,
+    PatchRecorder,
+};
+use anyhow::{bail, Context, Result};
 use gio::prelude::SettingsExt;
 use serde::{de, ser, Deserialize, Serialize};
 use std::{collections::BTreeMap, fmt};
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct GSettings {
     #[serde(default, skip_serializing_if = "Schemas::is_empty")]
@@ -12,8 +19,24 @@ pub struct GSettings {
 }
 
 impl GSettings {
-    pub fn is_empty(&self) -> bool {
-        self.schemas.is_empty()
+    // input which can never result in any changes
+    pub fn unused(&self, old: &Self) -> Vec<String> {
+        let mut unused = Vec::new();
+        for (id, schema) in &(self.schemas.0).0 {
+            let old_schema = (old.schemas.0).0.get(id);
+            for key in schema.values.keys() {
+                if old_schema
+                    .filter(|old| old.values.contains_key(key))
+                    .is_none()
+                {
+                    unused.push(format!(
+                        "gsettings key {} does not exist in schema {}",
+                        key, id
+                    ));
+                }
+            }
+        }
+        unused
     }
 }
 
@@ -29,18 +52,61 @@ impl Schemas {
 #[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 struct Schema {
     id: String,
-    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "crate::serde::deserialize_unannotated"
+    )]
     values: BTreeMap<String, Value>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 struct Value(glib::Variant);
 
-impl GSettings {
-    pub fn load(new_gsettings: &Self) -> Result<Self> {
-        let schemas = Schemas::load(&new_gsettings.schemas)?;
+impl Section for GSettings {
+    type Patch = GSettingsPatch;
+
+    fn is_empty(&self) -> bool {
+        self.schemas.is_empty()
+    }
+
+    fn schema() -> serde_json::Value {
+        // values are GVariant text, like `'dark'` or `uint32 3`
+        let values = schema::map(schema::string(), true);
+        let settings = schema::required(
+            schema::object(vec![("id", schema::string()), ("values", values)]),
+            &["id"],
+        );
+        schema::object(vec![("schemas", schema::array(settings))])
+    }
+
+    fn read(new: &Self, _dirs: &Dirs) -> Result<Self> {
+        let schemas = Schemas::load(&new.schemas)?;
         Ok(Self { schemas })
     }
+
+    fn diff(old: Self, new: Self) -> Result<GSettingsPatch> {
+        Ok(GSettingsPatch::diff(old, new))
+    }
+
+    fn retain(&mut self, id: &str) -> Result<()> {
+        if !self.schemas.0.retain_id(id) {
+            bail!("no schema {}", id);
+        }
+        Ok(())
+    }
+
+    fn apply_to_old(patch: GSettingsPatch, old: &mut Self) {
+        for (id, schema_patch) in patch.schemas.changed {
+            if let Some(schema) = (old.schemas.0).0.get_mut(&id) {
+                schema.values.extend(schema_patch.changed);
+            }
+        }
+    }
+
+    fn unused(&self, old: &Self) -> Vec<String> {
+        self.unused(old)
+    }
 }
 
 impl Schemas {
@@ -138,10 +204,6 @@ impl GSettingsPatch {
             schemas: SchemasPatch::diff(old.schemas, new.schemas),
         }
     }
-
-    pub fn is_empty(&self) -> bool {
-        self.schemas.is_empty()
-    }
 }
 
 #[derive(Debug, Serialize)]
@@ -165,8 +227,12 @@ impl SchemasPatch {
         Self { changed }
     }
 
+    fn change_count(&self) -> ChangeStats {
+        self.changed.values().map(SchemaPatch::change_count).sum()
+    }
+
     fn is_empty(&self) -> bool {
-        self.changed.is_empty()
+        self.change_count().is_empty()
     }
 }
 
@@ -175,28 +241,29 @@ impl SchemasPatch {
 struct SchemaPatch {
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     changed: BTreeMap<String, Value>,
-    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
-    added: BTreeMap<String, Value>,
 }
 
 impl SchemaPatch {
     fn diff(mut old: Schema, new: Schema) -> Self {
         let mut changed = BTreeMap::new();
-        let mut added = BTreeMap::new();
+        // keys missing from the loaded schema can't be set and are reported as
+        // unused instead
         for (key, new_value) in new.values.into_iter() {
             if let Some(old_value) = old.values.remove(&key) {
                 if old_value != new_value {
                     changed.insert(key, new_value);
                 }
-            } else {
-                added.insert(key, new_value);
             }
         }
-        Self { changed, added }
+        Self { changed }
+    }
+
+    fn change_count(&self) -> ChangeStats {
+        ChangeStats::changed(self.changed.len())
     }
 
     fn is_empty(&self) -> bool {
-        self.changed.is_empty() && self.added.is_empty()
+        self.change_count().is_empty()
     }
 }
 
@@ -282,6 +349,19 @@ impl GSettingsPatch {
     }
 }
 
+impl SectionPatch for GSettingsPatch {
+    fn change_count(&self) -> ChangeStats {
+        self.schemas.change_count()
+    }
+
+    fn apply(self: Box<Self>, applier: &mut crate::Applier<'_>) -> Result<()> {
+        (*self).apply(&mut Applier::new(
+            applier.dry_run,
+            &mut applier.patch_recorder,
+        ))
+    }
+}
+
 impl SchemasPatch {
     fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
         for (id, schema) in self.changed.into_iter() {
@@ -297,9 +377,6 @@ impl SchemaPatch {
         for (key, value) in self.changed.into_iter() {
             applier.set(&key, value)?;
         }
-        for (key, value) in self.added.into_iter() {
-            applier.set(&key, value)?;
-        }
         Ok(())
     }
 }
@@ -1,13 +1,19 @@
 use crate::{
     cfg::{Applier as CfgApplier, Cfg, CfgPatch},
+    external::ExternallyManaged,
     open_file,
+    schema,
+    section::{Section, SectionPatch},
+    symlinks::SymlinkPolicy,
+    ChangeStats,
+    Dirs,
     PatchRecorder,
 };
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::{borrow::Cow, fs, io, path::Path};
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Gtk {
     #[serde(default, skip_serializing_if = "Settings::is_empty")]
@@ -15,12 +21,26 @@ pub struct Gtk {
 }
 
 impl Gtk {
-    pub fn is_empty(&self) -> bool {
-        self.settings.is_empty()
+    pub fn setting(&self, key: &str) -> Option<&str> {
+        (self.settings.0)
+            .as_ref()?
+            .sections
+            .get("Settings")?
+            .get(key)
+            .map(String::as_str)
+    }
+
+    #[cfg(feature = "glib")]
+    pub fn remove_setting(&mut self, key: &str) -> Option<String> {
+        (self.settings.0)
+            .as_mut()?
+            .sections
+            .get_mut("Settings")?
+            .remove(key)
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 struct Settings(Option<Cfg>);
 
@@ -30,11 +50,35 @@ impl Settings {
     }
 }
 
-impl Gtk {
-    pub fn read(dir: &Path) -> Result<Self> {
-        let settings = Settings::read(dir)?;
+impl Section for Gtk {
+    type Patch = GtkPatch;
+
+    fn is_empty(&self) -> bool {
+        self.settings.is_empty()
+    }
+
+    fn schema() -> serde_json::Value {
+        schema::object(vec![("settings", schema::nullable(Cfg::schema()))])
+    }
+
+    fn read(_new: &Self, dirs: &Dirs) -> Result<Self> {
+        let settings = Settings::read(&dirs.gtk_config_dir)?;
         Ok(Self { settings })
     }
+
+    fn diff(old: Self, new: Self) -> Result<GtkPatch> {
+        Ok(GtkPatch::diff(old, new))
+    }
+
+    fn apply_to_old(patch: GtkPatch, old: &mut Self) {
+        match (patch.settings, &mut old.settings.0) {
+            (SettingsPatch::Added(cfg), settings) => *settings = Some(cfg),
+            (SettingsPatch::Changed(cfg_patch), Some(cfg)) => {
+                cfg_patch.apply_to_old(cfg)
+            },
+            _ => {},
+        }
+    }
 }
 
 impl Settings {
@@ -72,10 +116,6 @@ impl GtkPatch {
             settings: SettingsPatch::diff(old.settings, new.settings),
         }
     }
-
-    pub fn is_empty(&self) -> bool {
-        self.settings.is_empty()
-    }
 }
 
 impl SettingsPatch {
@@ -94,19 +134,25 @@ impl SettingsPatch {
         }
     }
 
-    fn is_empty(&self) -> bool {
+    fn change_count(&self) -> ChangeStats {
         match self {
-            Self::Added(_cfg) => false,
-            Self::Changed(cfg_patch) => cfg_patch.is_empty(),
-            Self::Unchanged => true,
+            Self::Added(_cfg) => ChangeStats::files_written(1),
+            Self::Changed(cfg_patch) => cfg_patch.change_count().in_file(),
+            Self::Unchanged => ChangeStats::default(),
         }
     }
+
+    fn is_empty(&self) -> bool {
+        self.change_count().is_empty()
+    }
 }
 
 pub struct Applier<'a> {
     dry_run: bool,
     patch_recorder: &'a mut PatchRecorder,
     dir: Cow<'a, Path>,
+    symlinks: SymlinkPolicy,
+    external: &'a ExternallyManaged,
 }
 
 impl<'a> Applier<'a> {
@@ -114,11 +160,15 @@ impl<'a> Applier<'a> {
         dry_run: bool,
         patch_recorder: &'a mut PatchRecorder,
         dir: Cow<'a, Path>,
+        symlinks: SymlinkPolicy,
+        external: &'a ExternallyManaged,
     ) -> Self {
         Self {
             dry_run,
             patch_recorder,
             dir,
+            symlinks,
+            external,
         }
     }
 
@@ -127,12 +177,16 @@ impl<'a> Applier<'a> {
             self.dry_run,
             self.patch_recorder,
             self.dir.join("settings.ini").into(),
+            self.symlinks,
+            self.external,
         )
     }
 
     fn ensure_dir(&mut self) -> Result<()> {
-        if !self.dry_run {
-            fs::create_dir_all(&self.dir)?;
+        if !self.dry_run && !self.external.skip_write(&self.dir) {
+            fs::create_dir_all(&self.dir).with_context(|| {
+                format!("error creating GTK config dir {}", self.dir.display())
+            })?;
         }
         Ok(())
     }
@@ -145,6 +199,22 @@ impl GtkPatch {
     }
 }
 
+impl SectionPatch for GtkPatch {
+    fn change_count(&self) -> ChangeStats {
+        self.settings.change_count()
+    }
+
+    fn apply(self: Box<Self>, applier: &mut crate::Applier<'_>) -> Result<()> {
+        (*self).apply(&mut Applier::new(
+            applier.dry_run,
+            &mut applier.patch_recorder,
+            applier.dirs.gtk_config_dir.as_path().into(),
+            applier.symlinks.gtk,
+            &applier.external,
+        ))
+    }
+}
+
 impl SettingsPatch {
     fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
         match self {
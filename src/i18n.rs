@@ -0,0 +1,164 @@
+use std::{env, fmt};
+
+// user-facing messages which are translated, anything not listed here is
+// only in english
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Message {
+    NoChanges,
+    // the change stats
+    WouldApply,
+    Applied,
+    // changed, added, removed, files written and files removed
+    ChangeStats,
+    // the number of paths
+    SkippedPaths,
+    Warning,
+    Error,
+    CausedBy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Lang {
+    En,
+    De,
+    Es,
+    Fr,
+}
+
+impl Lang {
+    // like gettext, LANGUAGE wins over LC_ALL, which wins over LC_MESSAGES,
+    // which wins over LANG
+    fn from_env() -> Self {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|locale| !locale.is_empty())
+            .unwrap_or_default();
+        // LANGUAGE is ignored for the C locale
+        if locale == "C" || locale == "POSIX" || locale.is_empty() {
+            return Self::En;
+        }
+        env::var("LANGUAGE")
+            .ok()
+            .into_iter()
+            .flat_map(|languages| {
+                languages.split(':').map(str::to_owned).collect::<Vec<_>>()
+            })
+            .chain(Some(locale))
+            .find_map(|locale| Self::parse(&locale))
+            .unwrap_or(Self::En)
+    }
+
+    // from a locale like de_AT.UTF-8@euro
+    fn parse(locale: &str) -> Option<Self> {
+        let language = locale.split(['_', '.', '@']).next().unwrap_or_default();
+        match language {
+            "en" => Some(Self::En),
+            "de" => Some(Self::De),
+            "es" => Some(Self::Es),
+            "fr" => Some(Self::Fr),
+            _ => None,
+        }
+    }
+}
+
+// the template of a message, with {0}, {1}, ... replaced by its arguments
+fn template(lang: Lang, message: Message) -> &'static str {
+    use Lang::*;
+    use Message::*;
+    match (lang, message) {
+        (En, NoChanges) => "no changes",
+        (En, WouldApply) => "would apply {0}",
+        (En, Applied) => "applied {0}",
+        (En, ChangeStats) => {
+            "{0} changed, {1} added, {2} removed, {3} files written, {4} files \
+             removed"
+        },
+        (En, SkippedPaths) => "skipped {0} non-UTF-8 paths",
+        (En, Warning) => "warning:",
+        (En, Error) => "error:",
+        (En, CausedBy) => "caused by:",
+
+        (De, NoChanges) => "keine Änderungen",
+        (De, WouldApply) => "würde anwenden: {0}",
+        (De, Applied) => "angewendet: {0}",
+        (De, ChangeStats) => {
+            "{0} geändert, {1} hinzugefügt, {2} entfernt, {3} Dateien \
+             geschrieben, {4} Dateien entfernt"
+        },
+        (De, SkippedPaths) => "{0} Pfade ohne gültiges UTF-8 übersprungen",
+        (De, Warning) => "Warnung:",
+        (De, Error) => "Fehler:",
+        (De, CausedBy) => "verursacht durch:",
+
+        (Es, NoChanges) => "sin cambios",
+        (Es, WouldApply) => "se aplicaría: {0}",
+        (Es, Applied) => "aplicado: {0}",
+        (Es, ChangeStats) => {
+            "{0} cambiados, {1} añadidos, {2} eliminados, {3} archivos \
+             escritos, {4} archivos eliminados"
+        },
+        (Es, SkippedPaths) => "{0} rutas no UTF-8 omitidas",
+        (Es, Warning) => "advertencia:",
+        (Es, Error) => "error:",
+        (Es, CausedBy) => "causado por:",
+
+        (Fr, NoChanges) => "aucune modification",
+        (Fr, WouldApply) => "appliquerait : {0}",
+        (Fr, Applied) => "appliqué : {0}",
+        (Fr, ChangeStats) => {
+            "{0} modifiés, {1} ajoutés, {2} supprimés, {3} fichiers écrits, \
+             {4} fichiers supprimés"
+        },
+        (Fr, SkippedPaths) => "{0} chemins non UTF-8 ignorés",
+        (Fr, Warning) => "avertissement :",
+        (Fr, Error) => "erreur :",
+        (Fr, CausedBy) => "causé par :",
+    }
+}
+
+// a message in the language of the environment
+pub fn text(message: Message, args: &[&dyn fmt::Display]) -> String {
+    fill(template(Lang::from_env(), message), args)
+}
+
+fn fill(template: &str, args: &[&dyn fmt::Display]) -> String {
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let arg = rest.find('}').and_then(|end| {
+            let index = rest[1..end].parse::<usize>().ok()?;
+            Some((index, end))
+        });
+        match arg.and_then(|(index, end)| Some((args.get(index)?, end))) {
+            Some((arg, end)) => {
+                text.push_str(&arg.to_string());
+                rest = &rest[end + 1..];
+            },
+            None => {
+                text.push('{');
+                rest = &rest[1..];
+            },
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill() {
+        assert_eq!(Lang::parse("de_AT.UTF-8@euro"), Some(Lang::De));
+        assert_eq!(Lang::parse("pt_BR"), None);
+        assert_eq!(
+            super::fill(template(Lang::De, Message::WouldApply), &[&"x"]),
+            "würde anwenden: x"
+        );
+        assert_eq!(super::fill("{1} {0} {2} {x}", &[&1, &2]), "2 1 {2} {x}");
+    }
+}
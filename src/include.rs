@@ -0,0 +1,314 @@
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+};
+
+// an object with only an `include` key is replaced by the contents of the
+// referenced file, and if it is an element of an array and the included file
+// is an array too, the included elements are spliced into that array, an
+// `include-encrypted` key does the same with a file decrypted by the shell
+// command given, which prints the decrypted contents of the file given as $1,
+// or by gpg for .gpg and .asc files
+pub fn resolve(
+    value: Value,
+    dir: &Path,
+    decrypt_command: Option<&str>,
+) -> Result<(Value, Secrets)> {
+    let mut resolver = Resolver::new(decrypt_command);
+    let value = resolver.resolve(value, dir)?;
+    Ok((value, resolver.secrets))
+}
+
+// the format is detected by extension if not given
+pub fn read(
+    path: &Path,
+    format: Option<InputFormat>,
+    decrypt_command: Option<&str>,
+) -> Result<(Value, Secrets)> {
+    let mut resolver = Resolver::new(decrypt_command);
+    let value = resolver.include(path, false, format)?;
+    Ok((value, resolver.secrets))
+}
+
+// the string values of encrypted includes, which are kept out of the logs so
+// they're only stored encrypted
+#[derive(Debug, Clone, Default)]
+pub struct Secrets(BTreeSet<String>);
+
+impl Secrets {
+    fn collect(&mut self, value: &Value) {
+        match value {
+            Value::String(s) if !s.is_empty() => {
+                self.0.insert(s.clone());
+            },
+            Value::Array(values) => {
+                values.iter().for_each(|value| self.collect(value))
+            },
+            Value::Object(map) => {
+                map.values().for_each(|value| self.collect(value))
+            },
+            _ => {},
+        }
+    }
+
+    pub fn extend(&mut self, other: Self) {
+        self.0.extend(other.0);
+    }
+
+    // the value as JSON with the secrets in its strings replaced
+    pub fn redact(&self, value: &impl Serialize) -> Result<Value> {
+        let mut value = serde_json::to_value(value)?;
+        if !self.0.is_empty() {
+            self.redact_value(&mut value);
+        }
+        Ok(value)
+    }
+
+    fn redact_value(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => {
+                for secret in &self.0 {
+                    if s.contains(secret.as_str()) {
+                        *s = s.replace(secret.as_str(), "<redacted>");
+                    }
+                }
+            },
+            Value::Array(values) => {
+                values.iter_mut().for_each(|value| self.redact_value(value))
+            },
+            Value::Object(map) => {
+                map.values_mut().for_each(|value| self.redact_value(value))
+            },
+            _ => {},
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl InputFormat {
+    // JSON unless the extension says otherwise
+    fn of(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext == "toml" => Self::Toml,
+            Some(ext) if ext == "yaml" || ext == "yml" => Self::Yaml,
+            _ => Self::Json,
+        }
+    }
+
+    pub fn parse(self, content: &[u8]) -> Result<Value> {
+        match self {
+            Self::Json => serde_json::from_slice(content).map_err(Into::into),
+            Self::Toml => toml::from_slice(content).map_err(Into::into),
+            // aliases are expanded while parsing
+            Self::Yaml => serde_yaml::from_slice(content).map_err(Into::into),
+        }
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(Self::Json),
+            "toml" => Ok(Self::Toml),
+            "yaml" => Ok(Self::Yaml),
+            _ => bail!("unknown format {}, expected json, toml or yaml", s),
+        }
+    }
+}
+
+struct Resolver<'a> {
+    decrypt_command: Option<&'a str>,
+    // files currently being included, used to detect cycles
+    stack: Vec<PathBuf>,
+    secrets: Secrets,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(decrypt_command: Option<&'a str>) -> Self {
+        Self {
+            decrypt_command,
+            stack: Vec::new(),
+            secrets: Secrets::default(),
+        }
+    }
+
+    fn resolve(&mut self, value: Value, dir: &Path) -> Result<Value> {
+        match value {
+            Value::Object(map) => {
+                if let Some(include) = Include::of(&map) {
+                    return self.include(
+                        &dir.join(include.path),
+                        include.encrypted,
+                        None,
+                    );
+                }
+                map.into_iter()
+                    .map(|(key, value)| Ok((key, self.resolve(value, dir)?)))
+                    .collect::<Result<Map<_, _>>>()
+                    .map(Value::Object)
+            },
+            Value::Array(values) => {
+                let mut resolved = Vec::with_capacity(values.len());
+                for value in values {
+                    let is_include = matches!(
+                        &value,
+                        Value::Object(map) if Include::of(map).is_some()
+                    );
+                    match self.resolve(value, dir)? {
+                        Value::Array(values) if is_include => {
+                            resolved.extend(values)
+                        },
+                        value => resolved.push(value),
+                    }
+                }
+                Ok(Value::Array(resolved))
+            },
+            value => Ok(value),
+        }
+    }
+
+    fn include(
+        &mut self,
+        path: &Path,
+        encrypted: bool,
+        format: Option<InputFormat>,
+    ) -> Result<Value> {
+        let path = path.canonicalize().with_context(|| {
+            format!("error resolving include path {}", path.display())
+        })?;
+        if self.stack.contains(&path) {
+            bail!("include cycle at {}", path.display());
+        }
+        let content = if encrypted {
+            decrypt(&path, self.decrypt_command)?
+        } else {
+            fs::read(&path).with_context(|| {
+                format!("error opening included file {}", path.display())
+            })?
+        };
+        // encrypted files are named like secrets.toml.age
+        let format = format.unwrap_or_else(|| {
+            if encrypted {
+                InputFormat::of(Path::new(path.file_stem().unwrap_or_default()))
+            } else {
+                InputFormat::of(&path)
+            }
+        });
+        let value = format.parse(&content).with_context(|| {
+            format!("error reading included file {}", path.display())
+        })?;
+        let dir = path.parent().map(Path::to_owned).unwrap_or_default();
+        self.stack.push(path);
+        let value = self.resolve(value, &dir);
+        let path = self.stack.pop().unwrap();
+        let value = value.with_context(|| {
+            format!("error resolving includes in {}", path.display())
+        })?;
+        if encrypted {
+            self.secrets.collect(&value);
+        }
+        Ok(value)
+    }
+}
+
+struct Include<'a> {
+    path: &'a str,
+    encrypted: bool,
+}
+
+impl<'a> Include<'a> {
+    fn of(map: &'a Map<String, Value>) -> Option<Self> {
+        if map.len() != 1 {
+            return None;
+        }
+        let (key, path) = map.iter().next()?;
+        let encrypted = match key.as_str() {
+            "include" => false,
+            "include-encrypted" => true,
+            _ => return None,
+        };
+        Some(Self {
+            path: path.as_str()?,
+            encrypted,
+        })
+    }
+}
+
+fn decrypt(path: &Path, command: Option<&str>) -> Result<Vec<u8>> {
+    let command = match command {
+        Some(command) => command,
+        None if path
+            .extension()
+            .map_or(false, |ext| ext == "gpg" || ext == "asc") =>
+        {
+            "gpg --quiet --batch --decrypt \"$1\""
+        },
+        None => bail!(
+            "no command to decrypt {} with, pass --decrypt-command",
+            path.display()
+        ),
+    };
+    // the command gets the path as $1
+    let child = process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .arg("sh")
+        .arg(path)
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::inherit())
+        .spawn()
+        .with_context(|| {
+            format!("error running decrypt command {}", command)
+        })?;
+    let output = crate::deadline::wait_with_output(child).with_context(|| {
+        format!("error waiting for decrypt command {}", command)
+    })?;
+    if !output.status.success() {
+        bail!(
+            "decrypting {} failed, {} exited with {}",
+            path.display(),
+            command,
+            output.status
+        );
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact() {
+        let mut secrets = Secrets::default();
+        secrets.collect(&serde_json::json!({ "token": "hunter2", "n": 1 }));
+        let redacted = secrets
+            .redact(&serde_json::json!({
+                "command": "login --token hunter2",
+                "other": ["kept"],
+            }))
+            .unwrap();
+        assert_eq!(
+            redacted,
+            serde_json::json!({
+                "command": "login --token <redacted>",
+                "other": ["kept"],
+            })
+        );
+    }
+}
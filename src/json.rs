@@ -1,4 +1,11 @@
-use crate::PatchRecorder;
+//! Reading, diffing and writing JSON files.
+
+use crate::{
+    external::ExternallyManaged,
+    symlinks::{has_content, replace_file, SymlinkPolicy},
+    ChangeStats,
+    PatchRecorder,
+};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Number, Value};
@@ -6,13 +13,20 @@ use std::{
     borrow::Cow,
     collections::BTreeMap,
     fs,
-    io::{self, Read, Write},
-    path::{Path, PathBuf},
+    io::{Read, Write},
+    path::Path,
 };
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+/// The value of a file, or the value a file should have when diffed against.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Json(Value);
 
+impl From<Value> for Json {
+    fn from(value: Value) -> Self {
+        Self(value)
+    }
+}
+
 impl Json {
     pub fn read<R>(reader: R) -> Result<Self>
     where
@@ -31,23 +45,33 @@ impl Json {
     }
 }
 
+/// The changes turning one [`Json`] into another, serializable to show them.
+/// Object keys are only ever changed or added, arrays are replaced whole.
 #[derive(Debug, Serialize)]
 pub struct JsonPatch {
     value: ValuePatch,
+    // the data the patch was diffed against, so applying it doesn't need to
+    // read the file again
+    #[serde(skip)]
+    old: Json,
 }
 
 impl JsonPatch {
     pub fn diff(old: Json, new: Json) -> Self {
         Self {
+            old: old.clone(),
             value: ValuePatch::diff(old.0, new.0),
         }
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.value.is_empty()
+    /// Changed values, not counting the file they're written to.
+    pub fn change_count(&self) -> ChangeStats {
+        self.value.change_count()
     }
 
-    fn apply_to_old(self, old: &mut Json) {
+    /// Makes the changes to old, which should be what the patch was diffed
+    /// against.
+    pub fn apply_to_old(self, old: &mut Json) {
         self.value.apply_to_old(&mut old.0);
     }
 }
@@ -87,16 +111,21 @@ impl ValuePatch {
         }
     }
 
+    fn change_count(&self) -> ChangeStats {
+        let changed = match self {
+            ValuePatch::Null => false,
+            ValuePatch::Bool(patch) => !patch.is_empty(),
+            ValuePatch::Number(patch) => !patch.is_empty(),
+            ValuePatch::String(patch) => !patch.is_empty(),
+            ValuePatch::Array(patch) => !patch.is_empty(),
+            ValuePatch::Object(patch) => return patch.change_count(),
+            ValuePatch::Changed(_) => true,
+        };
+        ChangeStats::changed(usize::from(changed))
+    }
+
     fn is_empty(&self) -> bool {
-        match self {
-            ValuePatch::Null => true,
-            ValuePatch::Bool(patch) => patch.is_empty(),
-            ValuePatch::Number(patch) => patch.is_empty(),
-            ValuePatch::String(patch) => patch.is_empty(),
-            ValuePatch::Array(patch) => patch.is_empty(),
-            ValuePatch::Object(patch) => patch.is_empty(),
-            ValuePatch::Changed(_) => false,
-        }
+        self.change_count().is_empty()
     }
 
     fn apply_to_old(self, old: &mut Value) {
@@ -151,8 +180,12 @@ impl ObjectPatch {
         Self { changed, added }
     }
 
-    fn is_empty(&self) -> bool {
-        self.changed.is_empty() && self.added.is_empty()
+    fn change_count(&self) -> ChangeStats {
+        self.changed
+            .values()
+            .map(ValuePatch::change_count)
+            .sum::<ChangeStats>()
+            + ChangeStats::added(self.added.len())
     }
 
     fn apply_to_old(self, old: &mut Map<String, Value>) {
@@ -194,10 +227,12 @@ where
     }
 }
 
-pub struct Applier<'a> {
+pub(crate) struct Applier<'a> {
     dry_run: bool,
     patch_recorder: &'a mut PatchRecorder,
     path: Cow<'a, Path>,
+    symlinks: SymlinkPolicy,
+    external: &'a ExternallyManaged,
 }
 
 impl<'a> Applier<'a> {
@@ -205,42 +240,47 @@ impl<'a> Applier<'a> {
         dry_run: bool,
         patch_recorder: &'a mut PatchRecorder,
         path: Cow<'a, Path>,
+        symlinks: SymlinkPolicy,
+        external: &'a ExternallyManaged,
     ) -> Self {
         Self {
             dry_run,
             patch_recorder,
             path,
+            symlinks,
+            external,
         }
     }
 
     fn write_json(&mut self, json: &Json) -> Result<()> {
+        if self.external.skip_write(&self.path) {
+            return Ok(());
+        }
+        let path = self.symlinks.resolve(&self.path)?;
+        let mut content = Vec::new();
+        json.write(&mut content)
+            .context("error rendering JSON file")?;
+        if has_content(&path, &content) {
+            return Ok(());
+        }
         self.patch_recorder
             .log(&crate::PatchEvent::Json { content: json })
             .context("error logging JSON write")?;
+        self.patch_recorder
+            .log_file_inverse(&path)
+            .context("error logging JSON file inverse")?;
         if !self.dry_run {
-            let mut tmp = self.path.clone().into_owned().into_os_string();
-            tmp.push(".new");
-            let tmp = PathBuf::from(tmp);
-            json.write(
-                fs::File::create(&tmp)
-                    .context("error creating temporary JSON file")?,
-            )
-            .context("error writing temporary JSON file")?;
-            fs::rename(tmp, &self.path)
-                .context("error moving temporary JSON file")?;
+            self.patch_recorder.back_up(&path)?;
+            replace_file(&path, |tmp| {
+                fs::write(tmp, &content)
+                    .context("error writing temporary JSON file")
+            })?;
         }
         Ok(())
     }
 
-    fn update_json(&mut self, json_patch: JsonPatch) -> Result<()> {
-        // TODO: remove double read of existing file
-        // instead of reading it here, the patch should keep the old data
-        let mut json = Json::read(
-            fs::File::open(&self.path)
-                .map(io::BufReader::new)
-                .context("error opening existing JSON file")?,
-        )
-        .context("error reading existing JSON file")?;
+    fn update_json(&mut self, mut json_patch: JsonPatch) -> Result<()> {
+        let mut json = std::mem::take(&mut json_patch.old);
         json_patch.apply_to_old(&mut json);
         self.write_json(&json)?;
         Ok(())
@@ -248,14 +288,14 @@ impl<'a> Applier<'a> {
 }
 
 impl Json {
-    pub fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
+    pub(crate) fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
         applier.write_json(&self)?;
         Ok(())
     }
 }
 
 impl JsonPatch {
-    pub fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
+    pub(crate) fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
         applier.update_json(self)?;
         Ok(())
     }
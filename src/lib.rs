@@ -1,219 +1,1912 @@
 #![warn(rust_2018_idioms, clippy::all)]
 #![deny(clippy::correctness)]
 
+mod autostart;
+mod baseline;
+#[cfg(feature = "file-patching")]
+pub mod cfg;
+#[cfg(not(feature = "file-patching"))]
 mod cfg;
+mod compare;
+#[cfg(feature = "glib")]
 mod dbus;
+mod deadline;
+mod default_applications;
+#[cfg(feature = "glib")]
+mod desktop;
+mod explain;
+mod external;
+mod fonts;
 mod general;
+#[cfg(feature = "glib")]
 mod gsettings;
 mod gtk;
+mod i18n;
+mod include;
+#[cfg(feature = "file-patching")]
+pub mod json;
+#[cfg(not(feature = "file-patching"))]
 mod json;
+mod logs;
+mod managed;
+#[cfg(feature = "glib")]
+mod migrate;
+#[cfg(feature = "glib")]
+mod migrations;
+#[cfg(feature = "model")]
+pub mod model;
+mod monitors;
+mod output;
 mod panel;
+#[cfg(feature = "glib")]
+mod panels;
+mod plugins;
+mod profiles;
+mod schema;
+mod section;
+#[cfg(feature = "glib")]
+mod sequence;
 mod serde;
+#[cfg(feature = "glib")]
+mod shortcuts;
+mod state;
+mod symlinks;
+mod wallpapers;
+#[cfg(feature = "glib")]
 mod xfconf;
+#[cfg(feature = "glib")]
+mod xfconf_blocks;
+#[cfg(feature = "glib")]
+mod xfwm4;
+mod xresources;
+#[cfg(feature = "glib")]
+mod xsettings;
 
-use ::serde::{Deserialize, Serialize};
-use anyhow::{Context, Result};
+use ::serde::{ser, ser::SerializeMap, Deserialize, Serialize};
+use anyhow::{bail, Context, Result};
+use cfg_if::cfg_if;
+pub use compare::{compare_runs, compare_states, RunDifference};
+#[cfg(feature = "glib")]
 use dbus::DBus;
+pub use deadline::{Deadline, SectionTimeouts, TimedOut};
+pub use explain::{explain, Explanation};
+use external::ExternallyManaged;
+pub use i18n::{text, Message};
+pub use include::{InputFormat, Secrets};
+pub use logs::LogCompression;
+pub use managed::ManagedState;
+pub use output::{
+    colored,
+    print_error,
+    set_color,
+    styled,
+    warn,
+    ColorChoice,
+    Stream,
+    Style,
+};
+use section::{PartFilter, Section, SectionPatch};
+#[cfg(feature = "glib")]
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+    Mutex,
+};
+#[cfg(feature = "glib")]
+use std::time::Duration;
 use std::{
-    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    fmt,
     fs,
     io::{self, Read, Write},
-    path::Path,
+    iter,
+    ops,
+    path::{self, Path, PathBuf},
 };
+pub use symlinks::{SymlinkPolicies, SymlinkPolicy};
+pub use wallpapers::remove_downloads as remove_wallpaper_downloads;
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
-pub struct XfceConfig {
-    #[serde(default, skip_serializing_if = "xfconf::Xfconf::is_empty")]
+// every section of the config, generating the config type and the methods
+// which have to cover all of them
+macro_rules! sections {
+    ($($(#[$attr:meta])* $name:ident: $section:ty,)*) => {
+        #[derive(Debug, Default, Clone, Serialize, Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        pub struct XfceConfig {
+            // the sections are only public with the model feature, which
+            // exports their types
+            $(
+                $(#[$attr])*
+                #[cfg(feature = "model")]
+                #[serde(default, skip_serializing_if = "Section::is_empty")]
+                pub $name: $section,
+                $(#[$attr])*
+                #[cfg(not(feature = "model"))]
+                #[serde(default, skip_serializing_if = "Section::is_empty")]
+                $name: $section,
+            )*
+            // only declared in the input, never read from the environment
+            #[serde(default, skip_serializing_if = "ExternallyManaged::is_empty")]
+            externally_managed: ExternallyManaged,
+            // files written without warning even if another tool seems to
+            // manage them
+            #[serde(default, skip_serializing_if = "Vec::is_empty")]
+            overwrite_managed: Vec<PathBuf>,
+            // oldest version of this tool the input works with
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            min_version: Option<String>,
+            // one-time cleanups, only declared in the input
+            #[cfg(feature = "glib")]
+            #[serde(
+                default,
+                skip_serializing_if = "Vec::is_empty",
+                deserialize_with = "crate::serde::deserialize_stripped"
+            )]
+            migrations: Vec<migrations::Migration>,
+            // the input its panel profiles are selected from once the config
+            // is on the machine it's applied to
+            #[serde(skip)]
+            unselected: Option<Unselected>,
+            // values of encrypted includes, kept out of the logs
+            #[serde(skip)]
+            secrets: Secrets,
+        }
+
+        // the names of the sections, as options naming them take them
+        const SECTION_NAMES: &[&str] = &[
+            $(
+                $(#[$attr])*
+                stringify!($name),
+            )*
+        ];
+
+        impl XfceConfig {
+            pub fn from_env(new_config: &Self, dirs: &Dirs) -> Result<Self> {
+                Self::read_env(new_config, dirs, &[])
+            }
+
+            // the environment with the given sections taken as empty instead
+            // of read
+            fn read_env(
+                new_config: &Self,
+                dirs: &Dirs,
+                unread: &[&str],
+            ) -> Result<Self> {
+                Ok(Self {
+                    $(
+                        $(#[$attr])*
+                        $name: if unread.contains(&stringify!($name)) {
+                            Default::default()
+                        } else {
+                            Section::read(&new_config.$name, dirs).context(
+                                concat!(
+                                    "error loading ",
+                                    stringify!($name),
+                                    " data"
+                                ),
+                            )?
+                        },
+                    )*
+                    externally_managed: ExternallyManaged::default(),
+                    overwrite_managed: Vec::new(),
+                    min_version: None,
+                    #[cfg(feature = "glib")]
+                    migrations: Vec::new(),
+                    unselected: None,
+                    secrets: Secrets::default(),
+                })
+            }
+
+            // drops everything but the part of the config at the path, a
+            // section name optionally followed by `.` and the id of one of
+            // its parts
+            pub fn scope(&mut self, path: &str) -> Result<()> {
+                let (section, id) = match path.split_once('.') {
+                    Some((section, id)) => (section, Some(id)),
+                    None => (path, None),
+                };
+                let kept = std::mem::take(self);
+                self.externally_managed = kept.externally_managed;
+                self.overwrite_managed = kept.overwrite_managed;
+                self.min_version = kept.min_version;
+                self.secrets = kept.secrets;
+                match section {
+                    $(
+                        $(#[$attr])*
+                        _ if section
+                            == stringify!($name).replace('_', "-") =>
+                        {
+                            self.$name = kept.$name;
+                            if let Some(id) = id {
+                                self.$name.retain(id).context(concat!(
+                                    "error scoping ",
+                                    stringify!($name)
+                                ))?;
+                            }
+                        },
+                    )*
+                    _ => bail!("unknown section {}", section),
+                }
+                Ok(())
+            }
+
+            // the state applying new over old would leave, without touching
+            // the environment
+            pub fn simulate(old: Self, new: Self) -> Result<Self> {
+                Ok(Self {
+                    $(
+                        $(#[$attr])*
+                        $name: {
+                            let mut state = old.$name.clone();
+                            let patch = Section::diff(old.$name, new.$name)
+                                .context(concat!(
+                                    "error diffing ",
+                                    stringify!($name)
+                                ))?;
+                            Section::apply_to_old(patch, &mut state);
+                            state
+                        },
+                    )*
+                    externally_managed: new.externally_managed,
+                    overwrite_managed: new.overwrite_managed,
+                    min_version: new.min_version,
+                    #[cfg(feature = "glib")]
+                    migrations: new.migrations,
+                    unselected: None,
+                    secrets: new.secrets,
+                })
+            }
+
+            // parts of this config which can't contribute any changes
+            // against old
+            fn unused(&self, old: &Self) -> Vec<String> {
+                let mut unused = Vec::new();
+                $(
+                    $(#[$attr])*
+                    unused.extend(Section::unused(&self.$name, &old.$name));
+                )*
+                unused
+            }
+
+            // paths which were left out when reading because they aren't
+            // UTF-8
+            fn skipped(&self) -> Vec<PathBuf> {
+                let mut skipped = Vec::new();
+                $(
+                    $(#[$attr])*
+                    skipped.extend(Section::skipped(&self.$name));
+                )*
+                skipped
+            }
+
+            // the schemas of the sections by their key in the input
+            fn section_schemas() -> serde_json::Map<String, serde_json::Value> {
+                let mut schemas = serde_json::Map::new();
+                $(
+                    $(#[$attr])*
+                    schemas.insert(
+                        stringify!($name).replace('_', "-"),
+                        <$section as Section>::schema(),
+                    );
+                )*
+                schemas
+            }
+
+            // pushed one by one since sections can be disabled by features
+            #[allow(clippy::vec_init_then_push)]
+            fn diff_sections(
+                old: Self,
+                new: Self,
+            ) -> Result<Vec<(&'static str, Box<dyn SectionPatch>)>> {
+                let mut sections = Vec::<(_, Box<dyn SectionPatch>)>::new();
+                $(
+                    $(#[$attr])*
+                    sections.push((
+                        stringify!($name),
+                        Box::new(
+                            Section::diff(old.$name, new.$name).context(
+                                concat!("error diffing ", stringify!($name)),
+                            )?,
+                        ),
+                    ));
+                )*
+                Ok(sections)
+            }
+        }
+    };
+}
+
+// in the order they're applied, wallpapers have to exist before xfconf is
+// pointed at them
+sections! {
+    wallpapers: wallpapers::Wallpapers,
+    #[cfg(feature = "glib")]
     xfconf: xfconf::Xfconf,
-    #[serde(default, skip_serializing_if = "panel::Panel::is_empty")]
     panel: panel::Panel,
-    #[serde(default, skip_serializing_if = "gtk::Gtk::is_empty")]
     gtk: gtk::Gtk,
-    #[serde(default, skip_serializing_if = "general::General::is_empty")]
+    default_applications: default_applications::DefaultApplications,
     general: general::General,
-    #[serde(default, skip_serializing_if = "gsettings::GSettings::is_empty")]
+    autostart: autostart::Autostart,
+    #[cfg(feature = "glib")]
     gsettings: gsettings::GSettings,
+    xresources: xresources::Xresources,
+    plugins: plugins::Plugins,
 }
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "kebab-case")]
+#[derive(Debug)]
 pub struct XfceConfigPatch {
-    #[serde(skip_serializing_if = "xfconf::XfconfPatch::is_empty")]
-    xfconf: xfconf::XfconfPatch,
-    #[serde(skip_serializing_if = "panel::PanelPatch::is_empty")]
-    panel: panel::PanelPatch,
-    #[serde(skip_serializing_if = "gtk::GtkPatch::is_empty")]
-    gtk: gtk::GtkPatch,
-    #[serde(skip_serializing_if = "general::GeneralPatch::is_empty")]
-    general: general::GeneralPatch,
-    #[serde(skip_serializing_if = "gsettings::GSettingsPatch::is_empty")]
-    gsettings: gsettings::GSettingsPatch,
+    sections: Vec<(&'static str, Box<dyn SectionPatch>)>,
+    unused: Vec<String>,
+    skipped: Vec<PathBuf>,
+    externally_managed: ExternallyManaged,
 }
 
 impl XfceConfigPatch {
     pub fn diff(old: XfceConfig, new: XfceConfig) -> Result<Self> {
+        let unused = new.unused(&old);
+        for unused in &unused {
+            warn(format_args!("unused config: {}", unused));
+        }
+        let skipped = old.skipped();
+        for path in &skipped {
+            warn(format_args!("skipped non-UTF-8 path {}", path.display()));
+        }
+        let externally_managed = new
+            .externally_managed
+            .clone()
+            .with_overwrite(&new.overwrite_managed);
         Ok(XfceConfigPatch {
-            xfconf: xfconf::XfconfPatch::diff(old.xfconf, new.xfconf),
-            panel: panel::PanelPatch::diff(old.panel, new.panel),
-            gtk: gtk::GtkPatch::diff(old.gtk, new.gtk),
-            general: general::GeneralPatch::diff(old.general, new.general)
-                .context("error diffing general")?,
-            gsettings: gsettings::GSettingsPatch::diff(
-                old.gsettings,
-                new.gsettings,
-            ),
+            sections: XfceConfig::diff_sections(old, new)?,
+            unused,
+            skipped,
+            externally_managed,
         })
     }
 
+    // what the environment still differs from the config in, e.g. values
+    // xfconfd clamped, read back after applying it
+    pub fn verify(new: &XfceConfig, dirs: &Dirs) -> Result<Self> {
+        let actual = XfceConfig::from_env(new, dirs)
+            .context("error reading config back from environment")?;
+        Ok(XfceConfigPatch {
+            sections: XfceConfig::diff_sections(actual, new.clone())?,
+            unused: Vec::new(),
+            skipped: Vec::new(),
+            externally_managed: new
+                .externally_managed
+                .clone()
+                .with_overwrite(&new.overwrite_managed),
+        })
+    }
+
+    // keeps only the changes within the paths of only if there are any, and
+    // drops the ones within the paths of skip, paths being like --path
+    pub fn filter(&mut self, only: &[String], skip: &[String]) -> Result<()> {
+        fn split(path: &str) -> (String, Option<&str>) {
+            match path.split_once('.') {
+                Some((section, id)) => (section.replace('-', "_"), Some(id)),
+                None => (path.replace('-', "_"), None),
+            }
+        }
+        let only = only.iter().map(|path| split(path)).collect::<Vec<_>>();
+        let skip = skip.iter().map(|path| split(path)).collect::<Vec<_>>();
+        for (section, _) in only.iter().chain(&skip) {
+            if !self.sections.iter().any(|(name, _)| name == section) {
+                bail!("unknown section {}", section);
+            }
+        }
+        // the ids of the parts the paths name in the section, or None if one
+        // names all of it
+        let ids = |paths: &[(String, Option<&str>)], name: &str| {
+            paths
+                .iter()
+                .filter(|(section, _)| section == name)
+                .map(|(_, id)| id.map(str::to_owned))
+                .collect::<Option<Vec<_>>>()
+        };
+        let mut sections = Vec::new();
+        for (name, mut patch) in std::mem::take(&mut self.sections) {
+            let only_ids = ids(&only, name);
+            let skip_ids = ids(&skip, name);
+            let skip_ids = match skip_ids {
+                Some(skip_ids) => skip_ids,
+                None => continue,
+            };
+            let only_ids = match only_ids {
+                Some(only_ids) if only_ids.is_empty() && !only.is_empty() => {
+                    continue
+                },
+                Some(only_ids) => only_ids,
+                None => Vec::new(),
+            };
+            if !only_ids.is_empty() || !skip_ids.is_empty() {
+                patch
+                    .filter_parts(&PartFilter::new(only_ids, skip_ids))
+                    .with_context(|| format!("error filtering {}", name))?;
+            }
+            sections.push((name, patch));
+        }
+        self.sections = sections;
+        Ok(())
+    }
+
+    // every change which can be declined on its own, changes to sections
+    // without parts are the whole section
+    pub fn changes(&self) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for (section, patch) in &self.sections {
+            if patch.is_empty() {
+                continue;
+            }
+            let parts = patch.parts();
+            if parts.is_empty() {
+                changes.push(Change {
+                    section,
+                    part: None,
+                    description: format!("apply {}", patch.change_count()),
+                });
+            }
+            for (part, description) in parts {
+                changes.push(Change {
+                    section,
+                    part: Some(part),
+                    description,
+                });
+            }
+        }
+        changes
+    }
+
+    // leaves out the changes, as listed by changes
+    pub fn decline(&mut self, declined: Vec<Change>) -> Result<()> {
+        let mut parts = BTreeMap::<_, BTreeSet<_>>::new();
+        let mut sections = BTreeSet::new();
+        for change in declined {
+            match change.part {
+                Some(part) => {
+                    parts.entry(change.section).or_default().insert(part);
+                },
+                None => {
+                    sections.insert(change.section);
+                },
+            }
+        }
+        self.sections.retain(|(name, _)| !sections.contains(name));
+        for (name, patch) in &mut self.sections {
+            if let Some(parts) = parts.remove(name) {
+                patch
+                    .filter_parts(&PartFilter::declined(parts))
+                    .with_context(|| format!("error filtering {}", name))?;
+            }
+        }
+        Ok(())
+    }
+
+    // the changes of proposed, as listed by changes before filtering or
+    // declining, which the patch no longer makes
+    pub fn left_out(&self, proposed: Vec<Change>) -> Vec<Change> {
+        let kept = self.changes();
+        proposed
+            .into_iter()
+            .filter(|change| !kept.contains(change))
+            .collect()
+    }
+
+    // paths in the environment which couldn't be compared against
+    pub fn skipped(&self) -> &[PathBuf] {
+        &self.skipped
+    }
+
+    pub fn change_count(&self) -> ChangeStats {
+        self.sections
+            .iter()
+            .map(|(_, patch)| patch.change_count())
+            .sum()
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.xfconf.is_empty()
-            && self.panel.is_empty()
-            && self.gtk.is_empty()
-            && self.general.is_empty()
-            && self.gsettings.is_empty()
+        self.change_count().is_empty()
+    }
+}
+
+impl Serialize for XfceConfigPatch {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        for (name, patch) in &self.sections {
+            if !patch.is_empty() {
+                let value = patch.to_json().map_err(ser::Error::custom)?;
+                map.serialize_entry(name, &value)?;
+            }
+        }
+        if !self.unused.is_empty() {
+            map.serialize_entry("unused", &self.unused)?;
+        }
+        if !self.skipped.is_empty() {
+            let skipped = self
+                .skipped
+                .iter()
+                .map(|path| path.to_string_lossy())
+                .collect::<Vec<_>>();
+            map.serialize_entry("skipped", &skipped)?;
+        }
+        map.end()
+    }
+}
+
+// a single change of a patch, which can be declined with
+// XfceConfigPatch::decline
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    section: &'static str,
+    part: Option<String>,
+    description: String,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.part {
+            Some(part) => {
+                write!(f, "{} {}: {}", self.section, part, self.description)
+            },
+            None => write!(f, "{}: {}", self.section, self.description),
+        }
     }
 }
 
+// where the sections are read from and applied to
+#[derive(Debug, Clone)]
+pub struct Dirs {
+    pub config_dir: PathBuf,
+    pub xfce4_config_dir: PathBuf,
+    pub gtk_config_dir: PathBuf,
+    pub home_dir: PathBuf,
+    pub wallpapers_dir: PathBuf,
+}
+
+impl Dirs {
+    // the current user's dirs, with wallpapers deployed to the data dir
+    pub fn new(data_dir: &Path) -> Result<Self> {
+        let config_dir =
+            dirs2::config_dir().context("could not get config dir")?;
+        Ok(Self {
+            xfce4_config_dir: config_dir.join("xfce4"),
+            gtk_config_dir: config_dir.join("gtk-3.0"),
+            config_dir,
+            home_dir: dirs2::home_dir().context("could not get home dir")?,
+            wallpapers_dir: data_dir.join("wallpapers"),
+        })
+    }
+
+    // XDG_DATA_HOME, which is only used if it's absolute
+    pub(crate) fn data_home(&self) -> PathBuf {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|data_home| data_home.is_absolute())
+            .unwrap_or_else(|| self.home_dir.join(".local/share"))
+    }
+}
+
+// the system data dirs in order of precedence
+fn xdg_data_dirs() -> Vec<PathBuf> {
+    let data_dirs = std::env::var_os("XDG_DATA_DIRS")
+        .filter(|data_dirs| !data_dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+    std::env::split_paths(&data_dirs).collect()
+}
+
+// how many properties and files a patch changes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ChangeStats {
+    pub changed: usize,
+    pub added: usize,
+    pub removed: usize,
+    pub files_written: usize,
+    pub files_removed: usize,
+}
+
+impl ChangeStats {
+    pub fn changed(count: usize) -> Self {
+        Self {
+            changed: count,
+            ..Self::default()
+        }
+    }
+
+    pub fn added(count: usize) -> Self {
+        Self {
+            added: count,
+            ..Self::default()
+        }
+    }
+
+    pub fn removed(count: usize) -> Self {
+        Self {
+            removed: count,
+            ..Self::default()
+        }
+    }
+
+    pub fn files_written(count: usize) -> Self {
+        Self {
+            files_written: count,
+            ..Self::default()
+        }
+    }
+
+    pub fn files_removed(count: usize) -> Self {
+        Self {
+            files_removed: count,
+            ..Self::default()
+        }
+    }
+
+    // counts the file holding these changes as written if there are any
+    fn in_file(self) -> Self {
+        if self.is_empty() {
+            self
+        } else {
+            self + Self::files_written(1)
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.changed
+            + self.added
+            + self.removed
+            + self.files_written
+            + self.files_removed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+}
+
+impl ops::Add for ChangeStats {
+    type Output = Self;
+
+    fn add(mut self, other: Self) -> Self {
+        self += other;
+        self
+    }
+}
+
+impl ops::AddAssign for ChangeStats {
+    fn add_assign(&mut self, other: Self) {
+        self.changed += other.changed;
+        self.added += other.added;
+        self.removed += other.removed;
+        self.files_written += other.files_written;
+        self.files_removed += other.files_removed;
+    }
+}
+
+impl iter::Sum for ChangeStats {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), ops::Add::add)
+    }
+}
+
+impl fmt::Display for ChangeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        text(
+            Message::ChangeStats,
+            &[
+                &self.changed,
+                &self.added,
+                &self.removed,
+                &self.files_written,
+                &self.files_removed,
+            ],
+        )
+        .fmt(f)
+    }
+}
+
+// sections a fresh environment has nothing of yet, which are diffed as empty
+// when bootstrapping so every declared prop is written, even ones matching the
+// system defaults xfconfd would report for them
+const BOOTSTRAP_UNREAD: &[&str] = &["xfconf", "panel"];
+
 impl XfceConfig {
-    pub fn from_json_reader<R>(reader: R) -> Result<Self>
+    // the environment of a first run, see bootstrap
+    pub fn from_bootstrap_env(new_config: &Self, dirs: &Dirs) -> Result<Self> {
+        Self::read_env(new_config, dirs, BOOTSTRAP_UNREAD)
+    }
+
+    // runs the migrations which haven't run on this machine yet, as recorded
+    // in the state file
+    #[cfg(feature = "glib")]
+    pub fn run_migrations(
+        &self,
+        applier: &mut Applier<'_>,
+        state_path: &Path,
+    ) -> Result<()> {
+        migrations::run(&self.migrations, applier, state_path)
+    }
+
+    // gives panel plugins whose ids were picked the ids the same plugins have
+    // in old, which has to be read from the environment first
+    #[cfg(feature = "glib")]
+    pub fn reuse_plugin_ids(&mut self, old: &XfceConfig) {
+        let moved = panels::reuse_plugin_ids(&mut self.xfconf, &old.xfconf);
+        self.panel.move_plugins(&moved);
+    }
+
+    // values of encrypted includes, for keeping them out of logs
+    pub fn secrets(&self) -> &Secrets {
+        &self.secrets
+    }
+
+    // selects the panel profile and compiles the parts of the config for the
+    // monitors of this machine, left for the machine it's applied to since
+    // configs may be sent to another, xrandr is asked for the number of
+    // monitors if not given
+    pub fn resolve_monitors(&mut self, monitors: Option<usize>) -> Result<()> {
+        if let Some(unselected) = self.unselected.take() {
+            let value = profiles::select(
+                unselected.value,
+                unselected.profiles,
+                || profiles::Facts::read(monitors),
+            )?;
+            let secrets = std::mem::take(&mut self.secrets);
+            *self = Self::from_selected_value(value, &unselected.dir)?;
+            self.secrets = secrets;
+        }
+        #[cfg(feature = "glib")]
+        self.xfconf.resolve_monitors()?;
+        Ok(())
+    }
+
+    // the config as sent to another machine, with the panel profiles still
+    // to select from there
+    pub fn to_portable_value(&self) -> Result<serde_json::Value> {
+        let unselected = match &self.unselected {
+            Some(unselected) => unselected,
+            None => return Ok(serde_json::to_value(self)?),
+        };
+        let mut value = unselected.value.clone();
+        if let Some(config) = value.as_object_mut() {
+            config.insert(
+                "panel-profiles".to_owned(),
+                unselected.profiles.clone(),
+            );
+        }
+        Ok(value)
+    }
+
+    // point panel launcher apps at the installed desktop files their ids name
+    pub fn resolve_apps(
+        &mut self,
+        dirs: &Dirs,
+        skip_missing_favorites: bool,
+    ) -> Result<()> {
+        self.panel.resolve_apps(dirs, skip_missing_favorites)
+    }
+
+    // make sure every panel plugin type used in this config is installed,
+    // either failing or only warning about the ones which aren't
+    pub fn check_plugins(&self, allow_missing: bool) -> Result<()> {
+        let installed = if let Some(installed) = panel::installed_plugin_types()
+            .context("error finding installed panel plugins")?
+        {
+            installed
+        } else {
+            warn("no panel plugin dirs found, not checking plugins");
+            return Ok(());
+        };
+        let used = self.panel.plugin_types();
+        #[cfg(feature = "glib")]
+        let used = used.chain(self.xfconf.panel_plugin_types());
+        let missing = used
+            .filter(|plugin_type| !installed.contains(*plugin_type))
+            .collect::<BTreeSet<_>>();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let missing = missing.into_iter().collect::<Vec<_>>().join(", ");
+        if allow_missing {
+            warn(format_args!("panel plugins not installed: {}", missing));
+            Ok(())
+        } else {
+            bail!("panel plugins not installed: {}", missing)
+        }
+    }
+
+    // make sure the preferred application helpers this config uses are
+    // installed
+    pub fn check_default_applications(&self, dirs: &Dirs) -> Result<()> {
+        let missing = self.default_applications.missing_helpers(dirs);
+        if !missing.is_empty() {
+            bail!(
+                "default application helpers not installed: {}",
+                missing.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    // make sure the fonts used in this config are installed, running the
+    // install hook for missing ones if given and warning about the rest, dry
+    // runs only warn
+    pub fn check_fonts(
+        &self,
+        install_hook: Option<&str>,
+        dry_run: bool,
+    ) -> Result<()> {
+        let descriptions = self.gtk.setting("gtk-font-name").into_iter();
+        #[cfg(feature = "glib")]
+        let descriptions =
+            descriptions.chain(fonts::XFCONF_PROPS.iter().filter_map(
+                |(channel, path)| self.xfconf.get_string(channel, path),
+            ));
+        let families = descriptions
+            .flat_map(fonts::families)
+            .collect::<BTreeSet<_>>();
+        if families.is_empty() {
+            return Ok(());
+        }
+        let mut missing = if let Some(missing) =
+            fonts::missing(&families).context("error finding missing fonts")?
+        {
+            missing
+        } else {
+            warn("fontconfig not available, not checking fonts");
+            return Ok(());
+        };
+        if let Some(install_hook) = install_hook.filter(|_| !dry_run) {
+            if !missing.is_empty() {
+                fonts::run_install_hook(install_hook, &missing)?;
+                missing = fonts::missing(&families)
+                    .context("error finding missing fonts")?
+                    .unwrap_or_default();
+            }
+        }
+        if !missing.is_empty() {
+            warn(format_args!("fonts not installed: {}", missing.join(", ")));
+        }
+        Ok(())
+    }
+
+    // warn about GTK settings which xfsettingsd overrides from the xsettings
+    // channel, or move them there if xfconf is preferred
+    #[cfg(feature = "glib")]
+    pub fn check_xsettings(&mut self, prefer_xfconf: bool) {
+        xsettings::check(&mut self.gtk, &mut self.xfconf, prefer_xfconf);
+    }
+
+    #[cfg(feature = "glib")]
+    pub fn write_kiosk(&self, dir: &Path) -> Result<()> {
+        self.xfconf
+            .write_kiosk(dir)
+            .context("error writing xfconf kiosk files")
+    }
+
+    // files on this machine the config reads when applied
+    pub fn local_files(&self) -> Vec<PathBuf> {
+        self.wallpapers
+            .local_files()
+            .chain(self.plugins.local_files())
+            .map(Path::to_owned)
+            .collect()
+    }
+
+    // files and dirs the config writes to, where any change may be drift
+    pub fn written_paths(&self, dirs: &Dirs) -> Vec<PathBuf> {
+        let panel_dir = dirs.xfce4_config_dir.join("panel");
+        let mut paths = vec![
+            dirs.xfce4_config_dir.clone(),
+            panel_dir.clone(),
+            dirs.gtk_config_dir.clone(),
+            autostart::autostart_dir(dirs),
+            dirs.home_dir.join(".Xresources"),
+            dirs.data_home().join("xfce4/helpers"),
+            dirs.wallpapers_dir.clone(),
+        ];
+        // launchers keep their desktop files in dirs of their own
+        if let Ok(entries) = fs::read_dir(&panel_dir) {
+            paths.extend(
+                entries
+                    .filter_map(|entry| Some(entry.ok()?.path()))
+                    .filter(|path| path.is_dir()),
+            );
+        }
+        paths.extend(self.general.files(&dirs.config_dir));
+        paths
+    }
+
+    // adds the system default xfconf props from the channel files in the
+    // given dir which aren't set, so simulated resets land on the default
+    #[cfg(feature = "glib")]
+    pub fn fill_xfconf_defaults(&mut self, dir: &Path) -> Result<()> {
+        let defaults = xfconf::Xfconf::read_defaults(dir)
+            .context("error reading xfconf defaults")?;
+        self.xfconf.fill_defaults(&defaults);
+        Ok(())
+    }
+
+    // points the wallpaper props at the files deployed to the given dir
+    #[cfg(feature = "glib")]
+    pub fn resolve_wallpapers(&mut self, wallpapers_dir: &Path) {
+        for (channel, path, file) in self.wallpapers.props(wallpapers_dir) {
+            match file.to_str() {
+                Some(file) => {
+                    self.xfconf.set_string(channel, path, file.to_owned())
+                },
+                None => warn(format_args!(
+                    "not setting {} {} to non-UTF-8 path {}",
+                    channel,
+                    path,
+                    file.display()
+                )),
+            }
+        }
+    }
+
+    // the user config is layered over the baseline config if given
+    pub fn from_json_reader<R>(
+        reader: R,
+        baseline: Option<&Path>,
+    ) -> Result<Self>
+    where
+        R: Read,
+    {
+        Self::from_reader(reader, InputFormat::Json, baseline, None)
+    }
+
+    pub fn from_toml_reader<R>(
+        reader: R,
+        baseline: Option<&Path>,
+    ) -> Result<Self>
+    where
+        R: Read,
+    {
+        Self::from_reader(reader, InputFormat::Toml, baseline, None)
+    }
+
+    pub fn from_yaml_reader<R>(
+        reader: R,
+        baseline: Option<&Path>,
+    ) -> Result<Self>
+    where
+        R: Read,
+    {
+        Self::from_reader(reader, InputFormat::Yaml, baseline, None)
+    }
+
+    // encrypted includes are decrypted by the shell command if given, see
+    // include::resolve
+    pub fn from_reader<R>(
+        mut reader: R,
+        format: InputFormat,
+        baseline: Option<&Path>,
+        decrypt_command: Option<&str>,
+    ) -> Result<Self>
     where
         R: Read,
     {
-        serde_json::from_reader(reader).map_err(Into::into)
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+        let value = format.parse(&content)?;
+        let dir =
+            std::env::current_dir().context("error getting current dir")?;
+        let (value, secrets) = include::resolve(value, &dir, decrypt_command)
+            .context("error resolving includes")?;
+        Self::from_json_value(value, secrets, &dir, baseline, decrypt_command)
     }
 
-    pub fn from_env(
-        new_config: &Self,
-        config_dir: &Path,
-        xfce4_config_dir: &Path,
-        gtk_config_dir: &Path,
+    // the format is detected by extension if not given
+    pub fn from_file(
+        path: &Path,
+        format: Option<InputFormat>,
+        baseline: Option<&Path>,
+        decrypt_command: Option<&str>,
     ) -> Result<Self> {
-        // TODO: consider new_config.xfconf to only load used channels
-        let xfconf =
-            xfconf::Xfconf::load().context("error loading xfconf data")?;
-        let panel = panel::Panel::read(&xfce4_config_dir.join("panel"))
-            .context("error loading panel data")?;
-        let gtk =
-            gtk::Gtk::read(gtk_config_dir).context("error loading gtk data")?;
-        let general = general::General::read(&new_config.general, config_dir)
-            .context("error loading general data")?;
-        let gsettings = gsettings::GSettings::load(&new_config.gsettings)
-            .context("error loading gsettings data")?;
-        Ok(Self {
-            xfconf,
-            panel,
-            gtk,
-            general,
-            gsettings,
-        })
+        let (value, secrets) = include::read(path, format, decrypt_command)?;
+        Self::from_json_value(
+            value,
+            secrets,
+            path.parent().unwrap_or(path),
+            baseline,
+            decrypt_command,
+        )
+    }
+
+    // relative paths in the config are relative to dir
+    fn from_json_value(
+        value: serde_json::Value,
+        mut secrets: Secrets,
+        dir: &Path,
+        baseline: Option<&Path>,
+        decrypt_command: Option<&str>,
+    ) -> Result<Self> {
+        // checked before anything else since newer configs may not parse
+        check_min_version(&value)?;
+        let value = match baseline {
+            Some(baseline) => {
+                let (mut baseline_value, baseline_secrets) =
+                    include::read(baseline, None, decrypt_command)
+                        .context("error reading baseline config")?;
+                secrets.extend(baseline_secrets);
+                if let Some(baseline_dir) = baseline.parent() {
+                    baseline::resolve_paths(&mut baseline_value, baseline_dir);
+                }
+                baseline::merge(baseline_value, value)
+            },
+            None => value,
+        };
+        let (value, profiles) = profiles::take(value)?;
+        let unselected = profiles.map(|profiles| Unselected {
+            value: value.clone(),
+            profiles,
+            dir: dir.to_owned(),
+        });
+        // usable as is until a profile is selected, like without profiles
+        let mut config = Self::from_selected_value(value, dir)?;
+        config.unselected = unselected;
+        config.secrets = secrets;
+        Ok(config)
+    }
+
+    fn from_selected_value(
+        value: serde_json::Value,
+        dir: &Path,
+    ) -> Result<Self> {
+        #[cfg(feature = "glib")]
+        let (value, xfconf_blocks) =
+            xfconf_blocks::XfconfBlocks::take(value)
+                .context("error reading typed xfconf config")?;
+        let mut config = serde_json::from_value::<Self>(value)?;
+        #[cfg(feature = "glib")]
+        xfconf_blocks.compile(&mut config.xfconf, &mut config.panel)?;
+        config.wallpapers.resolve_sources(dir);
+        config.plugins.resolve_commands(dir);
+        Ok(config)
+    }
+
+    // JSON Schema of the input, for editors to complete and check configs
+    // with
+    pub fn schema() -> serde_json::Value {
+        let mut props = Self::section_schemas();
+        let paths = schema::array(schema::string());
+        props.insert("externally-managed".to_owned(), paths.clone());
+        props.insert("overwrite-managed".to_owned(), paths);
+        props.insert("min-version".to_owned(), schema::string());
+        #[allow(unused_mut)]
+        let mut defs = serde_json::Map::new();
+        #[cfg(feature = "glib")]
+        {
+            props.insert(
+                "migrations".to_owned(),
+                schema::array(migrations::Migration::schema()),
+            );
+            for (key, block) in xfconf_blocks::XfconfBlocks::schema() {
+                props.insert(key.to_owned(), block);
+            }
+            defs.insert(xfconf::VALUE_DEF.to_owned(), xfconf::value_schema());
+        }
+        // after the typed blocks, which profiles can set too
+        let profiles = profiles::schema(&props);
+        props.insert("panel-profiles".to_owned(), profiles);
+        schema::document(props, defs)
+    }
+
+    #[cfg(feature = "glib")]
+    pub fn from_gsettings(schemas: &[String]) -> Result<Self> {
+        let value = migrate::gsettings_to_xfconf(schemas)
+            .context("error migrating gsettings")?;
+        serde_json::from_value(value).map_err(Into::into)
+    }
+}
+
+// input with panel profiles, and the dir its relative paths are relative to
+#[derive(Debug, Clone)]
+struct Unselected {
+    value: serde_json::Value,
+    profiles: serde_json::Value,
+    dir: PathBuf,
+}
+
+// major, minor and patch of a version like 1.2.3 or v1.2, ignoring any
+// pre-release suffix
+pub fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let version = version.trim().trim_start_matches('v');
+    let version = version.split(['-', '+']).next()?;
+    let mut parts = version.split('.').map(str::parse::<u64>);
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().transpose().ok()?.unwrap_or(0);
+    let patch = parts.next().transpose().ok()?.unwrap_or(0);
+    parts.next().is_none().then(|| (major, minor, patch))
+}
+
+fn check_min_version(value: &serde_json::Value) -> Result<()> {
+    let min_version = match value.get("min-version") {
+        Some(min_version) => min_version,
+        None => return Ok(()),
+    };
+    let min_version = min_version
+        .as_str()
+        .context("min-version must be a string")?;
+    let required = parse_version(min_version)
+        .with_context(|| format!("invalid min-version {}", min_version))?;
+    let version = env!("CARGO_PKG_VERSION");
+    if parse_version(version).map_or(false, |version| version < required) {
+        bail!(
+            "config requires xfce-config {} or newer, this is {}",
+            min_version,
+            version
+        );
+    }
+    Ok(())
+}
+
+// fails clearly when there's no session bus to reach xfconfd and dconf on,
+// e.g. over SSH, where gio would otherwise try to autolaunch one on X11 or
+// fall back to in-memory gsettings
+pub fn check_session_bus() -> Result<()> {
+    if std::env::var_os("DBUS_SESSION_BUS_ADDRESS").is_some() {
+        return Ok(());
+    }
+    let runtime_bus = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(|dir| PathBuf::from(dir).join("bus"));
+    if runtime_bus.map_or(false, |bus| bus.exists()) {
+        return Ok(());
+    }
+    bail!(
+        "no D-Bus session bus found, run this from the XFCE session, pass \
+         --bus-address, or use --simulate with --old to work offline"
+    )
+}
+
+// prepares a fresh environment for a first run, which must have no xfconf
+// channel or panel files yet, creating the skeleton dirs and starting xfconfd
+// so it doesn't have to be activated by the first property write
+pub fn bootstrap(dirs: &Dirs, dry_run: bool) -> Result<()> {
+    let channel_dir = dirs.xfce4_config_dir.join("xfconf/xfce-perchannel-xml");
+    let panel_dir = dirs.xfce4_config_dir.join("panel");
+    for dir in [&channel_dir, &panel_dir] {
+        let has_files = match fs::read_dir(dir) {
+            Ok(mut entries) => entries.next().is_some(),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => false,
+            Err(error) => {
+                return Err(error).with_context(|| {
+                    format!("error reading {}", dir.display())
+                });
+            },
+        };
+        if has_files {
+            bail!(
+                "{} already has files, only bootstrap a fresh environment",
+                dir.display()
+            );
+        }
+    }
+    if dry_run {
+        return Ok(());
+    }
+    for dir in [&channel_dir, &panel_dir, &dirs.gtk_config_dir] {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("error creating {}", dir.display()))?;
+    }
+    #[cfg(feature = "glib")]
+    DBus::new("org.freedesktop.DBus", "/org/freedesktop/DBus")?
+        .call("StartServiceByName", ("org.xfce.Xfconf", 0u32))
+        .context("error starting xfconfd")?;
+    Ok(())
+}
+
+// prints changes to the channel's properties as JSON lines until killed
+#[cfg(feature = "glib")]
+pub fn monitor_xfconf(channel: String) -> Result<()> {
+    let _watch = xfconf::watch(move |change| {
+        if change.channel() != channel {
+            return;
+        }
+        match serde_json::to_string(&change) {
+            Ok(line) => println!("{}", line),
+            Err(error) => warn(format_args!(
+                "error serializing property change: {}",
+                error
+            )),
+        }
+    })
+    .context("error watching xfconf")?;
+    glib::MainLoop::new(None, false).run();
+    Ok(())
+}
+
+// calls on_drift with what the environment differs from the config in, first
+// right away and then whenever an xfconf prop or one of the files the config
+// writes changes, at most every half second, until killed
+#[cfg(feature = "glib")]
+pub fn watch_drift<F>(
+    config: XfceConfig,
+    dirs: Dirs,
+    mut on_drift: F,
+) -> Result<()>
+where
+    F: FnMut(XfceConfigPatch) + 'static,
+{
+    let changed = Arc::new(AtomicBool::new(true));
+    let _watch = xfconf::watch({
+        let changed = Arc::clone(&changed);
+        move |_| changed.store(true, Ordering::SeqCst)
+    })
+    .context("error watching xfconf")?;
+    let mut monitors = Vec::new();
+    for path in config.written_paths(&dirs) {
+        let monitor = gio::prelude::FileExt::monitor(
+            &gio::File::for_path(&path),
+            gio::FileMonitorFlags::NONE,
+            None::<&gio::Cancellable>,
+        )
+        .with_context(|| format!("error watching {}", path.display()))?;
+        let changed = Arc::clone(&changed);
+        gio::prelude::FileMonitorExt::connect_changed(
+            &monitor,
+            move |_, _, _, _| changed.store(true, Ordering::SeqCst),
+        );
+        monitors.push(monitor);
+    }
+
+    glib::timeout_add_local(Duration::from_millis(500), move || {
+        if changed.swap(false, Ordering::SeqCst) {
+            match XfceConfigPatch::verify(&config, &dirs) {
+                Ok(drift) if drift.is_empty() => {},
+                Ok(drift) => on_drift(drift),
+                Err(error) => {
+                    warn(format_args!("error checking for drift: {:#}", error))
+                },
+            }
+        }
+        glib::Continue(true)
+    });
+    glib::MainLoop::new(None, false).run();
+    Ok(())
+}
+
+// records xfconf changes, and changes to files in the config dir if given,
+// until stdin is closed or a line is entered and returns them as input JSON,
+// leaving out props changed back to the defaults in defaults_dir if given
+#[cfg(feature = "glib")]
+pub fn record(
+    config_dir: Option<&Path>,
+    defaults_dir: Option<&Path>,
+) -> Result<serde_json::Value> {
+    let defaults = defaults_dir
+        .map(xfconf::Xfconf::read_defaults)
+        .transpose()
+        .context("error reading xfconf defaults")?;
+    let snapshot = config_dir
+        .map(general::Snapshot::take)
+        .transpose()
+        .context("error taking config dir snapshot")?;
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let _watch = xfconf::watch({
+        let changes = Arc::clone(&changes);
+        move |change| changes.lock().unwrap().push(change)
+    })
+    .context("error watching xfconf")?;
+
+    let main_loop = glib::MainLoop::new(None, false);
+    std::thread::spawn({
+        let main_loop = main_loop.clone();
+        move || {
+            let _ = io::stdin().read_line(&mut String::new());
+            main_loop.quit();
+        }
+    });
+    main_loop.run();
+
+    let changes = std::mem::take(&mut *changes.lock().unwrap());
+    let mut xfconf = xfconf::Xfconf::from_changes(changes);
+    if let Some(defaults) = &defaults {
+        xfconf.without_defaults(defaults);
     }
+    let config = XfceConfig {
+        xfconf,
+        ..XfceConfig::default()
+    };
+    let mut value = serde_json::to_value(config)?;
+    if let (Some(config_dir), Some(snapshot)) = (config_dir, snapshot) {
+        let configs = snapshot
+            .changed_configs(config_dir)
+            .context("error finding changed files")?;
+        if !configs.is_empty() {
+            value.as_object_mut().unwrap().insert(
+                "general".to_owned(),
+                serde_json::json!({ "configs": configs }),
+            );
+        }
+    }
+    Ok(value)
 }
 
 pub struct Applier<'a> {
     dry_run: bool,
     patch_recorder: PatchRecorder,
-    xfce4_config_dir: Cow<'a, Path>,
-    gtk_config_dir: Cow<'a, Path>,
-    config_dir: Cow<'a, Path>,
+    dirs: &'a Dirs,
+    symlinks: SymlinkPolicies,
+    // taken from the patch being applied
+    external: ExternallyManaged,
+    section_timeouts: SectionTimeouts,
 }
 
 struct PatchRecorder {
     file: fs::File,
+    inverse: fs::File,
+    // dry runs don't change anything to revert, so they log no inverses
+    dry_run: bool,
+    // redacted in the patch log, the inverse log keeps them to restore
+    secrets: Secrets,
+    // fsync after each event, not only flush it
+    sync: bool,
+    backup_dir: PathBuf,
+    home_dir: PathBuf,
 }
 
 impl<'a> Applier<'a> {
     pub fn new(
         dry_run: bool,
         log_dir: &Path,
-        xfce4_config_dir: Cow<'a, Path>,
-        gtk_config_dir: Cow<'a, Path>,
-        config_dir: Cow<'a, Path>,
+        dirs: &'a Dirs,
+        symlinks: SymlinkPolicies,
     ) -> Result<Self> {
-        let patch_recorder = PatchRecorder::new(&log_dir.join("patches.json"))
-            .context("error creating patch recorder")?;
+        let patch_recorder =
+            PatchRecorder::new(log_dir, &dirs.home_dir, dry_run)
+                .context("error creating patch recorder")?;
         Ok(Self {
             dry_run,
             patch_recorder,
-            xfce4_config_dir,
-            gtk_config_dir,
-            config_dir,
+            dirs,
+            symlinks,
+            external: ExternallyManaged::default(),
+            section_timeouts: SectionTimeouts::default(),
         })
     }
+
+    pub fn with_section_timeouts(
+        self,
+        section_timeouts: SectionTimeouts,
+    ) -> Self {
+        Self {
+            section_timeouts,
+            ..self
+        }
+    }
+
+    // makes the logs survive the system crashing too, not only xfce-config
+    pub fn with_synced_log(mut self, sync: bool) -> Self {
+        self.patch_recorder.sync = sync;
+        self
+    }
+
+    // keeps the secrets out of the patch log
+    pub fn with_secrets(mut self, secrets: Secrets) -> Self {
+        self.patch_recorder.secrets = secrets;
+        self
+    }
 }
 
 impl XfceConfigPatch {
     pub fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
         let panel_config_changed =
-            !self.panel.is_empty() || self.xfconf.has_panel_changes();
+            self.sections.iter().any(|(_, patch)| patch.changes_panel());
+        applier.external =
+            self.externally_managed.resolve(&applier.dirs.config_dir);
 
-        self.xfconf
-            .apply(
-                &mut xfconf::Applier::new(
-                    applier.dry_run,
-                    &mut applier.patch_recorder,
-                )
-                .context("error creating xfconf applier")?,
-            )
-            .context("error applying xfconf")?;
-        self.panel
-            .apply(&mut panel::Applier::new(
-                applier.dry_run,
-                &mut applier.patch_recorder,
-                applier.xfce4_config_dir.join("panel").into(),
-            ))
-            .context("error applying panel")?;
-        self.gtk
-            .apply(&mut gtk::Applier::new(
-                applier.dry_run,
-                &mut applier.patch_recorder,
-                applier.gtk_config_dir.clone(),
-            ))
-            .context("error applying gtk")?;
-        self.general
-            .apply(&mut general::Applier::new(
-                applier.dry_run,
-                &mut applier.patch_recorder,
-                applier.config_dir.clone(),
-            ))
-            .context("error applying general")?;
-        self.gsettings
-            .apply(&mut gsettings::Applier::new(
-                applier.dry_run,
-                &mut applier.patch_recorder,
-            ))
-            .context("error applying gsettings")?;
+        let mut applied = Vec::new();
+        for (name, patch) in self.sections {
+            let empty = patch.is_empty();
+            let _deadline =
+                applier.section_timeouts.get(name).map(Deadline::set);
+            if let Err(error) = patch.apply(applier) {
+                // what's in patches.json was applied
+                if TimedOut::caused(&error) {
+                    warn(format_args!(
+                        "timed out applying {}, fully applied: {}",
+                        name,
+                        if applied.is_empty() {
+                            "nothing".to_owned()
+                        } else {
+                            applied.join(", ")
+                        }
+                    ));
+                }
+                return Err(error.context(format!("error applying {}", name)));
+            }
+            if !empty {
+                applied.push(name);
+            }
+        }
 
         // restart panel if its config changed
         if panel_config_changed && !applier.dry_run {
+            restart_panel()?;
+        }
+
+        Ok(())
+    }
+}
+
+fn restart_panel() -> Result<()> {
+    cfg_if! {
+        if #[cfg(feature = "glib")] {
             DBus::new("org.xfce.Panel", "/org/xfce/Panel")?
                 .call("Terminate", (true,))
                 .context("error restarting panel")?;
+        } else {
+            let status = std::process::Command::new("xfce4-panel")
+                .arg("--restart")
+                .status()
+                .context("error restarting panel")?;
+            if !status.success() {
+                bail!("xfce4-panel exited with {}", status);
+            }
         }
+    }
+    Ok(())
+}
 
-        Ok(())
+// undoes the changes of the run logged in log_dir, newest first, only
+// printing what would be restored if dry_run
+pub fn revert(log_dir: &Path, dry_run: bool) -> Result<()> {
+    if logged_dry_run(log_dir)? {
+        bail!(
+            "{} is the log of a dry run, which changed nothing to revert",
+            log_dir.display()
+        );
+    }
+    let content = logs::read_log_file(log_dir, "inverse.json")?;
+    let events = content
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(serde_json::from_slice::<LoggedInverseEvent>)
+        .collect::<Result<Vec<_>, _>>()
+        .context("error reading inverse.json")?;
+
+    #[cfg(feature = "glib")]
+    let mut xfconf_dbus = None;
+    let mut panel_config_changed = false;
+    for event in events.into_iter().rev() {
+        let event = match event {
+            LoggedInverseEvent::Known(event) => event,
+            LoggedInverseEvent::Unknown(event) => {
+                warn(format_args!(
+                    "skipping {} event this version can't restore",
+                    event["type"].as_str().unwrap_or("an unknown")
+                ));
+                continue;
+            },
+        };
+        let unknown = event.unknown_fields();
+        if !unknown.is_empty() {
+            warn(format_args!(
+                "ignoring unknown fields of {}: {}",
+                event,
+                unknown.join(", ")
+            ));
+        }
+        eprintln!("restoring {}", event);
+        if dry_run {
+            continue;
+        }
+        panel_config_changed |= event.changes_panel();
+        match event {
+            #[cfg(feature = "glib")]
+            InverseEvent::Channel(event) => {
+                let dbus = match &mut xfconf_dbus {
+                    Some(dbus) => dbus,
+                    None => xfconf_dbus.insert(DBus::new(
+                        "org.xfce.Xfconf",
+                        "/org/xfce/Xfconf",
+                    )?),
+                };
+                let description = event.to_string();
+                event.revert(dbus).with_context(|| {
+                    format!("error restoring {}", description)
+                })?;
+            },
+            InverseEvent::File { path, prior, .. } => {
+                match prior {
+                    Some(prior) => fs::write(&path, prior),
+                    None => fs::remove_file(&path),
+                }
+                .with_context(|| {
+                    format!("error restoring file {}", path.display())
+                })?;
+            },
+            InverseEvent::Backup { path, backup, .. } => {
+                fs::copy(log_dir.join("backup").join(backup), &path)
+                    .with_context(|| {
+                        format!("error restoring file {}", path.display())
+                    })?;
+            },
+            InverseEvent::Link { path, target, .. } => {
+                restore_link(&path, &target).with_context(|| {
+                    format!("error restoring link {}", path.display())
+                })?;
+            },
+            InverseEvent::Rename {
+                old_path, new_path, ..
+            } => {
+                fs::rename(&new_path, &old_path).with_context(|| {
+                    format!("error renaming back {}", old_path.display())
+                })?;
+            },
+            InverseEvent::Dir { path, existed, .. } => {
+                if existed {
+                    fs::create_dir_all(&path)
+                } else {
+                    fs::remove_dir(&path)
+                }
+                .with_context(|| {
+                    format!("error restoring dir {}", path.display())
+                })?;
+            },
+        }
+    }
+    if panel_config_changed {
+        restart_panel()?;
+    }
+    Ok(())
+}
+
+// whether the header of the run's patch log says it was a dry run, logs from
+// before it said aren't taken as one
+fn logged_dry_run(log_dir: &Path) -> Result<bool> {
+    let content = logs::read_log_file(log_dir, "patches.json")?;
+    let header = content.split(|&b| b == b'\n').next().unwrap_or_default();
+    let header = serde_json::from_slice::<serde_json::Value>(header)
+        .context("error reading patches.json header")?;
+    Ok(header["type"] == "header" && header["value"]["dry-run"] == true)
+}
+
+// puts back a link which was removed, or replaced by a file
+fn restore_link(path: &Path, target: &Path) -> Result<()> {
+    if fs::symlink_metadata(path).is_ok() {
+        fs::remove_file(path)?;
+    }
+    cfg_if! {
+        if #[cfg(unix)] {
+            std::os::unix::fs::symlink(target, path)?;
+            Ok(())
+        } else {
+            bail!("platform does support FS linking")
+        }
     }
 }
 
 impl PatchRecorder {
-    fn new(path: &Path) -> Result<Self> {
-        let file = fs::File::create(path)?;
-        Ok(Self { file })
+    fn new(log_dir: &Path, home_dir: &Path, dry_run: bool) -> Result<Self> {
+        let file = fs::File::create(log_dir.join("patches.json"))?;
+        let inverse = fs::File::create(log_dir.join("inverse.json"))?;
+        let mut recorder = Self {
+            file,
+            inverse,
+            dry_run,
+            secrets: Secrets::default(),
+            sync: false,
+            backup_dir: log_dir.join("backup"),
+            home_dir: home_dir.to_owned(),
+        };
+        // lets readers of old logs tell which format they're in
+        recorder.log(&PatchEvent::Header {
+            version: env!("CARGO_PKG_VERSION"),
+            dry_run,
+        })?;
+        Ok(recorder)
     }
 
+    // every change is logged before it's made, so this is where running past
+    // the deadline stops applying
     fn log(&mut self, event: &PatchEvent<'_>) -> Result<()> {
-        serde_json::to_writer(&mut self.file, event)?;
-        writeln!(&mut self.file)?;
+        deadline::remaining()?;
+        let event = self.secrets.redact(event)?;
+        write_line(&mut self.file, &event, self.sync)
+    }
+
+    // logs what restores the state a change is about to replace
+    fn log_inverse(&mut self, event: &InverseEvent) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        write_line(&mut self.inverse, event, self.sync)
+    }
+
+    // copies a file about to be replaced or removed into the backup dir, only
+    // the first time in a run so the backup is what it was before the run,
+    // the files in a dir are copied one by one
+    fn back_up(&mut self, path: &Path) -> Result<()> {
+        if fs::symlink_metadata(path)
+            .map_or(false, |metadata| metadata.is_dir())
+        {
+            let entries = fs::read_dir(path)
+                .with_context(|| format!("error reading {}", path.display()))?;
+            for entry in entries {
+                let entry = entry.with_context(|| {
+                    format!("error reading {}", path.display())
+                })?;
+                self.back_up(&entry.path())?;
+            }
+            return Ok(());
+        }
+        let backup = self.backup_dir.join(self.backup_path(path));
+        if backup.exists() || !path.exists() {
+            return Ok(());
+        }
+        if let Some(parent) = backup.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("error creating backup dir {}", parent.display())
+            })?;
+        }
+        fs::copy(path, &backup)
+            .with_context(|| format!("error backing up {}", path.display()))?;
         Ok(())
     }
+
+    // where a file is backed up to, relative to the backup dir
+    fn backup_path(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.home_dir) {
+            Ok(relative) => Path::new("home").join(relative),
+            Err(_) => Path::new("root").join(
+                path.components()
+                    .filter(|component| {
+                        matches!(component, path::Component::Normal(_))
+                    })
+                    .collect::<PathBuf>(),
+            ),
+        }
+    }
+
+    // logs the content of a file about to be written, content which isn't
+    // text is restored from the backup instead
+    fn log_file_inverse(&mut self, path: &Path) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        let prior = match fs::read_to_string(path) {
+            Ok(content) => Some(content),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                self.back_up(path)?;
+                return self.log_inverse(&InverseEvent::Backup {
+                    path: path.to_owned(),
+                    backup: self.backup_path(path),
+                    unknown: BTreeMap::new(),
+                });
+            },
+            Err(error) => {
+                return Err(error).with_context(|| {
+                    format!("error reading {}", path.display())
+                });
+            },
+        };
+        self.log_inverse(&InverseEvent::File {
+            path: path.to_owned(),
+            prior,
+            unknown: BTreeMap::new(),
+        })
+    }
+
+    // logs a file about to be removed, keeping it a link if it's one
+    fn log_entry_inverse(&mut self, path: &Path) -> Result<()> {
+        let is_link = fs::symlink_metadata(path)
+            .map_or(false, |metadata| metadata.file_type().is_symlink());
+        if !is_link {
+            return self.log_file_inverse(path);
+        }
+        let target = fs::read_link(path).with_context(|| {
+            format!("error reading link {}", path.display())
+        })?;
+        self.log_inverse(&InverseEvent::Link {
+            path: path.to_owned(),
+            target,
+            unknown: BTreeMap::new(),
+        })
+    }
+
+    // logs a dir about to be removed along with everything in it, the dir
+    // last so it's restored before its contents
+    fn log_removed_dir_inverse(&mut self, path: &Path) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        let mut entries = fs::read_dir(path)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<io::Result<Vec<_>>>()
+            })
+            .with_context(|| format!("error reading {}", path.display()))?;
+        entries.sort();
+        for entry in entries {
+            let is_dir = fs::symlink_metadata(&entry)
+                .map_or(false, |metadata| metadata.is_dir());
+            if is_dir {
+                self.log_removed_dir_inverse(&entry)?;
+            } else {
+                self.log_entry_inverse(&entry)?;
+            }
+        }
+        self.log_dir_inverse(path, true)
+    }
+
+    fn log_dir_inverse(&mut self, path: &Path, existed: bool) -> Result<()> {
+        self.log_inverse(&InverseEvent::Dir {
+            path: path.to_owned(),
+            existed,
+            unknown: BTreeMap::new(),
+        })
+    }
+
+    fn log_rename_inverse(
+        &mut self,
+        old_path: &Path,
+        new_path: &Path,
+    ) -> Result<()> {
+        self.log_inverse(&InverseEvent::Rename {
+            old_path: old_path.to_owned(),
+            new_path: new_path.to_owned(),
+            unknown: BTreeMap::new(),
+        })
+    }
+}
+
+// a log without the end marker was cut off by a crash, and its last change
+// may only have been partly made
+impl Drop for PatchRecorder {
+    fn drop(&mut self) {
+        // nowhere to report the error to, the log just looks cut off
+        let _ = write_line(&mut self.file, &PatchEvent::End, self.sync);
+    }
+}
+
+// writes an event as a whole line at once so a crash can't leave half of one
+fn write_line(
+    file: &mut fs::File,
+    event: &impl Serialize,
+    sync: bool,
+) -> Result<()> {
+    let mut line = serde_json::to_vec(event)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    file.flush()?;
+    if sync {
+        file.sync_data()?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "kebab-case")]
+enum InverseEvent {
+    #[cfg(feature = "glib")]
+    Channel(xfconf::InverseEvent),
+    // a file which didn't exist has no prior content
+    #[serde(rename_all = "kebab-case")]
+    File {
+        path: PathBuf,
+        prior: Option<String>,
+        // logged by newer versions, which older ones still revert without
+        #[serde(flatten)]
+        unknown: BTreeMap<String, serde_json::Value>,
+    },
+    // a file which isn't text, restored from its copy in the backup dir
+    #[serde(rename_all = "kebab-case")]
+    Backup {
+        path: PathBuf,
+        backup: PathBuf,
+        #[serde(flatten)]
+        unknown: BTreeMap<String, serde_json::Value>,
+    },
+    #[serde(rename_all = "kebab-case")]
+    Link {
+        path: PathBuf,
+        target: PathBuf,
+        #[serde(flatten)]
+        unknown: BTreeMap<String, serde_json::Value>,
+    },
+    #[serde(rename_all = "kebab-case")]
+    Rename {
+        old_path: PathBuf,
+        new_path: PathBuf,
+        #[serde(flatten)]
+        unknown: BTreeMap<String, serde_json::Value>,
+    },
+    // a dir which didn't exist is removed, once its contents are
+    #[serde(rename_all = "kebab-case")]
+    Dir {
+        path: PathBuf,
+        existed: bool,
+        #[serde(flatten)]
+        unknown: BTreeMap<String, serde_json::Value>,
+    },
+}
+
+// a line of inverse.json, which may be an event only a newer version, or one
+// with features this one lacks, can restore
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum LoggedInverseEvent {
+    Known(InverseEvent),
+    Unknown(serde_json::Value),
+}
+
+impl InverseEvent {
+    fn changes_panel(&self) -> bool {
+        match self {
+            #[cfg(feature = "glib")]
+            Self::Channel(event) => event.channel() == "xfce4-panel",
+            Self::File { path, .. }
+            | Self::Backup { path, .. }
+            | Self::Link { path, .. }
+            | Self::Rename { old_path: path, .. }
+            | Self::Dir { path, .. } => {
+                path.components().collect::<Vec<_>>().windows(2).any(
+                    |components| {
+                        components[0].as_os_str() == "xfce4"
+                            && components[1].as_os_str() == "panel"
+                    },
+                )
+            },
+        }
+    }
+
+    fn unknown_fields(&self) -> Vec<&str> {
+        match self {
+            #[cfg(feature = "glib")]
+            Self::Channel(event) => event.unknown_fields(),
+            Self::File { unknown, .. }
+            | Self::Backup { unknown, .. }
+            | Self::Link { unknown, .. }
+            | Self::Rename { unknown, .. }
+            | Self::Dir { unknown, .. } => {
+                unknown.keys().map(|key| &**key).collect()
+            },
+        }
+    }
+}
+
+impl fmt::Display for InverseEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "glib")]
+            Self::Channel(event) => event.fmt(f),
+            Self::File { path, .. }
+            | Self::Backup { path, .. }
+            | Self::Rename { old_path: path, .. } => {
+                write!(f, "file {}", path.display())
+            },
+            Self::Link { path, .. } => write!(f, "link {}", path.display()),
+            Self::Dir { path, .. } => write!(f, "dir {}", path.display()),
+        }
+    }
 }
 
 #[derive(Serialize)]
 #[serde(tag = "type", content = "value", rename_all = "kebab-case")]
 enum PatchEvent<'a> {
+    #[serde(rename_all = "kebab-case")]
+    Header {
+        version: &'static str,
+        dry_run: bool,
+    },
+    // the run is done with the log, whether it applied everything or not
+    End,
+    #[cfg(feature = "glib")]
     Channel(xfconf::PatchEvent),
+    #[cfg(feature = "glib")]
     GSettings(gsettings::PatchEvent<'a>),
     Panel(panel::PatchEvent<'a>),
+    Xresources(xresources::PatchEvent<'a>),
+    Wallpapers(wallpapers::PatchEvent<'a>),
+    Plugin(plugins::PatchEvent<'a>),
+    Autostart(autostart::PatchEvent<'a>),
     #[serde(rename_all = "kebab-case")]
     Cfg {
         content: &'a cfg::Cfg,
@@ -233,3 +1926,124 @@ fn open_file(path: impl AsRef<Path>) -> io::Result<Option<fs::File>> {
         Err(error) => Err(error),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn newer_inverse_events() {
+        let event = |line: &str| {
+            serde_json::from_str::<LoggedInverseEvent>(line).unwrap()
+        };
+        match event(
+            r#"{"type":"file","value":{"path":"/a","prior":null,"mode":420}}"#,
+        ) {
+            LoggedInverseEvent::Known(file) => {
+                assert_eq!(file.unknown_fields(), ["mode"]);
+            },
+            LoggedInverseEvent::Unknown(_) => panic!("file event is unknown"),
+        }
+        assert!(matches!(
+            event(r#"{"type":"symlink","value":{"path":"/a"}}"#),
+            LoggedInverseEvent::Unknown(_)
+        ));
+        assert!(matches!(
+            event(r#"{"type":"channel","value":{"type":"restore-lock"}}"#),
+            LoggedInverseEvent::Unknown(_)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn revert_removed_dir() {
+        let temp_dir = std::env::temp_dir()
+            .join(format!("xfce-config-revert-{}", std::process::id()));
+        let log_dir = temp_dir.join("log");
+        let dir = temp_dir.join("launcher-1");
+        fs::create_dir_all(&log_dir).unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("1.desktop"), b"\xff\xfe").unwrap();
+        std::os::unix::fs::symlink("1.desktop", dir.join("2.desktop")).unwrap();
+        fs::write(dir.join("3.desktop"), "[Desktop Entry]\n").unwrap();
+
+        let mut recorder =
+            PatchRecorder::new(&log_dir, &temp_dir, false).unwrap();
+        recorder
+            .log_rename_inverse(&dir.join("3.desktop"), &dir.join("4.desktop"))
+            .unwrap();
+        fs::rename(dir.join("3.desktop"), dir.join("4.desktop")).unwrap();
+        recorder.log_removed_dir_inverse(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        drop(recorder);
+
+        revert(&log_dir, false).unwrap();
+        assert_eq!(fs::read(dir.join("1.desktop")).unwrap(), b"\xff\xfe");
+        assert_eq!(
+            fs::read_link(dir.join("2.desktop")).unwrap(),
+            Path::new("1.desktop")
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("3.desktop")).unwrap(),
+            "[Desktop Entry]\n"
+        );
+        assert!(!dir.join("4.desktop").exists());
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn revert_refuses_dry_run() {
+        let log_dir = std::env::temp_dir()
+            .join(format!("xfce-config-dry-run-{}", std::process::id()));
+        fs::create_dir_all(&log_dir).unwrap();
+        let mut recorder =
+            PatchRecorder::new(&log_dir, &log_dir, true).unwrap();
+        recorder.log_file_inverse(&log_dir.join("gtk.css")).unwrap();
+        drop(recorder);
+        assert!(fs::read(log_dir.join("inverse.json")).unwrap().is_empty());
+        assert!(revert(&log_dir, false).is_err());
+        fs::remove_dir_all(&log_dir).unwrap();
+    }
+
+    #[test]
+    fn portable_value_keeps_profiles() {
+        let profiles = serde_json::json!([{
+            "name": "docked",
+            "when": { "min-monitors": 2 },
+            "config": { "panel": { "plugin-configs": [] } },
+        }]);
+        let config = XfceConfig::from_json_value(
+            serde_json::json!({
+                "xresources": { "resources": { "Xft.dpi": "96" } },
+                "panel-profiles": profiles,
+            }),
+            Secrets::default(),
+            Path::new("/"),
+            None,
+            None,
+        )
+        .unwrap();
+        let value = config.to_portable_value().unwrap();
+        assert_eq!(value["panel-profiles"], profiles);
+        assert_eq!(value["xresources"]["resources"]["Xft.dpi"], "96");
+    }
+
+    #[test]
+    fn schema_covers_sections() {
+        let config = serde_json::from_value::<XfceConfig>(serde_json::json!({
+            "default-applications": { "terminal-emulator": "xfce4-terminal" },
+            "xresources": { "resources": { "Xft.dpi": "96" } },
+            "min-version": "0.1.0",
+        }))
+        .unwrap();
+        let schema = XfceConfig::schema();
+        for key in serde_json::to_value(config)
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .keys()
+        {
+            assert!(schema["properties"].get(key).is_some(), "{}", key);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::{bail, Context, Result};
+use std::{fmt, fs, io, path::Path, process, str::FromStr};
+
+// how run logs are compressed, by the gzip or zstd commands so no
+// compression library has to be linked
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogCompression {
+    Gzip,
+    Zstd,
+}
+
+impl LogCompression {
+    const ALL: [Self; 2] = [Self::Gzip, Self::Zstd];
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Gzip => "gz",
+            Self::Zstd => "zst",
+        }
+    }
+
+    fn command(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+        }
+    }
+
+    // replaces the file with its compressed version
+    pub fn compress(self, path: &Path) -> Result<()> {
+        let mut command = process::Command::new(self.command());
+        match self {
+            Self::Gzip => command.arg("-f"),
+            Self::Zstd => command.args(["-q", "-f", "--rm"]),
+        };
+        let status = command
+            .arg(path)
+            .status()
+            .with_context(|| format!("error running {}", self.command()))?;
+        if !status.success() {
+            bail!(
+                "compressing {} failed, {} exited with {}",
+                path.display(),
+                self.command(),
+                status
+            );
+        }
+        Ok(())
+    }
+
+    fn decompress(self, path: &Path) -> Result<Vec<u8>> {
+        let output = process::Command::new(self.command())
+            .args(["-d", "-c"])
+            .arg(path)
+            .stderr(process::Stdio::inherit())
+            .output()
+            .with_context(|| format!("error running {}", self.command()))?;
+        if !output.status.success() {
+            bail!(
+                "decompressing {} failed, {} exited with {}",
+                path.display(),
+                self.command(),
+                output.status
+            );
+        }
+        Ok(output.stdout)
+    }
+}
+
+impl FromStr for LogCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "gzip" => Ok(Self::Gzip),
+            "zstd" => Ok(Self::Zstd),
+            _ => bail!("unknown compression {}, expected gzip or zstd", s),
+        }
+    }
+}
+
+impl fmt::Display for LogCompression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.command().fmt(f)
+    }
+}
+
+// contents of a file in a log dir, whether it was compressed or not
+pub fn read_log_file(dir: &Path, name: &str) -> Result<Vec<u8>> {
+    let path = dir.join(name);
+    match fs::read(&path) {
+        Ok(content) => return Ok(content),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("error reading {}", path.display()));
+        },
+    }
+    for compression in LogCompression::ALL {
+        let path = dir.join(format!("{}.{}", name, compression.extension()));
+        if path.exists() {
+            return compression.decompress(&path);
+        }
+    }
+    bail!("no {} in {}", name, dir.display())
+}
@@ -1,100 +1,1249 @@
 #![warn(rust_2018_idioms, clippy::all)]
 #![deny(clippy::correctness)]
 
-use anyhow::{Context, Result};
-use std::{fs, path::PathBuf};
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    fs,
+    hash::{Hash, Hasher},
+    io::{self, Write},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant},
+};
 use structopt::StructOpt;
-use xfce_config::{Applier, XfceConfig, XfceConfigPatch};
+use xfce_config::{
+    print_error,
+    styled,
+    text,
+    warn,
+    Applier,
+    Change,
+    ColorChoice,
+    Deadline,
+    Dirs,
+    InputFormat,
+    LogCompression,
+    ManagedState,
+    Message,
+    RunDifference,
+    Secrets,
+    SectionTimeouts,
+    Stream,
+    Style,
+    SymlinkPolicies,
+    XfceConfig,
+    XfceConfigPatch,
+};
+
+// system-wide config which user configs are layered over
+const BASELINE_CONFIG: &str = "/etc/xfce-config/config.json";
+// where releases are tagged
+const REPO_URL: &str = "https://github.com/dbeckwith/xfce-config";
+// system-wide channel files holding xfconf defaults
+const XFCONF_DEFAULTS_DIR: &str = "/etc/xdg/xfce4/xfconf/xfce-perchannel-xml";
 
 #[derive(StructOpt)]
 struct Args {
     #[structopt(long)]
     apply: bool,
+    /// When to color output, auto, always or never. Auto colors output to
+    /// terminals unless NO_COLOR is set.
+    #[structopt(long, global = true)]
+    color: Option<ColorChoice>,
+    /// D-Bus address of the session bus of the XFCE session to configure.
+    #[structopt(long, global = true)]
+    bus_address: Option<String>,
+    /// Apply on this machine over SSH instead, e.g. user@host, which needs
+    /// xfce-config installed there.
+    #[structopt(long, conflicts_with = "simulate")]
+    remote: Option<String>,
+    /// Compress the logs of this run with gzip or zstd once it's done.
+    #[structopt(long)]
+    compress_logs: Option<LogCompression>,
+    /// Fsync the patch logs after every change, so they're intact even if the
+    /// system crashes while applying.
+    #[structopt(long, requires = "apply")]
+    sync_log: bool,
+    /// After applying, read everything back and report what doesn't match.
+    #[structopt(long, requires = "apply")]
+    verify: bool,
+    /// Print whether a newer release than this one is tagged, then exit.
+    #[structopt(long)]
+    check_update: bool,
+    /// Read the config from this file instead of stdin.
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+    /// Format of the config, json, toml or yaml, by default JSON on stdin and
+    /// detected by extension for files.
+    #[structopt(long)]
+    format: Option<InputFormat>,
+    /// Shell command printing the decrypted contents of the file given as
+    /// $1, used for include-encrypted. Defaults to gpg for .gpg and .asc
+    /// files.
+    #[structopt(long, global = true)]
+    decrypt_command: Option<String>,
+    /// Number of monitors to select panel profiles by, instead of asking
+    /// xrandr. With --remote it's the number of the remote's monitors.
+    #[structopt(long, global = true)]
+    monitors: Option<usize>,
+    /// Don't layer the config over the system-wide baseline config.
+    #[structopt(long)]
+    no_baseline: bool,
+    /// Only warn about panel plugins which aren't installed.
+    #[structopt(long)]
+    allow_missing_plugins: bool,
+    /// Leave WhiskerMenu favorites whose apps aren't installed out of the
+    /// menu with a warning, instead of showing them as broken entries.
+    #[structopt(long)]
+    skip_missing_favorites: bool,
+    /// Only apply the part of the config at this path, a section optionally
+    /// followed by the id of one of its parts, e.g. xfconf.xfce4-panel or
+    /// general.configs.{config}/foo.json.
+    #[structopt(long)]
+    path: Option<String>,
+    /// Print the state applying the config would leave as JSON instead of
+    /// applying it.
+    #[structopt(long, conflicts_with = "apply")]
+    simulate: bool,
+    /// Undo the changes of the run logged in this dir, or with this
+    /// timestamp, instead of applying a config. Only prints what would be
+    /// restored without --apply.
+    #[structopt(
+        long,
+        parse(from_os_str),
+        conflicts_with_all = &["simulate", "remote", "verify", "bootstrap"]
+    )]
+    revert: Option<PathBuf>,
+    /// Provision a fresh environment with no xfconf channel or panel files
+    /// yet: create the config dirs, start xfconfd and write every declared
+    /// xfconf and panel setting, even ones matching the system defaults.
+    #[structopt(long, conflicts_with_all = &["simulate", "remote"])]
+    bootstrap: bool,
+    /// Remove the xfconf props and autostart entries which earlier runs set
+    /// but the config no longer declares. Files other sections wrote, like
+    /// general configs, panel files, Xresources and wallpapers, aren't
+    /// tracked and stay.
+    #[structopt(long)]
+    prune: bool,
+    /// Ask before each change whether to apply it, like `git add -p`.
+    #[structopt(
+        long,
+        requires_all = &["apply", "config"],
+        conflicts_with_all = &["simulate", "diff-only", "remote"]
+    )]
+    interactive: bool,
+    /// Only apply the changes within this path, like --path but filtering the
+    /// changes rather than the config. Xfconf props are named like
+    /// xfconf.xfce4-panel/plugins/plugin-1.
+    #[structopt(
+        long,
+        number_of_values = 1,
+        conflicts_with_all = &["simulate", "diff-only"]
+    )]
+    only: Vec<String>,
+    /// Don't apply the changes within this path, like --only.
+    #[structopt(
+        long,
+        number_of_values = 1,
+        conflicts_with_all = &["simulate", "diff-only"]
+    )]
+    skip: Vec<String>,
+    /// Print what applying the config would change, colored on a terminal,
+    /// instead of applying it.
+    #[structopt(
+        long,
+        conflicts_with_all = &["apply", "simulate", "remote", "revert"]
+    )]
+    diff_only: bool,
+    /// Simulate against the state in this file, like a run's old.json,
+    /// instead of reading it from the environment.
+    #[structopt(long, parse(from_os_str), requires = "simulate")]
+    old: Option<PathBuf>,
+    /// Move GTK settings xfsettingsd overrides to the xsettings channel.
+    #[structopt(long)]
+    prefer_xfconf: bool,
+    /// Give up after this many seconds, failing D-Bus calls and killing
+    /// commands which would still be running. What was applied until then is
+    /// in the run's logs. With watch --enforce, bounds each revert.
+    #[structopt(long)]
+    timeout: Option<u64>,
+    /// Give up applying a section after this many seconds, as
+    /// SECTION=SECONDS.
+    #[structopt(long = "section-timeout", number_of_values = 1)]
+    section_timeouts: Vec<String>,
+    /// Shell command run with missing font families as arguments, not on dry
+    /// runs.
+    #[structopt(long)]
+    font_install_hook: Option<String>,
+    /// How writes treat symlinked files, as SUBSYSTEM=follow|replace|error
+    /// where SUBSYSTEM is wallpapers, panel, gtk, default-applications,
+    /// general, xresources, autostart or all.
+    #[structopt(long = "symlinks", number_of_values = 1)]
+    symlink_policies: Vec<String>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 
-fn main() -> Result<()> {
+#[derive(StructOpt)]
+enum Command {
+    /// Print xfconf config equivalent to the GNOME settings XFCE mirrors.
+    MigrateGsettings {
+        /// Only read these gsettings schemas.
+        schemas: Vec<String>,
+    },
+    /// Print changes to an xfconf channel's properties as they happen.
+    Monitor { channel: String },
+    /// Watch xfconf and the files the config writes, printing where they
+    /// drift from the config as it happens, until killed.
+    Watch {
+        /// Revert drift by applying the config again, logged like any run.
+        #[structopt(long)]
+        enforce: bool,
+    },
+    /// Record xfconf changes until enter is pressed and print them as config.
+    Record {
+        /// Also record changed files in the config dir.
+        #[structopt(long)]
+        files: bool,
+        /// Leave out props changed back to their system default.
+        #[structopt(long)]
+        minimal: bool,
+    },
+    /// Print where settings matching a description live in the config.
+    Explain {
+        /// Words from the setting's name, e.g. "clock format".
+        query: String,
+    },
+    /// Print how the changes planned by two runs differ.
+    CompareRuns {
+        /// Log dir of the first run, or its timestamp.
+        #[structopt(parse(from_os_str))]
+        first: PathBuf,
+        /// Log dir of the second run, or its timestamp.
+        #[structopt(parse(from_os_str))]
+        second: PathBuf,
+    },
+    /// Print the current environment as config, to start a config from.
+    Dump {
+        /// Write the config to this file instead of stdout.
+        #[structopt(long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Print a JSON Schema of the config, for editors to complete and check
+    /// configs with.
+    Schema,
+    /// Remove state this tool keeps in its data dir, reporting its size.
+    Clean {
+        /// Remove the logs of past runs, including quarantined ones.
+        #[structopt(long)]
+        logs: bool,
+    },
+    /// Apply the config sent on stdin by --remote.
+    ServePlan,
+    /// Write system-wide xfconf files locking the config's locked props,
+    /// merged into the channel files already there, whose originals are kept
+    /// as <channel>.xml.orig.
+    Kiosk {
+        #[structopt(
+            long,
+            parse(from_os_str),
+            default_value = "/etc/xdg/xfce4/xfconf/xfce-perchannel-xml"
+        )]
+        output_dir: PathBuf,
+    },
+}
+
+fn main() {
+    let result = run();
+    xfce_config::remove_wallpaper_downloads();
+    if let Err(error) = result {
+        print_error(&error);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
     let args = Args::from_args();
+    // gio reads the session bus address from the environment
+    if let Some(address) = &args.bus_address {
+        std::env::set_var("DBUS_SESSION_BUS_ADDRESS", address);
+    }
+    if let Some(color) = args.color {
+        xfce_config::set_color(color);
+    }
 
-    let dry_run = !args.apply;
+    if args.check_update {
+        return check_update();
+    }
 
-    let log_dir = rotating_log_dir(
-        dirs2::data_local_dir()
-            .context("could not get data local dir")?
-            .join("xfce-config"),
-    )?;
+    if let Some(command) = &args.command {
+        return match command {
+            Command::MigrateGsettings { schemas } => migrate_gsettings(schemas),
+            Command::Monitor { channel } => {
+                xfce_config::check_session_bus()?;
+                xfce_config::monitor_xfconf(channel.clone())
+            },
+            Command::Watch { enforce } => watch(&args, *enforce),
+            Command::Record { files, minimal } => {
+                xfce_config::check_session_bus()?;
+                record(*files, *minimal)
+            },
+            Command::Explain { query } => explain(query),
+            Command::CompareRuns { first, second } => {
+                compare_runs(first, second)
+            },
+            Command::Kiosk { output_dir } => {
+                read_config(&args)?.write_kiosk(output_dir)
+            },
+            Command::Dump { output } => dump(&args, output.as_deref()),
+            Command::Schema => schema(),
+            Command::Clean { logs } => clean(*logs),
+            Command::ServePlan => apply(&args),
+        };
+    }
 
-    let config_dir = dirs2::config_dir().context("could not get config dir")?;
-    let xfce4_config_dir = config_dir.join("xfce4");
-    let gtk_config_dir = config_dir.join("gtk-3.0");
+    if let Some(run) = &args.revert {
+        xfce_config::check_session_bus()?;
+        return xfce_config::revert(&run_log_dir(run)?, !args.apply)
+            .context("error reverting run");
+    }
 
-    let new_config = XfceConfig::from_json_reader(std::io::stdin())
-        .context("error reading input JSON")?;
+    apply(&args)
+}
+
+fn apply(args: &Args) -> Result<()> {
+    let _deadline = args.timeout.map(Duration::from_secs).map(Deadline::set);
+    let data_dir = data_dir()?;
+    let dirs = Dirs::new(&data_dir)?;
+
+    let mut symlinks = SymlinkPolicies::default();
+    for spec in &args.symlink_policies {
+        symlinks.set(spec).context("invalid --symlinks")?;
+    }
+    let mut section_timeouts = SectionTimeouts::default();
+    for spec in &args.section_timeouts {
+        section_timeouts
+            .set(spec)
+            .context("invalid --section-timeout")?;
+    }
+
+    let mut new_config = read_config(args)?;
+    if let Some(remote) = &args.remote {
+        return apply_remote(remote, &new_config, args);
+    }
+    let managed_state = data_dir.join("managed.json");
+    if args.prune {
+        ManagedState::read(&managed_state)
+            .context("error reading managed state")?
+            .prune(&mut new_config);
+    }
+    new_config.resolve_wallpapers(&dirs.wallpapers_dir);
+    new_config
+        .resolve_apps(&dirs, args.skip_missing_favorites)
+        .context("error resolving panel launcher apps")?;
+    new_config.check_xsettings(args.prefer_xfconf);
+    if let Some(path) = &args.path {
+        new_config.scope(path).context("invalid --path")?;
+    }
+    if args.simulate {
+        return simulate(new_config, args.old.as_deref(), &dirs);
+    }
+    if args.diff_only {
+        return diff_only(new_config, &dirs);
+    }
+
+    xfce_config::check_session_bus()?;
+    if args.bootstrap {
+        xfce_config::bootstrap(&dirs, !args.apply)
+            .context("error bootstrapping environment")?;
+    }
+    let state_dir = data_dir.clone();
+    let log_dir = rotating_log_dir(data_dir)?;
+    let started = Instant::now();
+    let result = apply_logged(
+        args,
+        new_config,
+        &dirs,
+        symlinks,
+        section_timeouts,
+        &log_dir,
+        &state_dir,
+    );
+    write_meta(&log_dir, args, started.elapsed(), &result)
+        .context("error writing meta.json")?;
+    // the uncompressed logs are still complete, so this doesn't fail the run
+    if let Some(compression) = args.compress_logs {
+        if let Err(err) = compress_logs(&log_dir, compression) {
+            warn(format_args!("error compressing logs: {:#}", err));
+        }
+    }
+    fs::File::create(log_dir.join(RUN_FINISHED))
+        .context("error marking log dir finished")?;
+    result
+}
+
+// applies the config, logging the states and changes to the log dir
+fn apply_logged(
+    args: &Args,
+    mut new_config: XfceConfig,
+    dirs: &Dirs,
+    symlinks: SymlinkPolicies,
+    section_timeouts: SectionTimeouts,
+    log_dir: &Path,
+    // where the state across runs is kept
+    state_dir: &Path,
+) -> Result<()> {
+    let dry_run = !args.apply;
+    // values of encrypted includes, redacted in every log
+    let secrets = new_config.secrets().clone();
+    new_config
+        .check_plugins(args.allow_missing_plugins)
+        .context("error checking panel plugins")?;
+    new_config
+        .check_default_applications(dirs)
+        .context("error checking default applications")?;
+    new_config
+        .check_fonts(args.font_install_hook.as_deref(), dry_run)
+        .context("error checking fonts")?;
+    let mut applier = Applier::new(dry_run, log_dir, dirs, symlinks)
+        .context("error creating applier")?
+        .with_section_timeouts(section_timeouts)
+        .with_synced_log(args.sync_log)
+        .with_secrets(secrets.clone());
+    // before reading the environment so it's diffed as migrated
+    new_config
+        .run_migrations(&mut applier, &state_dir.join("migrations.json"))
+        .context("error running migrations")?;
+
+    let old_config = if args.bootstrap {
+        XfceConfig::from_bootstrap_env(&new_config, dirs)
+    } else {
+        XfceConfig::from_env(&new_config, dirs)
+    }
+    .context("error reading config from environment")?;
+    new_config.reuse_plugin_ids(&old_config);
     serde_json::to_writer(
         fs::File::create(log_dir.join("new.json"))
             .context("error creating new.json")?,
-        &new_config,
+        &secrets.redact(&new_config)?,
     )
     .context("error writing new.json")?;
-
-    let old_config = XfceConfig::from_env(
-        &new_config,
-        &config_dir,
-        &xfce4_config_dir,
-        &gtk_config_dir,
-    )
-    .context("error reading config from environment")?;
     serde_json::to_writer(
         fs::File::create(log_dir.join("old.json"))
             .context("error creating old.json")?,
-        &old_config,
+        &secrets.redact(&old_config)?,
     )
     .context("error writing old.json")?;
 
-    let diff = XfceConfigPatch::diff(old_config, new_config)
+    // kept to compare what was applied against
+    let desired = (args.verify && !dry_run).then(|| new_config.clone());
+    let managed_state = state_dir.join("managed.json");
+    let mut managed = ManagedState::read(&managed_state)
+        .context("error reading managed state")?;
+    let declared = new_config.clone();
+    let mut diff = XfceConfigPatch::diff(old_config, new_config)
         .context("error diffing configs")?;
+    let proposed = diff.changes();
+    diff.filter(&args.only, &args.skip)
+        .context("invalid --only or --skip")?;
+    if args.interactive {
+        choose_changes(&mut diff)?;
+    }
+    let left_out = diff.left_out(proposed);
+    managed.update(&declared, &left_out);
     serde_json::to_writer(
         fs::File::create(log_dir.join("diff.json"))
             .context("error creating diff.json")?,
-        &diff,
+        &secrets.redact(&diff)?,
     )
     .context("error writing diff.json")?;
 
-    diff.apply(
-        &mut Applier::new(
-            dry_run,
-            &log_dir,
-            xfce4_config_dir.into(),
-            gtk_config_dir.into(),
-            config_dir.into(),
-        )
-        .context("error creating applier")?,
+    let stats = diff.change_count();
+    let skipped = diff.skipped().len();
+    diff.apply(&mut applier).context("error applying config")?;
+    // ends the patch log
+    drop(applier);
+    if !dry_run {
+        managed
+            .write(&managed_state)
+            .context("error writing managed state")?;
+    }
+    if stats.is_empty() {
+        summary(text(Message::NoChanges, &[]));
+    } else if dry_run {
+        summary(text(Message::WouldApply, &[&stats]));
+    } else {
+        summary(text(Message::Applied, &[&stats]));
+    }
+    if skipped > 0 {
+        eprintln!("{}", text(Message::SkippedPaths, &[&skipped]));
+    }
+
+    if let Some(desired) = desired {
+        verify(&desired, left_out, dirs, log_dir, &secrets)?;
+    }
+
+    Ok(())
+}
+
+// prints drift from the config as it happens, reverting it if enforce
+fn watch(args: &Args, enforce: bool) -> Result<()> {
+    xfce_config::check_session_bus()?;
+    let data_dir = data_dir()?;
+    let dirs = Dirs::new(&data_dir)?;
+    let mut symlinks = SymlinkPolicies::default();
+    for spec in &args.symlink_policies {
+        symlinks.set(spec).context("invalid --symlinks")?;
+    }
+
+    let mut config = read_config(args)?;
+    config.resolve_wallpapers(&dirs.wallpapers_dir);
+    config
+        .resolve_apps(&dirs, args.skip_missing_favorites)
+        .context("error resolving panel launcher apps")?;
+    config.check_xsettings(args.prefer_xfconf);
+    if let Some(path) = &args.path {
+        config.scope(path).context("invalid --path")?;
+    }
+    let old_config = XfceConfig::from_env(&config, &dirs)
+        .context("error reading config from environment")?;
+    config.reuse_plugin_ids(&old_config);
+    let secrets = config.secrets().clone();
+    let timeout = args.timeout.map(Duration::from_secs);
+    xfce_config::watch_drift(config, dirs.clone(), move |drift| {
+        for change in drift.changes() {
+            println!("{}", styled(Stream::Stdout, Style::Changed, change));
+        }
+        if enforce {
+            // each revert gets the whole timeout of a run
+            let _deadline = timeout.map(Deadline::set);
+            if let Err(error) = revert_drift(
+                drift,
+                &dirs,
+                symlinks,
+                &secrets,
+                data_dir.clone(),
+            ) {
+                print_error(&error);
+            }
+        }
+    })
+}
+
+// applies the drift as a run of its own, so it can be reverted in turn
+fn revert_drift(
+    drift: XfceConfigPatch,
+    dirs: &Dirs,
+    symlinks: SymlinkPolicies,
+    secrets: &Secrets,
+    data_dir: PathBuf,
+) -> Result<()> {
+    let log_dir = rotating_log_dir(data_dir)?;
+    serde_json::to_writer(
+        fs::File::create(log_dir.join("diff.json"))
+            .context("error creating diff.json")?,
+        &secrets.redact(&drift)?,
+    )
+    .context("error writing diff.json")?;
+    let stats = drift.change_count();
+    let mut applier = Applier::new(false, &log_dir, dirs, symlinks)
+        .context("error creating applier")?
+        .with_secrets(secrets.clone());
+    drift.apply(&mut applier).context("error reverting drift")?;
+    // ends the patch log
+    drop(applier);
+    fs::File::create(log_dir.join(RUN_FINISHED))
+        .context("error marking log dir finished")?;
+    summary(text(Message::Applied, &[&stats]));
+    Ok(())
+}
+
+// asks which changes to apply, declining the rest
+fn choose_changes(diff: &mut XfceConfigPatch) -> Result<()> {
+    let changes = diff.changes();
+    let count = changes.len();
+    let mut declined = Vec::new();
+    // what to do with the remaining changes once decided for all of them
+    let mut remaining = None;
+    for (index, change) in changes.into_iter().enumerate() {
+        let accept = loop {
+            if let Some(accept) = remaining {
+                break accept;
+            }
+            eprint!(
+                "({}/{}) {} [y,n,a,q,?] ",
+                index + 1,
+                count,
+                styled(Stream::Stderr, Style::Changed, &change)
+            );
+            io::stderr().flush().context("error writing prompt")?;
+            let mut answer = String::new();
+            if io::stdin()
+                .read_line(&mut answer)
+                .context("error reading answer")?
+                == 0
+            {
+                remaining = Some(false);
+                continue;
+            }
+            match answer.trim() {
+                "y" => break true,
+                "n" => break false,
+                "a" => remaining = Some(true),
+                "q" => remaining = Some(false),
+                _ => eprintln!(
+                    "y - apply this change\nn - don't apply this change\na - \
+                     apply this and all remaining changes\nq - don't apply \
+                     this or any remaining changes"
+                ),
+            }
+        };
+        if !accept {
+            declined.push(change);
+        }
+    }
+    diff.decline(declined)
+        .context("error leaving out declined changes")
+}
+
+// changes left out by --only, --skip or declined aren't mismatches
+fn verify(
+    desired: &XfceConfig,
+    left_out: Vec<Change>,
+    dirs: &Dirs,
+    log_dir: &Path,
+    secrets: &Secrets,
+) -> Result<()> {
+    let mut mismatches = XfceConfigPatch::verify(desired, dirs)
+        .context("error verifying applied config")?;
+    mismatches
+        .decline(left_out)
+        .context("error leaving out changes which weren't applied")?;
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+    serde_json::to_writer(
+        fs::File::create(log_dir.join("verify.json"))
+            .context("error creating verify.json")?,
+        &secrets.redact(&mismatches)?,
     )
-    .context("error applying config")?;
+    .context("error writing verify.json")?;
+    warn(format_args!(
+        "environment doesn't match the config after applying, {} still \
+         differ, see {}",
+        mismatches.change_count(),
+        log_dir.join("verify.json").display()
+    ));
+    Ok(())
+}
+
+// the baseline config the input is layered over, if any
+fn baseline(args: &Args) -> Option<&'static Path> {
+    let baseline = Path::new(BASELINE_CONFIG);
+    // configs sent by --remote were already layered over the sender's
+    let serving = matches!(args.command, Some(Command::ServePlan));
+    (!args.no_baseline && !serving && baseline.is_file()).then(|| baseline)
+}
+
+fn read_config(args: &Args) -> Result<XfceConfig> {
+    let baseline = baseline(args);
+    let decrypt_command = args.decrypt_command.as_deref();
+    let mut config = match &args.config {
+        Some(path) => XfceConfig::from_file(
+            path,
+            args.format,
+            baseline,
+            decrypt_command,
+        ),
+        None => XfceConfig::from_reader(
+            std::io::stdin(),
+            args.format.unwrap_or(InputFormat::Json),
+            baseline,
+            decrypt_command,
+        ),
+    }
+    .context("error reading input config")?;
+    // the remote resolves them for its own monitors
+    if args.remote.is_none() {
+        config.resolve_monitors(args.monitors)?;
+    }
+    Ok(config)
+}
 
+// the big files of a run, meta.json and the markers stay readable as is
+fn compress_logs(log_dir: &Path, compression: LogCompression) -> Result<()> {
+    for name in [
+        "new.json",
+        "old.json",
+        "diff.json",
+        "patches.json",
+        "verify.json",
+    ] {
+        let path = log_dir.join(name);
+        if path.exists() {
+            compression.compress(&path)?;
+        }
+    }
     Ok(())
 }
 
-fn rotating_log_dir(dir: PathBuf) -> Result<PathBuf> {
-    let log_dir =
-        dir.join(chrono::Local::now().format("%Y-%m-%d-%H-%M-%S").to_string());
-    fs::create_dir_all(&log_dir).context("error creating log dir")?;
-    let mut log_dirs = fs::read_dir(dir)
+// what fleets aggregating logs need to attribute a run
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct RunMeta {
+    machine_id: Option<String>,
+    hostname: Option<String>,
+    user: Option<String>,
+    version: &'static str,
+    args: Vec<String>,
+    // hashes of the input files, stdin input isn't hashed
+    config_hashes: BTreeMap<PathBuf, String>,
+    duration_secs: f64,
+    // "ok" or the error
+    result: String,
+}
+
+fn write_meta(
+    log_dir: &Path,
+    args: &Args,
+    duration: Duration,
+    result: &Result<()>,
+) -> Result<()> {
+    let read_trimmed = |path: &str| {
+        fs::read_to_string(path)
+            .ok()
+            .map(|s| s.trim().to_owned())
+            .filter(|s| !s.is_empty())
+    };
+    let mut config_hashes = BTreeMap::new();
+    for path in args.config.as_deref().into_iter().chain(baseline(args)) {
+        let content = fs::read(path)
+            .with_context(|| format!("error reading {}", path.display()))?;
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        config_hashes
+            .insert(path.to_owned(), format!("{:016x}", hasher.finish()));
+    }
+    let meta = RunMeta {
+        machine_id: read_trimmed("/etc/machine-id"),
+        hostname: read_trimmed("/proc/sys/kernel/hostname"),
+        user: std::env::var("USER").ok(),
+        version: env!("CARGO_PKG_VERSION"),
+        args: std::env::args().collect(),
+        config_hashes,
+        duration_secs: duration.as_secs_f64(),
+        result: match result {
+            Ok(()) => "ok".to_owned(),
+            Err(err) => format!("{:#}", err),
+        },
+    };
+    serde_json::to_writer_pretty(
+        fs::File::create(log_dir.join("meta.json"))
+            .context("error creating meta.json")?,
+        &meta,
+    )
+    .context("error writing meta.json")
+}
+
+// sends the config to serve-plan on the remote over SSH, with the flags which
+// affect applying it
+fn apply_remote(remote: &str, config: &XfceConfig, args: &Args) -> Result<()> {
+    let local_files = config.local_files();
+    if !local_files.is_empty() {
+        bail!(
+            "config reads files which aren't sent to {}: {}",
+            remote,
+            local_files
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    // the config was already layered over this machine's baseline
+    let mut remote_args =
+        vec!["xfce-config".to_owned(), "--no-baseline".to_owned()];
+    let flags = [
+        ("--apply", args.apply),
+        ("--verify", args.verify),
+        ("--sync-log", args.sync_log),
+        ("--prune", args.prune),
+        ("--allow-missing-plugins", args.allow_missing_plugins),
+        ("--skip-missing-favorites", args.skip_missing_favorites),
+        ("--prefer-xfconf", args.prefer_xfconf),
+    ];
+    for (flag, set) in flags {
+        if set {
+            remote_args.push(flag.to_owned());
+        }
+    }
+    let options = [
+        ("--path", &args.path),
+        ("--font-install-hook", &args.font_install_hook),
+        ("--bus-address", &args.bus_address),
+    ];
+    for (option, value) in options {
+        if let Some(value) = value {
+            remote_args.push(option.to_owned());
+            remote_args.push(shell_quote(value));
+        }
+    }
+    for spec in &args.symlink_policies {
+        remote_args.push("--symlinks".to_owned());
+        remote_args.push(shell_quote(spec));
+    }
+    if let Some(monitors) = args.monitors {
+        remote_args.push("--monitors".to_owned());
+        remote_args.push(monitors.to_string());
+    }
+    if let Some(timeout) = args.timeout {
+        remote_args.push("--timeout".to_owned());
+        remote_args.push(timeout.to_string());
+    }
+    if let Some(compression) = args.compress_logs {
+        remote_args.push("--compress-logs".to_owned());
+        remote_args.push(compression.to_string());
+    }
+    for spec in &args.section_timeouts {
+        remote_args.push("--section-timeout".to_owned());
+        remote_args.push(shell_quote(spec));
+    }
+    for path in &args.only {
+        remote_args.push("--only".to_owned());
+        remote_args.push(shell_quote(path));
+    }
+    for path in &args.skip {
+        remote_args.push("--skip".to_owned());
+        remote_args.push(shell_quote(path));
+    }
+    // output comes back through ssh, colored if it would be here
+    let color = if xfce_config::colored(Stream::Stderr) {
+        ColorChoice::Always
+    } else {
+        ColorChoice::Never
+    };
+    remote_args.push(format!("--color={}", color));
+    remote_args.push("serve-plan".to_owned());
+
+    // ssh runs the command through the remote user's shell
+    let mut ssh = process::Command::new("ssh")
+        .arg("--")
+        .arg(remote)
+        .arg(remote_args.join(" "))
+        .stdin(process::Stdio::piped())
+        .spawn()
+        .context("error running ssh")?;
+    serde_json::to_writer(
+        ssh.stdin.take().unwrap(),
+        &config.to_portable_value()?,
+    )
+    .context("error sending config")?;
+    let status = ssh.wait().context("error waiting for ssh")?;
+    if !status.success() {
+        bail!("applying on {} failed, ssh exited with {}", remote, status);
+    }
+    Ok(())
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+fn simulate(
+    mut new_config: XfceConfig,
+    old: Option<&Path>,
+    dirs: &Dirs,
+) -> Result<()> {
+    let old_config = match old {
+        Some(path) => XfceConfig::from_file(path, None, None, None)
+            .context("error reading old state")?,
+        None => {
+            xfce_config::check_session_bus()?;
+            XfceConfig::from_env(&new_config, dirs)
+                .context("error reading config from environment")?
+        },
+    };
+    new_config.reuse_plugin_ids(&old_config);
+    let mut state = XfceConfig::simulate(old_config, new_config)
+        .context("error simulating config")?;
+    // reset props fall back to their defaults
+    state.fill_xfconf_defaults(Path::new(XFCONF_DEFAULTS_DIR))?;
+    serde_json::to_writer_pretty(io::stdout(), &state)
+        .context("error writing simulated state")?;
+    println!();
+    Ok(())
+}
+
+// prints the differences between the environment and the state applying the
+// config would leave, without writing any logs
+fn diff_only(mut new_config: XfceConfig, dirs: &Dirs) -> Result<()> {
+    xfce_config::check_session_bus()?;
+    let old_config = XfceConfig::from_env(&new_config, dirs)
+        .context("error reading config from environment")?;
+    new_config.reuse_plugin_ids(&old_config);
+    let stats = XfceConfigPatch::diff(old_config.clone(), new_config.clone())
+        .context("error diffing configs")?
+        .change_count();
+    if stats.is_empty() {
+        summary(text(Message::NoChanges, &[]));
+        return Ok(());
+    }
+    let state = XfceConfig::simulate(old_config.clone(), new_config)
+        .context("error simulating config")?;
+    let differences = xfce_config::compare_states(&old_config, &state)
+        .context("error comparing states")?;
+    for difference in differences {
+        print_difference(&difference);
+    }
+    summary(text(Message::WouldApply, &[&stats]));
+    Ok(())
+}
+
+fn print_difference(difference: &RunDifference) {
+    let style = match difference {
+        RunDifference::Added { .. } => Style::Added,
+        RunDifference::Dropped { .. } => Style::Removed,
+        RunDifference::Changed { .. } => Style::Changed,
+    };
+    println!("{}", styled(Stream::Stdout, style, difference));
+}
+
+fn summary(message: impl std::fmt::Display) {
+    eprintln!("{}", styled(Stream::Stderr, Style::Summary, message));
+}
+
+fn clean(logs: bool) -> Result<()> {
+    if !logs {
+        bail!("nothing to clean, pass --logs");
+    }
+    let data_dir = data_dir()?;
+    let entries = match fs::read_dir(&data_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            eprintln!("nothing to clean");
+            return Ok(());
+        },
+        Err(err) => return Err(err).context("error reading data dir"),
+    };
+    let mut removed = 0;
+    let mut size = 0;
+    for entry in entries {
+        let entry = entry.context("error reading data dir entry")?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        // the timestamped run dirs and the quarantined ones
+        let is_log_dir = name.starts_with(|c: char| c.is_ascii_digit())
+            || name == "quarantine";
+        if !is_log_dir || !entry.path().is_dir() {
+            continue;
+        }
+        size += dir_size(&entry.path())
+            .with_context(|| format!("error measuring {}", name))?;
+        fs::remove_dir_all(entry.path())
+            .with_context(|| format!("error removing {}", name))?;
+        removed += 1;
+    }
+    eprintln!("removed {} log dirs, {}", removed, format_size(size));
+    Ok(())
+}
+
+fn dir_size(dir: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    for unit in ["KiB", "MiB"] {
+        if size < 1024.0 {
+            return format!("{:.1} {}", size, unit);
+        }
+        size /= 1024.0;
+    }
+    format!("{:.1} GiB", size)
+}
+
+fn check_update() -> Result<()> {
+    let output = process::Command::new("git")
+        .args(["ls-remote", "--tags", "--refs", REPO_URL])
+        .stderr(process::Stdio::inherit())
+        .output()
+        .context("error running git ls-remote")?;
+    if !output.status.success() {
+        bail!("git ls-remote exited with {}", output.status);
+    }
+    let tags = String::from_utf8_lossy(&output.stdout);
+    let latest = tags
+        .lines()
+        .filter_map(|line| line.rsplit_once("refs/tags/"))
+        .filter_map(|(_, tag)| Some((xfce_config::parse_version(tag)?, tag)))
+        .max();
+    let version = env!("CARGO_PKG_VERSION");
+    match latest {
+        Some((latest, tag))
+            if xfce_config::parse_version(version)
+                .map_or(false, |version| version < latest) =>
+        {
+            println!("{} is available, this is {}", tag, version);
+        },
+        Some(_) => println!("{} is the latest release", version),
+        None => bail!("no release tags found at {}", REPO_URL),
+    }
+    Ok(())
+}
+
+// the environment as config, only the parts the config declares if one is
+// given with --config
+fn dump(args: &Args, output: Option<&Path>) -> Result<()> {
+    xfce_config::check_session_bus()?;
+    let dirs = Dirs::new(&data_dir()?)?;
+    let declared = match &args.config {
+        Some(_) => read_config(args)?,
+        None => XfceConfig::default(),
+    };
+    let config = XfceConfig::from_env(&declared, &dirs)
+        .context("error reading config from environment")?;
+    match output {
+        Some(path) => {
+            let file = fs::File::create(path).with_context(|| {
+                format!("error creating {}", path.display())
+            })?;
+            serde_json::to_writer_pretty(io::BufWriter::new(file), &config)
+        },
+        None => serde_json::to_writer_pretty(io::stdout(), &config),
+    }
+    .context("error writing dumped config")?;
+    if output.is_none() {
+        println!();
+    }
+    Ok(())
+}
+
+fn data_dir() -> Result<PathBuf> {
+    Ok(dirs2::data_local_dir()
+        .context("could not get data local dir")?
+        .join("xfce-config"))
+}
+
+fn compare_runs(first: &Path, second: &Path) -> Result<()> {
+    let log_dir = |run: &Path| -> Result<PathBuf> {
+        let log_dir = run_log_dir(run)?;
+        if log_dir.join(RUN_STARTED).exists()
+            && !log_dir.join(RUN_FINISHED).exists()
+        {
+            bail!(
+                "run {} didn't finish, its logs may be truncated",
+                run.display()
+            );
+        }
+        Ok(log_dir)
+    };
+    let differences =
+        xfce_config::compare_runs(&log_dir(first)?, &log_dir(second)?)
+            .context("error comparing runs")?;
+    if differences.is_empty() {
+        eprintln!("runs planned the same changes");
+    }
+    for difference in differences {
+        print_difference(&difference);
+    }
+    Ok(())
+}
+
+// bare timestamps name log dirs in the data dir or its quarantine dir
+fn run_log_dir(run: &Path) -> Result<PathBuf> {
+    if run.is_dir() {
+        return Ok(run.to_owned());
+    }
+    let data_dir = data_dir()?;
+    let quarantined = data_dir.join("quarantine").join(run);
+    Ok(if quarantined.is_dir() {
+        quarantined
+    } else {
+        data_dir.join(run)
+    })
+}
+
+fn explain(query: &str) -> Result<()> {
+    let explanations = xfce_config::explain(query);
+    if explanations.is_empty() {
+        bail!("no known settings match {}", query);
+    }
+    for explanation in explanations {
+        println!("{}: {}", explanation.setting, explanation.config);
+    }
+    Ok(())
+}
+
+fn schema() -> Result<()> {
+    serde_json::to_writer_pretty(io::stdout(), &XfceConfig::schema())
+        .context("error writing schema")?;
+    println!();
+    Ok(())
+}
+
+fn migrate_gsettings(schemas: &[String]) -> Result<()> {
+    let config = XfceConfig::from_gsettings(schemas)?;
+    serde_json::to_writer_pretty(io::stdout(), &config)
+        .context("error writing migrated config")?;
+    println!();
+    Ok(())
+}
+
+fn record(files: bool, minimal: bool) -> Result<()> {
+    let config_dir = files
+        .then(|| dirs2::config_dir().context("could not get config dir"))
+        .transpose()?;
+    eprintln!("recording changes, press enter to stop");
+    let defaults_dir = minimal.then(|| Path::new(XFCONF_DEFAULTS_DIR));
+    let config = xfce_config::record(config_dir.as_deref(), defaults_dir)?;
+    serde_json::to_writer_pretty(io::stdout(), &config)
+        .context("error writing recorded config")?;
+    println!();
+    Ok(())
+}
+
+// a log dir with the first but not the second belongs to a run which crashed
+// or was killed, so its files may be truncated, unless the run is still going
+// and holds a lock on the first
+const RUN_STARTED: &str = "started";
+const RUN_FINISHED: &str = "finished";
+
+// how many log dirs of past runs are kept, and of quarantined ones
+const KEPT_LOG_DIRS: usize = 10;
+
+// the log dir of this run, locked as long as it's alive
+struct LogDir {
+    path: PathBuf,
+    _lock: fs::File,
+}
+
+impl std::ops::Deref for LogDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+// takes the lock marking the run owning a log dir alive, which is released
+// when the file is closed, also when the run is killed
+fn try_lock(file: &fs::File) -> bool {
+    let fd = file.as_raw_fd();
+    // SAFETY: flock only changes the lock of the file descriptor
+    unsafe { libc::flock(fd, libc::LOCK_EX | libc::LOCK_NB) == 0 }
+}
+
+// whether the run logging to the dir is still going, like a concurrent watch
+fn is_running(log_dir: &Path) -> bool {
+    fs::File::open(log_dir.join(RUN_STARTED))
+        .map_or(false, |started| !try_lock(&started))
+}
+
+// timestamped dirs in the dir, oldest first
+fn timestamped_dirs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = fs::read_dir(dir)
         .context("error reading log dir")?
         .filter_map(|entry| {
             entry
                 .context("error reading log dir entry")
                 .map(|entry| {
                     let path = entry.path();
-                    path.is_dir().then(|| path)
+                    let is_log_dir = path.is_dir()
+                        && entry
+                            .file_name()
+                            .to_str()
+                            .and_then(|name| name.chars().next())
+                            .map_or(false, |c| c.is_ascii_digit());
+                    is_log_dir.then(|| path)
                 })
                 .transpose()
         })
         .collect::<Result<Vec<_>>>()?;
-    log_dirs.sort();
-    // remove all but the last 10
-    for expired_log_dir in log_dirs.into_iter().rev().skip(10) {
-        fs::remove_dir_all(expired_log_dir)
-            .context("error removing old log dir")?;
+    dirs.sort();
+    Ok(dirs)
+}
+
+// removes all but the last few of the dirs, which are sorted
+fn remove_expired(dirs: Vec<PathBuf>) -> Result<()> {
+    for expired in dirs.into_iter().rev().skip(KEPT_LOG_DIRS) {
+        fs::remove_dir_all(expired).context("error removing old log dir")?;
+    }
+    Ok(())
+}
+
+fn rotating_log_dir(dir: PathBuf) -> Result<LogDir> {
+    fs::create_dir_all(&dir).context("error creating log dir")?;
+    let timestamp = chrono::Local::now().format("%Y-%m-%d-%H-%M-%S");
+    // runs started in the same second get numbered dirs of their own
+    let mut log_dir = dir.join(timestamp.to_string());
+    let mut number = 1;
+    loop {
+        match fs::create_dir(&log_dir) {
+            Ok(()) => break,
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                number += 1;
+                log_dir = dir.join(format!("{}-{}", timestamp, number));
+            },
+            Err(error) => {
+                return Err(error).context("error creating log dir");
+            },
+        }
+    }
+    let lock = fs::File::create(log_dir.join(RUN_STARTED))
+        .context("error marking log dir started")?;
+    if !try_lock(&lock) {
+        bail!("error locking log dir {}", log_dir.display());
+    }
+    // only the timestamped dirs are log dirs, the ones of runs still going
+    // are left alone
+    let log_dirs = timestamped_dirs(&dir)?
+        .into_iter()
+        .filter(|path| *path != log_dir && !is_running(path))
+        .collect::<Vec<_>>();
+    // moved aside so they aren't mistaken for complete runs
+    let quarantine_dir = dir.join("quarantine");
+    let (mut log_dirs, unfinished) =
+        log_dirs.into_iter().partition::<Vec<_>, _>(|path| {
+            !path.join(RUN_STARTED).exists() || path.join(RUN_FINISHED).exists()
+        });
+    for unfinished in &unfinished {
+        warn(format_args!(
+            "quarantining log dir of unfinished run {}",
+            unfinished.display()
+        ));
+        fs::create_dir_all(&quarantine_dir)
+            .context("error creating quarantine dir")?;
+        fs::rename(
+            unfinished,
+            quarantine_dir.join(unfinished.file_name().unwrap()),
+        )
+        .context("error quarantining log dir")?;
+    }
+    if quarantine_dir.is_dir() {
+        remove_expired(timestamped_dirs(&quarantine_dir)?)?;
     }
-    Ok(log_dir)
+    log_dirs.push(log_dir.clone());
+    log_dirs.sort();
+    remove_expired(log_dirs)?;
+    Ok(LogDir {
+        path: log_dir,
+        _lock: lock,
+    })
 }
@@ -0,0 +1,159 @@
+use crate::{Change, XfceConfig};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+// what applying configs on this machine set over all runs, so what a config
+// no longer declares can be pruned without touching anything it never managed.
+// only xfconf props and autostart entries are tracked, the files the other
+// sections write stay when a config stops declaring them
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ManagedState {
+    // prop paths by channel
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    xfconf: BTreeMap<String, BTreeSet<String>>,
+    // entry names
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    autostart: BTreeSet<String>,
+}
+
+impl ManagedState {
+    pub fn read(path: &Path) -> Result<Self> {
+        crate::state::read(path)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        crate::state::write(path, self)
+    }
+
+    // removes what was managed before but isn't declared in the config
+    pub fn prune(&self, config: &mut XfceConfig) {
+        #[cfg(feature = "glib")]
+        for (channel, paths) in &self.xfconf {
+            for path in paths {
+                config.xfconf.remove_undeclared(channel, path);
+            }
+        }
+        for name in &self.autostart {
+            config.autostart.remove_undeclared(name);
+        }
+    }
+
+    // records what applying the config sets and removes, except for the
+    // changes left out of the applied patch by --only, --skip or declining
+    pub fn update(&mut self, config: &XfceConfig, left_out: &[Change]) {
+        let applied = |section: &str, id: &str| {
+            !left_out.iter().any(|change| {
+                change.section == section
+                    && change.part.as_deref().map_or(true, |part| part == id)
+            })
+        };
+        #[cfg(feature = "glib")]
+        {
+            for (channel, paths) in config.xfconf.declared_props() {
+                let applied = paths
+                    .into_iter()
+                    .filter(|path| {
+                        applied("xfconf", &format!("{}{}", channel, path))
+                    })
+                    .collect::<Vec<_>>();
+                self.xfconf.entry(channel).or_default().extend(applied);
+            }
+            for (channel, removed) in config.xfconf.removed_props() {
+                let removed = removed
+                    .iter()
+                    .filter(|path| {
+                        applied("xfconf", &format!("{}{}", channel, path))
+                    })
+                    .collect::<Vec<_>>();
+                if let Some(paths) = self.xfconf.get_mut(channel) {
+                    // the props under removed ones are gone too
+                    paths.retain(|path| {
+                        !removed.iter().any(|removed| {
+                            path == *removed
+                                || path.starts_with(&format!("{}/", removed))
+                        })
+                    });
+                }
+            }
+            self.xfconf.retain(|_, paths| !paths.is_empty());
+        }
+        let (written, removed) = config.autostart.declared();
+        self.autostart.extend(
+            written
+                .into_iter()
+                .filter(|name| applied("autostart", name))
+                .map(str::to_owned),
+        );
+        for name in removed {
+            if applied("autostart", name) {
+                self.autostart.remove(name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn prune() {
+        let config =
+            |value| serde_json::from_value::<XfceConfig>(value).unwrap();
+        let mut state = ManagedState::default();
+        state.update(
+            &config(json!({
+                "autostart": {
+                    "entries": { "redshift": { "Exec": "redshift-gtk" } },
+                },
+            })),
+            &[],
+        );
+        let mut pruned = config(json!({}));
+        state.prune(&mut pruned);
+        state.update(&pruned, &[]);
+        assert!(state.autostart.is_empty());
+
+        // declined entries weren't written, so they aren't managed
+        let declined = Change {
+            section: "autostart",
+            part: Some("redshift".to_owned()),
+            description: "write".to_owned(),
+        };
+        state.update(
+            &config(json!({
+                "autostart": {
+                    "entries": { "redshift": { "Exec": "redshift-gtk" } },
+                },
+            })),
+            &[declined],
+        );
+        assert!(state.autostart.is_empty());
+
+        #[cfg(feature = "glib")]
+        {
+            state.update(&config(json!({
+                "xfconf": {
+                    "channels": [{
+                        "name": "xfwm4",
+                        "props": { "general": { "props": { "theme": "Arc" } } },
+                    }],
+                },
+            })), &[]);
+            let mut pruned = config(json!({}));
+            state.prune(&mut pruned);
+            assert_eq!(
+                pruned.xfconf.removed_props().collect::<Vec<_>>(),
+                vec![("xfwm4", &["/general/theme".to_owned()][..])]
+            );
+            state.update(&pruned, &[]);
+            assert!(state.xfconf.is_empty());
+        }
+    }
+}
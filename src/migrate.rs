@@ -0,0 +1,280 @@
+use anyhow::{Context, Result};
+use gio::prelude::SettingsExt;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+// a gsettings key which XFCE mirrors in an xfconf property
+struct Mapping {
+    schema: &'static str,
+    key: &'static str,
+    channel: &'static str,
+    property: &'static str,
+    convert: fn(&glib::Variant) -> Option<Value>,
+}
+
+const MAPPINGS: &[Mapping] = &[
+    Mapping {
+        schema: "org.gnome.desktop.interface",
+        key: "gtk-theme",
+        channel: "xsettings",
+        property: "/Net/ThemeName",
+        convert: string,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.interface",
+        key: "icon-theme",
+        channel: "xsettings",
+        property: "/Net/IconThemeName",
+        convert: string,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.interface",
+        key: "cursor-theme",
+        channel: "xsettings",
+        property: "/Gtk/CursorThemeName",
+        convert: string,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.interface",
+        key: "cursor-size",
+        channel: "xsettings",
+        property: "/Gtk/CursorThemeSize",
+        convert: int,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.interface",
+        key: "cursor-blink",
+        channel: "xsettings",
+        property: "/Net/CursorBlink",
+        convert: boolean,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.interface",
+        key: "cursor-blink-time",
+        channel: "xsettings",
+        property: "/Net/CursorBlinkTime",
+        convert: int,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.interface",
+        key: "font-name",
+        channel: "xsettings",
+        property: "/Gtk/FontName",
+        convert: string,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.interface",
+        key: "monospace-font-name",
+        channel: "xsettings",
+        property: "/Gtk/MonospaceFontName",
+        convert: string,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.interface",
+        key: "gtk-key-theme",
+        channel: "xsettings",
+        property: "/Gtk/KeyThemeName",
+        convert: string,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.peripherals.mouse",
+        key: "double-click",
+        channel: "xsettings",
+        property: "/Net/DoubleClickTime",
+        convert: int,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.sound",
+        key: "theme-name",
+        channel: "xsettings",
+        property: "/Net/SoundThemeName",
+        convert: string,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.sound",
+        key: "event-sounds",
+        channel: "xsettings",
+        property: "/Net/EnableEventSounds",
+        convert: boolean,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.sound",
+        key: "input-feedback-sounds",
+        channel: "xsettings",
+        property: "/Net/EnableInputFeedbackSounds",
+        convert: boolean,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.wm.preferences",
+        key: "theme",
+        channel: "xfwm4",
+        property: "/general/theme",
+        convert: string,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.wm.preferences",
+        key: "titlebar-font",
+        channel: "xfwm4",
+        property: "/general/title_font",
+        convert: string,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.wm.preferences",
+        key: "button-layout",
+        channel: "xfwm4",
+        property: "/general/button_layout",
+        convert: button_layout,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.wm.preferences",
+        key: "focus-mode",
+        channel: "xfwm4",
+        property: "/general/click_to_focus",
+        convert: click_to_focus,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.wm.preferences",
+        key: "num-workspaces",
+        channel: "xfwm4",
+        property: "/general/workspace_count",
+        convert: int,
+    },
+    Mapping {
+        schema: "org.gnome.desktop.wm.preferences",
+        key: "workspace-names",
+        channel: "xfwm4",
+        property: "/general/workspace_names",
+        convert: strings,
+    },
+];
+
+fn string(variant: &glib::Variant) -> Option<Value> {
+    let s = variant.str()?;
+    Some(json!({ "type": "string", "value": s }))
+}
+
+fn int(variant: &glib::Variant) -> Option<Value> {
+    let n = variant.get::<i32>()?;
+    Some(json!({ "type": "int", "value": n }))
+}
+
+fn boolean(variant: &glib::Variant) -> Option<Value> {
+    let b = variant.get::<bool>()?;
+    Some(json!({ "type": "bool", "value": b }))
+}
+
+fn strings(variant: &glib::Variant) -> Option<Value> {
+    let strings = variant.get::<Vec<String>>()?;
+    Some(json!({
+        "type": "array",
+        "value": strings
+            .into_iter()
+            .map(|s| json!({ "type": "string", "value": s }))
+            .collect::<Vec<_>>(),
+    }))
+}
+
+// GNOME uses `appmenu:minimize,maximize,close` where xfwm4 uses `O|HMC`
+fn button_layout(variant: &glib::Variant) -> Option<Value> {
+    let layout = variant.str()?;
+    let (left, right) = layout.split_once(':').unwrap_or((layout, ""));
+    let buttons = |buttons: &str| {
+        buttons
+            .split(',')
+            .filter_map(|button| match button {
+                "menu" | "appmenu" => Some('O'),
+                "minimize" => Some('H'),
+                "maximize" => Some('M'),
+                "close" => Some('C'),
+                "shade" => Some('S'),
+                "stick" => Some('T'),
+                _ => None,
+            })
+            .collect::<String>()
+    };
+    Some(json!({
+        "type": "string",
+        "value": format!("{}|{}", buttons(left), buttons(right)),
+    }))
+}
+
+fn click_to_focus(variant: &glib::Variant) -> Option<Value> {
+    let mode = variant.str()?;
+    Some(json!({ "type": "bool", "value": mode == "click" }))
+}
+
+// reads the gsettings keys XFCE mirrors and returns the equivalent xfconf
+// config, limited to the given schemas if there are any
+pub fn gsettings_to_xfconf(schemas: &[String]) -> Result<Value> {
+    let source = gio::SettingsSchemaSource::default()
+        .context("no gsettings schema source")?;
+    for schema in schemas {
+        if !MAPPINGS.iter().any(|mapping| mapping.schema == schema) {
+            crate::warn(format_args!(
+                "schema {} has no xfconf equivalents",
+                schema
+            ));
+        }
+    }
+    let mut channels = BTreeMap::<&str, Map<String, Value>>::new();
+    let mut settings = BTreeMap::new();
+    for mapping in MAPPINGS {
+        if !schemas.is_empty()
+            && !schemas.iter().any(|schema| schema == mapping.schema)
+        {
+            continue;
+        }
+        let settings =
+            match settings.entry(mapping.schema).or_insert_with(|| {
+                source
+                    .lookup(mapping.schema, true)
+                    .map(|schema| (schema, gio::Settings::new(mapping.schema)))
+            }) {
+                Some((schema, settings)) if schema.has_key(mapping.key) => {
+                    settings
+                },
+                _ => continue,
+            };
+        let variant = settings.value(mapping.key);
+        match (mapping.convert)(&variant) {
+            Some(value) => insert_property(
+                channels.entry(mapping.channel).or_default(),
+                mapping.property,
+                value,
+            ),
+            None => crate::warn(format_args!(
+                "can't convert gsettings value {} for key {} in schema {}",
+                variant, mapping.key, mapping.schema
+            )),
+        }
+    }
+    Ok(json!({
+        "xfconf": {
+            "channels": channels
+                .into_iter()
+                .map(|(name, props)| json!({ "name": name, "props": props }))
+                .collect::<Vec<_>>(),
+        },
+    }))
+}
+
+fn insert_property(
+    mut props: &mut Map<String, Value>,
+    property: &str,
+    value: Value,
+) {
+    // property starts with / so skip first empty part
+    let mut parts = property.split('/').skip(1).peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            props.insert(part.to_owned(), value);
+            return;
+        }
+        props = props
+            .entry(part)
+            .or_insert_with(|| json!({ "props": {} }))
+            .get_mut("props")
+            .and_then(Value::as_object_mut)
+            .expect("intermediate property has props");
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{
+    schema,
+    xfconf::{Applier as XfconfApplier, Operation},
+    Applier,
+};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, path::Path};
+
+// one-time cleanups run in order before the config is diffed, each only once
+// per machine, like database migrations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct Migration {
+    name: String,
+    ops: Vec<Operation>,
+}
+
+impl Migration {
+    pub(crate) fn schema() -> serde_json::Value {
+        schema::strict(schema::required(
+            schema::object(vec![
+                ("name", schema::string()),
+                ("ops", schema::array(Operation::schema())),
+            ]),
+            &["name", "ops"],
+        ))
+    }
+}
+
+// the migrations run on this machine so far
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct State {
+    #[serde(default)]
+    applied: Vec<Applied>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Applied {
+    name: String,
+    // of xfce-config
+    version: String,
+    applied_at: String,
+}
+
+// runs the migrations not in the state file yet, recording each one as soon
+// as it ran so a failed one is retried next time but not the ones before it
+pub(crate) fn run(
+    migrations: &[Migration],
+    applier: &mut Applier<'_>,
+    state_path: &Path,
+) -> Result<()> {
+    let mut names = BTreeSet::new();
+    for migration in migrations {
+        if !names.insert(&migration.name) {
+            bail!("migration {} is declared twice", migration.name);
+        }
+    }
+    let mut state = crate::state::read::<State>(state_path)?;
+    let applied = state
+        .applied
+        .iter()
+        .map(|applied| applied.name.clone())
+        .collect::<BTreeSet<_>>();
+    for migration in migrations {
+        if applied.contains(&migration.name) {
+            continue;
+        }
+        let mut xfconf_applier =
+            XfconfApplier::new(applier.dry_run, &mut applier.patch_recorder)
+                .context("error creating xfconf applier")?;
+        for op in migration.ops.iter().cloned() {
+            op.apply(&mut xfconf_applier).with_context(|| {
+                format!("error running migration {}", migration.name)
+            })?;
+        }
+        if applier.dry_run {
+            eprintln!("would run migration {}", migration.name);
+            continue;
+        }
+        state.applied.push(Applied {
+            name: migration.name.clone(),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            applied_at: chrono::Local::now().to_rfc3339(),
+        });
+        crate::state::write(state_path, &state)?;
+        eprintln!("ran migration {}", migration.name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn deserialize() {
+        let migrations = serde_json::from_value::<Vec<Migration>>(json!([{
+            "name": "drop-legacy-launcher",
+            "ops": [
+                {
+                    "op": "reset",
+                    "channel": "xfce4-panel",
+                    "property": "/plugins/plugin-12",
+                },
+                {
+                    "op": "set",
+                    "channel": "xfce4-panel",
+                    "property": "/panels/panel-1/size",
+                    "value": { "type": "uint", "value": 28 },
+                },
+            ],
+        }]))
+        .unwrap();
+        assert_eq!(migrations[0].ops.len(), 2);
+        let migrations =
+            crate::serde::deserialize_stripped::<_, Vec<Migration>>(json!([{
+                "_comment": "left over from the old image",
+                "name": "drop-legacy-launcher",
+                "ops": [{
+                    "x-ticket": "IT-12",
+                    "op": "reset",
+                    "channel": "xfce4-panel",
+                    "property": "/plugins/plugin-12",
+                }],
+            }]))
+            .unwrap();
+        assert_eq!(migrations[0].ops.len(), 1);
+        assert!(serde_json::from_value::<Vec<Migration>>(json!([{
+            "name": "typo",
+            "ops": [{ "op": "delete", "channel": "xfwm4", "property": "/a" }],
+        }]))
+        .is_err());
+    }
+}
@@ -0,0 +1,24 @@
+// the sections of XfceConfig, which are its public fields, for tools building
+// configs in code instead of writing them as JSON. sections are changed with
+// their setters, or deserialized from the same JSON the config takes, and the
+// config is diffed and applied with the patch and applier types
+pub use crate::{
+    autostart::Autostart,
+    default_applications::DefaultApplications,
+    general::General,
+    gtk::Gtk,
+    panel::Panel,
+    plugins::Plugins,
+    wallpapers::Wallpapers,
+    xresources::Xresources,
+    Applier,
+    ChangeStats,
+    Dirs,
+    SectionTimeouts,
+    SymlinkPolicies,
+    SymlinkPolicy,
+    XfceConfig,
+    XfceConfigPatch,
+};
+#[cfg(feature = "glib")]
+pub use crate::{gsettings::GSettings, xfconf::Xfconf};
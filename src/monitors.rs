@@ -0,0 +1,81 @@
+use anyhow::{bail, Context, Result};
+use std::process;
+
+#[derive(Debug, PartialEq)]
+pub struct Monitor {
+    // the output name, like HDMI-0
+    pub name: String,
+    pub primary: bool,
+}
+
+// the connected monitors, asking xrandr
+pub fn list() -> Result<Vec<Monitor>> {
+    let output = process::Command::new("xrandr")
+        .arg("--listmonitors")
+        .stderr(process::Stdio::inherit())
+        .output()
+        .context("error running xrandr")?;
+    if !output.status.success() {
+        bail!(
+            "listing monitors failed, xrandr exited with {}",
+            output.status
+        );
+    }
+    parse(&String::from_utf8_lossy(&output.stdout))
+}
+
+// output like
+//  Monitors: 2
+//   0: +*eDP-1 1920/344x1080/194+0+0  eDP-1
+//   1: +HDMI-1 1920/531x1080/299+1920+0  HDMI-1
+fn parse(output: &str) -> Result<Vec<Monitor>> {
+    let mut lines = output.lines();
+    let count = lines
+        .next()
+        .and_then(|line| line.strip_prefix("Monitors:"))
+        .and_then(|count| count.trim().parse::<usize>().ok())
+        .context("unexpected xrandr output")?;
+    let monitors = lines
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            let flags = fields.nth(1)?;
+            let name = fields.last()?;
+            Some(Monitor {
+                name: name.to_owned(),
+                primary: flags.contains('*'),
+            })
+        })
+        .collect::<Option<Vec<_>>>()
+        .context("unexpected xrandr monitor line")?;
+    if monitors.len() != count {
+        bail!("xrandr listed {} of {} monitors", monitors.len(), count);
+    }
+    Ok(monitors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            super::parse(
+                "Monitors: 2\n 0: +*eDP-1 1920/344x1080/194+0+0  eDP-1\n 1: \
+                 +HDMI-1 1920/531x1080/299+1920+0  HDMI-1\n"
+            )
+            .unwrap(),
+            [
+                Monitor {
+                    name: "eDP-1".into(),
+                    primary: true,
+                },
+                Monitor {
+                    name: "HDMI-1".into(),
+                    primary: false,
+                },
+            ]
+        );
+        assert!(super::parse("Monitors: 2\n").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use crate::i18n::{text, Message};
+use anyhow::{bail, Result};
+use std::{
+    env,
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+// --color, set once for the process since all output goes through here
+static COLOR: AtomicU8 = AtomicU8::new(ColorChoice::Auto as u8);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => bail!(
+                "unknown color choice {}, expected auto, always or never",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auto => "auto",
+            Self::Always => "always",
+            Self::Never => "never",
+        }
+        .fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+pub fn set_color(choice: ColorChoice) {
+    COLOR.store(choice as u8, Ordering::Relaxed);
+}
+
+// whether output to the stream is colored, --color wins over NO_COLOR, which
+// wins over whether the stream is a terminal
+pub fn colored(stream: Stream) -> bool {
+    let choice = match COLOR.load(Ordering::Relaxed) {
+        choice if choice == ColorChoice::Always as u8 => ColorChoice::Always,
+        choice if choice == ColorChoice::Never as u8 => ColorChoice::Never,
+        _ => ColorChoice::Auto,
+    };
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            env::var_os("NO_COLOR").map_or(true, |no_color| no_color.is_empty())
+                && is_terminal(stream)
+        },
+    }
+}
+
+fn is_terminal(stream: Stream) -> bool {
+    let fd = match stream {
+        Stream::Stdout => libc::STDOUT_FILENO,
+        Stream::Stderr => libc::STDERR_FILENO,
+    };
+    // SAFETY: isatty only reads the file descriptor's state
+    unsafe { libc::isatty(fd) == 1 }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Style {
+    Added,
+    Removed,
+    Changed,
+    Warning,
+    Error,
+    Summary,
+}
+
+impl Style {
+    fn code(self) -> &'static str {
+        match self {
+            Self::Added => "32",
+            Self::Removed => "31",
+            Self::Changed => "33",
+            Self::Warning => "1;33",
+            Self::Error => "1;31",
+            Self::Summary => "1",
+        }
+    }
+}
+
+// text in a style if output to the stream it's written to is colored
+pub struct Styled<T> {
+    text: T,
+    style: Option<Style>,
+}
+
+pub fn styled<T>(stream: Stream, style: Style, text: T) -> Styled<T> {
+    Styled {
+        text,
+        style: colored(stream).then(|| style),
+    }
+}
+
+impl<T> fmt::Display for Styled<T>
+where
+    T: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.style {
+            Some(style) => {
+                write!(f, "\x1b[{}m{}\x1b[0m", style.code(), self.text)
+            },
+            None => self.text.fmt(f),
+        }
+    }
+}
+
+pub fn warn(message: impl fmt::Display) {
+    eprintln!(
+        "{} {}",
+        styled(Stream::Stderr, Style::Warning, text(Message::Warning, &[])),
+        message
+    );
+}
+
+// like anyhow does when main returns an error, but translated
+pub fn print_error(error: &anyhow::Error) {
+    eprintln!(
+        "{} {}",
+        styled(Stream::Stderr, Style::Error, text(Message::Error, &[])),
+        error
+    );
+    let causes = error.chain().skip(1).collect::<Vec<_>>();
+    if !causes.is_empty() {
+        eprintln!("\n{}", text(Message::CausedBy, &[]));
+        for (index, cause) in causes.iter().enumerate() {
+            eprintln!("    {}: {}", index, cause);
+        }
+    }
+}
@@ -1,34 +1,232 @@
 use crate::{
     cfg::{Applier as CfgApplier, Cfg, CfgPatch},
+    external::ExternallyManaged,
+    schema,
+    section::{Section, SectionPatch},
     serde::IdMap,
+    symlinks::SymlinkPolicy,
+    ChangeStats,
+    Dirs,
     PatchRecorder,
 };
 use anyhow::{bail, Context, Result};
 use cfg_if::cfg_if;
-use serde::{ser, Deserialize, Serialize};
+use serde::{de, ser, Deserialize, Serialize};
 use std::{
     borrow::Cow,
-    collections::{BTreeMap, BTreeSet},
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet},
+    ffi::OsStr,
     fmt,
     fs,
+    hash::{Hash, Hasher},
     io,
+    iter,
     path::{Path, PathBuf},
 };
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Panel {
     #[serde(default, skip_serializing_if = "PluginConfigs::is_empty")]
     plugin_configs: PluginConfigs,
+    #[serde(skip)]
+    skipped: Vec<PathBuf>,
 }
 
 impl Panel {
-    pub fn is_empty(&self) -> bool {
-        self.plugin_configs.is_empty()
+    // input which the panel plugins won't read
+    pub fn unused(&self) -> Vec<String> {
+        (self.plugin_configs.0)
+            .0
+            .values()
+            .filter_map(|plugin_config| match &plugin_config.file {
+                PluginConfigFile::Rc(cfg) => {
+                    let layout = RcLayout::of(&plugin_config.id.r#type)?;
+                    Some(layout.ignored_keys(cfg).into_iter().map(move |key| {
+                        format!(
+                            "plugin {} ignores RC key {}, it only reads {}",
+                            plugin_config.id, key, layout
+                        )
+                    }))
+                },
+                PluginConfigFile::DesktopDir(_) => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    // the files of each plugin's dir have to be named the way it names them
+    fn check_file_names(&self) -> Result<()> {
+        for plugin_config in (self.plugin_configs.0).0.values() {
+            let desktop_dir = match &plugin_config.file {
+                PluginConfigFile::DesktopDir(desktop_dir) => desktop_dir,
+                PluginConfigFile::Rc(_) => continue,
+            };
+            let naming = FileNaming::of(&plugin_config.id.r#type);
+            if let Some(name) =
+                desktop_dir.files.0.keys().find(|name| !naming.allows(name))
+            {
+                bail!(
+                    "plugin {} only uses {}, not {}",
+                    plugin_config.id,
+                    naming,
+                    name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // declares the RC file of a plugin of the typed panels block, laid out
+    // the way its type reads it
+    #[cfg(feature = "glib")]
+    pub(crate) fn declare_rc(
+        &mut self,
+        plugin_type: &str,
+        id: u32,
+        keys: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<()> {
+        let id = PluginId {
+            r#type: plugin_type.to_owned(),
+            id: id.into(),
+        };
+        let layout = RcLayout::of(plugin_type).with_context(|| {
+            format!(
+                "RC layout of {} plugins is unknown, declare the RC file of \
+                 plugin {} in the panel section instead",
+                plugin_type, id
+            )
+        })?;
+        let cfg = layout
+            .compile(keys)
+            .with_context(|| format!("invalid RC keys of plugin {}", id))?;
+        let configs = &mut (self.plugin_configs.0).0;
+        if configs.contains_key(&id) {
+            bail!("plugin {} has an RC file in the panel section too", id);
+        }
+        configs.insert(
+            id.clone(),
+            PluginConfig {
+                id,
+                file: PluginConfigFile::Rc(cfg),
+            },
+        );
+        Ok(())
+    }
+
+    // renumbers the files of plugins by type, old and new id, all at once so
+    // ids can be swapped
+    #[cfg(feature = "glib")]
+    pub(crate) fn move_plugins(&mut self, moves: &[(String, u32, u32)]) {
+        let configs = &mut (self.plugin_configs.0).0;
+        let moved = moves
+            .iter()
+            .filter_map(|(plugin_type, from, to)| {
+                let mut config = configs.remove(&PluginId {
+                    r#type: plugin_type.clone(),
+                    id: (*from).into(),
+                })?;
+                config.id.id = (*to).into();
+                Some(config)
+            })
+            .collect::<Vec<_>>();
+        for config in moved {
+            configs.insert(config.id.clone(), config);
+        }
+    }
+
+    pub fn plugin_types(&self) -> impl Iterator<Item = &str> + '_ {
+        (self.plugin_configs.0)
+            .0
+            .keys()
+            .map(|plugin_id| plugin_id.r#type.as_str())
+    }
+
+    // replaces launcher apps with links to or copies of the installed desktop
+    // files their desktop ids name
+    // and leaves WhiskerMenu favorites whose apps aren't installed out of the
+    // menu if skip_missing_favorites
+    pub fn resolve_apps(
+        &mut self,
+        dirs: &Dirs,
+        skip_missing_favorites: bool,
+    ) -> Result<()> {
+        let data_dirs = iter::once(dirs.data_home())
+            .chain(crate::xdg_data_dirs())
+            .collect::<Vec<_>>();
+        let desktop_ids = DesktopIds::read(&data_dirs)
+            .context("error reading installed desktop files")?;
+        for plugin_config in (self.plugin_configs.0).0.values_mut() {
+            let desktop_dir = match &mut plugin_config.file {
+                PluginConfigFile::DesktopDir(desktop_dir) => desktop_dir,
+                PluginConfigFile::Rc(rc) => {
+                    if skip_missing_favorites
+                        && plugin_config.id.r#type == "whiskermenu"
+                    {
+                        skip_missing_favorites_of(
+                            rc,
+                            &desktop_ids,
+                            &plugin_config.id,
+                        );
+                    }
+                    continue;
+                },
+            };
+            for file in desktop_dir.files.0.values_mut() {
+                if let DesktopFileContent::App(app) = &file.content {
+                    let content =
+                        app.resolve(&desktop_ids).with_context(|| {
+                            format!(
+                                "error resolving desktop id {} of plugin {}",
+                                app.desktop_id, plugin_config.id
+                            )
+                        })?;
+                    file.content = content;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// plugin types which have a module desktop file in one of the panel plugin
+// dirs, or none if no such dir exists
+pub fn installed_plugin_types() -> Result<Option<BTreeSet<String>>> {
+    let dirs = crate::xdg_data_dirs()
+        .into_iter()
+        .map(|data_dir| data_dir.join("xfce4/panel/plugins"))
+        .chain(iter::once(PathBuf::from("/usr/lib/xfce4/panel/plugins")))
+        .filter(|dir| dir.is_dir())
+        .collect::<Vec<_>>();
+    if dirs.is_empty() {
+        return Ok(None);
+    }
+
+    let mut plugin_types = BTreeSet::new();
+    for dir in dirs {
+        let entries = dir.read_dir().with_context(|| {
+            format!("error reading plugin dir {}", dir.display())
+        })?;
+        for entry in entries {
+            let path = entry.context("error reading dir entry")?.path();
+            if path.extension() != Some("desktop".as_ref()) {
+                continue;
+            }
+            match path.file_stem().and_then(OsStr::to_str) {
+                Some(plugin_type) => {
+                    plugin_types.insert(plugin_type.to_owned());
+                },
+                None => crate::warn(format_args!(
+                    "skipping plugin with non-UTF-8 path {}",
+                    path.display()
+                )),
+            }
+        }
     }
+    Ok(Some(plugin_types))
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct PluginConfigs(IdMap<PluginConfig>);
 
 impl PluginConfigs {
@@ -37,7 +235,7 @@ impl PluginConfigs {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 struct PluginConfig {
     #[serde(rename = "plugin")]
@@ -67,56 +265,494 @@ impl ser::Serialize for PluginId {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "kebab-case")]
 enum PluginConfigFile {
     Rc(Cfg),
     DesktopDir(DesktopDir),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// how a plugin type lays out its RC file, to lint declared and existing RC
+// files with and to lay out the ones of the typed panels block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RcLayout {
+    // keys outside of any section
+    Root,
+    // keys inside sections
+    Sections,
+}
+
+// plugin types not listed here aren't linted and can't have RC files in the
+// typed panels block
+const RC_LAYOUTS: &[(&str, RcLayout)] = &[
+    ("battery", RcLayout::Root),
+    ("fsguard", RcLayout::Root),
+    ("genmon", RcLayout::Root),
+    ("sensors", RcLayout::Sections),
+    ("systemload", RcLayout::Sections),
+    ("weather", RcLayout::Root),
+    ("whiskermenu", RcLayout::Root),
+];
+
+impl RcLayout {
+    fn of(plugin_type: &str) -> Option<Self> {
+        RC_LAYOUTS
+            .iter()
+            .find(|(r#type, _)| *r#type == plugin_type)
+            .map(|(_, layout)| *layout)
+    }
+
+    // the RC file of keys, which are values or sections of them depending on
+    // the layout
+    #[cfg(feature = "glib")]
+    fn compile(
+        self,
+        keys: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Cfg> {
+        let string = |key: &str, value: serde_json::Value| match value {
+            serde_json::Value::String(value) => Ok(value),
+            _ => bail!("value of {} isn't a string", key),
+        };
+        let mut cfg = Cfg::default();
+        for (key, value) in keys {
+            match (self, value) {
+                (Self::Root, value) => {
+                    let value = string(&key, value)?;
+                    cfg.root.insert(key, value);
+                },
+                (Self::Sections, serde_json::Value::Object(props)) => {
+                    let section = cfg.sections.entry(key.clone()).or_default();
+                    for (name, value) in props {
+                        let value =
+                            string(&format!("[{}] {}", key, name), value)?;
+                        section.insert(name, value);
+                    }
+                },
+                (Self::Sections, _) => {
+                    bail!(
+                        "{} isn't a section, the plugin only reads {}",
+                        key,
+                        self
+                    )
+                },
+            }
+        }
+        Ok(cfg)
+    }
+
+    // keys of the cfg which the plugin won't read
+    fn ignored_keys(self, cfg: &Cfg) -> Vec<String> {
+        match self {
+            Self::Root => cfg
+                .sections
+                .iter()
+                .flat_map(|(section, props)| {
+                    props
+                        .keys()
+                        .map(move |key| format!("[{}] {}", section, key))
+                })
+                .collect(),
+            Self::Sections => cfg.root.keys().cloned().collect(),
+        }
+    }
+}
+
+impl fmt::Display for RcLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Root => write!(f, "keys outside of sections"),
+            Self::Sections => write!(f, "keys inside sections"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 struct DesktopDir {
     files: IdMap<DesktopFile>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 struct DesktopFile {
-    id: u64,
+    id: FileName,
     content: DesktopFileContent,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// the name of a file in a plugin's dir, a number n standing for `<n>.desktop`
+// like launchers name theirs
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+struct FileName(String);
+
+impl FileName {
+    fn number(&self) -> Option<u64> {
+        self.0.strip_suffix(".desktop")?.parse().ok()
+    }
+}
+
+impl fmt::Display for FileName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<'de> de::Deserialize<'de> for FileName {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(u64),
+            Name(String),
+        }
+
+        let name = match Raw::deserialize(deserializer)? {
+            Raw::Number(number) => format!("{}.desktop", number),
+            Raw::Name(name) => name,
+        };
+        if name.is_empty() || name == "." || name == ".." || name.contains('/')
+        {
+            return Err(de::Error::custom(format_args!(
+                "invalid file name {:?}",
+                name
+            )));
+        }
+        Ok(Self(name))
+    }
+}
+
+// how a plugin type names the files in its dir, files named otherwise are
+// left alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileNaming {
+    // `<n>.desktop` for a number n, a file whose content moved to another
+    // number is renamed instead of written again
+    Numbered,
+    // any name, which the plugin looks the file up by
+    Named,
+}
+
+// plugin types not listed here may name their files anything
+const FILE_NAMINGS: &[(&str, FileNaming)] =
+    &[("launcher", FileNaming::Numbered)];
+
+impl FileNaming {
+    fn of(plugin_type: &str) -> Self {
+        FILE_NAMINGS
+            .iter()
+            .find(|(r#type, _)| *r#type == plugin_type)
+            .map_or(Self::Named, |(_, naming)| *naming)
+    }
+
+    fn allows(self, name: &FileName) -> bool {
+        match self {
+            Self::Numbered => name.number().is_some(),
+            Self::Named => true,
+        }
+    }
+}
+
+impl fmt::Display for FileNaming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Numbered => write!(f, "files named like <n>.desktop"),
+            Self::Named => write!(f, "files with any name"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "kebab-case")]
 enum DesktopFileContent {
     Cfg(Cfg),
     Link(Link),
+    // only in input, resolved to one of the others before diffing
+    App(App),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 struct Link {
+    // relative targets are relative to the launcher dir
+    #[serde(deserialize_with = "deserialize_link_target")]
     path: PathBuf,
 }
 
-impl Panel {
-    pub fn read(dir: &Path) -> Result<Self> {
+fn deserialize_link_target<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let path = PathBuf::deserialize(deserializer)?;
+    if path.as_os_str().is_empty() {
+        return Err(de::Error::custom("link target is empty"));
+    }
+    Ok(path)
+}
+
+// an installed application's desktop file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct App {
+    // e.g. `firefox.desktop`
+    desktop_id: String,
+    #[serde(default)]
+    install: AppInstall,
+}
+
+// how an app's desktop file gets into the launcher dir
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum AppInstall {
+    // link to the installed file, following its updates
+    Link,
+    // copy its content, which then stays as it was when applied
+    Copy,
+}
+
+impl Default for AppInstall {
+    fn default() -> Self {
+        Self::Link
+    }
+}
+
+impl App {
+    fn resolve(&self, desktop_ids: &DesktopIds) -> Result<DesktopFileContent> {
+        if !self.desktop_id.ends_with(".desktop")
+            || self.desktop_id.contains('/')
+        {
+            bail!("desktop ids are file names ending in .desktop");
+        }
+        let path = desktop_ids
+            .find(&self.desktop_id)
+            .context("no such desktop file in the XDG data dirs")?
+            .to_owned();
+        Ok(match self.install {
+            AppInstall::Link => DesktopFileContent::Link(Link { path }),
+            AppInstall::Copy => {
+                let file = fs::File::open(&path).with_context(|| {
+                    format!("error opening {}", path.display())
+                })?;
+                let cfg =
+                    Cfg::read(io::BufReader::new(file)).with_context(|| {
+                        format!("error reading {}", path.display())
+                    })?;
+                DesktopFileContent::Cfg(cfg)
+            },
+        })
+    }
+}
+
+// the installed desktop files by desktop id, which is a file's path in an
+// applications dir with dashes for the separators of its subdirs, the files in
+// the first data dir having one taking precedence
+struct DesktopIds(BTreeMap<String, PathBuf>);
+
+impl DesktopIds {
+    fn read(data_dirs: &[PathBuf]) -> Result<Self> {
+        let mut ids = BTreeMap::new();
+        for data_dir in data_dirs {
+            read_desktop_ids(&data_dir.join("applications"), "", &mut ids)?;
+        }
+        Ok(Self(ids))
+    }
+
+    fn find(&self, desktop_id: &str) -> Option<&Path> {
+        self.0.get(desktop_id).map(PathBuf::as_path)
+    }
+}
+
+// adds the desktop files under the dir to ids, prefix being the id of the dir
+// followed by a dash
+fn read_desktop_ids(
+    dir: &Path,
+    prefix: &str,
+    ids: &mut BTreeMap<String, PathBuf>,
+) -> Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("error reading {}", dir.display()));
+        },
+    };
+    for entry in entries {
+        let path = entry
+            .with_context(|| format!("error reading {}", dir.display()))?
+            .path();
+        // ids are UTF-8, so other files can't be named by one
+        let name = match path.file_name().and_then(OsStr::to_str) {
+            Some(name) => name,
+            None => continue,
+        };
+        let id = format!("{}{}", prefix, name);
+        if path.is_dir() {
+            read_desktop_ids(&path, &format!("{}-", id), ids)?;
+        } else if name.ends_with(".desktop") && path.is_file() {
+            ids.entry(id).or_insert(path);
+        }
+    }
+    Ok(())
+}
+
+// drops the favorites, desktop ids or paths of desktop files separated by
+// commas, which aren't installed, which the menu would show as broken entries
+fn skip_missing_favorites_of(
+    rc: &mut Cfg,
+    desktop_ids: &DesktopIds,
+    plugin_id: &PluginId,
+) {
+    let favorites = match rc.root.get_mut("favorites") {
+        Some(favorites) => favorites,
+        None => return,
+    };
+    let mut installed = Vec::new();
+    for favorite in favorites.split(',').filter(|favorite| !favorite.is_empty())
+    {
+        let found = if Path::new(favorite).is_absolute() {
+            Path::new(favorite).is_file()
+        } else {
+            desktop_ids.find(favorite).is_some()
+        };
+        if found {
+            installed.push(favorite);
+        } else {
+            crate::warn(format_args!(
+                "skipping favorite {} of plugin {}, it isn't installed",
+                favorite, plugin_id
+            ));
+        }
+    }
+    *favorites = installed.join(",");
+}
+
+impl Section for Panel {
+    type Patch = PanelPatch;
+
+    fn is_empty(&self) -> bool {
+        self.plugin_configs.is_empty()
+    }
+
+    fn schema() -> serde_json::Value {
+        let content = schema::tagged(
+            "type",
+            vec![
+                ("cfg", Cfg::schema()),
+                (
+                    "link",
+                    schema::required(
+                        schema::object(vec![("path", schema::string())]),
+                        &["path"],
+                    ),
+                ),
+                (
+                    "app",
+                    schema::required(
+                        schema::object(vec![
+                            ("desktop-id", schema::string()),
+                            ("install", schema::string_enum(&["link", "copy"])),
+                        ]),
+                        &["desktop-id"],
+                    ),
+                ),
+            ],
+        );
+        // a file name, or a number n for `<n>.desktop`
+        let name = schema::any_of(vec![schema::integer(), schema::string()]);
+        let desktop_file = schema::required(
+            schema::object(vec![("id", name), ("content", content)]),
+            &["id", "content"],
+        );
+        let file = schema::tagged(
+            "type",
+            vec![
+                ("rc", Cfg::schema()),
+                (
+                    "desktop-dir",
+                    schema::required(
+                        schema::object(vec![(
+                            "files",
+                            schema::array(desktop_file),
+                        )]),
+                        &["files"],
+                    ),
+                ),
+            ],
+        );
+        let plugin = schema::required(
+            schema::object(vec![
+                ("type", schema::string()),
+                ("id", schema::integer()),
+            ]),
+            &["type", "id"],
+        );
+        let plugin_config = schema::required(
+            schema::object(vec![("plugin", plugin), ("file", file)]),
+            &["plugin", "file"],
+        );
+        schema::object(vec![("plugin-configs", schema::array(plugin_config))])
+    }
+
+    fn read(_new: &Self, dirs: &Dirs) -> Result<Self> {
+        let dir = dirs.xfce4_config_dir.join("panel");
+        let mut skipped = Vec::new();
         Ok(Self {
-            plugin_configs: PluginConfigs::read(dir)
+            plugin_configs: PluginConfigs::read(&dir, &mut skipped)
                 .context("error reading plugin configs")?,
+            skipped,
         })
     }
+
+    fn diff(old: Self, new: Self) -> Result<PanelPatch> {
+        new.check_file_names()?;
+        Ok(PanelPatch::diff(old, new))
+    }
+
+    fn retain(&mut self, id: &str) -> Result<()> {
+        if !self.plugin_configs.0.retain_id(id) {
+            bail!("no plugin config {}", id);
+        }
+        Ok(())
+    }
+
+    fn apply_to_old(patch: PanelPatch, old: &mut Self) {
+        (patch.plugin_configs.0).apply_to_old(
+            &mut (old.plugin_configs.0).0,
+            PluginConfigPatch::apply_to_old,
+        );
+    }
+
+    fn unused(&self, _old: &Self) -> Vec<String> {
+        self.unused()
+    }
+
+    fn skipped(&self) -> Vec<PathBuf> {
+        self.skipped.clone()
+    }
 }
 
 impl PluginConfigs {
-    fn read(dir: &Path) -> Result<Self> {
-        dir.read_dir()
-            .context("error reading dir")?
+    fn read(dir: &Path, skipped: &mut Vec<PathBuf>) -> Result<Self> {
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            // the panel dir doesn't exist until a plugin is configured
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::default());
+            },
+            Err(error) => return Err(error).context("error reading dir"),
+        };
+        entries
             .map(|entry| {
                 let entry = entry.context("error reading dir entry")?;
                 let path = entry.path();
-                PluginConfig::read(&path)
+                if path.file_name().and_then(OsStr::to_str).is_none() {
+                    skipped.push(path);
+                    return Ok(None);
+                }
+                PluginConfig::read(&path, skipped)
             })
             .filter_map(Result::transpose)
             .collect::<Result<IdMap<_>>>()
@@ -125,10 +761,10 @@ impl PluginConfigs {
 }
 
 impl PluginConfig {
-    fn read(path: &Path) -> Result<Option<Self>> {
+    // non-UTF-8 desktop files are added to skipped
+    fn read(path: &Path, skipped: &mut Vec<PathBuf>) -> Result<Option<Self>> {
         let id = (|| {
-            let file_name = path.file_stem()?;
-            let file_name = file_name.to_str()?;
+            let file_name = path.file_stem()?.to_str()?;
             let (r#type, id) = file_name.rsplit_once('-')?;
             let id = id.parse().ok()?;
             let r#type = r#type.to_owned();
@@ -141,6 +777,7 @@ impl PluginConfig {
         };
 
         let file = if path.is_dir() {
+            let naming = FileNaming::of(&id.r#type);
             let files = path
                 .read_dir()
                 .context("error reading desktop dir")?
@@ -151,21 +788,30 @@ impl PluginConfig {
                     )?;
                     let path = entry.path();
 
-                    let id = (|| {
-                        let file_name = entry.file_name();
-                        let file_name = file_name.to_str()?;
-                        let (id, ext) = file_name.split_once('.')?;
-                        if ext != "desktop" {
-                            return None;
-                        }
-                        let id = id.parse().ok()?;
-                        Some(id)
-                    })();
-                    let id = if let Some(id) = id {
-                        id
+                    let file_name = entry.file_name();
+                    let name = if let Some(file_name) = file_name.to_str() {
+                        FileName(file_name.to_owned())
                     } else {
+                        skipped.push(path);
                         return Ok(None);
                     };
+                    if !naming.allows(&name) {
+                        crate::warn(format_args!(
+                            "leaving {} alone, plugin {} only uses {}",
+                            path.display(),
+                            id,
+                            naming
+                        ));
+                        return Ok(None);
+                    }
+                    if metadata.is_dir() {
+                        crate::warn(format_args!(
+                            "leaving dir {} of plugin {} alone",
+                            path.display(),
+                            id
+                        ));
+                        return Ok(None);
+                    }
 
                     let content = if metadata.file_type().is_symlink() {
                         let path = path
@@ -173,15 +819,29 @@ impl PluginConfig {
                             .context("error reading desktop link")?;
                         DesktopFileContent::Link(Link { path })
                     } else {
-                        let file = fs::File::open(path)
+                        let file = fs::File::open(&path)
                             .context("error opening desktop file")?;
                         let reader = io::BufReader::new(file);
-                        let cfg = Cfg::read(reader)
-                            .context("error reading desktop file")?;
-                        DesktopFileContent::Cfg(cfg)
+                        match Cfg::read(reader) {
+                            Ok(cfg) => DesktopFileContent::Cfg(cfg),
+                            // other files may hold anything
+                            Err(error) if !name.0.ends_with(".desktop") => {
+                                crate::warn(format_args!(
+                                    "leaving {} alone, it isn't key=value \
+                                     lines: {:#}",
+                                    path.display(),
+                                    error
+                                ));
+                                return Ok(None);
+                            },
+                            Err(error) => {
+                                return Err(error)
+                                    .context("error reading desktop file");
+                            },
+                        }
                     };
 
-                    Ok(Some((id, DesktopFile { id, content })))
+                    Ok(Some((name.clone(), DesktopFile { id: name, content })))
                 })
                 .filter_map(Result::transpose)
                 .collect::<Result<BTreeMap<_, _>>>()
@@ -195,6 +855,18 @@ impl PluginConfig {
                 fs::File::open(path).context("error opening plugin RC file")?;
             let reader = io::BufReader::new(file);
             let cfg = Cfg::read(reader).context("error reading plugin RC")?;
+            if let Some(layout) = RcLayout::of(&id.r#type) {
+                let ignored_keys = layout.ignored_keys(&cfg);
+                if !ignored_keys.is_empty() {
+                    crate::warn(format_args!(
+                        "existing RC file for plugin {} has keys {} which the \
+                         plugin ignores, it only reads {}",
+                        id,
+                        ignored_keys.join(", "),
+                        layout
+                    ));
+                }
+            }
             PluginConfigFile::Rc(cfg)
         } else {
             return Ok(None);
@@ -213,7 +885,7 @@ impl crate::serde::Id for PluginConfig {
 }
 
 impl crate::serde::Id for DesktopFile {
-    type Id = u64;
+    type Id = FileName;
 
     fn id(&self) -> &Self::Id {
         &self.id
@@ -225,7 +897,14 @@ trait Patch {
 
     fn diff(old: Self::Data, new: Self::Data) -> Self;
 
-    fn is_empty(&self) -> bool;
+    fn change_count(&self) -> ChangeStats;
+
+    // changes made by writing data which didn't exist yet
+    fn added_count(data: &Self::Data) -> ChangeStats;
+
+    fn is_empty(&self) -> bool {
+        self.change_count().is_empty()
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -275,8 +954,39 @@ where
         }
     }
 
-    fn is_empty(&self) -> bool {
-        self.changed.is_empty() && self.added.is_empty()
+    fn change_count(&self) -> ChangeStats {
+        self.changed
+            .values()
+            .map(V::change_count)
+            .chain(self.added.values().map(V::added_count))
+            .sum::<ChangeStats>()
+            + ChangeStats::files_removed(self.removed.len())
+    }
+
+    fn added_count(data: &Self::Data) -> ChangeStats {
+        data.values().map(V::added_count).sum()
+    }
+}
+
+impl<K, V> MapPatch<K, V>
+where
+    K: Ord,
+    V: Patch,
+{
+    fn apply_to_old(
+        self,
+        old: &mut BTreeMap<K, V::Data>,
+        apply_to_old: impl Fn(V, &mut V::Data),
+    ) {
+        for (key, patch) in self.changed {
+            if let Some(old_value) = old.get_mut(&key) {
+                apply_to_old(patch, old_value);
+            }
+        }
+        old.extend(self.added);
+        for key in self.removed {
+            old.remove(&key);
+        }
     }
 }
 
@@ -296,10 +1006,6 @@ impl PanelPatch {
             ),
         }
     }
-
-    pub fn is_empty(&self) -> bool {
-        self.plugin_configs.is_empty()
-    }
 }
 
 #[derive(Debug, Serialize)]
@@ -310,8 +1016,12 @@ impl PluginConfigsPatch {
         Self(MapPatch::diff((old.0).0, (new.0).0))
     }
 
+    fn change_count(&self) -> ChangeStats {
+        self.0.change_count()
+    }
+
     fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.change_count().is_empty()
     }
 }
 
@@ -354,11 +1064,26 @@ impl Patch for PluginConfigPatch {
         }
     }
 
-    fn is_empty(&self) -> bool {
+    fn change_count(&self) -> ChangeStats {
         match self {
-            Self::Rc(rc_patch) => rc_patch.is_empty(),
-            Self::DesktopDir(desktop_dir_patch) => desktop_dir_patch.is_empty(),
-            Self::Changed(_) => false,
+            Self::Rc(rc_patch) => rc_patch.change_count(),
+            Self::DesktopDir(desktop_dir_patch) => {
+                desktop_dir_patch.change_count()
+            },
+            // the old file is removed before writing the new one
+            Self::Changed(plugin_config) => {
+                ChangeStats::files_removed(1) + Self::added_count(plugin_config)
+            },
+        }
+    }
+
+    // the RC file or the desktop dir and all its files
+    fn added_count(data: &Self::Data) -> ChangeStats {
+        match &data.file {
+            PluginConfigFile::Rc(_) => ChangeStats::files_written(1),
+            PluginConfigFile::DesktopDir(desktop_dir) => {
+                ChangeStats::files_written(1 + desktop_dir.files.0.len())
+            },
         }
     }
 }
@@ -380,8 +1105,12 @@ impl Patch for RcPatch {
         }
     }
 
-    fn is_empty(&self) -> bool {
-        self.cfg.is_empty()
+    fn change_count(&self) -> ChangeStats {
+        self.cfg.change_count().in_file()
+    }
+
+    fn added_count(_data: &Self::Data) -> ChangeStats {
+        ChangeStats::files_written(1)
     }
 }
 
@@ -389,21 +1118,100 @@ impl Patch for RcPatch {
 #[serde(rename_all = "kebab-case")]
 struct DesktopDirPatch {
     id: PluginId,
-    files: MapPatch<u64, DesktopFilePatch>,
+    files: MapPatch<FileName, DesktopFilePatch>,
+    // old name to new name of files which only changed name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    renamed: BTreeMap<FileName, FileName>,
 }
 
 impl Patch for DesktopDirPatch {
     type Data = (PluginId, DesktopDir);
 
     fn diff(old: Self::Data, new: Self::Data) -> Self {
+        let mut old_files = old.1.files.0;
+        let mut new_files = new.1.files.0;
+
+        // move removed files to ids whose new content is the same instead of
+        // recreating them, matching by content hash first, unless the plugin
+        // looks its files up by name
+        let renames = FileNaming::of(&new.0.r#type) == FileNaming::Numbered;
+        let mut removed_by_hash = BTreeMap::<_, Vec<_>>::new();
+        for (id, file) in &old_files {
+            if renames && !new_files.contains_key(id) {
+                removed_by_hash
+                    .entry(file.content.content_hash())
+                    .or_default()
+                    .push(id.clone());
+            }
+        }
+        let mut renamed = BTreeMap::new();
+        for (new_id, new_file) in &new_files {
+            if matches!(
+                old_files.get(new_id),
+                Some(old_file) if old_file.content.same_as(&new_file.content)
+            ) {
+                continue;
+            }
+            if let Some(old_ids) =
+                removed_by_hash.get_mut(&new_file.content.content_hash())
+            {
+                if let Some(index) = old_ids.iter().position(|old_id| {
+                    old_files[old_id].content.same_as(&new_file.content)
+                }) {
+                    renamed.insert(old_ids.remove(index), new_id.clone());
+                }
+            }
+        }
+        for (old_id, new_id) in &renamed {
+            old_files.remove(old_id);
+            // the rename replaces whatever was at the new id
+            old_files.remove(new_id);
+            new_files.remove(new_id);
+        }
+
         Self {
             id: new.0,
-            files: MapPatch::diff(old.1.files.0, new.1.files.0),
+            files: MapPatch::diff(old_files, new_files),
+            renamed,
         }
     }
 
-    fn is_empty(&self) -> bool {
-        self.files.is_empty()
+    // a rename writes the new file and removes the old one
+    fn change_count(&self) -> ChangeStats {
+        self.files.change_count()
+            + ChangeStats::files_written(self.renamed.len())
+            + ChangeStats::files_removed(self.renamed.len())
+    }
+
+    fn added_count(data: &Self::Data) -> ChangeStats {
+        ChangeStats::files_written(1 + data.1.files.0.len())
+    }
+}
+
+impl DesktopFileContent {
+    fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        match self {
+            Self::Cfg(cfg) => {
+                cfg.root.hash(&mut hasher);
+                cfg.sections.hash(&mut hasher);
+            },
+            Self::Link(link) => link.path.hash(&mut hasher),
+            Self::App(app) => app.desktop_id.hash(&mut hasher),
+        }
+        hasher.finish()
+    }
+
+    fn same_as(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Cfg(cfg), Self::Cfg(other_cfg)) => {
+                cfg.root == other_cfg.root && cfg.sections == other_cfg.sections
+            },
+            (Self::Link(link), Self::Link(other_link)) => {
+                link.path == other_link.path
+            },
+            _ => false,
+        }
     }
 }
 
@@ -450,26 +1258,30 @@ impl Patch for DesktopFilePatch {
         }
     }
 
-    fn is_empty(&self) -> bool {
+    fn change_count(&self) -> ChangeStats {
         match self {
             Self::Cfg(desktop_file_cfg_patch) => {
-                desktop_file_cfg_patch.is_empty()
+                desktop_file_cfg_patch.change_count()
             },
-            Self::Link(link_patch) => link_patch.is_empty(),
-            Self::Changed(_) => false,
+            Self::Link(link_patch) => link_patch.change_count(),
+            Self::Changed(_) => ChangeStats::files_written(1),
         }
     }
+
+    fn added_count(_data: &Self::Data) -> ChangeStats {
+        ChangeStats::files_written(1)
+    }
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "kebab-case")]
 struct DesktopFileCfgPatch {
-    id: u64,
+    id: FileName,
     cfg: CfgPatch,
 }
 
 impl Patch for DesktopFileCfgPatch {
-    type Data = (u64, Cfg);
+    type Data = (FileName, Cfg);
 
     fn diff(old: Self::Data, new: Self::Data) -> Self {
         Self {
@@ -478,20 +1290,24 @@ impl Patch for DesktopFileCfgPatch {
         }
     }
 
-    fn is_empty(&self) -> bool {
-        self.cfg.is_empty()
+    fn change_count(&self) -> ChangeStats {
+        self.cfg.change_count().in_file()
+    }
+
+    fn added_count(_data: &Self::Data) -> ChangeStats {
+        ChangeStats::files_written(1)
     }
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "kebab-case")]
 struct LinkPatch {
-    id: u64,
+    id: FileName,
     path: Option<PathBuf>,
 }
 
 impl Patch for LinkPatch {
-    type Data = (u64, Link);
+    type Data = (FileName, Link);
 
     fn diff(old: Self::Data, new: Self::Data) -> Self {
         Self {
@@ -500,8 +1316,12 @@ impl Patch for LinkPatch {
         }
     }
 
-    fn is_empty(&self) -> bool {
-        self.path.is_none()
+    fn change_count(&self) -> ChangeStats {
+        ChangeStats::files_written(usize::from(self.path.is_some()))
+    }
+
+    fn added_count(_data: &Self::Data) -> ChangeStats {
+        ChangeStats::files_written(1)
     }
 }
 
@@ -509,6 +1329,8 @@ pub struct Applier<'a> {
     dry_run: bool,
     patch_recorder: &'a mut PatchRecorder,
     dir: Cow<'a, Path>,
+    symlinks: SymlinkPolicy,
+    external: &'a ExternallyManaged,
 }
 
 impl<'a> Applier<'a> {
@@ -516,11 +1338,15 @@ impl<'a> Applier<'a> {
         dry_run: bool,
         patch_recorder: &'a mut PatchRecorder,
         dir: Cow<'a, Path>,
+        symlinks: SymlinkPolicy,
+        external: &'a ExternallyManaged,
     ) -> Self {
         Self {
             dry_run,
             patch_recorder,
             dir,
+            symlinks,
+            external,
         }
     }
 
@@ -541,12 +1367,9 @@ impl<'a> Applier<'a> {
     fn desktop_file_path(
         &self,
         plugin_id: &PluginId,
-        desktop_id: u64,
+        name: &FileName,
     ) -> PathBuf {
-        self.dir.join(format!(
-            "{}-{}/{}.desktop",
-            plugin_id.r#type, plugin_id.id, desktop_id
-        ))
+        self.desktop_dir_path(plugin_id).join(&name.0)
     }
 
     fn rc_cfg_applier(&mut self, plugin_id: &PluginId) -> CfgApplier<'_> {
@@ -554,18 +1377,22 @@ impl<'a> Applier<'a> {
             self.dry_run,
             self.patch_recorder,
             self.rc_file_path(plugin_id).into(),
+            self.symlinks,
+            self.external,
         )
     }
 
     fn desktop_cfg_applier(
         &mut self,
         plugin_id: &PluginId,
-        desktop_id: u64,
+        name: &FileName,
     ) -> CfgApplier<'_> {
         CfgApplier::new(
             self.dry_run,
             self.patch_recorder,
-            self.desktop_file_path(plugin_id, desktop_id).into(),
+            self.desktop_file_path(plugin_id, name).into(),
+            self.symlinks,
+            self.external,
         )
     }
 
@@ -573,20 +1400,34 @@ impl<'a> Applier<'a> {
         let rc_file_path = self.rc_file_path(plugin_id);
         let desktop_dir_path = self.desktop_dir_path(plugin_id);
         if rc_file_path.is_file() {
+            if self.external.skip_write(&rc_file_path) {
+                return Ok(());
+            }
             self.log(PatchEvent::RemovePluginRcFile {
                 path: &rc_file_path,
             })
             .context("error logging remove plugin RC file")?;
+            self.patch_recorder
+                .log_file_inverse(&rc_file_path)
+                .context("error logging plugin RC file inverse")?;
             if !self.dry_run {
+                self.patch_recorder.back_up(&rc_file_path)?;
                 fs::remove_file(rc_file_path)
                     .context("error removing RC file")?;
             }
         } else if desktop_dir_path.is_dir() {
+            if self.external.skip_write(&desktop_dir_path) {
+                return Ok(());
+            }
             self.log(PatchEvent::RemovePluginDesktopDir {
                 path: &desktop_dir_path,
             })
             .context("error logging remove plugin desktop dir")?;
+            self.patch_recorder
+                .log_removed_dir_inverse(&desktop_dir_path)
+                .context("error logging plugin desktop dir inverse")?;
             if !self.dry_run {
+                self.patch_recorder.back_up(&desktop_dir_path)?;
                 fs::remove_dir_all(desktop_dir_path)
                     .context("error removing desktop dir")?;
             }
@@ -596,10 +1437,35 @@ impl<'a> Applier<'a> {
         Ok(())
     }
 
+    // creates the panel dir and the ones above it, which a fresh account may
+    // not have, only readable by the user like the XDG base dirs
+    fn create_dir(&mut self) -> Result<()> {
+        if self.dir.is_dir() || self.external.skip_write(&self.dir) {
+            return Ok(());
+        }
+        let path = self.dir.clone();
+        self.log(PatchEvent::CreatePanelDir { path: &path })
+            .context("error logging create panel dir")?;
+        if !self.dry_run {
+            let mut builder = fs::DirBuilder::new();
+            builder.recursive(true);
+            #[cfg(unix)]
+            std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+            builder.create(&path).context("error creating panel dir")?;
+        }
+        Ok(())
+    }
+
     fn create_desktop_dir(&mut self, plugin_id: &PluginId) -> Result<()> {
         let path = self.desktop_dir_path(plugin_id);
+        if self.external.skip_write(&path) {
+            return Ok(());
+        }
         self.log(PatchEvent::CreateDesktopDir { path: &path })
             .context("error logging create desktop dir")?;
+        self.patch_recorder
+            .log_dir_inverse(&path, false)
+            .context("error logging create desktop dir inverse")?;
         if !self.dry_run {
             fs::create_dir(path).context("error creating desktop dir")?;
         }
@@ -609,15 +1475,32 @@ impl<'a> Applier<'a> {
     fn link_desktop_file(
         &mut self,
         plugin_id: &PluginId,
-        desktop_id: u64,
+        name: &FileName,
         target_path: &Path,
     ) -> Result<()> {
-        let path = self.desktop_file_path(plugin_id, desktop_id);
+        let path = self.desktop_file_path(plugin_id, name);
+        if self.external.skip_write(&path) {
+            return Ok(());
+        }
         self.log(PatchEvent::LinkDesktopFile {
             path: &path,
             target_path,
         })
         .context("error logging link desktop file")?;
+        self.patch_recorder
+            .log_entry_inverse(&path)
+            .context("error logging link desktop file inverse")?;
+        let target = match path.parent() {
+            Some(dir) => dir.join(target_path),
+            None => target_path.to_owned(),
+        };
+        if !target.exists() {
+            crate::warn(format_args!(
+                "linking {} to missing {}",
+                path.display(),
+                target_path.display()
+            ));
+        }
         if !self.dry_run {
             {
                 cfg_if! {
@@ -634,15 +1517,58 @@ impl<'a> Applier<'a> {
         Ok(())
     }
 
+    fn rename_desktop_file(
+        &mut self,
+        plugin_id: &PluginId,
+        old_name: &FileName,
+        new_name: &FileName,
+    ) -> Result<()> {
+        let old_path = self.desktop_file_path(plugin_id, old_name);
+        let new_path = self.desktop_file_path(plugin_id, new_name);
+        if self.external.skip_write(&old_path)
+            || self.external.skip_write(&new_path)
+        {
+            return Ok(());
+        }
+        self.log(PatchEvent::RenameDesktopFile {
+            old_path: &old_path,
+            new_path: &new_path,
+        })
+        .context("error logging rename desktop file")?;
+        // a file renamed over comes back once the rename is undone
+        if fs::symlink_metadata(&new_path).is_ok() {
+            self.patch_recorder
+                .log_entry_inverse(&new_path)
+                .context("error logging replaced desktop file inverse")?;
+        }
+        self.patch_recorder
+            .log_rename_inverse(&old_path, &new_path)
+            .context("error logging rename desktop file inverse")?;
+        if !self.dry_run {
+            self.patch_recorder.back_up(&old_path)?;
+            self.patch_recorder.back_up(&new_path)?;
+            fs::rename(old_path, new_path)
+                .context("error renaming desktop file")?;
+        }
+        Ok(())
+    }
+
     fn remove_desktop_file(
         &mut self,
         plugin_id: &PluginId,
-        desktop_id: u64,
+        name: &FileName,
     ) -> Result<()> {
-        let path = self.desktop_file_path(plugin_id, desktop_id);
+        let path = self.desktop_file_path(plugin_id, name);
+        if self.external.skip_write(&path) {
+            return Ok(());
+        }
         self.log(PatchEvent::RemoveDesktopFile { path: &path })
             .context("error logging remove desktop file")?;
+        self.patch_recorder
+            .log_entry_inverse(&path)
+            .context("error logging remove desktop file inverse")?;
         if !self.dry_run {
+            self.patch_recorder.back_up(&path)?;
             fs::remove_file(path).context("error removing desktop file")?;
         }
         Ok(())
@@ -652,6 +1578,8 @@ impl<'a> Applier<'a> {
 #[derive(Serialize)]
 #[serde(tag = "type", rename_all = "kebab-case")]
 pub enum PatchEvent<'a> {
+    #[serde(rename_all = "kebab-case")]
+    CreatePanelDir { path: &'a Path },
     #[serde(rename_all = "kebab-case")]
     RemovePluginRcFile { path: &'a Path },
     #[serde(rename_all = "kebab-case")]
@@ -664,16 +1592,44 @@ pub enum PatchEvent<'a> {
         target_path: &'a Path,
     },
     #[serde(rename_all = "kebab-case")]
+    RenameDesktopFile {
+        old_path: &'a Path,
+        new_path: &'a Path,
+    },
+    #[serde(rename_all = "kebab-case")]
     RemoveDesktopFile { path: &'a Path },
 }
 
 impl PanelPatch {
     pub fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
+        if !self.is_empty() {
+            applier.create_dir()?;
+        }
         self.plugin_configs.apply(applier)?;
         Ok(())
     }
 }
 
+impl SectionPatch for PanelPatch {
+    fn change_count(&self) -> ChangeStats {
+        self.plugin_configs.change_count()
+    }
+
+    fn changes_panel(&self) -> bool {
+        !self.is_empty()
+    }
+
+    fn apply(self: Box<Self>, applier: &mut crate::Applier<'_>) -> Result<()> {
+        (*self).apply(&mut Applier::new(
+            applier.dry_run,
+            &mut applier.patch_recorder,
+            applier.dirs.xfce4_config_dir.join("panel").into(),
+            applier.symlinks.panel,
+            &applier.external,
+        ))
+    }
+}
+
 impl PluginConfigsPatch {
     fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
         for plugin_config_patch in self.0.changed.into_values() {
@@ -714,16 +1670,34 @@ impl DesktopFile {
     ) -> Result<()> {
         match self.content {
             DesktopFileContent::Cfg(cfg) => {
-                cfg.apply(&mut applier.desktop_cfg_applier(plugin_id, self.id))
+                cfg.apply(&mut applier.desktop_cfg_applier(plugin_id, &self.id))
             },
             DesktopFileContent::Link(link) => {
-                applier.link_desktop_file(plugin_id, self.id, &*link.path)
+                applier.link_desktop_file(plugin_id, &self.id, &*link.path)
+            },
+            DesktopFileContent::App(app) => {
+                bail!("desktop id {} was never resolved", app.desktop_id)
             },
         }
     }
 }
 
 impl PluginConfigPatch {
+    fn apply_to_old(self, old: &mut PluginConfig) {
+        match (self, &mut old.file) {
+            (Self::Rc(rc_patch), PluginConfigFile::Rc(cfg)) => {
+                rc_patch.cfg.apply_to_old(cfg)
+            },
+            (
+                Self::DesktopDir(desktop_dir_patch),
+                PluginConfigFile::DesktopDir(desktop_dir),
+            ) => desktop_dir_patch.apply_to_old(desktop_dir),
+            (Self::Changed(plugin_config), _) => *old = plugin_config,
+            // patches only come from diffing the same kind of file
+            _ => {},
+        }
+    }
+
     fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
         match self {
             Self::Rc(rc_patch) => rc_patch.apply(applier),
@@ -747,7 +1721,21 @@ impl RcPatch {
 }
 
 impl DesktopDirPatch {
+    fn apply_to_old(self, old: &mut DesktopDir) {
+        for (old_id, new_id) in self.renamed {
+            if let Some(mut file) = old.files.0.remove(&old_id) {
+                file.id = new_id.clone();
+                old.files.0.insert(new_id, file);
+            }
+        }
+        self.files
+            .apply_to_old(&mut old.files.0, DesktopFilePatch::apply_to_old);
+    }
+
     fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
+        for (old_id, new_id) in self.renamed {
+            applier.rename_desktop_file(&self.id, &old_id, &new_id)?;
+        }
         for desktop_file_patch in self.files.changed.into_values() {
             desktop_file_patch.apply(applier, &self.id)?;
         }
@@ -755,13 +1743,29 @@ impl DesktopDirPatch {
             desktop_file.apply(applier, &self.id)?;
         }
         for id in self.files.removed {
-            applier.remove_desktop_file(&self.id, id)?;
+            applier.remove_desktop_file(&self.id, &id)?;
         }
         Ok(())
     }
 }
 
 impl DesktopFilePatch {
+    fn apply_to_old(self, old: &mut DesktopFile) {
+        match (self, &mut old.content) {
+            (Self::Cfg(cfg_patch), DesktopFileContent::Cfg(cfg)) => {
+                cfg_patch.cfg.apply_to_old(cfg)
+            },
+            (Self::Link(link_patch), DesktopFileContent::Link(link)) => {
+                if let Some(path) = link_patch.path {
+                    link.path = path;
+                }
+            },
+            (Self::Changed(desktop_file), _) => *old = desktop_file,
+            // patches only come from diffing the same kind of content
+            _ => {},
+        }
+    }
+
     fn apply(
         self,
         applier: &mut Applier<'_>,
@@ -773,6 +1777,9 @@ impl DesktopFilePatch {
             },
             Self::Link(link_patch) => link_patch.apply(applier, plugin_id),
             Self::Changed(desktop_file) => {
+                // links are part of the config rather than something to
+                // write through, so the old file always goes
+                applier.remove_desktop_file(plugin_id, &desktop_file.id)?;
                 desktop_file.apply(applier, plugin_id)
             },
         }
@@ -786,7 +1793,7 @@ impl DesktopFileCfgPatch {
         plugin_id: &PluginId,
     ) -> Result<()> {
         self.cfg
-            .apply(&mut applier.desktop_cfg_applier(plugin_id, self.id))
+            .apply(&mut applier.desktop_cfg_applier(plugin_id, &self.id))
     }
 }
 
@@ -797,9 +1804,144 @@ impl LinkPatch {
         plugin_id: &PluginId,
     ) -> Result<()> {
         if let Some(path) = self.path {
-            applier.remove_desktop_file(plugin_id, self.id)?;
-            applier.link_desktop_file(plugin_id, self.id, &*path)?;
+            applier.remove_desktop_file(plugin_id, &self.id)?;
+            applier.link_desktop_file(plugin_id, &self.id, &*path)?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_missing_dir() {
+        let dir = std::env::temp_dir()
+            .join(format!("xfce-config-missing-{}", std::process::id()))
+            .join("panel");
+        let mut skipped = Vec::new();
+        let plugin_configs = PluginConfigs::read(&dir, &mut skipped).unwrap();
+        assert!(plugin_configs.0.is_empty());
+        assert!(skipped.is_empty());
+    }
+
+    #[test]
+    fn read_file_names() {
+        let temp_dir = std::env::temp_dir()
+            .join(format!("xfce-config-file-names-{}", std::process::id()));
+        let dir = temp_dir.join("panel");
+        let launcher = dir.join("launcher-2");
+        let places = dir.join("places-5");
+        fs::create_dir_all(&launcher).unwrap();
+        fs::create_dir_all(&places).unwrap();
+        fs::write(launcher.join("1.desktop"), "[Desktop Entry]\nName=a\n")
+            .unwrap();
+        fs::write(launcher.join("notes"), "").unwrap();
+        fs::write(places.join("home.desktop"), "[Desktop Entry]\nName=b\n")
+            .unwrap();
+        fs::write(places.join("state"), "opaque\n").unwrap();
+        let mut skipped = Vec::new();
+        let plugin_configs = PluginConfigs::read(&dir, &mut skipped).unwrap();
+        let names = |r#type: &str, id| {
+            let plugin_id = PluginId {
+                r#type: r#type.to_owned(),
+                id,
+            };
+            match &(plugin_configs.0).0[&plugin_id].file {
+                PluginConfigFile::DesktopDir(desktop_dir) => desktop_dir
+                    .files
+                    .0
+                    .keys()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>(),
+                PluginConfigFile::Rc(_) => panic!("not a desktop dir"),
+            }
+        };
+        assert_eq!(names("launcher", 2), ["1.desktop"]);
+        assert_eq!(names("places", 5), ["home.desktop"]);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn check_file_names() {
+        let panel = |r#type: &str, id: serde_json::Value| {
+            serde_json::from_value::<Panel>(serde_json::json!({
+                "plugin-configs": [{
+                    "plugin": { "type": r#type, "id": 1 },
+                    "file": {
+                        "type": "desktop-dir",
+                        "files": [{
+                            "id": id,
+                            "content": { "type": "link", "path": "a" },
+                        }],
+                    },
+                }],
+            }))
+            .unwrap()
+        };
+        assert!(panel("launcher", 3.into()).check_file_names().is_ok());
+        assert!(panel("launcher", "a.desktop".into())
+            .check_file_names()
+            .is_err());
+        assert!(panel("places", "a.desktop".into())
+            .check_file_names()
+            .is_ok());
+    }
+
+    #[test]
+    fn skip_missing_favorites() {
+        let temp_dir = std::env::temp_dir()
+            .join(format!("xfce-config-favorites-{}", std::process::id()));
+        let data_dir = temp_dir.join("data");
+        let applications = data_dir.join("applications/org.gnome");
+        fs::create_dir_all(&applications).unwrap();
+        fs::write(applications.join("Nautilus.desktop"), "").unwrap();
+        let mut rc = Cfg::default();
+        rc.root.insert(
+            "favorites".to_owned(),
+            "org.gnome-Nautilus.desktop,gone.desktop".to_owned(),
+        );
+        skip_missing_favorites_of(
+            &mut rc,
+            &DesktopIds::read(&[data_dir]).unwrap(),
+            &PluginId {
+                r#type: "whiskermenu".to_owned(),
+                id: 1,
+            },
+        );
+        assert_eq!(rc.root["favorites"], "org.gnome-Nautilus.desktop");
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn desktop_ids() {
+        let temp_dir = std::env::temp_dir()
+            .join(format!("xfce-config-desktop-ids-{}", std::process::id()));
+        let first = temp_dir.join("first/applications");
+        let second = temp_dir.join("second/applications");
+        fs::create_dir_all(first.join("org.kde")).unwrap();
+        fs::create_dir_all(second.join("kde4-foo")).unwrap();
+        fs::write(first.join("org.kde/dolphin-x.desktop"), "").unwrap();
+        fs::write(first.join("kde4-foo-bar-baz.desktop"), "").unwrap();
+        fs::write(second.join("kde4-foo/bar-baz.desktop"), "").unwrap();
+        fs::write(second.join("kde4-foo/notes.txt"), "").unwrap();
+        let ids = DesktopIds::read(&[
+            temp_dir.join("first"),
+            temp_dir.join("second"),
+            temp_dir.join("missing"),
+        ])
+        .unwrap();
+        assert_eq!(
+            ids.find("org.kde-dolphin-x.desktop"),
+            Some(&*first.join("org.kde/dolphin-x.desktop"))
+        );
+        // the first data dir's file takes precedence
+        assert_eq!(
+            ids.find("kde4-foo-bar-baz.desktop"),
+            Some(&*first.join("kde4-foo-bar-baz.desktop"))
+        );
+        assert_eq!(ids.find("kde4-foo-notes.txt"), None);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
@@ -0,0 +1,717 @@
+use crate::{
+    panel::Panel as PanelSection,
+    schema,
+    xfconf::{Xfconf, VALUE_DEF},
+};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+const CHANNEL: &str = "xfce4-panel";
+
+// panels compiled into the /panels array of the panel channel and the props of
+// each /panels/panel-N, panels and plugins without an id get the lowest free
+// ones in order, plugins then the ones they already have once the environment
+// is read
+#[derive(Debug, Deserialize)]
+#[serde(transparent)]
+pub struct Panels(Vec<Panel>);
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Panel {
+    #[serde(default)]
+    id: Option<u32>,
+    #[serde(default)]
+    display: Display,
+    #[serde(default)]
+    measurements: Measurements,
+    // the plugins on the panel in order, by their plugin-N ids
+    #[serde(default)]
+    plugin_ids: Option<Vec<i32>>,
+    // the plugins on the panel in order, declared here instead
+    #[serde(default)]
+    plugins: Option<Vec<Plugin>>,
+}
+
+// compiled into /plugins/plugin-N, and the plugin's RC file in the panel
+// section if it has one
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Plugin {
+    // like clock or launcher
+    #[serde(rename = "type")]
+    plugin_type: String,
+    #[serde(default)]
+    id: Option<u32>,
+    // props under /plugins/plugin-N, like the props of an xfconf channel
+    #[serde(default)]
+    props: serde_json::Map<String, serde_json::Value>,
+    // keys of the RC file, by section for plugin types which read sections
+    #[serde(default)]
+    rc: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Display {
+    #[serde(default)]
+    mode: Option<Mode>,
+    #[serde(default)]
+    position: Option<Position>,
+    #[serde(default)]
+    locked: Option<bool>,
+    // the monitor by output name, the primary one if not given
+    #[serde(default)]
+    output: Option<String>,
+    #[serde(default)]
+    span_monitors: Option<bool>,
+    #[serde(default)]
+    autohide: Option<Autohide>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Mode {
+    Horizontal,
+    Vertical,
+    Deskbar,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Autohide {
+    Never,
+    Intelligently,
+    Always,
+}
+
+// where the panel snaps to on its monitor, offset by x and y
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Position {
+    snap: Snap,
+    #[serde(default)]
+    x: i32,
+    #[serde(default)]
+    y: i32,
+}
+
+// numbered like the panel's own snap positions
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Snap {
+    None = 0,
+    Right = 1,
+    TopRight = 2,
+    RightCenter = 3,
+    BottomRight = 4,
+    Left = 5,
+    TopLeft = 6,
+    LeftCenter = 7,
+    BottomLeft = 8,
+    TopCenter = 9,
+    BottomCenter = 10,
+    Top = 11,
+    Bottom = 12,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Measurements {
+    // in pixels
+    #[serde(default)]
+    size: Option<u32>,
+    // percent of the monitor
+    #[serde(default)]
+    length: Option<u32>,
+    #[serde(default)]
+    length_adjust: Option<bool>,
+    // in pixels, 0 adjusts it to the size
+    #[serde(default)]
+    icon_size: Option<u32>,
+    #[serde(default)]
+    rows: Option<u32>,
+}
+
+impl Panels {
+    pub fn schema() -> serde_json::Value {
+        let snaps = [
+            "none",
+            "right",
+            "top-right",
+            "right-center",
+            "bottom-right",
+            "left",
+            "top-left",
+            "left-center",
+            "bottom-left",
+            "top-center",
+            "bottom-center",
+            "top",
+            "bottom",
+        ];
+        let position = schema::strict(schema::required(
+            schema::object(vec![
+                ("snap", schema::string_enum(&snaps)),
+                ("x", schema::integer()),
+                ("y", schema::integer()),
+            ]),
+            &["snap"],
+        ));
+        let display = schema::strict(schema::object(vec![
+            (
+                "mode",
+                schema::string_enum(&["horizontal", "vertical", "deskbar"]),
+            ),
+            ("position", position),
+            ("locked", schema::boolean()),
+            ("output", schema::string()),
+            ("span-monitors", schema::boolean()),
+            (
+                "autohide",
+                schema::string_enum(&["never", "intelligently", "always"]),
+            ),
+        ]));
+        let measurements = schema::strict(schema::object(vec![
+            ("size", schema::integer()),
+            ("length", schema::integer()),
+            ("length-adjust", schema::boolean()),
+            ("icon-size", schema::integer()),
+            ("rows", schema::integer()),
+        ]));
+        let plugin = schema::strict(schema::required(
+            schema::object(vec![
+                ("type", schema::string()),
+                ("id", schema::integer()),
+                ("props", schema::map(schema::reference(VALUE_DEF), false)),
+                (
+                    "rc",
+                    schema::map(
+                        schema::any_of(vec![
+                            schema::string(),
+                            schema::map(schema::string(), true),
+                        ]),
+                        true,
+                    ),
+                ),
+            ]),
+            &["type"],
+        ));
+        schema::array(schema::strict(schema::object(vec![
+            ("id", schema::integer()),
+            ("display", display),
+            ("measurements", measurements),
+            ("plugin-ids", schema::array(schema::integer())),
+            ("plugins", schema::array(plugin)),
+        ])))
+    }
+
+    pub fn compile(
+        self,
+        xfconf: &mut Xfconf,
+        section: &mut PanelSection,
+    ) -> Result<()> {
+        let ids = self.ids()?;
+        let mut plugin_ids = self.plugin_ids()?.into_iter();
+        xfconf.set_int_array(
+            CHANNEL,
+            "/panels",
+            ids.iter().map(|&id| id as i32).collect(),
+        );
+        for (mut panel, id) in self.0.into_iter().zip(ids) {
+            if let Some(plugins) = panel.plugins.take() {
+                if panel.plugin_ids.is_some() {
+                    bail!("panel {} has both plugins and plugin-ids", id);
+                }
+                let mut ids = Vec::new();
+                for (position, plugin) in plugins.into_iter().enumerate() {
+                    let plugin_id =
+                        plugin_ids.next().unwrap_or_default() as i32;
+                    if plugin.id.is_none() {
+                        xfconf.assign_plugin(AssignedPlugin {
+                            panel: id,
+                            position,
+                            plugin_type: plugin.plugin_type.clone(),
+                            id: plugin_id as u32,
+                        });
+                    }
+                    plugin.compile(xfconf, section, plugin_id).with_context(
+                        || format!("error compiling plugin {}", plugin_id),
+                    )?;
+                    ids.push(plugin_id);
+                }
+                panel.plugin_ids = Some(ids);
+            }
+            panel.compile(xfconf, id)?;
+        }
+        Ok(())
+    }
+
+    fn ids(&self) -> Result<Vec<u32>> {
+        allocate(
+            self.0.iter().map(|panel| panel.id),
+            BTreeSet::new(),
+            "panel",
+        )
+    }
+
+    // ids of the declared plugins of all panels in order, the ones other
+    // panels list in plugin-ids are taken
+    fn plugin_ids(&self) -> Result<Vec<u32>> {
+        let listed = self
+            .0
+            .iter()
+            .flat_map(|panel| panel.plugin_ids.iter().flatten())
+            .filter_map(|&id| u32::try_from(id).ok())
+            .collect();
+        allocate(
+            self.0
+                .iter()
+                .flat_map(|panel| panel.plugins.iter().flatten())
+                .map(|plugin| plugin.id),
+            listed,
+            "plugin",
+        )
+    }
+}
+
+// a plugin whose id was picked for it, which can still be swapped for the id
+// the same plugin has in the environment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct AssignedPlugin {
+    panel: u32,
+    // in the plugin-ids of the panel
+    position: usize,
+    plugin_type: String,
+    id: u32,
+}
+
+// gives plugins with picked ids the ids the same plugins have in old instead,
+// matched by panel, type and position, or only panel and type if they moved,
+// so their other props aren't reset by renumbering them. the ones without a
+// match get ids which are free in old too, so they don't inherit the props
+// of some removed plugin. returns the type, picked id and new id of the
+// plugins whose ids changed
+pub(crate) fn reuse_plugin_ids(
+    new: &mut Xfconf,
+    old: &Xfconf,
+) -> Vec<(String, u32, u32)> {
+    let assigned = new.take_assigned_plugins();
+    if assigned.is_empty() {
+        return Vec::new();
+    }
+    let assigned_ids = assigned
+        .iter()
+        .map(|plugin| plugin.id)
+        .collect::<BTreeSet<_>>();
+    let listed = new
+        .get_int_array(CHANNEL, "/panels")
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|panel| {
+            new.get_int_array(
+                CHANNEL,
+                &format!("/panels/panel-{}/plugin-ids", panel),
+            )
+            .unwrap_or_default()
+        })
+        .filter_map(|id| u32::try_from(id).ok())
+        .collect::<Vec<_>>();
+    let mut taken = new
+        .panel_plugins()
+        .into_keys()
+        .chain(listed)
+        .filter(|id| !assigned_ids.contains(id))
+        .collect::<BTreeSet<_>>();
+    let old_plugins = old.panel_plugins();
+    let old_panel = |panel: u32| {
+        old.get_int_array(
+            CHANNEL,
+            &format!("/panels/panel-{}/plugin-ids", panel),
+        )
+        .unwrap_or_default()
+    };
+
+    let mut ids = vec![None; assigned.len()];
+    for (i, plugin) in assigned.iter().enumerate() {
+        let old_id = old_panel(plugin.panel)
+            .get(plugin.position)
+            .map(|&id| id as u32)
+            .filter(|id| {
+                old_plugins.get(id) == Some(&&*plugin.plugin_type)
+                    && !taken.contains(id)
+            });
+        if let Some(old_id) = old_id {
+            taken.insert(old_id);
+            ids[i] = Some(old_id);
+        }
+    }
+    for (i, plugin) in assigned.iter().enumerate() {
+        if ids[i].is_some() {
+            continue;
+        }
+        let old_id = old_panel(plugin.panel)
+            .into_iter()
+            .map(|id| id as u32)
+            .find(|id| {
+                old_plugins.get(id) == Some(&&*plugin.plugin_type)
+                    && !taken.contains(id)
+            });
+        if let Some(old_id) = old_id {
+            taken.insert(old_id);
+            ids[i] = Some(old_id);
+        }
+    }
+    let mut next = 1;
+    for id in &mut ids {
+        if id.is_none() {
+            while taken.contains(&next) || old_plugins.contains_key(&next) {
+                next += 1;
+            }
+            taken.insert(next);
+            *id = Some(next);
+        }
+    }
+
+    let mut moves = BTreeMap::new();
+    let mut moved = Vec::new();
+    let mut panels = BTreeMap::<u32, Vec<(usize, u32)>>::new();
+    for (plugin, id) in assigned.iter().zip(ids.into_iter().flatten()) {
+        if id != plugin.id {
+            moves.insert(
+                format!("/plugins/plugin-{}", plugin.id),
+                format!("/plugins/plugin-{}", id),
+            );
+            moved.push((plugin.plugin_type.clone(), plugin.id, id));
+        }
+        panels
+            .entry(plugin.panel)
+            .or_default()
+            .push((plugin.position, id));
+    }
+    if moves.is_empty() {
+        return moved;
+    }
+    new.move_props(CHANNEL, &moves);
+    for (panel, positions) in panels {
+        let path = format!("/panels/panel-{}/plugin-ids", panel);
+        if let Some(mut plugin_ids) = new.get_int_array(CHANNEL, &path) {
+            for (position, id) in positions {
+                if let Some(plugin_id) = plugin_ids.get_mut(position) {
+                    *plugin_id = id as i32;
+                }
+            }
+            new.set_int_array(CHANNEL, &path, plugin_ids);
+        }
+    }
+    moved
+}
+
+// the given ids, and the lowest free ones starting at 1 for the rest in order,
+// none of them taken already
+fn allocate(
+    requested: impl Iterator<Item = Option<u32>> + Clone,
+    mut taken: BTreeSet<u32>,
+    what: &str,
+) -> Result<Vec<u32>> {
+    for id in requested.clone().flatten() {
+        if id == 0 {
+            bail!("{} ids start at 1", what);
+        }
+        if !taken.insert(id) {
+            bail!("{} id {} is used twice", what, id);
+        }
+    }
+    let mut next = 1;
+    let mut ids = Vec::new();
+    for id in requested {
+        let id = match id {
+            Some(id) => id,
+            None => {
+                while taken.contains(&next) {
+                    next += 1;
+                }
+                taken.insert(next);
+                next
+            },
+        };
+        ids.push(id);
+    }
+    Ok(ids)
+}
+
+impl Panel {
+    fn compile(self, xfconf: &mut Xfconf, id: u32) -> Result<()> {
+        let Self {
+            id: _,
+            display,
+            measurements,
+            plugin_ids,
+            plugins: _,
+        } = self;
+        let prop = |prop: &str| format!("/panels/panel-{}/{}", id, prop);
+
+        if let Some(length) = measurements.length {
+            if length > 100 {
+                bail!("length of panel {} must be a percentage", id);
+            }
+        }
+        if let Some(rows) = measurements.rows {
+            if rows < 1 {
+                bail!("panel {} needs at least 1 row", id);
+            }
+        }
+        let uints = [
+            ("mode", display.mode.map(|mode| mode as u32)),
+            (
+                "autohide-behavior",
+                display.autohide.map(|autohide| autohide as u32),
+            ),
+            ("size", measurements.size),
+            ("length", measurements.length),
+            ("icon-size", measurements.icon_size),
+            ("nrows", measurements.rows),
+        ];
+        for (name, value) in uints {
+            if let Some(value) = value {
+                xfconf.set_uint(CHANNEL, &prop(name), value);
+            }
+        }
+
+        let bools = [
+            ("position-locked", display.locked),
+            ("span-monitors", display.span_monitors),
+            ("length-adjust", measurements.length_adjust),
+        ];
+        for (name, value) in bools {
+            if let Some(value) = value {
+                xfconf.set_bool(CHANNEL, &prop(name), value);
+            }
+        }
+
+        if let Some(position) = display.position {
+            xfconf.set_string(
+                CHANNEL,
+                &prop("position"),
+                format!(
+                    "p={};x={};y={}",
+                    position.snap as u32, position.x, position.y
+                ),
+            );
+        }
+        if let Some(output) = display.output {
+            xfconf.set_string(CHANNEL, &prop("output-name"), output);
+        }
+        if let Some(plugin_ids) = plugin_ids {
+            xfconf.set_int_array(CHANNEL, &prop("plugin-ids"), plugin_ids);
+        }
+        Ok(())
+    }
+}
+
+impl Plugin {
+    fn compile(
+        self,
+        xfconf: &mut Xfconf,
+        panel: &mut PanelSection,
+        id: i32,
+    ) -> Result<()> {
+        if let Some(rc) = self.rc {
+            panel.declare_rc(&self.plugin_type, id as u32, rc)?;
+        }
+        let path = format!("/plugins/plugin-{}", id);
+        xfconf.set_string(CHANNEL, &path, self.plugin_type);
+        for (name, value) in self.props {
+            xfconf
+                .set_json(CHANNEL, &format!("{}/{}", path, name), value)
+                .with_context(|| format!("invalid prop {}", name))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn compile() {
+        let panels = serde_json::from_value::<Panels>(json!([
+            {
+                "display": {
+                    "position": { "snap": "top-left" },
+                    "output": "HDMI-0",
+                },
+                "measurements": { "size": 26, "length": 100 },
+                "plugin-ids": [2, 3],
+            },
+            {
+                "id": 1,
+                "display": { "span-monitors": true },
+                "plugins": [
+                    {
+                        "type": "whiskermenu",
+                        "rc": { "button-title": "Menu" },
+                    },
+                    {
+                        "type": "clock",
+                        "id": 1,
+                        "props": { "digital-format": "%R" },
+                    },
+                ],
+            },
+        ]))
+        .unwrap();
+        let mut xfconf = Xfconf::default();
+        let mut panel = PanelSection::default();
+        panels.compile(&mut xfconf, &mut panel).unwrap();
+        assert_eq!(
+            xfconf.get_string(CHANNEL, "/panels/panel-2/position"),
+            Some("p=6;x=0;y=0")
+        );
+        assert_eq!(
+            xfconf.get_string(CHANNEL, "/panels/panel-2/output-name"),
+            Some("HDMI-0")
+        );
+        assert_eq!(
+            xfconf.get_bool(CHANNEL, "/panels/panel-1/span-monitors"),
+            Some(true)
+        );
+        // the ids panel-2 lists are taken
+        assert_eq!(
+            xfconf.get_string(CHANNEL, "/plugins/plugin-4"),
+            Some("whiskermenu")
+        );
+        assert_eq!(
+            xfconf.get_int_array(CHANNEL, "/panels/panel-1/plugin-ids"),
+            Some(vec![4, 1])
+        );
+        assert_eq!(
+            xfconf.get_string(CHANNEL, "/plugins/plugin-1/digital-format"),
+            Some("%R")
+        );
+        assert_eq!(
+            serde_json::to_value(&panel).unwrap(),
+            json!({
+                "plugin-configs": [{
+                    "plugin": "whiskermenu-4",
+                    "file": {
+                        "type": "rc",
+                        "root": { "button-title": "Menu" },
+                    },
+                }],
+            })
+        );
+
+        let compile = |panels| {
+            serde_json::from_value::<Panels>(panels)
+                .unwrap()
+                .compile(&mut Xfconf::default(), &mut PanelSection::default())
+        };
+        assert!(compile(json!([{ "id": 2 }, { "id": 2 }])).is_err());
+        assert!(compile(json!([
+            { "plugin-ids": [1] },
+            { "plugins": [{ "type": "clock", "id": 1 }] },
+        ]))
+        .is_err());
+        // sensors reads sections, clock has no known layout
+        assert!(compile(json!([{
+            "plugins": [{ "type": "sensors", "rc": { "key": "value" } }],
+        }]))
+        .is_err());
+        assert!(compile(json!([{
+            "plugins": [{ "type": "clock", "rc": { "key": "value" } }],
+        }]))
+        .is_err());
+    }
+
+    #[test]
+    fn snap_numbers() {
+        for (name, number) in [
+            ("none", 0),
+            ("right", 1),
+            ("top-right", 2),
+            ("right-center", 3),
+            ("bottom-right", 4),
+            ("left", 5),
+            ("top-left", 6),
+            ("left-center", 7),
+            ("bottom-left", 8),
+            ("top-center", 9),
+            ("bottom-center", 10),
+            ("top", 11),
+            ("bottom", 12),
+        ] {
+            let snap = serde_json::from_value::<Snap>(json!(name)).unwrap();
+            assert_eq!(snap as u32, number, "{}", name);
+        }
+        assert!(serde_json::from_value::<Snap>(json!("center")).is_err());
+    }
+
+    #[test]
+    fn reuse_plugin_ids() {
+        let panels = serde_json::from_value::<Panels>(json!([{
+            "plugins": [
+                { "type": "whiskermenu", "rc": { "button-title": "Menu" } },
+                { "type": "clock", "props": { "digital-format": "%R" } },
+                { "type": "separator" },
+            ],
+        }]))
+        .unwrap();
+        let mut new = Xfconf::default();
+        let mut panel = PanelSection::default();
+        panels.compile(&mut new, &mut panel).unwrap();
+        // as sent to a remote
+        let mut new = serde_json::from_value::<Xfconf>(
+            serde_json::to_value(new).unwrap(),
+        )
+        .unwrap();
+        let mut old = Xfconf::default();
+        old.set_int_array(CHANNEL, "/panels/panel-1/plugin-ids", vec![7, 3, 5]);
+        old.set_int_array(CHANNEL, "/panels/panel-2/plugin-ids", vec![1]);
+        for (id, plugin_type) in [
+            (7, "whiskermenu"),
+            (3, "tasklist"),
+            (5, "clock"),
+            (1, "separator"),
+        ] {
+            old.set_string(
+                CHANNEL,
+                &format!("/plugins/plugin-{}", id),
+                plugin_type.to_owned(),
+            );
+        }
+        let moved = super::reuse_plugin_ids(&mut new, &old);
+        assert_eq!(
+            moved,
+            vec![
+                ("whiskermenu".to_owned(), 1, 7),
+                ("clock".to_owned(), 2, 5),
+                ("separator".to_owned(), 3, 2),
+            ]
+        );
+        panel.move_plugins(&moved);
+        assert_eq!(
+            serde_json::to_value(&panel).unwrap()["plugin-configs"][0]
+                ["plugin"],
+            "whiskermenu-7"
+        );
+        assert_eq!(
+            new.get_int_array(CHANNEL, "/panels/panel-1/plugin-ids"),
+            Some(vec![7, 5, 2])
+        );
+        assert_eq!(
+            new.get_string(CHANNEL, "/plugins/plugin-5/digital-format"),
+            Some("%R")
+        );
+        assert_eq!(
+            new.get_string(CHANNEL, "/plugins/plugin-2"),
+            Some("separator")
+        );
+        assert_eq!(new.get_string(CHANNEL, "/plugins/plugin-1"), None);
+        assert_eq!(new.get_string(CHANNEL, "/plugins/plugin-3"), None);
+    }
+}
@@ -0,0 +1,279 @@
+use crate::{
+    json::{Json, JsonPatch},
+    schema,
+    section::{Section, SectionPatch},
+    serde::OrderedIdMap,
+    ChangeStats,
+    Dirs,
+    PatchRecorder,
+};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process,
+};
+
+// external executables managing config the built-in sections don't know about,
+// applied in the order they're given
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Plugins(OrderedIdMap<Plugin>);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Plugin {
+    name: String,
+    // run as `<command> read-state` and `<command> apply-patch`
+    command: PathBuf,
+    // opaque to everything but the plugin itself
+    #[serde(default)]
+    config: Json,
+}
+
+impl crate::serde::Id for Plugin {
+    type Id = String;
+
+    fn id(&self) -> &Self::Id {
+        &self.name
+    }
+}
+
+impl Plugins {
+    // makes relative commands relative to the given dir, bare command names
+    // are left to be looked up in PATH
+    pub fn resolve_commands(&mut self, dir: &Path) {
+        for plugin in self.0.iter_mut() {
+            if plugin.command.is_relative()
+                && plugin.command.components().count() > 1
+            {
+                plugin.command = dir.join(&plugin.command);
+            }
+        }
+    }
+
+    // plugin commands run from a path rather than looked up on PATH
+    pub fn local_files(&self) -> impl Iterator<Item = &Path> + '_ {
+        self.0
+            .iter()
+            .map(|plugin| &*plugin.command)
+            .filter(|command| command.components().count() > 1)
+    }
+}
+
+// runs the command with the input as JSON on stdin and returns its stdout
+fn run(
+    command: &Path,
+    action: &str,
+    input: &impl Serialize,
+) -> Result<Vec<u8>> {
+    let mut child = process::Command::new(command)
+        .arg(action)
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .spawn()
+        .with_context(|| format!("error running {}", command.display()))?;
+    let mut stdin = child.stdin.take().context("plugin has no stdin")?;
+    serde_json::to_writer(&mut stdin, input)
+        .context("error writing plugin input")?;
+    stdin.flush().context("error writing plugin input")?;
+    drop(stdin);
+    let output = crate::deadline::wait_with_output(child)
+        .context("error waiting for plugin")?;
+    if !output.status.success() {
+        bail!("{} exited with {}", command.display(), output.status);
+    }
+    Ok(output.stdout)
+}
+
+impl Section for Plugins {
+    type Patch = PluginsPatch;
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn schema() -> serde_json::Value {
+        schema::array(schema::required(
+            schema::object(vec![
+                ("name", schema::string()),
+                ("command", schema::string()),
+                ("config", schema::any()),
+            ]),
+            &["name", "command"],
+        ))
+    }
+
+    // asks each plugin for the current state of the config it was given
+    fn read(new: &Self, _dirs: &Dirs) -> Result<Self> {
+        new.0
+            .iter()
+            .map(|plugin| {
+                let output = run(&plugin.command, "read-state", &plugin.config)
+                    .with_context(|| {
+                        format!("error reading state of plugin {}", plugin.name)
+                    })?;
+                let config =
+                    Json::read(output.as_slice()).with_context(|| {
+                        format!("error parsing state of plugin {}", plugin.name)
+                    })?;
+                Ok(Plugin {
+                    name: plugin.name.clone(),
+                    command: plugin.command.clone(),
+                    config,
+                })
+            })
+            .collect::<Result<OrderedIdMap<_>>>()
+            .map(Self)
+    }
+
+    fn diff(mut old: Self, new: Self) -> Result<PluginsPatch> {
+        let mut changed = Vec::new();
+        for new_plugin in new.0 {
+            let old_config = old
+                .0
+                .remove(&new_plugin.name)
+                .map(|old_plugin| old_plugin.config)
+                .unwrap_or_default();
+            let patch = JsonPatch::diff(old_config, new_plugin.config.clone());
+            if !patch.change_count().is_empty() {
+                changed.push(PluginPatch {
+                    name: new_plugin.name,
+                    command: new_plugin.command,
+                    config: new_plugin.config,
+                    patch,
+                });
+            }
+        }
+        Ok(PluginsPatch { changed })
+    }
+
+    fn retain(&mut self, id: &str) -> Result<()> {
+        if !self.0.retain_id(id) {
+            bail!("no plugin {}", id);
+        }
+        Ok(())
+    }
+
+    fn apply_to_old(patch: PluginsPatch, old: &mut Self) {
+        for plugin_patch in patch.changed {
+            match old.0.get_mut(&plugin_patch.name) {
+                Some(plugin) => {
+                    plugin_patch.patch.apply_to_old(&mut plugin.config)
+                },
+                None => {
+                    let mut config = Json::default();
+                    plugin_patch.patch.apply_to_old(&mut config);
+                    old.0.insert(Plugin {
+                        name: plugin_patch.name,
+                        command: plugin_patch.command,
+                        config,
+                    });
+                },
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PluginsPatch {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    changed: Vec<PluginPatch>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct PluginPatch {
+    name: String,
+    #[serde(skip)]
+    command: PathBuf,
+    #[serde(skip)]
+    config: Json,
+    patch: JsonPatch,
+}
+
+// what a plugin is given on stdin to apply, it can either apply the patch or
+// make its state match the whole config
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ApplyInput<'a> {
+    config: &'a Json,
+    patch: &'a JsonPatch,
+}
+
+pub struct Applier<'a> {
+    dry_run: bool,
+    patch_recorder: &'a mut PatchRecorder,
+}
+
+impl<'a> Applier<'a> {
+    pub(crate) fn new(
+        dry_run: bool,
+        patch_recorder: &'a mut PatchRecorder,
+    ) -> Self {
+        Self {
+            dry_run,
+            patch_recorder,
+        }
+    }
+
+    fn log(&mut self, event: PatchEvent<'_>) -> Result<()> {
+        self.patch_recorder.log(&crate::PatchEvent::Plugin(event))
+    }
+
+    fn apply_patch(&mut self, plugin_patch: &PluginPatch) -> Result<()> {
+        self.log(PatchEvent::ApplyPatch {
+            name: &plugin_patch.name,
+            patch: &plugin_patch.patch,
+        })
+        .context("error logging apply patch")?;
+        if self.dry_run {
+            return Ok(());
+        }
+
+        run(
+            &plugin_patch.command,
+            "apply-patch",
+            &ApplyInput {
+                config: &plugin_patch.config,
+                patch: &plugin_patch.patch,
+            },
+        )?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum PatchEvent<'a> {
+    #[serde(rename_all = "kebab-case")]
+    ApplyPatch { name: &'a str, patch: &'a JsonPatch },
+}
+
+impl PluginsPatch {
+    pub fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
+        for plugin_patch in &self.changed {
+            applier.apply_patch(plugin_patch).with_context(|| {
+                format!("error applying plugin {}", plugin_patch.name)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl SectionPatch for PluginsPatch {
+    fn change_count(&self) -> ChangeStats {
+        self.changed
+            .iter()
+            .map(|plugin_patch| plugin_patch.patch.change_count())
+            .sum()
+    }
+
+    fn apply(self: Box<Self>, applier: &mut crate::Applier<'_>) -> Result<()> {
+        (*self).apply(&mut Applier::new(
+            applier.dry_run,
+            &mut applier.patch_recorder,
+        ))
+    }
+}
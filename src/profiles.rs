@@ -0,0 +1,203 @@
+use crate::schema;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+// the only parts of the config a panel profile can set, the typed panels
+// block among them
+const PROFILE_SECTIONS: &[&str] = &["panel", "panels", "xfconf"];
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct PanelProfile {
+    name: String,
+    #[serde(default)]
+    when: Rule,
+    // panel config layered over the rest of the config
+    config: Value,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Rule {
+    monitors: Option<usize>,
+    min_monitors: Option<usize>,
+    max_monitors: Option<usize>,
+}
+
+impl Rule {
+    fn matches(&self, facts: &Facts) -> bool {
+        self.monitors
+            .map_or(true, |monitors| facts.monitors == monitors)
+            && self.min_monitors.map_or(true, |min| facts.monitors >= min)
+            && self.max_monitors.map_or(true, |max| facts.monitors <= max)
+    }
+}
+
+// what profiles are selected by, read from the environment
+#[derive(Debug)]
+pub(crate) struct Facts {
+    monitors: usize,
+}
+
+impl Facts {
+    // the number of monitors is asked of xrandr if not given
+    pub(crate) fn read(monitors: Option<usize>) -> Result<Self> {
+        let monitors = match monitors {
+            Some(monitors) => monitors,
+            None => crate::monitors::list()
+                .context("error counting monitors, pass --monitors instead")?
+                .len(),
+        };
+        Ok(Self { monitors })
+    }
+}
+
+// the profiles' config takes the schemas of the sections it can set
+pub fn schema(sections: &Map<String, Value>) -> Value {
+    let config = PROFILE_SECTIONS
+        .iter()
+        .filter_map(|name| Some((*name, sections.get(*name)?.clone())))
+        .collect();
+    let rule = schema::strict(schema::object(vec![
+        ("monitors", schema::integer()),
+        ("min-monitors", schema::integer()),
+        ("max-monitors", schema::integer()),
+    ]));
+    schema::array(schema::strict(schema::required(
+        schema::object(vec![
+            ("name", schema::string()),
+            ("when", rule),
+            ("config", schema::strict(schema::object(config))),
+        ]),
+        &["name", "config"],
+    )))
+}
+
+// removes the `panel-profiles` from the config, checked but left to select
+// from on the machine the config is applied to, if there are any
+pub(crate) fn take(mut value: Value) -> Result<(Value, Option<Value>)> {
+    let profiles = match value
+        .as_object_mut()
+        .and_then(|config| config.remove("panel-profiles"))
+    {
+        Some(profiles) => profiles,
+        None => return Ok((value, None)),
+    };
+    let any = !parse(profiles.clone())?.is_empty();
+    Ok((value, any.then(|| profiles)))
+}
+
+fn parse(mut profiles: Value) -> Result<Vec<PanelProfile>> {
+    crate::serde::strip_annotations(&mut profiles);
+    let profiles = serde_json::from_value::<Vec<PanelProfile>>(profiles)
+        .context("error reading panel-profiles")?;
+    for profile in &profiles {
+        let sections = profile.config.as_object().with_context(|| {
+            format!(
+                "config of panel profile {} must be an object",
+                profile.name
+            )
+        })?;
+        if let Some(section) = sections
+            .keys()
+            .find(|section| !PROFILE_SECTIONS.contains(&section.as_str()))
+        {
+            bail!(
+                "panel profile {} sets {}, only {} can be set",
+                profile.name,
+                section,
+                PROFILE_SECTIONS.join(", ")
+            );
+        }
+    }
+    Ok(profiles)
+}
+
+// layers the first profile whose rule matches over the config taken from, so
+// the other profiles aren't diffed
+pub(crate) fn select(
+    value: Value,
+    profiles: Value,
+    facts: impl FnOnce() -> Result<Facts>,
+) -> Result<Value> {
+    let profiles = parse(profiles)?;
+    let facts = facts().context("error reading panel profile facts")?;
+    match profiles
+        .into_iter()
+        .find(|profile| profile.when.matches(&facts))
+    {
+        Some(profile) => Ok(crate::baseline::merge(value, profile.config)),
+        None => {
+            crate::warn(format_args!(
+                "no panel profile matches {} monitors",
+                facts.monitors
+            ));
+            Ok(value)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn select() {
+        let docked = || Ok(Facts { monitors: 2 });
+        let config = json!({
+            "panel-profiles": [
+                {
+                    "_comment": "built-in screen only",
+                    "name": "laptop",
+                    "when": { "monitors": 1, "x-seen-on": "X1 Carbon" },
+                    "config": { "panel": { "plugin-configs": [] } }
+                },
+                {
+                    "name": "docked",
+                    "when": { "min-monitors": 2 },
+                    "config": {
+                        "panels": [{ "id": 2, "plugins": [] }],
+                        "xfconf": {
+                            "channels": [{
+                                "name": "xfce4-panel",
+                                "props": { "panels": [0, 1] }
+                            }]
+                        }
+                    }
+                }
+            ],
+            "xfconf": {
+                "channels": [{ "name": "xfwm4", "props": {} }]
+            }
+        });
+        let (config, profiles) = take(config).unwrap();
+        assert_eq!(
+            super::select(config, profiles.unwrap(), docked).unwrap(),
+            json!({
+                "panels": [{ "id": 2, "plugins": [] }],
+                "xfconf": {
+                    "channels": [
+                        { "name": "xfwm4", "props": {} },
+                        {
+                            "name": "xfce4-panel",
+                            "props": { "panels": [0, 1] }
+                        }
+                    ]
+                }
+            })
+        );
+        let config = json!({
+            "panel-profiles": [{
+                "name": "laptop",
+                "config": { "gtk": {} }
+            }]
+        });
+        assert!(take(config).is_err());
+        assert_eq!(
+            take(json!({ "panel-profiles": [] })).unwrap(),
+            (json!({}), None)
+        );
+    }
+}
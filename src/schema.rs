@@ -0,0 +1,107 @@
+use serde_json::{json, Map, Value};
+
+// builders for the JSON Schema of the input, each part of the config
+// describes its own shape with these next to its serde model
+
+const ANNOTATION_PATTERN: &str = "^(_comment|x-.*)$";
+
+pub fn any() -> Value {
+    json!({})
+}
+
+pub fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+pub fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+pub fn integer() -> Value {
+    json!({ "type": "integer" })
+}
+
+pub fn array(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+pub fn string_enum(values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values })
+}
+
+// a schema in the definitions of the document
+#[cfg(feature = "glib")]
+pub fn reference(def: &str) -> Value {
+    json!({ "$ref": format!("#/$defs/{}", def) })
+}
+
+pub fn any_of(schemas: Vec<Value>) -> Value {
+    json!({ "anyOf": schemas })
+}
+
+pub fn nullable(schema: Value) -> Value {
+    any_of(vec![json!({ "type": "null" }), schema])
+}
+
+// a map with arbitrary keys, which takes annotations too where the input
+// drops them
+pub fn map(values: Value, annotated: bool) -> Value {
+    let mut schema =
+        json!({ "type": "object", "additionalProperties": values });
+    if annotated {
+        schema["patternProperties"][ANNOTATION_PATTERN] = any();
+    }
+    schema
+}
+
+// an object with known keys, unknown ones are ignored like serde does unless
+// it denies them
+pub fn object(props: Vec<(&str, Value)>) -> Value {
+    let props = props
+        .into_iter()
+        .map(|(name, schema)| (name.to_owned(), schema))
+        .collect::<Map<_, _>>();
+    json!({ "type": "object", "properties": props })
+}
+
+pub fn strict(mut schema: Value) -> Value {
+    schema["additionalProperties"] = json!(false);
+    schema
+}
+
+pub fn required(mut schema: Value, names: &[&str]) -> Value {
+    schema["required"] = json!(names);
+    schema
+}
+
+// objects told apart by the value of their tag key
+pub fn tagged(tag: &str, variants: Vec<(&str, Value)>) -> Value {
+    any_of(
+        variants
+            .into_iter()
+            .map(|(name, mut schema)| {
+                schema["properties"][tag] = json!({ "const": name });
+                schema["type"] = json!("object");
+                let mut required = vec![json!(tag)];
+                if let Some(names) = schema["required"].as_array() {
+                    required.extend(names.iter().cloned());
+                }
+                schema["required"] = json!(required);
+                schema
+            })
+            .collect(),
+    )
+}
+
+// the whole document, given the schemas of the keys at the top of it and the
+// definitions referenced from within them, includes aren't described since
+// any value may be one
+pub fn document(props: Map<String, Value>, defs: Map<String, Value>) -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "xfce-config",
+        "type": "object",
+        "properties": props,
+        "$defs": defs,
+    })
+}
@@ -0,0 +1,152 @@
+use crate::{Applier, ChangeStats, Dirs};
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::{collections::BTreeSet, fmt, path::PathBuf};
+
+// a part of the config which is read from the environment, diffed against it
+// and applied as a patch
+pub trait Section: Sized {
+    type Patch: SectionPatch + 'static;
+
+    fn is_empty(&self) -> bool;
+
+    // JSON Schema of the section in the input
+    fn schema() -> serde_json::Value;
+
+    // reads the current state of what the new config declares
+    fn read(new: &Self, dirs: &Dirs) -> Result<Self>;
+
+    fn diff(old: Self, new: Self) -> Result<Self::Patch>;
+
+    // changes old the way applying the patch would change what's read from
+    // the environment afterwards
+    fn apply_to_old(patch: Self::Patch, old: &mut Self);
+
+    // drops everything but the part with the given id, so only that part is
+    // applied
+    fn retain(&mut self, _id: &str) -> Result<()> {
+        bail!("section has no parts to pick from")
+    }
+
+    // parts of this config which can't contribute any changes against old
+    fn unused(&self, _old: &Self) -> Vec<String> {
+        Vec::new()
+    }
+
+    // paths which were left out when reading because they aren't UTF-8
+    fn skipped(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+}
+
+pub trait SectionPatch: fmt::Debug + ToJson {
+    fn change_count(&self) -> ChangeStats;
+
+    fn is_empty(&self) -> bool {
+        self.change_count().is_empty()
+    }
+
+    // whether the panel has to be restarted to pick up the changes
+    fn changes_panel(&self) -> bool {
+        false
+    }
+
+    // drops the changes to the parts the filter doesn't keep
+    fn filter_parts(&mut self, _filter: &PartFilter) -> Result<()> {
+        bail!("section has no parts to filter")
+    }
+
+    // each change by the id of the part it's made to and a description of
+    // it, empty if the section can only be applied as a whole
+    fn parts(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    fn apply(self: Box<Self>, applier: &mut Applier<'_>) -> Result<()>;
+}
+
+// which parts of a section to apply changes to, by ids like the ones of
+// --path, where a part within another one has an id like `<id>/<sub-path>`
+#[derive(Debug)]
+pub struct PartFilter {
+    // every part if empty
+    only: Vec<String>,
+    skip: Vec<String>,
+    // single changes left out, but not the ones within them
+    declined: BTreeSet<String>,
+}
+
+impl PartFilter {
+    pub fn new(only: Vec<String>, skip: Vec<String>) -> Self {
+        Self {
+            only,
+            skip,
+            declined: BTreeSet::new(),
+        }
+    }
+
+    pub fn declined(declined: BTreeSet<String>) -> Self {
+        Self {
+            only: Vec::new(),
+            skip: Vec::new(),
+            declined,
+        }
+    }
+
+    // whether changes to the part itself are kept
+    pub fn keeps(&self, id: &str) -> bool {
+        (self.only.is_empty() || self.only.iter().any(|only| within(id, only)))
+            && !self.skip.iter().any(|skip| within(id, skip))
+            && !self.declined.contains(id)
+    }
+
+    // whether the part and everything within it is kept, so it can be
+    // removed as a whole
+    pub fn keeps_all(&self, id: &str) -> bool {
+        self.keeps(id) && !self.skip.iter().any(|skip| within(skip, id))
+    }
+}
+
+fn within(id: &str, path: &str) -> bool {
+    id.strip_prefix(path).map_or(false, |rest| {
+        rest.is_empty() || rest.starts_with('/') || path.ends_with('/')
+    })
+}
+
+// lets section patches be serialized as trait objects
+pub trait ToJson {
+    fn to_json(&self) -> serde_json::Result<serde_json::Value>;
+}
+
+impl<T> ToJson for T
+where
+    T: Serialize,
+{
+    fn to_json(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn part_filter() {
+        let filter = PartFilter::new(
+            vec!["xfce4-panel/plugins".into()],
+            vec!["xfce4-panel/plugins/plugin-2".into()],
+        );
+        assert!(filter.keeps("xfce4-panel/plugins/plugin-1/mode"));
+        assert!(!filter.keeps("xfce4-panel/plugins-extra"));
+        assert!(!filter.keeps("xfce4-panel/plugins/plugin-2"));
+        assert!(!filter.keeps("xfce4-panel/panels"));
+        assert!(filter.keeps("xfce4-panel/plugins"));
+        assert!(!filter.keeps_all("xfce4-panel/plugins"));
+        let filter = PartFilter::declined(
+            ["xfce4-panel/plugins".to_owned()].into_iter().collect(),
+        );
+        assert!(!filter.keeps("xfce4-panel/plugins"));
+        assert!(filter.keeps("xfce4-panel/plugins/plugin-1"));
+    }
+}
@@ -0,0 +1,101 @@
+use serde::Serialize;
+
+// one step turning an old sequence into a new one, indices of removed and
+// moved elements are in the old sequence and of added and moved elements in
+// the new one
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum Edit<T> {
+    Added { index: usize, value: T },
+    Removed { index: usize, value: T },
+    Moved { from: usize, to: usize, value: T },
+}
+
+// the elements kept in place are a longest common subsequence, the rest are
+// moved if they're in both sequences and added or removed otherwise
+pub fn edit_script<T>(old: &[T], new: &[T]) -> Vec<Edit<T>>
+where
+    T: PartialEq + Clone,
+{
+    // lcs[i][j] is the length of the LCS of old[i..] and new[j..]
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut unmatched_old = Vec::new();
+    let mut unmatched_new = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            unmatched_old.push(i);
+            i += 1;
+        } else {
+            unmatched_new.push(j);
+            j += 1;
+        }
+    }
+    unmatched_old.extend(i..old.len());
+    unmatched_new.extend(j..new.len());
+
+    let mut edits = Vec::new();
+    for from in unmatched_old {
+        let to = unmatched_new.iter().position(|&to| new[to] == old[from]);
+        edits.push(match to {
+            Some(to) => Edit::Moved {
+                from,
+                to: unmatched_new.remove(to),
+                value: old[from].clone(),
+            },
+            None => Edit::Removed {
+                index: from,
+                value: old[from].clone(),
+            },
+        });
+    }
+    edits.extend(unmatched_new.into_iter().map(|index| Edit::Added {
+        index,
+        value: new[index].clone(),
+    }));
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_script() {
+        assert_eq!(
+            super::edit_script(
+                &["a", "b", "c", "d"],
+                &["a", "c", "d", "b", "e"]
+            ),
+            [
+                Edit::Moved {
+                    from: 1,
+                    to: 3,
+                    value: "b"
+                },
+                Edit::Added {
+                    index: 4,
+                    value: "e"
+                },
+            ]
+        );
+        assert_eq!(
+            super::edit_script(&[1, 2, 3], &[1, 3]),
+            [Edit::Removed { index: 1, value: 2 }]
+        );
+        assert!(super::edit_script(&[1, 2], &[1, 2]).is_empty());
+    }
+}
@@ -35,6 +35,16 @@ where
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
+
+    // keeps only the item whose id displays as the given one, returning
+    // whether there was one
+    pub fn retain_id(&mut self, id: &str) -> bool
+    where
+        T::Id: fmt::Display,
+    {
+        self.0.retain(|item_id, _| item_id.to_string() == id);
+        !self.0.is_empty()
+    }
 }
 
 impl<T> FromIterator<T> for IdMap<T>
@@ -95,17 +105,241 @@ where
     }
 }
 
+// like IdMap but keeping items in the order they were given, for lists where
+// the order means something, lookups are linear since such lists are short
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderedIdMap<T>(Vec<T>)
+where
+    T: Id;
+
+impl<T> Default for OrderedIdMap<T>
+where
+    T: Id,
+{
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<T> OrderedIdMap<T>
+where
+    T: Id,
+{
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.0.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.0.iter_mut()
+    }
+
+    pub fn get_mut(&mut self, id: &T::Id) -> Option<&mut T> {
+        self.0.iter_mut().find(|item| item.id() == id)
+    }
+
+    pub fn remove(&mut self, id: &T::Id) -> Option<T> {
+        let index = self.0.iter().position(|item| item.id() == id)?;
+        Some(self.0.remove(index))
+    }
+
+    // keeps only the item whose id displays as the given one, returning
+    // whether there was one
+    pub fn retain_id(&mut self, id: &str) -> bool
+    where
+        T::Id: fmt::Display,
+    {
+        self.0.retain(|item| item.id().to_string() == id);
+        !self.0.is_empty()
+    }
+
+    // replaces the item with the same id where it is, or adds it at the end
+    pub fn insert(&mut self, item: T) {
+        match self.get_mut(item.id()) {
+            Some(existing) => *existing = item,
+            None => self.0.push(item),
+        }
+    }
+}
+
+impl<T> FromIterator<T> for OrderedIdMap<T>
+where
+    T: Id,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut map = Self::default();
+        for item in iter {
+            map.insert(item);
+        }
+        map
+    }
+}
+
+impl<T> IntoIterator for OrderedIdMap<T>
+where
+    T: Id,
+{
+    type IntoIter = std::vec::IntoIter<T>;
+    type Item = T;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<T> ser::Serialize for OrderedIdMap<T>
+where
+    T: ser::Serialize + Id,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.collect_seq(&self.0)
+    }
+}
+
+impl<'de, T> de::Deserialize<'de> for OrderedIdMap<T>
+where
+    T: de::Deserialize<'de> + Id,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct Visitor<T>(PhantomData<T>);
+
+        impl<'de, T> de::Visitor<'de> for Visitor<T>
+        where
+            T: de::Deserialize<'de> + Id,
+        {
+            type Value = OrderedIdMap<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "id mapped list")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                iter::from_fn(|| seq.next_element().transpose()).collect()
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor(PhantomData))
+    }
+}
+
+// keys annotating the input, ignored wherever the input expects a map
+pub fn is_annotation(key: &str) -> bool {
+    key == "_comment" || key.starts_with("x-")
+}
+
+// drops the annotations of every map in the value, for input read by types
+// which deny unknown fields
+pub fn strip_annotations(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            *map = std::mem::take(map)
+                .into_iter()
+                .filter(|(key, _)| !is_annotation(key))
+                .map(|(key, mut value)| {
+                    strip_annotations(&mut value);
+                    (key, value)
+                })
+                .collect();
+        },
+        serde_json::Value::Array(values) => {
+            values.iter_mut().for_each(strip_annotations);
+        },
+        _ => {},
+    }
+}
+
+#[cfg(feature = "glib")]
+pub fn deserialize_stripped<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: de::Deserializer<'de>,
+    T: de::DeserializeOwned,
+{
+    let mut value: serde_json::Value =
+        de::Deserialize::deserialize(deserializer)?;
+    strip_annotations(&mut value);
+    T::deserialize(value).map_err(de::Error::custom)
+}
+
+#[derive(Debug)]
+pub struct Unannotated<V>(pub BTreeMap<String, V>);
+
+impl<'de, V> de::Deserialize<'de> for Unannotated<V>
+where
+    V: de::Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct Visitor<V>(PhantomData<V>);
+
+        impl<'de, V> de::Visitor<'de> for Visitor<V>
+        where
+            V: de::Deserialize<'de>,
+        {
+            type Value = Unannotated<V>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "map")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                let mut values = BTreeMap::new();
+                while let Some(key) = map.next_key::<String>()? {
+                    if is_annotation(&key) {
+                        map.next_value::<de::IgnoredAny>()?;
+                    } else {
+                        values.insert(key, map.next_value()?);
+                    }
+                }
+                Ok(Unannotated(values))
+            }
+        }
+
+        deserializer.deserialize_map(Visitor(PhantomData))
+    }
+}
+
+pub fn deserialize_unannotated<'de, D, V>(
+    deserializer: D,
+) -> Result<BTreeMap<String, V>, D::Error>
+where
+    D: de::Deserializer<'de>,
+    V: de::Deserialize<'de>,
+{
+    de::Deserialize::deserialize(deserializer).map(|map: Unannotated<V>| map.0)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub struct RelativePathBuf(PathBuf);
 
 #[derive(Debug)]
 pub struct RelativePathBufError {
-    _priv: (),
+    path: PathBuf,
 }
 
 impl fmt::Display for RelativePathBufError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "path is not relative")
+        write!(
+            f,
+            "{} is absolute, expected a relative path",
+            self.path.display()
+        )
     }
 }
 
@@ -116,7 +350,7 @@ impl RelativePathBuf {
         if path.is_relative() {
             Ok(Self(path))
         } else {
-            Err(RelativePathBufError { _priv: () })
+            Err(RelativePathBufError { path })
         }
     }
 }
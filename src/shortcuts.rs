@@ -0,0 +1,146 @@
+use crate::{schema, xfconf::Xfconf};
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+const CHANNEL: &str = "xfce4-keyboard-shortcuts";
+
+// keyboard shortcuts by key combination, like `<Primary><Alt>t`, compiled into
+// the custom props of the shortcuts channel, a declared group replaces all
+// custom shortcuts of its kind so stale ones are cleared
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Shortcuts {
+    #[serde(default)]
+    commands: Option<BTreeMap<String, CommandShortcut>>,
+    // xfwm4 actions, like close_window_key
+    #[serde(default)]
+    window: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum CommandShortcut {
+    Command(String),
+    Full(FullCommandShortcut),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct FullCommandShortcut {
+    command: String,
+    #[serde(default)]
+    startup_notify: bool,
+}
+
+impl Shortcuts {
+    pub fn schema() -> serde_json::Value {
+        let command = schema::any_of(vec![
+            schema::string(),
+            schema::strict(schema::required(
+                schema::object(vec![
+                    ("command", schema::string()),
+                    ("startup-notify", schema::boolean()),
+                ]),
+                &["command"],
+            )),
+        ]);
+        schema::strict(schema::object(vec![
+            ("commands", schema::map(command, false)),
+            ("window", schema::map(schema::string(), false)),
+        ]))
+    }
+
+    pub fn compile(self, xfconf: &mut Xfconf) -> Result<()> {
+        if let Some(commands) = self.commands {
+            override_defaults(xfconf, "commands")?;
+            for (key, shortcut) in commands {
+                let path = format!("/commands/custom/{}", key);
+                match shortcut {
+                    CommandShortcut::Command(command) => {
+                        xfconf.set_string(CHANNEL, &path, command);
+                    },
+                    CommandShortcut::Full(FullCommandShortcut {
+                        command,
+                        startup_notify,
+                    }) => {
+                        xfconf.set_string(CHANNEL, &path, command);
+                        xfconf.set_bool(
+                            CHANNEL,
+                            &format!("{}/startup-notify", path),
+                            startup_notify,
+                        );
+                    },
+                }
+            }
+        }
+        if let Some(window) = self.window {
+            override_defaults(xfconf, "xfwm4")?;
+            for (key, action) in window {
+                if !action.ends_with("_key") {
+                    bail!(
+                        "unknown xfwm4 action {} for shortcut {}, actions are \
+                         like close_window_key",
+                        action,
+                        key
+                    );
+                }
+                xfconf.set_string(
+                    CHANNEL,
+                    &format!("/xfwm4/custom/{}", key),
+                    action,
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+// makes the custom shortcuts of the group used instead of the defaults, and
+// clears the ones which aren't declared
+fn override_defaults(xfconf: &mut Xfconf, group: &str) -> Result<()> {
+    xfconf.set_bool(CHANNEL, &format!("/{}/custom/override", group), true);
+    xfconf.add_clear_path(&format!("{}.{}.custom.*", CHANNEL, group))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn compile() {
+        let shortcuts = serde_json::from_value::<Shortcuts>(json!({
+            "commands": {
+                "<Primary><Alt>t": "xfce4-terminal",
+                "<Super>r": {
+                    "command": "xfce4-appfinder",
+                    "startup-notify": true,
+                },
+            },
+        }))
+        .unwrap();
+        let mut xfconf = Xfconf::default();
+        shortcuts.compile(&mut xfconf).unwrap();
+        assert_eq!(
+            xfconf.get_string(CHANNEL, "/commands/custom/<Primary><Alt>t"),
+            Some("xfce4-terminal")
+        );
+        assert_eq!(
+            xfconf
+                .get_bool(CHANNEL, "/commands/custom/<Super>r/startup-notify"),
+            Some(true)
+        );
+        assert_eq!(
+            xfconf.get_bool(CHANNEL, "/commands/custom/override"),
+            Some(true)
+        );
+        assert_eq!(xfconf.get_bool(CHANNEL, "/xfwm4/custom/override"), None);
+
+        let shortcuts = serde_json::from_value::<Shortcuts>(json!({
+            "window": { "<Alt>F4": "close window" },
+        }))
+        .unwrap();
+        assert!(shortcuts.compile(&mut Xfconf::default()).is_err());
+    }
+}
@@ -0,0 +1,32 @@
+use crate::symlinks::replace_file;
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fs, io, path::Path};
+
+// state kept in the data dir across runs, empty before the first one
+pub(crate) fn read<T>(path: &Path) -> Result<T>
+where
+    T: DeserializeOwned + Default,
+{
+    match fs::read(path) {
+        Ok(content) => serde_json::from_slice(&content)
+            .with_context(|| format!("error parsing {}", path.display())),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            Ok(T::default())
+        },
+        Err(error) => Err(error)
+            .with_context(|| format!("error reading {}", path.display())),
+    }
+}
+
+pub(crate) fn write(path: &Path, state: &impl Serialize) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| {
+            format!("error creating state dir {}", parent.display())
+        })?;
+    }
+    let content = serde_json::to_vec_pretty(state)?;
+    replace_file(path, |tmp| {
+        fs::write(tmp, content).context("error writing temporary state file")
+    })
+}
@@ -0,0 +1,244 @@
+use anyhow::{bail, Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+// how many links are followed before giving up, same as the kernel's limit
+const MAX_LINKS: usize = 40;
+
+// what writing a file does when its path is a symlink, e.g. into a dotfiles
+// repo
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    // write to the file the link points to, keeping the link
+    Follow,
+    // replace the link with a regular file
+    Replace,
+    // refuse to write
+    Error,
+}
+
+impl Default for SymlinkPolicy {
+    fn default() -> Self {
+        Self::Follow
+    }
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "follow" => Self::Follow,
+            "replace" => Self::Replace,
+            "error" => Self::Error,
+            _ => bail!("unknown symlink policy {}", s),
+        })
+    }
+}
+
+impl SymlinkPolicy {
+    // the path a file at the given path should actually be written to, links
+    // in the dirs above it are always followed by the write itself
+    pub(crate) fn resolve(self, path: &Path) -> Result<PathBuf> {
+        let mut path = path.to_owned();
+        for _ in 0..MAX_LINKS {
+            if !is_symlink(&path)? {
+                return Ok(path);
+            }
+            match self {
+                Self::Follow => {
+                    let target = path.read_link().with_context(|| {
+                        format!("error reading link {}", path.display())
+                    })?;
+                    // relative targets are relative to the link's dir
+                    path = match path.parent() {
+                        Some(dir) => dir.join(target),
+                        None => target,
+                    };
+                },
+                Self::Replace => return Ok(path),
+                Self::Error => bail!("{} is a symlink", path.display()),
+            }
+        }
+        bail!("too many levels of symlinks at {}", path.display())
+    }
+}
+
+fn is_symlink(path: &Path) -> Result<bool> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) => Ok(metadata.file_type().is_symlink()),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(error).with_context(|| {
+            format!("error getting metadata for {}", path.display())
+        }),
+    }
+}
+
+// the policy of each subsystem which writes files
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SymlinkPolicies {
+    pub wallpapers: SymlinkPolicy,
+    pub panel: SymlinkPolicy,
+    pub gtk: SymlinkPolicy,
+    pub default_applications: SymlinkPolicy,
+    pub general: SymlinkPolicy,
+    pub xresources: SymlinkPolicy,
+    pub autostart: SymlinkPolicy,
+}
+
+impl SymlinkPolicies {
+    // sets a policy from `<subsystem>=<policy>`, `all` sets every subsystem
+    pub fn set(&mut self, spec: &str) -> Result<()> {
+        let (subsystem, policy) = spec
+            .split_once('=')
+            .context("symlink policy must be <subsystem>=<policy>")?;
+        let policy = policy.parse()?;
+        match subsystem {
+            "wallpapers" => self.wallpapers = policy,
+            "panel" => self.panel = policy,
+            "gtk" => self.gtk = policy,
+            "default-applications" => self.default_applications = policy,
+            "general" => self.general = policy,
+            "xresources" => self.xresources = policy,
+            "autostart" => self.autostart = policy,
+            "all" => {
+                *self = Self {
+                    wallpapers: policy,
+                    panel: policy,
+                    gtk: policy,
+                    default_applications: policy,
+                    general: policy,
+                    xresources: policy,
+                    autostart: policy,
+                }
+            },
+            _ => bail!("unknown subsystem {}", subsystem),
+        }
+        Ok(())
+    }
+}
+
+// whether the file at the resolved path already has exactly this content, so
+// writing it would only touch its mtime and wake up anything monitoring it
+pub(crate) fn has_content(path: &Path, content: &[u8]) -> bool {
+    fs::read(path).map_or(false, |existing| existing == content)
+}
+
+// replaces the file at the resolved path with one written next to it by the
+// given function, so it's never left half written
+pub(crate) fn replace_file(
+    path: &Path,
+    write: impl FnOnce(&Path) -> Result<()>,
+) -> Result<()> {
+    let mut tmp = path.to_owned().into_os_string();
+    tmp.push(".new");
+    let tmp = PathBuf::from(tmp);
+    write(&tmp)?;
+    fs::rename(tmp, path).context("error moving temporary file into place")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    // a fresh dir with a config root linked into a dotfiles dir
+    fn setup(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!(
+            "xfce-config-symlinks-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        let dotfiles = dir.join("dotfiles");
+        fs::create_dir_all(&dotfiles).unwrap();
+        let root = dir.join("config");
+        symlink(&dotfiles, &root).unwrap();
+        (dir, dotfiles, root)
+    }
+
+    fn write(policy: SymlinkPolicy, path: &Path, content: &str) -> Result<()> {
+        let path = policy.resolve(path)?;
+        replace_file(&path, |tmp| Ok(fs::write(tmp, content)?))
+    }
+
+    #[test]
+    fn symlinked_root() {
+        let (dir, dotfiles, root) = setup("root");
+        for policy in [
+            SymlinkPolicy::Follow,
+            SymlinkPolicy::Replace,
+            SymlinkPolicy::Error,
+        ] {
+            write(policy, &root.join("settings.ini"), "a").unwrap();
+            assert_eq!(
+                fs::read_to_string(dotfiles.join("settings.ini")).unwrap(),
+                "a"
+            );
+            assert!(is_symlink(&root).unwrap());
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn symlinked_file() {
+        let (dir, dotfiles, root) = setup("file");
+        fs::write(dotfiles.join("settings.ini"), "a").unwrap();
+        let path = root.join("linked.ini");
+        // relative to the link's dir
+        symlink("settings.ini", &path).unwrap();
+
+        write(SymlinkPolicy::Follow, &path, "b").unwrap();
+        assert!(is_symlink(&path).unwrap());
+        assert_eq!(
+            fs::read_to_string(dotfiles.join("settings.ini")).unwrap(),
+            "b"
+        );
+
+        assert!(write(SymlinkPolicy::Error, &path, "c").is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b");
+
+        write(SymlinkPolicy::Replace, &path, "c").unwrap();
+        assert!(!is_symlink(&path).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "c");
+        assert_eq!(
+            fs::read_to_string(dotfiles.join("settings.ini")).unwrap(),
+            "b"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn has_content() {
+        let (dir, _dotfiles, root) = setup("content");
+        let path = root.join("settings.ini");
+        assert!(!super::has_content(&path, b""));
+        write(SymlinkPolicy::Follow, &path, "a").unwrap();
+        assert!(super::has_content(&path, b"a"));
+        assert!(!super::has_content(&path, b"b"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn symlink_loop() {
+        let (dir, _dotfiles, root) = setup("loop");
+        let path = root.join("loop.ini");
+        symlink("loop.ini", &path).unwrap();
+        assert!(SymlinkPolicy::Follow.resolve(&path).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn policies() {
+        let mut policies = SymlinkPolicies::default();
+        policies.set("all=replace").unwrap();
+        policies.set("panel=error").unwrap();
+        assert_eq!(policies.gtk, SymlinkPolicy::Replace);
+        assert_eq!(policies.panel, SymlinkPolicy::Error);
+        assert!(policies.set("xfconf=follow").is_err());
+        assert!(policies.set("panel").is_err());
+    }
+}
@@ -0,0 +1,405 @@
+use crate::{
+    external::ExternallyManaged,
+    schema,
+    section::{Section, SectionPatch},
+    serde::IdMap,
+    symlinks::{replace_file, SymlinkPolicy},
+    ChangeStats,
+    Dirs,
+    PatchRecorder,
+};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    process,
+};
+
+thread_local! {
+    static DOWNLOADS: RefCell<Downloads> = RefCell::new(Downloads::default());
+}
+
+// where each URL was downloaded to by this process, so the image a diff
+// hashed is the one deployed and a URL is only fetched once per run
+#[derive(Default)]
+struct Downloads {
+    // created by this process, so no one else can swap the images in it
+    dir: Option<PathBuf>,
+    files: BTreeMap<String, PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Wallpapers(IdMap<Wallpaper>);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Wallpaper {
+    // file name in the managed wallpapers dir
+    name: String,
+    #[serde(flatten)]
+    source: Source,
+    // xfconf string props which are pointed at the deployed file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    props: Vec<Prop>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Source {
+    File(PathBuf),
+    Url(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Prop {
+    channel: String,
+    path: String,
+}
+
+impl crate::serde::Id for Wallpaper {
+    type Id = String;
+
+    fn id(&self) -> &Self::Id {
+        &self.name
+    }
+}
+
+impl Wallpapers {
+    // makes relative source files relative to the given dir
+    pub fn resolve_sources(&mut self, dir: &Path) {
+        for wallpaper in (self.0).0.values_mut() {
+            if let Source::File(path) = &mut wallpaper.source {
+                if path.is_relative() {
+                    *path = dir.join(&*path);
+                }
+            }
+        }
+    }
+
+    // wallpaper files read from this machine
+    pub fn local_files(&self) -> impl Iterator<Item = &Path> + '_ {
+        (self.0)
+            .0
+            .values()
+            .filter_map(|wallpaper| match &wallpaper.source {
+                Source::File(path) => Some(&**path),
+                Source::Url(_) => None,
+            })
+    }
+
+    // channel, prop path and deployed file path of each wallpaper prop
+    #[cfg(feature = "glib")]
+    pub fn props<'a>(
+        &'a self,
+        dir: &'a Path,
+    ) -> impl Iterator<Item = (&'a str, &'a str, PathBuf)> + 'a {
+        (self.0).0.values().flat_map(move |wallpaper| {
+            wallpaper.props.iter().map(move |prop| {
+                (
+                    prop.channel.as_str(),
+                    prop.path.as_str(),
+                    dir.join(&wallpaper.name),
+                )
+            })
+        })
+    }
+}
+
+impl Section for Wallpapers {
+    type Patch = WallpapersPatch;
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn schema() -> serde_json::Value {
+        let prop = schema::required(
+            schema::object(vec![
+                ("channel", schema::string()),
+                ("path", schema::string()),
+            ]),
+            &["channel", "path"],
+        );
+        let mut wallpaper = schema::required(
+            schema::object(vec![
+                ("name", schema::string()),
+                ("file", schema::string()),
+                ("url", schema::string()),
+                ("props", schema::array(prop)),
+            ]),
+            &["name"],
+        );
+        // the source is either a file or a url
+        wallpaper["oneOf"] = serde_json::json!([
+            { "required": ["file"] },
+            { "required": ["url"] },
+        ]);
+        schema::array(wallpaper)
+    }
+
+    fn read(new: &Self, dirs: &Dirs) -> Result<Self> {
+        let wallpapers = (new.0)
+            .0
+            .keys()
+            .filter_map(|name| {
+                let path = dirs.wallpapers_dir.join(name);
+                path.is_file().then(|| Wallpaper {
+                    name: name.clone(),
+                    source: Source::File(path),
+                    props: Vec::new(),
+                })
+            })
+            .collect::<IdMap<_>>();
+        Ok(Self(wallpapers))
+    }
+
+    fn diff(old: Self, new: Self) -> Result<WallpapersPatch> {
+        WallpapersPatch::diff(old, new)
+    }
+
+    fn retain(&mut self, id: &str) -> Result<()> {
+        if !self.0.retain_id(id) {
+            bail!("no wallpaper {}", id);
+        }
+        Ok(())
+    }
+
+    fn apply_to_old(patch: WallpapersPatch, old: &mut Self) {
+        for wallpaper in patch.deployed {
+            (old.0).0.insert(wallpaper.name.clone(), wallpaper);
+        }
+    }
+}
+
+fn file_hash(path: &Path) -> Result<u64> {
+    let content = fs::read(path)
+        .with_context(|| format!("error reading {}", path.display()))?;
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+// a new dir only this user can write to, failing if the path is taken since
+// whoever made it could change what's in it
+fn create_downloads_dir() -> Result<PathBuf> {
+    let dir = std::env::temp_dir()
+        .join(format!("xfce-config-wallpapers-{}", process::id()));
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(&dir).with_context(|| {
+        format!("error creating wallpaper downloads dir {}", dir.display())
+    })?;
+    Ok(dir)
+}
+
+// the image a URL points to now, which it may not have pointed to when the
+// wallpaper was deployed, downloaded the first time it's asked for
+fn downloaded(url: &str) -> Result<PathBuf> {
+    DOWNLOADS.with(|downloads| {
+        let mut downloads = downloads.borrow_mut();
+        if let Some(path) = downloads.files.get(url) {
+            return Ok(path.clone());
+        }
+        let dir = match &downloads.dir {
+            Some(dir) => dir.clone(),
+            None => downloads.dir.insert(create_downloads_dir()?).clone(),
+        };
+        let path = dir.join(downloads.files.len().to_string());
+        if let Err(error) = download(url, &path) {
+            let _ = fs::remove_file(&path);
+            return Err(error);
+        }
+        downloads.files.insert(url.to_owned(), path.clone());
+        Ok(path)
+    })
+}
+
+// removes the images downloaded by this process, once it's done with them
+pub fn remove_downloads() {
+    let downloads = DOWNLOADS.with(|downloads| downloads.take());
+    if let Some(dir) = downloads.dir {
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+fn download(url: &str, path: &Path) -> Result<()> {
+    let mut child = process::Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        .arg("--output")
+        .arg(path)
+        .arg(url)
+        .spawn()
+        .context("error running curl")?;
+    let status =
+        crate::deadline::wait(&mut child).context("error waiting for curl")?;
+    if !status.success() {
+        bail!("curl exited with {}", status);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WallpapersPatch {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    deployed: Vec<Wallpaper>,
+}
+
+impl WallpapersPatch {
+    pub fn diff(mut old: Wallpapers, new: Wallpapers) -> Result<Self> {
+        let mut deployed = Vec::new();
+        for (name, new_wallpaper) in (new.0).0 {
+            if name.is_empty()
+                || name == "."
+                || name == ".."
+                || name.contains(std::path::is_separator)
+            {
+                bail!("wallpaper name {:?} is not a file name", name);
+            }
+            let unchanged =
+                match ((old.0).0.remove(&name), &new_wallpaper.source) {
+                    (
+                        Some(Wallpaper {
+                            source: Source::File(old_path),
+                            ..
+                        }),
+                        Source::File(new_path),
+                    ) => file_hash(&old_path)? == file_hash(new_path)?,
+                    (
+                        Some(Wallpaper {
+                            source: Source::File(old_path),
+                            ..
+                        }),
+                        Source::Url(url),
+                    ) => match downloaded(url) {
+                        Ok(path) => file_hash(&old_path)? == file_hash(&path)?,
+                        // offline the deployed image is kept
+                        Err(error) => {
+                            crate::warn(format_args!(
+                                "keeping wallpaper {}, error downloading it: \
+                                 {:#}",
+                                name, error
+                            ));
+                            true
+                        },
+                    },
+                    _ => false,
+                };
+            if !unchanged {
+                deployed.push(new_wallpaper);
+            }
+        }
+        Ok(Self { deployed })
+    }
+}
+
+pub struct Applier<'a> {
+    dry_run: bool,
+    patch_recorder: &'a mut PatchRecorder,
+    dir: Cow<'a, Path>,
+    symlinks: SymlinkPolicy,
+    external: &'a ExternallyManaged,
+}
+
+impl<'a> Applier<'a> {
+    pub(crate) fn new(
+        dry_run: bool,
+        patch_recorder: &'a mut PatchRecorder,
+        dir: Cow<'a, Path>,
+        symlinks: SymlinkPolicy,
+        external: &'a ExternallyManaged,
+    ) -> Self {
+        Self {
+            dry_run,
+            patch_recorder,
+            dir,
+            symlinks,
+            external,
+        }
+    }
+
+    fn log(&mut self, event: PatchEvent<'_>) -> Result<()> {
+        self.patch_recorder
+            .log(&crate::PatchEvent::Wallpapers(event))
+    }
+
+    fn deploy(&mut self, wallpaper: &Wallpaper) -> Result<()> {
+        let path = self.dir.join(&wallpaper.name);
+        if self.external.skip_write(&path) {
+            return Ok(());
+        }
+        let path = self.symlinks.resolve(&path)?;
+        match &wallpaper.source {
+            Source::File(source) => self.log(PatchEvent::CopyWallpaper {
+                source,
+                path: &path,
+            }),
+            Source::Url(url) => {
+                self.log(PatchEvent::DownloadWallpaper { url, path: &path })
+            },
+        }
+        .context("error logging deploy wallpaper")?;
+        self.patch_recorder
+            .log_file_inverse(&path)
+            .context("error logging wallpaper file inverse")?;
+        if self.dry_run {
+            return Ok(());
+        }
+
+        // the image the diff hashed if it was downloaded already
+        let source = match &wallpaper.source {
+            Source::File(source) => Cow::Borrowed(&**source),
+            Source::Url(url) => Cow::Owned(downloaded(url)?),
+        };
+        fs::create_dir_all(&self.dir)
+            .context("error creating wallpapers dir")?;
+        self.patch_recorder.back_up(&path)?;
+        replace_file(&path, |tmp| {
+            fs::copy(&source, tmp).context("error copying wallpaper")?;
+            Ok(())
+        })
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum PatchEvent<'a> {
+    #[serde(rename_all = "kebab-case")]
+    CopyWallpaper { source: &'a Path, path: &'a Path },
+    #[serde(rename_all = "kebab-case")]
+    DownloadWallpaper { url: &'a str, path: &'a Path },
+}
+
+impl WallpapersPatch {
+    pub fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
+        for wallpaper in &self.deployed {
+            applier.deploy(wallpaper).with_context(|| {
+                format!("error deploying wallpaper {}", wallpaper.name)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl SectionPatch for WallpapersPatch {
+    fn change_count(&self) -> ChangeStats {
+        ChangeStats::files_written(self.deployed.len())
+    }
+
+    fn apply(self: Box<Self>, applier: &mut crate::Applier<'_>) -> Result<()> {
+        (*self).apply(&mut Applier::new(
+            applier.dry_run,
+            &mut applier.patch_recorder,
+            applier.dirs.wallpapers_dir.as_path().into(),
+            applier.symlinks.wallpapers,
+            &applier.external,
+        ))
+    }
+}
@@ -1,30 +1,386 @@
-use crate::{dbus::DBus, serde::IdMap, PatchRecorder};
+use crate::{
+    dbus::DBus,
+    desktop::Desktop,
+    panels::AssignedPlugin,
+    schema,
+    section::{PartFilter, Section, SectionPatch},
+    sequence::{edit_script, Edit},
+    serde::IdMap,
+    ChangeStats,
+    Dirs,
+    PatchRecorder,
+};
 use anyhow::{anyhow, bail, Context, Error, Result};
 use glib::variant::DictEntry;
 use serde::{de, ser, Deserialize, Serialize};
 use std::{
+    borrow::Cow,
     collections::{btree_map, BTreeMap, BTreeSet},
+    env,
     fmt,
+    fs,
+    io,
     iter,
+    path::{Path, PathBuf},
     str::FromStr,
 };
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Xfconf {
     #[serde(default, skip_serializing_if = "Channels::is_empty")]
     channels: Channels,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     clear_paths: Vec<ClearPath>,
+    // plugins of the typed panels block whose ids were picked for them, sent
+    // along to remotes so they're matched against the panels there
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    assigned_plugins: Vec<AssignedPlugin>,
+    // the typed desktop block if it selects monitors, compiled where the
+    // config is applied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    desktop: Option<Desktop>,
 }
 
 impl Xfconf {
-    pub fn is_empty(&self) -> bool {
-        self.channels.is_empty()
+    // input which can never result in any changes
+    pub fn unused(&self) -> Vec<String> {
+        let mut unused = Vec::new();
+        for clear_path in &self.clear_paths {
+            if !(self.channels.0).0.contains_key(&clear_path.channel) {
+                unused.push(format!(
+                    "xfconf clear path {} targets undeclared channel {}",
+                    clear_path, clear_path.channel
+                ));
+            }
+        }
+        for channel in (self.channels.0).0.values() {
+            channel.props.collect_unused(&channel.name, "", &mut unused);
+        }
+        unused
+    }
+
+    pub fn get_string(&self, channel: &str, path: &str) -> Option<&str> {
+        let value = (self.channels.0).0.get(channel)?.props.get(path)?;
+        match &value.value {
+            TypedValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn get_int(&self, channel: &str, path: &str) -> Option<i32> {
+        let value = (self.channels.0).0.get(channel)?.props.get(path)?;
+        match value.value {
+            TypedValue::Int(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    pub fn get_int_array(&self, channel: &str, path: &str) -> Option<Vec<i32>> {
+        let value = (self.channels.0).0.get(channel)?.props.get(path)?;
+        match &value.value {
+            TypedValue::Array(values) => values
+                .iter()
+                .map(|value| match value.value {
+                    TypedValue::Int(i) => Some(i),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+
+    pub fn get_bool(&self, channel: &str, path: &str) -> Option<bool> {
+        let value = (self.channels.0).0.get(channel)?.props.get(path)?;
+        match value.value {
+            TypedValue::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    // config setting every changed property to its last value
+    pub fn from_changes(changes: Vec<PropertyChange>) -> Self {
+        let mut last_values = BTreeMap::new();
+        for change in changes {
+            last_values.insert((change.channel, change.property), change.value);
+        }
+        let mut xfconf = Self::default();
+        for ((channel, property), value) in last_values {
+            match value {
+                Some(value) => xfconf.set(&channel, &property, value),
+                None => crate::warn(format_args!(
+                    "property {} in channel {} was removed, add a clear path \
+                     to remove it",
+                    property, channel
+                )),
+            }
+        }
+        xfconf
+    }
+
+    pub fn set_string(&mut self, channel: &str, path: &str, value: String) {
+        self.set(channel, path, TypedValue::String(value));
+    }
+
+    pub fn set_int(&mut self, channel: &str, path: &str, value: i32) {
+        self.set(channel, path, TypedValue::Int(value));
+    }
+
+    pub fn set_uint(&mut self, channel: &str, path: &str, value: u32) {
+        self.set(channel, path, TypedValue::Uint(value));
+    }
+
+    pub fn set_bool(&mut self, channel: &str, path: &str, value: bool) {
+        self.set(channel, path, TypedValue::Bool(value));
+    }
+
+    pub fn set_string_array(
+        &mut self,
+        channel: &str,
+        path: &str,
+        values: Vec<String>,
+    ) {
+        self.set_array(
+            channel,
+            path,
+            values.into_iter().map(TypedValue::String),
+        );
+    }
+
+    pub fn set_int_array(
+        &mut self,
+        channel: &str,
+        path: &str,
+        values: Vec<i32>,
+    ) {
+        self.set_array(channel, path, values.into_iter().map(TypedValue::Int));
+    }
+
+    pub fn set_double_array(
+        &mut self,
+        channel: &str,
+        path: &str,
+        values: Vec<f64>,
+    ) {
+        self.set_array(
+            channel,
+            path,
+            values.into_iter().map(TypedValue::Double),
+        );
+    }
+
+    fn set_array(
+        &mut self,
+        channel: &str,
+        path: &str,
+        values: impl Iterator<Item = TypedValue>,
+    ) {
+        let values = values
+            .map(|value| Value {
+                value,
+                normalize: Vec::new(),
+                props: Properties::default(),
+            })
+            .collect();
+        self.set(channel, path, TypedValue::Array(values));
+    }
+
+    pub fn add_clear_path(&mut self, clear_path: &str) -> Result<()> {
+        let clear_path = clear_path
+            .parse::<ClearPath>()
+            .with_context(|| format!("invalid clear path {}", clear_path))?;
+        let declared = self
+            .clear_paths
+            .iter()
+            .any(|declared| declared.to_string() == clear_path.to_string());
+        if !declared {
+            self.clear_paths.push(clear_path);
+        }
+        Ok(())
+    }
+
+    // sets a prop and the ones under it from config JSON, in any form the
+    // props of a channel take
+    pub fn set_json(
+        &mut self,
+        channel: &str,
+        path: &str,
+        value: serde_json::Value,
+    ) -> Result<()> {
+        let value = serde_json::from_value::<Value>(value)?;
+        if let Some(prop) = self.entry(channel, path) {
+            *prop = value;
+        }
+        Ok(())
+    }
+
+    // sets a prop, adding the channel and parent props as needed
+    fn set(&mut self, channel: &str, path: &str, value: TypedValue) {
+        if let Some(prop) = self.entry(channel, path) {
+            prop.value = value;
+        }
+    }
+
+    // paths of the props with a value by channel
+    pub(crate) fn declared_props(&self) -> BTreeMap<String, BTreeSet<String>> {
+        (self.channels.0)
+            .0
+            .values()
+            .map(|channel| {
+                let mut declared = BTreeSet::new();
+                channel.props.collect_declared("", &mut declared);
+                (channel.name.clone(), declared)
+            })
+            .collect()
+    }
+
+    // paths of the props which must not exist by channel
+    pub(crate) fn removed_props(
+        &self,
+    ) -> impl Iterator<Item = (&str, &[String])> + '_ {
+        (self.channels.0)
+            .0
+            .values()
+            .map(|channel| (&*channel.name, &*channel.removed_props))
+    }
+
+    // removes the prop unless it's declared
+    pub(crate) fn remove_undeclared(&mut self, channel: &str, path: &str) {
+        let channel = self.channel(channel);
+        if channel.props.get(path).is_none()
+            && !channel.removed_props.iter().any(|removed| removed == path)
+        {
+            channel.removed_props.push(path.to_owned());
+        }
+    }
+
+    // moves props and the ones under them, all at once so they can be swapped,
+    // dropping removals of the new paths which were pruned before they moved
+    pub(crate) fn move_props(
+        &mut self,
+        channel: &str,
+        moves: &BTreeMap<String, String>,
+    ) {
+        let channel = self.channel(channel);
+        let mut moved = Vec::new();
+        for (from, to) in moves {
+            if let Some(value) = channel.props.take(from) {
+                moved.push((to, value));
+            }
+        }
+        for (to, value) in moved {
+            channel.removed_props.retain(|removed| {
+                removed != to && !removed.starts_with(&format!("{}/", to))
+            });
+            if let Some((parent, name)) = to.rsplit_once('/') {
+                let props = match parent.is_empty() {
+                    true => &mut channel.props,
+                    false => match channel.props.get_mut(parent) {
+                        Some(parent) => &mut parent.props,
+                        None => continue,
+                    },
+                };
+                props.0.insert(name.to_owned(), value);
+            }
+        }
+    }
+
+    pub(crate) fn assign_plugin(&mut self, plugin: AssignedPlugin) {
+        self.assigned_plugins.push(plugin);
+    }
+
+    pub(crate) fn take_assigned_plugins(&mut self) -> Vec<AssignedPlugin> {
+        std::mem::take(&mut self.assigned_plugins)
+    }
+
+    pub(crate) fn defer_desktop(&mut self, desktop: Desktop) {
+        self.desktop = Some(desktop);
+    }
+
+    // compiles the deferred desktop block for the monitors of this machine
+    pub(crate) fn resolve_monitors(&mut self) -> Result<()> {
+        match self.desktop.take() {
+            Some(desktop) => desktop
+                .compile_for_monitors(self)
+                .context("error compiling desktop"),
+            None => Ok(()),
+        }
+    }
+
+    // the channel, which is added if it's missing
+    fn channel(&mut self, name: &str) -> &mut Channel {
+        (self.channels.0)
+            .0
+            .entry(name.to_owned())
+            .or_insert_with(|| Channel {
+                name: name.to_owned(),
+                props: Properties::default(),
+                locked: Vec::new(),
+                removed_props: Vec::new(),
+                exclusive: false,
+            })
+    }
+
+    // the prop at the path, which is added with its channel and parents if
+    // it's missing
+    fn entry(&mut self, channel: &str, path: &str) -> Option<&mut Value> {
+        let channel = self.channel(channel);
+        let mut props = &mut channel.props;
+        let mut parts = path.split('/').filter(|part| !part.is_empty());
+        let mut part = parts.next();
+        while let Some(name) = part {
+            let prop =
+                props.0.entry(name.to_owned()).or_insert_with(|| Value {
+                    value: TypedValue::Empty,
+                    normalize: Vec::new(),
+                    props: Properties::default(),
+                });
+            part = parts.next();
+            if part.is_none() {
+                return Some(prop);
+            }
+            props = &mut prop.props;
+        }
+        None
+    }
+
+    // ids and types of the /plugins/plugin-N props of the panel channel
+    pub(crate) fn panel_plugins(&self) -> BTreeMap<u32, &str> {
+        (self.channels.0)
+            .0
+            .get("xfce4-panel")
+            .and_then(|channel| channel.props.0.get("plugins"))
+            .into_iter()
+            .flat_map(|plugins| plugins.props.0.iter())
+            .filter_map(|(name, plugin)| {
+                let id = name.strip_prefix("plugin-")?.parse().ok()?;
+                match &plugin.value {
+                    TypedValue::String(plugin_type) => {
+                        Some((id, &**plugin_type))
+                    },
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    // plugin types of the /plugins/plugin-N props of the panel channel
+    pub fn panel_plugin_types(&self) -> impl Iterator<Item = &str> + '_ {
+        (self.channels.0)
+            .0
+            .get("xfce4-panel")
+            .and_then(|channel| channel.props.0.get("plugins"))
+            .into_iter()
+            .flat_map(|plugins| plugins.props.0.iter())
+            .filter(|(name, _)| name.starts_with("plugin-"))
+            .filter_map(|(_, plugin)| match &plugin.value {
+                TypedValue::String(plugin_type) => Some(plugin_type.as_str()),
+                _ => None,
+            })
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct Channels(IdMap<Channel>);
 
 impl Channels {
@@ -39,27 +395,112 @@ struct Channel {
     name: String,
     #[serde(default, skip_serializing_if = "Properties::is_empty")]
     props: Properties,
+    // paths of props which are locked in the generated kiosk files
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    locked: Vec<String>,
+    // paths of props which must not exist, reset if they do
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    removed_props: Vec<String>,
+    // every prop which isn't declared is reset
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    exclusive: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
-struct Properties(BTreeMap<String, Value>);
+struct Properties(
+    #[serde(deserialize_with = "crate::serde::deserialize_unannotated")]
+    BTreeMap<String, Value>,
+);
 
 impl Properties {
     fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
+
+    // number of props with a value, not counting empty parents
+    fn prop_count(&self) -> usize {
+        self.0.values().map(Value::prop_count).sum()
+    }
+
+    fn filter(&mut self, filter: &PartFilter, id: &str) {
+        self.0.retain(|key, value| {
+            value.filter(filter, &format!("{}/{}", id, key));
+            value.prop_count() > 0
+        });
+    }
+
+    fn parts(&self, id: &str, parts: &mut Vec<(String, String)>) {
+        for (key, value) in &self.0 {
+            value.parts(&format!("{}/{}", id, key), parts);
+        }
+    }
+
+    fn collect_unused(
+        &self,
+        channel: &str,
+        path: &str,
+        unused: &mut Vec<String>,
+    ) {
+        for (name, value) in &self.0 {
+            let path = format!("{}/{}", path, name);
+            if matches!(value.value, TypedValue::Empty)
+                && value.props.is_empty()
+            {
+                unused.push(format!(
+                    "xfconf property {} in channel {} has no value or props",
+                    path, channel
+                ));
+            }
+            value.props.collect_unused(channel, &path, unused);
+        }
+    }
+
+    fn collect_declared(&self, path: &str, declared: &mut BTreeSet<String>) {
+        for (name, value) in &self.0 {
+            let path = format!("{}/{}", path, name);
+            if !matches!(value.value, TypedValue::Empty) {
+                declared.insert(path.clone());
+            }
+            value.props.collect_declared(&path, declared);
+        }
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 #[serde(rename_all = "kebab-case")]
 struct Value {
     #[serde(flatten)]
     value: TypedValue,
+    // applied to both old and new string values before comparing them, after
+    // the default rules for the prop
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    normalize: Vec<Normalize>,
     #[serde(default, skip_serializing_if = "Properties::is_empty")]
     props: Properties,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+impl Value {
+    fn filter(&mut self, filter: &PartFilter, id: &str) {
+        if !filter.keeps(id) {
+            self.value = TypedValue::Empty;
+        }
+        self.props.filter(filter, id);
+    }
+
+    fn parts(&self, id: &str, parts: &mut Vec<(String, String)>) {
+        if !matches!(self.value, TypedValue::Empty) {
+            parts.push((id.to_owned(), format!("set to {}", self.value)));
+        }
+        self.props.parts(id, parts);
+    }
+
+    fn prop_count(&self) -> usize {
+        usize::from(!matches!(self.value, TypedValue::Empty))
+            + self.props.prop_count()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 #[serde(tag = "type", content = "value", rename_all = "kebab-case")]
 enum TypedValue {
     Bool(bool),
@@ -71,33 +512,148 @@ enum TypedValue {
     Empty,
 }
 
-#[derive(Debug)]
+// for prompts, arrays only by their length
+impl fmt::Display for TypedValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(value) => value.fmt(f),
+            Self::Int(value) => value.fmt(f),
+            Self::Uint(value) => value.fmt(f),
+            Self::Double(value) => value.fmt(f),
+            Self::String(value) => write!(f, "{:?}", value),
+            Self::Array(values) => write!(f, "an array of {}", values.len()),
+            Self::Empty => "nothing".fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Normalize {
+    Trim,
+    CollapseWhitespace,
+    // ~ and $HOME at the start are expanded
+    HomeDir,
+    TrailingSlash,
+    Lowercase,
+}
+
+// turns a string value into the form it's compared in, so different
+// representations of the same setting don't show up as changes
+trait Normalizer {
+    fn normalize(&self, s: &str) -> String;
+}
+
+// rules for props whose values xfce rewrites to an equivalent form, applied
+// before the ones from the config, a * matches within one path segment
+const NORMALIZE_DEFAULTS: &[(&str, &str, &[Normalize])] = &[
+    (
+        "xfce4-desktop",
+        "/backdrop/*/*/*/last-image",
+        &[Normalize::HomeDir],
+    ),
+    ("xfce4-terminal", "/color-*", &[Normalize::Lowercase]),
+];
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ValueType {
+    Bool,
+    Int,
+    Uint,
+    Double,
+    String,
+    Array,
+    Empty,
+}
+
+#[derive(Debug, Clone)]
 struct ClearPath {
     channel: String,
     parts: Vec<ClearPathPart>,
     props: ClearPathProps,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct ClearPathPart {
     prop: String,
     prefix: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct ClearPathProps {
     value_changed: bool,
     prefix: Option<String>,
 }
 
-impl Xfconf {
-    pub fn load() -> Result<Self> {
+impl Section for Xfconf {
+    type Patch = XfconfPatch;
+
+    fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    fn schema() -> serde_json::Value {
+        let channel = schema::required(
+            schema::object(vec![
+                ("name", schema::string()),
+                ("props", schema::map(schema::reference(VALUE_DEF), true)),
+                ("locked", schema::array(schema::string())),
+                ("removed-props", schema::array(schema::string())),
+                ("exclusive", schema::boolean()),
+            ]),
+            &["name"],
+        );
+        schema::object(vec![
+            ("channels", schema::array(channel)),
+            // like `<channel>.<prop>.<prefix>*`
+            ("clear-paths", schema::array(schema::string())),
+        ])
+    }
+
+    // TODO: consider new.channels to only load used channels
+    fn read(_new: &Self, _dirs: &Dirs) -> Result<Self> {
         Ok(Self {
             channels: Channels::load().context("error loading channels")?,
             // clear paths from env are unused (only ones from input are used)
             clear_paths: Vec::new(),
+            assigned_plugins: Vec::new(),
+            desktop: None,
         })
     }
+
+    fn diff(old: Self, new: Self) -> Result<XfconfPatch> {
+        for channel in (new.channels.0).0.values() {
+            for path in &channel.removed_props {
+                if channel.props.get(path).is_some() {
+                    bail!(
+                        "property {} in channel {} is both declared and \
+                         removed",
+                        path,
+                        channel.name
+                    );
+                }
+            }
+        }
+        Ok(XfconfPatch::diff(old, new))
+    }
+
+    fn retain(&mut self, id: &str) -> Result<()> {
+        if !self.channels.0.retain_id(id) {
+            bail!("no channel {}", id);
+        }
+        self.clear_paths
+            .retain(|clear_path| clear_path.channel == id);
+        Ok(())
+    }
+
+    fn apply_to_old(patch: XfconfPatch, old: &mut Self) {
+        patch.channels.apply_to_old(&mut old.channels);
+    }
+
+    fn unused(&self, _old: &Self) -> Vec<String> {
+        self.unused()
+    }
 }
 
 impl Channels {
@@ -108,54 +664,6 @@ impl Channels {
             .try_child_value(0)
             .context("ListChannels had empty return value")?;
 
-        fn value_from_variant(variant: &glib::Variant) -> Result<TypedValue> {
-            variant
-                .get::<bool>()
-                .map(TypedValue::Bool)
-                .or_else(|| variant.get::<i32>().map(TypedValue::Int))
-                .or_else(|| variant.get::<u32>().map(TypedValue::Uint))
-                .or_else(|| variant.get::<f64>().map(TypedValue::Double))
-                .or_else(|| variant.get::<String>().map(TypedValue::String))
-                .map(Ok)
-                .or_else(|| {
-                    variant.get::<Vec<glib::Variant>>().map(|array| {
-                        array
-                            .into_iter()
-                            .map(array_value_from_variant)
-                            .map(|value| {
-                                value.map(|value| Value {
-                                    value,
-                                    props: Properties::default(),
-                                })
-                            })
-                            .collect::<Result<Vec<_>>>()
-                            .map(TypedValue::Array)
-                    })
-                })
-                .with_context(|| {
-                    format!("unknown value type {}", variant.type_().as_str())
-                })
-                .and_then(std::convert::identity)
-        }
-
-        fn array_value_from_variant(
-            variant: glib::Variant,
-        ) -> Result<TypedValue> {
-            variant
-                .get::<bool>()
-                .map(TypedValue::Bool)
-                .or_else(|| variant.get::<i32>().map(TypedValue::Int))
-                .or_else(|| variant.get::<u32>().map(TypedValue::Uint))
-                .or_else(|| variant.get::<f64>().map(TypedValue::Double))
-                .or_else(|| variant.get::<String>().map(TypedValue::String))
-                .with_context(|| {
-                    format!(
-                        "unknown array value type {}",
-                        variant.type_().as_str()
-                    )
-                })
-        }
-
         channels
             .array_iter_str()
             .context("error reading iterating channels")?
@@ -172,7 +680,13 @@ impl Channels {
                             .context("error getting prop entry")?;
                         let path = entry.key();
                         let value = entry.value();
-                        let value = value_from_variant(value)?;
+                        let value =
+                            value_from_variant(value).with_context(|| {
+                                format!(
+                                    "error reading property {} in channel {}",
+                                    path, name
+                                )
+                            })?;
                         Ok((path.clone(), value))
                     })
                     .collect::<Result<Vec<_>>>()?;
@@ -194,6 +708,7 @@ impl Channels {
                                 .entry(path_part)
                                 .or_insert_with(|| Value {
                                     value: TypedValue::Empty,
+                                    normalize: Vec::new(),
                                     props: Properties::default(),
                                 })
                                 .props
@@ -205,6 +720,7 @@ impl Channels {
                         btree_map::Entry::Vacant(entry) => {
                             entry.insert(Value {
                                 value,
+                                normalize: Vec::new(),
                                 props: Properties::default(),
                             });
                         },
@@ -213,51 +729,511 @@ impl Channels {
                         },
                     }
                 }
-                Ok(Channel { name, props })
+                Ok(Channel {
+                    name,
+                    props,
+                    locked: Vec::new(),
+                    removed_props: Vec::new(),
+                    exclusive: false,
+                })
             })
             .collect::<Result<IdMap<_>>>()
             .map(Self)
     }
 }
 
-impl crate::serde::Id for Channel {
-    type Id = String;
+impl Normalize {
+    // the default rules for a prop followed by the configured ones
+    fn pipeline(
+        channel: &str,
+        prop_path: &str,
+        configured: &[Self],
+    ) -> Vec<Self> {
+        NORMALIZE_DEFAULTS
+            .iter()
+            .filter(|(default_channel, pattern, _)| {
+                *default_channel == channel && path_matches(pattern, prop_path)
+            })
+            .flat_map(|(_, _, rules)| rules.iter().copied())
+            .chain(configured.iter().copied())
+            .collect()
+    }
+}
 
-    fn id(&self) -> &Self::Id {
-        &self.name
+impl Normalizer for Normalize {
+    fn normalize(&self, s: &str) -> String {
+        match self {
+            Self::Trim => s.trim().to_owned(),
+            Self::CollapseWhitespace => {
+                let mut collapsed = String::with_capacity(s.len());
+                let mut last_whitespace = false;
+                for c in s.chars() {
+                    if c.is_whitespace() {
+                        if !last_whitespace {
+                            collapsed.push(' ');
+                        }
+                        last_whitespace = true;
+                    } else {
+                        collapsed.push(c);
+                        last_whitespace = false;
+                    }
+                }
+                collapsed
+            },
+            Self::HomeDir => match env::var("HOME") {
+                Ok(home) => expand_home(s, &home),
+                Err(_) => s.to_owned(),
+            },
+            Self::TrailingSlash => match s.trim_end_matches('/') {
+                "" if !s.is_empty() => "/".to_owned(),
+                trimmed => trimmed.to_owned(),
+            },
+            Self::Lowercase => s.to_lowercase(),
+        }
     }
 }
 
-impl FromStr for ClearPath {
-    type Err = Error;
+impl<N: Normalizer> Normalizer for [N] {
+    fn normalize(&self, s: &str) -> String {
+        self.iter().fold(s.to_owned(), |s, step| step.normalize(&s))
+    }
+}
 
-    fn from_str(input: &str) -> Result<Self, Self::Err> {
-        let mut input_parts = input.split('.').peekable();
-        let channel = input_parts.next().context("missing channel")?;
-        let mut parts = Vec::new();
-        let mut props = None;
-        while let Some(part) = input_parts.next() {
-            if input_parts.peek().is_none() {
-                let (part, value_changed) =
-                    if let Some(part) = part.strip_prefix('~') {
-                        (part, true)
-                    } else {
-                        (part, false)
-                    };
-                let prefix = if let Some(prefix) = part.strip_suffix('*') {
-                    (!prefix.is_empty()).then(|| prefix)
-                } else {
-                    bail!("missing `*` in final prop specifier")
+fn expand_home(s: &str, home: &str) -> String {
+    for prefix in ["~", "$HOME", "${HOME}"] {
+        if let Some(rest) = s.strip_prefix(prefix) {
+            if rest.is_empty() || rest.starts_with('/') {
+                return format!("{}{}", home, rest);
+            }
+        }
+    }
+    s.to_owned()
+}
+
+fn path_matches(pattern: &str, path: &str) -> bool {
+    let mut pattern_parts = pattern.split('/');
+    let mut path_parts = path.split('/');
+    loop {
+        match (pattern_parts.next(), path_parts.next()) {
+            (Some(pattern), Some(part)) => {
+                let matches = match pattern.split_once('*') {
+                    Some((prefix, suffix)) => {
+                        part.len() >= prefix.len() + suffix.len()
+                            && part.starts_with(prefix)
+                            && part.ends_with(suffix)
+                    },
+                    None => pattern == part,
                 };
-                props = Some(ClearPathProps {
-                    value_changed,
-                    prefix: prefix.map(|prefix| prefix.to_owned()),
-                });
-            } else {
-                let (prop, prefix) = if let Some(prop) = part.strip_suffix('*')
-                {
-                    (prop, true)
-                } else {
+                if !matches {
+                    return false;
+                }
+            },
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+fn value_from_variant(variant: &glib::Variant) -> Result<TypedValue> {
+    variant
+        .get::<bool>()
+        .map(TypedValue::Bool)
+        .or_else(|| variant.get::<i32>().map(TypedValue::Int))
+        .or_else(|| variant.get::<u32>().map(TypedValue::Uint))
+        .or_else(|| variant.get::<f64>().map(TypedValue::Double))
+        .or_else(|| variant.get::<String>().map(TypedValue::String))
+        .map(Ok)
+        .or_else(|| {
+            variant.get::<Vec<glib::Variant>>().map(|array| {
+                array
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, variant)| {
+                        array_value_from_variant(variant).with_context(|| {
+                            format!("error reading array element {}", i)
+                        })
+                    })
+                    .map(|value| {
+                        value.map(|value| Value {
+                            value,
+                            normalize: Vec::new(),
+                            props: Properties::default(),
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()
+                    .map(TypedValue::Array)
+            })
+        })
+        .with_context(|| {
+            format!("unknown value type {}", variant.type_().as_str())
+        })
+        .and_then(std::convert::identity)
+}
+
+fn array_value_to_variant(value: TypedValue) -> Result<glib::Variant> {
+    match value {
+        TypedValue::Bool(b) => Ok(glib::variant::ToVariant::to_variant(&b)),
+        TypedValue::Int(n) => Ok(glib::variant::ToVariant::to_variant(&n)),
+        TypedValue::Uint(n) => Ok(glib::variant::ToVariant::to_variant(&n)),
+        TypedValue::Double(f) => Ok(glib::variant::ToVariant::to_variant(&f)),
+        TypedValue::String(s) => Ok(glib::variant::ToVariant::to_variant(&*s)),
+        TypedValue::Array(_) => bail!("xfconf arrays can't be nested"),
+        TypedValue::Empty => bail!("xfconf arrays can't hold empty values"),
+    }
+}
+
+fn array_value_from_variant(variant: glib::Variant) -> Result<TypedValue> {
+    variant
+        .get::<bool>()
+        .map(TypedValue::Bool)
+        .or_else(|| variant.get::<i32>().map(TypedValue::Int))
+        .or_else(|| variant.get::<u32>().map(TypedValue::Uint))
+        .or_else(|| variant.get::<f64>().map(TypedValue::Double))
+        .or_else(|| variant.get::<String>().map(TypedValue::String))
+        .with_context(|| {
+            format!("unknown array value type {}", variant.type_().as_str())
+        })
+}
+
+// a property change reported by xfconfd
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PropertyChange {
+    channel: String,
+    property: String,
+    // none if the property was removed
+    value: Option<TypedValue>,
+}
+
+impl PropertyChange {
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    fn from_signal(signal: &str, args: &glib::Variant) -> Result<Option<Self>> {
+        match signal {
+            "PropertyChanged" => {
+                let (channel, property, value) = args
+                    .get::<(String, String, glib::Variant)>()
+                    .context("invalid PropertyChanged args")?;
+                Ok(Some(Self {
+                    channel,
+                    property,
+                    value: Some(value_from_variant(&value)?),
+                }))
+            },
+            "PropertyRemoved" => {
+                let (channel, property) = args
+                    .get::<(String, String)>()
+                    .context("invalid PropertyRemoved args")?;
+                Ok(Some(Self {
+                    channel,
+                    property,
+                    value: None,
+                }))
+            },
+            _ => Ok(None),
+        }
+    }
+}
+
+// calls f with every property change while the main loop runs, the returned
+// connection has to be kept alive for that
+pub fn watch<F>(f: F) -> Result<DBus>
+where
+    F: Fn(PropertyChange) + Send + Sync + 'static,
+{
+    let dbus = DBus::new("org.xfce.Xfconf", "/org/xfce/Xfconf")?;
+    dbus.connect_signal(move |signal, args| match PropertyChange::from_signal(
+        signal, args,
+    ) {
+        Ok(Some(change)) => f(change),
+        Ok(None) => {},
+        Err(error) => crate::warn(format_args!("{:#}", error)),
+    });
+    Ok(dbus)
+}
+
+impl ValueType {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Bool => "bool",
+            Self::Int => "int",
+            Self::Uint => "uint",
+            Self::Double => "double",
+            Self::String => "string",
+            Self::Array => "array",
+            Self::Empty => "empty",
+        }
+    }
+}
+
+impl TypedValue {
+    // infers the type of a shorthand value, integers are assumed to be `int`
+    // unless they are out of range so `uint` has to be given explicitly
+    fn infer(value: serde_json::Value) -> Result<Self, String> {
+        use serde_json::Value as Json;
+
+        match value {
+            Json::Null => Ok(Self::Empty),
+            Json::Bool(b) => Ok(Self::Bool(b)),
+            Json::Number(n) if n.is_f64() => {
+                n.as_f64().map(Self::Double).ok_or_else(|| n.to_string())
+            },
+            Json::Number(n) => n
+                .as_i64()
+                .and_then(|n| i32::try_from(n).ok())
+                .map(Self::Int)
+                .or_else(|| {
+                    n.as_u64()
+                        .and_then(|n| u32::try_from(n).ok())
+                        .map(Self::Uint)
+                })
+                .ok_or_else(|| format!("integer {} out of range", n)),
+            Json::String(s) => Ok(Self::String(s)),
+            Json::Array(values) => {
+                serde_json::from_value(Json::Array(promote_integers(values)))
+                    .map(Self::Array)
+                    .map_err(|error| error.to_string())
+            },
+            Json::Object(_) => {
+                Err("cannot infer value type of object".to_owned())
+            },
+        }
+    }
+
+    fn from_json(
+        r#type: ValueType,
+        value: serde_json::Value,
+    ) -> Result<Self, String> {
+        use serde_json::Value as Json;
+
+        fn invalid(r#type: ValueType, value: &Json) -> String {
+            format!("invalid {} value {}", r#type.name(), value)
+        }
+
+        fn coerce(r#type: ValueType, value: &Json) {
+            crate::warn(format_args!(
+                "coercing {} to {} value",
+                value,
+                r#type.name()
+            ));
+        }
+
+        fn parse<T>(r#type: ValueType, value: &Json) -> Result<T, String>
+        where
+            T: FromStr,
+        {
+            let parsed = value
+                .as_str()
+                .and_then(|s| s.trim().parse().ok())
+                .ok_or_else(|| invalid(r#type, value))?;
+            coerce(r#type, value);
+            Ok(parsed)
+        }
+
+        match (r#type, value) {
+            (ValueType::Bool, Json::Bool(b)) => Ok(Self::Bool(b)),
+            (ValueType::Bool, value @ Json::String(_)) => {
+                parse(r#type, &value).map(Self::Bool)
+            },
+            (ValueType::Int, Json::Number(n)) => n
+                .as_i64()
+                .and_then(|n| i32::try_from(n).ok())
+                .map(Self::Int)
+                .ok_or_else(|| invalid(r#type, &Json::Number(n))),
+            (ValueType::Int, value @ Json::String(_)) => {
+                parse(r#type, &value).map(Self::Int)
+            },
+            (ValueType::Uint, Json::Number(n)) => n
+                .as_u64()
+                .and_then(|n| u32::try_from(n).ok())
+                .map(Self::Uint)
+                .ok_or_else(|| invalid(r#type, &Json::Number(n))),
+            (ValueType::Uint, value @ Json::String(_)) => {
+                parse(r#type, &value).map(Self::Uint)
+            },
+            (ValueType::Double, Json::Number(n)) => n
+                .as_f64()
+                .map(Self::Double)
+                .ok_or_else(|| invalid(r#type, &Json::Number(n))),
+            (ValueType::Double, value @ Json::String(_)) => {
+                parse(r#type, &value).map(Self::Double)
+            },
+            (ValueType::String, Json::String(s)) => Ok(Self::String(s)),
+            (ValueType::String, value @ (Json::Number(_) | Json::Bool(_))) => {
+                coerce(r#type, &value);
+                Ok(Self::String(value.to_string()))
+            },
+            (ValueType::Array, Json::Array(values)) => {
+                serde_json::from_value(Json::Array(promote_integers(values)))
+                    .map(Self::Array)
+                    .map_err(|error| error.to_string())
+            },
+            (ValueType::Empty, Json::Null) => Ok(Self::Empty),
+            (_, value) => Err(invalid(r#type, &value)),
+        }
+    }
+}
+
+// shorthand integers in an array which also has shorthand doubles are doubles
+// too, e.g. display scales like `[1, 1.5]`
+fn promote_integers(values: Vec<serde_json::Value>) -> Vec<serde_json::Value> {
+    use serde_json::Value as Json;
+
+    if !values
+        .iter()
+        .any(|value| matches!(value, Json::Number(n) if n.is_f64()))
+    {
+        return values;
+    }
+    values
+        .into_iter()
+        .map(|value| match value {
+            Json::Number(n) => n
+                .as_f64()
+                .and_then(serde_json::Number::from_f64)
+                .map_or(Json::Number(n), Json::Number),
+            value => value,
+        })
+        .collect()
+}
+
+impl<'de> de::Deserialize<'de> for TypedValue {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Tagged {
+            #[serde(default)]
+            r#type: Option<ValueType>,
+            #[serde(default)]
+            value: serde_json::Value,
+        }
+
+        let Tagged { r#type, value } = Tagged::deserialize(deserializer)?;
+        match r#type {
+            Some(r#type) => Self::from_json(r#type, value),
+            None => Self::infer(value),
+        }
+        .map_err(de::Error::custom)
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Full {
+            #[serde(flatten)]
+            value: TypedValue,
+            #[serde(default)]
+            normalize: Vec<Normalize>,
+            #[serde(default)]
+            props: Properties,
+        }
+
+        // anything other than an object is a shorthand value without props
+        match serde_json::Value::deserialize(deserializer)? {
+            value @ serde_json::Value::Object(_) => {
+                serde_json::from_value(value)
+                    .map(
+                        |Full {
+                             value,
+                             normalize,
+                             props,
+                         }| Self {
+                            value,
+                            normalize,
+                            props,
+                        },
+                    )
+                    .map_err(|error| error.to_string())
+            },
+            value => TypedValue::infer(value).map(|value| Self {
+                value,
+                normalize: Vec::new(),
+                props: Properties::default(),
+            }),
+        }
+        .map_err(de::Error::custom)
+    }
+}
+
+// name of the definition of a prop's value in the schema, which props nested
+// in it refer back to
+pub(crate) const VALUE_DEF: &str = "xfconf-value";
+
+// shorthands have their type inferred, only objects can hold nested props
+pub(crate) fn value_schema() -> serde_json::Value {
+    let value = schema::reference(VALUE_DEF);
+    schema::any_of(vec![
+        serde_json::json!({ "type": ["null", "boolean", "number", "string"] }),
+        schema::array(value.clone()),
+        schema::object(vec![
+            (
+                "type",
+                schema::string_enum(&[
+                    "bool", "int", "uint", "double", "string", "array", "empty",
+                ]),
+            ),
+            ("value", schema::any()),
+            (
+                "normalize",
+                schema::array(schema::string_enum(&[
+                    "trim",
+                    "collapse-whitespace",
+                    "home-dir",
+                    "trailing-slash",
+                    "lowercase",
+                ])),
+            ),
+            ("props", schema::map(value, true)),
+        ]),
+    ])
+}
+
+impl crate::serde::Id for Channel {
+    type Id = String;
+
+    fn id(&self) -> &Self::Id {
+        &self.name
+    }
+}
+
+impl FromStr for ClearPath {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut input_parts = input.split('.').peekable();
+        let channel = input_parts.next().context("missing channel")?;
+        let mut parts = Vec::new();
+        let mut props = None;
+        while let Some(part) = input_parts.next() {
+            if input_parts.peek().is_none() {
+                let (part, value_changed) =
+                    if let Some(part) = part.strip_prefix('~') {
+                        (part, true)
+                    } else {
+                        (part, false)
+                    };
+                let prefix = if let Some(prefix) = part.strip_suffix('*') {
+                    (!prefix.is_empty()).then(|| prefix)
+                } else {
+                    bail!("missing `*` in final prop specifier")
+                };
+                props = Some(ClearPathProps {
+                    value_changed,
+                    prefix: prefix.map(|prefix| prefix.to_owned()),
+                });
+            } else {
+                let (prop, prefix) = if let Some(prop) = part.strip_suffix('*')
+                {
+                    (prop, true)
+                } else {
                     (part, false)
                 };
                 parts.push(ClearPathPart {
@@ -356,10 +1332,6 @@ impl XfconfPatch {
         }
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.channels.is_empty()
-    }
-
     pub fn has_panel_changes(&self) -> bool {
         self.channels.changed.contains_key("xfce4-panel")
             || self
@@ -380,6 +1352,17 @@ struct ChannelsPatch {
 }
 
 impl ChannelsPatch {
+    fn apply_to_old(self, old: &mut Channels) {
+        for (name, channel_patch) in self.changed {
+            if let Some(channel) = (old.0).0.get_mut(&name) {
+                channel_patch.props.apply_to_old(&mut channel.props);
+            }
+        }
+        for channel in self.added {
+            (old.0).0.insert(channel.name.clone(), channel);
+        }
+    }
+
     fn diff(
         mut old: Channels,
         new: Channels,
@@ -401,8 +1384,20 @@ impl ChannelsPatch {
         Self { changed, added }
     }
 
+    fn change_count(&self) -> ChangeStats {
+        self.changed
+            .values()
+            .map(ChannelPatch::change_count)
+            .chain(
+                self.added.iter().map(|channel| {
+                    ChangeStats::added(channel.props.prop_count())
+                }),
+            )
+            .sum()
+    }
+
     fn is_empty(&self) -> bool {
-        self.changed.is_empty() && self.added.is_empty()
+        self.change_count().is_empty()
     }
 }
 
@@ -422,24 +1417,40 @@ impl ChannelPatch {
             props: im::Vector::new(),
         };
         let properties_ctx = PropertiesCtx::Channel(old.clone(), new.clone());
+        let mut props = PropertiesPatch::diff(
+            old.props.clone(),
+            new.props.clone(),
+            &path,
+            properties_ctx,
+            clear_paths,
+        );
+        if new.exclusive {
+            props.remove_undeclared(&old.props, &new.props);
+        }
+        for path in &new.removed_props {
+            let parts = path
+                .split('/')
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>();
+            props.remove(&old.props, &parts);
+        }
         Self {
             name: SimplePatch::diff(old.name, new.name),
-            props: PropertiesPatch::diff(
-                old.props,
-                new.props,
-                &path,
-                properties_ctx,
-                clear_paths,
-            ),
+            props,
         }
     }
 
+    fn change_count(&self) -> ChangeStats {
+        ChangeStats::changed(usize::from(!self.name.is_empty()))
+            + self.props.change_count()
+    }
+
     fn is_empty(&self) -> bool {
-        self.name.is_empty() && self.props.is_empty()
+        self.change_count().is_empty()
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Default, Serialize)]
 #[serde(rename_all = "kebab-case")]
 struct PropertiesPatch {
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
@@ -497,6 +1508,66 @@ impl ClearPath {
 }
 
 impl PropertiesPatch {
+    // removes the prop at the path if it exists in old, through the patches
+    // of its parents
+    fn remove(&mut self, old: &Properties, parts: &[&str]) {
+        let (key, rest) = match parts.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        let old_value = match old.0.get(*key) {
+            Some(old_value) => old_value,
+            None => return,
+        };
+        if rest.is_empty() {
+            self.changed.remove(*key);
+            self.removed.insert((*key).to_owned());
+        } else if !self.removed.contains(*key) {
+            self.changed
+                .entry((*key).to_owned())
+                .or_insert_with(ValuePatch::empty)
+                .props
+                .remove(&old_value.props, rest);
+        }
+    }
+
+    // removes every prop of old which new doesn't declare
+    fn remove_undeclared(&mut self, old: &Properties, new: &Properties) {
+        for (key, old_value) in &old.0 {
+            let new_value = match new.0.get(key) {
+                Some(new_value) => new_value,
+                None => {
+                    self.changed.remove(key);
+                    self.removed.insert(key.clone());
+                    continue;
+                },
+            };
+            let value_patch = self
+                .changed
+                .entry(key.clone())
+                .or_insert_with(ValuePatch::empty);
+            value_patch
+                .props
+                .remove_undeclared(&old_value.props, &new_value.props);
+            if value_patch.is_empty() {
+                self.changed.remove(key);
+            }
+        }
+    }
+
+    fn apply_to_old(self, old: &mut Properties) {
+        for (key, value_patch) in self.changed {
+            if let Some(value) = old.0.get_mut(&key) {
+                value_patch.value.apply_to_old(&mut value.value);
+                value_patch.props.apply_to_old(&mut value.props);
+            }
+        }
+        old.0.extend(self.added);
+        for key in self.removed {
+            old.0.remove(&key);
+        }
+    }
+
     fn diff(
         mut old: Properties,
         new: Properties,
@@ -546,10 +1617,56 @@ impl PropertiesPatch {
         }
     }
 
+    fn change_count(&self) -> ChangeStats {
+        self.changed
+            .values()
+            .map(ValuePatch::change_count)
+            .chain(
+                self.added
+                    .values()
+                    .map(|value| ChangeStats::added(value.prop_count())),
+            )
+            .sum::<ChangeStats>()
+            + ChangeStats::removed(self.removed.len())
+    }
+
+    // drops the changes to props the filter doesn't keep, ids of props being
+    // the id of their parent followed by `/<key>`
+    fn filter(&mut self, filter: &PartFilter, id: &str) {
+        self.changed.retain(|key, value_patch| {
+            let id = format!("{}/{}", id, key);
+            if !filter.keeps(&id) {
+                value_patch.value = TypedValuePatch::Empty;
+            }
+            value_patch.props.filter(filter, &id);
+            !value_patch.is_empty()
+        });
+        self.added.retain(|key, value| {
+            value.filter(filter, &format!("{}/{}", id, key));
+            value.prop_count() > 0
+        });
+        self.removed
+            .retain(|key| filter.keeps_all(&format!("{}/{}", id, key)));
+    }
+
+    fn parts(&self, id: &str, parts: &mut Vec<(String, String)>) {
+        for (key, value_patch) in &self.changed {
+            let id = format!("{}/{}", id, key);
+            if let Some(value) = value_patch.value.new_value() {
+                parts.push((id.clone(), format!("set to {}", value)));
+            }
+            value_patch.props.parts(&id, parts);
+        }
+        for (key, value) in &self.added {
+            value.parts(&format!("{}/{}", id, key), parts);
+        }
+        for key in &self.removed {
+            parts.push((format!("{}/{}", id, key), "remove".to_owned()));
+        }
+    }
+
     fn is_empty(&self) -> bool {
-        self.changed.is_empty()
-            && self.added.is_empty()
-            && self.removed.is_empty()
+        self.change_count().is_empty()
     }
 }
 
@@ -563,6 +1680,14 @@ struct ValuePatch {
 }
 
 impl ValuePatch {
+    // changes nothing until props under it are changed
+    fn empty() -> Self {
+        Self {
+            value: TypedValuePatch::Empty,
+            props: PropertiesPatch::default(),
+        }
+    }
+
     fn diff(
         old: Value,
         new: Value,
@@ -570,8 +1695,14 @@ impl ValuePatch {
         clear_paths: &[ClearPath],
     ) -> Self {
         let properties_ctx = PropertiesCtx::Value(old.clone(), new.clone());
+        let normalize = match path.prop_path() {
+            Some((channel, prop_path)) => {
+                Normalize::pipeline(channel, &prop_path, &new.normalize)
+            },
+            None => new.normalize.clone(),
+        };
         Self {
-            value: TypedValuePatch::diff(old.value, new.value),
+            value: TypedValuePatch::diff(old.value, new.value, &normalize),
             props: PropertiesPatch::diff(
                 old.props,
                 new.props,
@@ -582,8 +1713,13 @@ impl ValuePatch {
         }
     }
 
+    fn change_count(&self) -> ChangeStats {
+        ChangeStats::changed(usize::from(!self.value.is_empty()))
+            + self.props.change_count()
+    }
+
     fn is_empty(&self) -> bool {
-        self.value.is_empty() && self.props.is_empty()
+        self.change_count().is_empty()
     }
 }
 
@@ -595,13 +1731,29 @@ enum TypedValuePatch {
     Uint(SimplePatch<u32>),
     Double(SimplePatch<f64>),
     String(SimplePatch<String>),
-    Array(SimplePatch<Vec<Value>>),
+    Array(ArrayPatch),
     Empty,
     Changed(TypedValue),
 }
 
 impl TypedValuePatch {
-    fn diff(old: TypedValue, new: TypedValue) -> Self {
+    fn apply_to_old(self, old: &mut TypedValue) {
+        let new = match self {
+            Self::Bool(patch) => patch.value.map(TypedValue::Bool),
+            Self::Int(patch) => patch.value.map(TypedValue::Int),
+            Self::Uint(patch) => patch.value.map(TypedValue::Uint),
+            Self::Double(patch) => patch.value.map(TypedValue::Double),
+            Self::String(patch) => patch.value.map(TypedValue::String),
+            Self::Array(patch) => patch.value.map(TypedValue::Array),
+            Self::Empty => None,
+            Self::Changed(value) => Some(value),
+        };
+        if let Some(new) = new {
+            *old = new;
+        }
+    }
+
+    fn diff(old: TypedValue, new: TypedValue, normalize: &[Normalize]) -> Self {
         match (old, new) {
             (TypedValue::Bool(old_bool), TypedValue::Bool(new_bool)) => {
                 Self::Bool(SimplePatch::diff(old_bool, new_bool))
@@ -619,15 +1771,37 @@ impl TypedValuePatch {
             (
                 TypedValue::String(old_string),
                 TypedValue::String(new_string),
-            ) => Self::String(SimplePatch::diff(old_string, new_string)),
+            ) => Self::String(
+                if normalize.normalize(&old_string)
+                    == normalize.normalize(&new_string)
+                {
+                    SimplePatch { value: None }
+                } else {
+                    SimplePatch::diff(old_string, new_string)
+                },
+            ),
             (TypedValue::Array(old_array), TypedValue::Array(new_array)) => {
-                Self::Array(SimplePatch::diff(old_array, new_array))
+                Self::Array(ArrayPatch::diff(old_array, new_array))
             },
             (TypedValue::Empty, TypedValue::Empty) => Self::Empty,
             (_old, new) => Self::Changed(new),
         }
     }
 
+    // the value the prop is set to, if it's changed
+    fn new_value(&self) -> Option<TypedValue> {
+        match self {
+            Self::Bool(patch) => patch.value.map(TypedValue::Bool),
+            Self::Int(patch) => patch.value.map(TypedValue::Int),
+            Self::Uint(patch) => patch.value.map(TypedValue::Uint),
+            Self::Double(patch) => patch.value.map(TypedValue::Double),
+            Self::String(patch) => patch.value.clone().map(TypedValue::String),
+            Self::Array(patch) => patch.value.clone().map(TypedValue::Array),
+            Self::Empty => None,
+            Self::Changed(value) => Some(value.clone()),
+        }
+    }
+
     fn is_empty(&self) -> bool {
         match self {
             Self::Bool(patch) => patch.is_empty(),
@@ -663,6 +1837,29 @@ where
     }
 }
 
+// the whole array is set when applied, the edits only describe the change
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ArrayPatch {
+    #[serde(skip)]
+    value: Option<Vec<Value>>,
+    edits: Vec<Edit<Value>>,
+}
+
+impl ArrayPatch {
+    fn diff(old: Vec<Value>, new: Vec<Value>) -> Self {
+        let edits = edit_script(&old, &new);
+        Self {
+            value: (!edits.is_empty()).then(|| new),
+            edits,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_none()
+    }
+}
+
 #[derive(Debug, Clone)]
 struct DiffPath {
     channel: Option<((Channel, Channel), String)>,
@@ -681,6 +1878,17 @@ impl DiffPath {
         path.props.push_back(prop);
         path
     }
+
+    // channel name and path of the prop, like /backdrop/screen0/last-image
+    fn prop_path(&self) -> Option<(&str, String)> {
+        let ((_, channel), first) = self.channel.as_ref()?;
+        let mut prop_path = format!("/{}", first);
+        for (_, prop) in &self.props {
+            prop_path.push('/');
+            prop_path.push_str(prop);
+        }
+        Some((&channel.name, prop_path))
+    }
 }
 
 pub struct Applier<'a> {
@@ -731,7 +1939,61 @@ impl<'a> Applier<'a> {
     }
 
     fn set(&mut self, path: &ApplyPath, value: glib::Variant) -> Result<()> {
+        self.set_inner(path, value)
+            .with_context(|| path.error_context("setting"))
+    }
+
+    // logs the props under the property so reverting can bring them back,
+    // called before changing it, dry runs change nothing to bring back
+    fn log_inverse(&mut self, channel: &str, property: &str) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        let prior = match self
+            .dbus
+            .call("GetAllProperties", (channel, property))
+        {
+            Ok(props) => props
+                .try_child_value(0)
+                .context("GetAllProperties had empty return value")?
+                .iter()
+                .map(|prop| {
+                    let entry = prop
+                        .try_get::<DictEntry<String, glib::Variant>>()
+                        .context("error getting prop entry")?;
+                    Ok((entry.key().clone(), entry.value().print(true).into()))
+                })
+                .collect::<Result<BTreeMap<_, _>>>()?,
+            // xfconfd fails if there's nothing under the property
+            Err(error) if is_not_found(&error) => BTreeMap::new(),
+            Err(error) => {
+                return Err(error).with_context(|| {
+                    format!(
+                        "error reading {} in channel {} to log its inverse",
+                        property, channel
+                    )
+                })
+            },
+        };
+        self.patch_recorder
+            .log_inverse(&crate::InverseEvent::Channel(
+                InverseEvent::RestoreProperty {
+                    channel: channel.to_owned(),
+                    property: property.to_owned(),
+                    prior,
+                    unknown: BTreeMap::new(),
+                },
+            ))
+            .context("error logging xfconf property inverse")
+    }
+
+    fn set_inner(
+        &mut self,
+        path: &ApplyPath,
+        value: glib::Variant,
+    ) -> Result<()> {
         let (channel, property) = Self::path_to_channel_property(path);
+        self.log_inverse(channel, &property)?;
         let recursive = true;
         if self
             .dbus
@@ -768,43 +2030,106 @@ impl<'a> Applier<'a> {
     }
 
     fn set_array(&mut self, path: &ApplyPath, array: Vec<Value>) -> Result<()> {
-        self.set(
-            path,
-            glib::variant::ToVariant::to_variant(
-                &array
-                    .into_iter()
-                    .map(|value| match value.value {
-                        TypedValue::Bool(b) => {
-                            Ok(glib::variant::ToVariant::to_variant(&b))
-                        },
-                        TypedValue::Int(n) => {
-                            Ok(glib::variant::ToVariant::to_variant(&n))
-                        },
-                        TypedValue::Uint(n) => {
-                            Ok(glib::variant::ToVariant::to_variant(&n))
-                        },
-                        TypedValue::Double(f) => {
-                            Ok(glib::variant::ToVariant::to_variant(&f))
-                        },
-                        TypedValue::String(s) => {
-                            Ok(glib::variant::ToVariant::to_variant(&*s))
-                        },
-                        TypedValue::Array(_) => {
-                            Err(anyhow!("array value in array value"))
-                        },
-                        TypedValue::Empty => {
-                            Err(anyhow!("empty value in array value"))
-                        },
-                    })
-                    .collect::<Result<Vec<_>>>()?,
-            ),
-        )
+        let array = array
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| {
+                array_value_to_variant(value.value).with_context(|| {
+                    path.error_context(&format!("setting element {} of", i))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.set(path, glib::variant::ToVariant::to_variant(&array))
     }
 
     fn remove(&mut self, path: &ApplyPath) -> Result<()> {
         let (channel, property) = Self::path_to_channel_property(path);
         let recursive = true;
-        self.call("ResetProperty", (channel, property.as_str(), recursive))
+        self.log_inverse(channel, &property)
+            .and_then(|()| {
+                self.call(
+                    "ResetProperty",
+                    (channel, property.as_str(), recursive),
+                )
+            })
+            .with_context(|| path.error_context("removing"))
+    }
+}
+
+// restores what was under a property before it was set or removed, values
+// are in GVariant text format so they keep their types
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum InverseEvent {
+    #[serde(rename_all = "kebab-case")]
+    RestoreProperty {
+        channel: String,
+        property: String,
+        prior: BTreeMap<String, String>,
+        // logged by newer versions, which older ones still revert without
+        #[serde(flatten)]
+        unknown: BTreeMap<String, serde_json::Value>,
+    },
+}
+
+impl InverseEvent {
+    pub fn channel(&self) -> &str {
+        match self {
+            Self::RestoreProperty { channel, .. } => channel,
+        }
+    }
+
+    pub fn unknown_fields(&self) -> Vec<&str> {
+        match self {
+            Self::RestoreProperty { unknown, .. } => {
+                unknown.keys().map(|key| &**key).collect()
+            },
+        }
+    }
+
+    pub fn revert(self, dbus: &mut DBus) -> Result<()> {
+        let (channel, property, prior) = match self {
+            Self::RestoreProperty {
+                channel,
+                property,
+                prior,
+                ..
+            } => (channel, property, prior),
+        };
+        let recursive = true;
+        if dbus
+            .call("PropertyExists", (channel.as_str(), property.as_str()))
+            .context("error checking if property exists")?
+            .try_get::<(bool,)>()
+            .context("error checking PropertyExists return")?
+            .0
+        {
+            dbus.call(
+                "ResetProperty",
+                (channel.as_str(), property.as_str(), recursive),
+            )?;
+        }
+        for (property, value) in prior {
+            let value =
+                glib::Variant::parse(None, &value).with_context(|| {
+                    format!("error parsing prior value of {}", property)
+                })?;
+            dbus.call(
+                "SetProperty",
+                (channel.as_str(), property.as_str(), value),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for InverseEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RestoreProperty {
+                channel, property, ..
+            } => write!(f, "property {} in channel {}", property, channel),
+        }
     }
 }
 
@@ -825,6 +2150,48 @@ impl XfconfPatch {
     }
 }
 
+impl SectionPatch for XfconfPatch {
+    fn change_count(&self) -> ChangeStats {
+        self.channels.change_count()
+    }
+
+    fn changes_panel(&self) -> bool {
+        self.has_panel_changes()
+    }
+
+    // parts are channels and the props in them, as `<channel>/<prop path>`
+    fn filter_parts(&mut self, filter: &PartFilter) -> Result<()> {
+        let channels = &mut self.channels;
+        channels.changed.retain(|name, channel_patch| {
+            channel_patch.props.filter(filter, name);
+            !channel_patch.is_empty()
+        });
+        for channel in &mut channels.added {
+            channel.props.filter(filter, &channel.name);
+        }
+        channels.added.retain(|channel| !channel.props.is_empty());
+        Ok(())
+    }
+
+    fn parts(&self) -> Vec<(String, String)> {
+        let mut parts = Vec::new();
+        for (name, channel_patch) in &self.channels.changed {
+            channel_patch.props.parts(name, &mut parts);
+        }
+        for channel in &self.channels.added {
+            channel.props.parts(&channel.name, &mut parts);
+        }
+        parts
+    }
+
+    fn apply(self: Box<Self>, applier: &mut crate::Applier<'_>) -> Result<()> {
+        (*self).apply(
+            &mut Applier::new(applier.dry_run, &mut applier.patch_recorder)
+                .context("error creating xfconf applier")?,
+        )
+    }
+}
+
 impl ChannelsPatch {
     fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
         for (name, channel_patch) in self.changed {
@@ -837,6 +2204,77 @@ impl ChannelsPatch {
     }
 }
 
+// a one-time change like an xfconf-query call, see migrations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct Operation(Op);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "kebab-case", deny_unknown_fields)]
+enum Op {
+    // removes the prop and everything under it
+    Reset {
+        channel: String,
+        property: String,
+    },
+    Set {
+        channel: String,
+        property: String,
+        value: Value,
+    },
+}
+
+impl Operation {
+    pub(crate) fn schema() -> serde_json::Value {
+        let prop = vec![
+            ("channel", schema::string()),
+            ("property", schema::string()),
+        ];
+        let mut set = prop.clone();
+        set.push(("value", schema::reference(VALUE_DEF)));
+        schema::tagged(
+            "op",
+            vec![
+                (
+                    "reset",
+                    schema::strict(schema::required(
+                        schema::object(prop),
+                        &["channel", "property"],
+                    )),
+                ),
+                (
+                    "set",
+                    schema::strict(schema::required(
+                        schema::object(set),
+                        &["channel", "property", "value"],
+                    )),
+                ),
+            ],
+        )
+    }
+
+    pub(crate) fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
+        let path = |channel, property: &str| ApplyPath {
+            channel,
+            props: property
+                .split('/')
+                .filter(|part| !part.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        };
+        match self.0 {
+            Op::Reset { channel, property } => {
+                applier.remove(&path(channel, &property))
+            },
+            Op::Set {
+                channel,
+                property,
+                value,
+            } => value.apply(applier, &path(channel, &property)),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct ApplyPath {
     channel: String,
@@ -849,6 +2287,25 @@ impl ApplyPath {
         path.props.push_back(prop);
         path
     }
+
+    // names the prop both as xfconf sees it and where it is in the config
+    fn error_context(&self, action: &str) -> String {
+        format!(
+            "error {} property {} in channel {} (config path \
+             xfconf.channels.{}{})",
+            action,
+            self.props
+                .iter()
+                .map(|prop| format!("/{}", prop))
+                .collect::<String>(),
+            self.channel,
+            self.channel,
+            self.props
+                .iter()
+                .map(|prop| format!(".props.{}", prop))
+                .collect::<String>(),
+        )
+    }
 }
 
 impl Channel {
@@ -862,9 +2319,42 @@ impl Channel {
     }
 }
 
+// props other props of the same channel and parent depend on, which have to
+// be set first, in this order
+const APPLY_FIRST: &[(&str, &[&str])] = &[
+    (
+        "xfce4-panel",
+        &["mode", "size", "length-adjust", "length", "position"],
+    ),
+    (
+        "xfwm4",
+        &["use_compositing", "workspace_count", "workspace_names"],
+    ),
+    ("xsettings", &["ThemeName", "IconThemeName"]),
+];
+
+// props of a channel ordered by the hints, then by name
+fn apply_order<T>(channel: &str, props: &mut [(String, T)]) {
+    let first = APPLY_FIRST
+        .iter()
+        .find(|(hinted, _)| *hinted == channel)
+        .map_or(&[][..], |(_, first)| first);
+    props.sort_by_cached_key(|(name, _)| {
+        (
+            first
+                .iter()
+                .position(|first| first == name)
+                .unwrap_or(first.len()),
+            name.clone(),
+        )
+    });
+}
+
 impl Properties {
     fn apply(self, applier: &mut Applier<'_>, path: &ApplyPath) -> Result<()> {
-        for (name, value) in self.0 {
+        let mut props = self.0.into_iter().collect::<Vec<_>>();
+        apply_order(&path.channel, &mut props);
+        for (name, value) in props {
             let path = path.push(name);
             value.apply(applier, &path)?;
         }
@@ -905,16 +2395,34 @@ impl ChannelPatch {
     }
 }
 
+enum PropChange {
+    Changed(ValuePatch),
+    Added(Value),
+}
+
 impl PropertiesPatch {
     fn apply(self, applier: &mut Applier<'_>, path: &ApplyPath) -> Result<()> {
-        // keys of changed, added, removed are disjoint so order doesn't matter
-        for (name, value_patch) in self.changed {
-            let path = path.push(name);
-            value_patch.apply(applier, &path)?;
-        }
-        for (name, value) in self.added {
+        // keys of changed, added, removed are disjoint so only the hints
+        // order them
+        let mut props = self
+            .changed
+            .into_iter()
+            .map(|(name, value_patch)| (name, PropChange::Changed(value_patch)))
+            .chain(
+                self.added
+                    .into_iter()
+                    .map(|(name, value)| (name, PropChange::Added(value))),
+            )
+            .collect::<Vec<_>>();
+        apply_order(&path.channel, &mut props);
+        for (name, prop) in props {
             let path = path.push(name);
-            value.apply(applier, &path)?;
+            match prop {
+                PropChange::Changed(value_patch) => {
+                    value_patch.apply(applier, &path)?
+                },
+                PropChange::Added(value) => value.apply(applier, &path)?,
+            }
         }
         for name in self.removed {
             let path = path.push(name);
@@ -969,7 +2477,32 @@ impl_simple_patch_apply!(i32, set_int);
 impl_simple_patch_apply!(u32, set_uint);
 impl_simple_patch_apply!(f64, set_double);
 impl_simple_patch_apply!(String, set_string);
-impl_simple_patch_apply!(Vec<Value>, set_array);
+
+impl ArrayPatch {
+    fn apply(self, applier: &mut Applier<'_>, path: &ApplyPath) -> Result<()> {
+        if let Some(value) = self.value {
+            applier.set_array(path, value)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// whether xfconfd failed a call since the property or channel doesn't exist,
+// told apart by the remote error name gio puts in the message
+fn is_not_found(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<glib::Error>())
+        .any(|error| {
+            [
+                "GDBus.Error:org.xfce.Xfconf.Error.PropertyNotFound:",
+                "GDBus.Error:org.xfce.Xfconf.Error.ChannelNotFound:",
+            ]
+            .iter()
+            .any(|name| error.message().starts_with(name))
+        })
+}
 
 fn variant_to_json(v: glib::Variant) -> Result<serde_json::Value> {
     match v.type_().as_str() {
@@ -988,6 +2521,553 @@ fn variant_to_json(v: glib::Variant) -> Result<serde_json::Value> {
     }
 }
 
+impl Xfconf {
+    // writes system-wide channel files with the locked props of each channel,
+    // which xfconfd then won't let users change. they're merged into the
+    // channel files already there, like the defaults distributions ship,
+    // whose originals are backed up next to them and merged into instead on
+    // later runs, so locks which aren't declared anymore are dropped
+    pub fn write_kiosk(&self, dir: &Path) -> Result<()> {
+        for channel in (self.channels.0).0.values() {
+            let path = dir.join(format!("{}.xml", channel.name));
+            let backup = kiosk_backup_path(&path);
+            if channel.locked.is_empty() && !backup.exists() {
+                continue;
+            }
+            for locked in &channel.locked {
+                if channel.props.get(locked).is_none() {
+                    crate::warn(format_args!(
+                        "locked property {} in channel {} is not declared",
+                        locked, channel.name
+                    ));
+                }
+            }
+
+            fs::create_dir_all(dir).context("error creating kiosk dir")?;
+            let existing = match read_kiosk_file(&backup)? {
+                Some(original) => Some(original),
+                None => {
+                    let existing = read_kiosk_file(&path)?;
+                    if existing.is_some() {
+                        back_up_kiosk_file(&path, &backup)?;
+                    }
+                    existing
+                },
+            };
+            let xml = channel.kiosk(existing).kiosk_xml();
+            let mut tmp = path.clone().into_os_string();
+            tmp.push(".new");
+            let tmp = PathBuf::from(tmp);
+            fs::write(&tmp, xml)
+                .context("error writing temporary kiosk file")?;
+            fs::rename(tmp, path)
+                .context("error moving temporary kiosk file")?;
+        }
+        Ok(())
+    }
+}
+
+fn kiosk_backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.to_owned().into_os_string();
+    backup.push(".orig");
+    PathBuf::from(backup)
+}
+
+fn read_kiosk_file(path: &Path) -> Result<Option<Channel>> {
+    match fs::read_to_string(path) {
+        Ok(xml) => Channel::from_xml(&xml)
+            .map(Some)
+            .with_context(|| format!("error reading {}", path.display())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err)
+            .with_context(|| format!("error reading {}", path.display())),
+    }
+}
+
+// keeps the file as it was before the first kiosk run, which later runs merge
+// into again
+fn back_up_kiosk_file(path: &Path, backup: &Path) -> Result<()> {
+    fs::copy(path, backup).with_context(|| {
+        format!(
+            "error backing up {} to {}",
+            path.display(),
+            backup.display()
+        )
+    })?;
+    crate::warn(format_args!(
+        "merging locked properties into {}, the original is kept at {}",
+        path.display(),
+        backup.display()
+    ));
+    Ok(())
+}
+
+impl Channel {
+    // the existing channel file's props, with the locked ones set to their
+    // declared values and locked
+    fn kiosk(&self, existing: Option<Self>) -> Self {
+        let mut kiosk = Xfconf::default();
+        let name = self.name.clone();
+        (kiosk.channels.0).0.insert(
+            name.clone(),
+            existing.unwrap_or_else(|| Self {
+                name: name.clone(),
+                props: Properties::default(),
+                locked: Vec::new(),
+                removed_props: Vec::new(),
+                exclusive: false,
+            }),
+        );
+        for path in &self.locked {
+            if let Some(value) = self.props.get(path) {
+                if let Some(prop) = kiosk.entry(&name, path) {
+                    prop.value = value.value.clone();
+                }
+            }
+        }
+        let mut channel = (kiosk.channels.0).0.remove(&name).unwrap();
+        for path in &self.locked {
+            if !channel.locked.contains(path) {
+                channel.locked.push(path.clone());
+            }
+        }
+        channel
+    }
+
+    fn kiosk_xml(&self) -> String {
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\n<channel \
+             name=\"{}\" version=\"1.0\">\n",
+            xml_escape(&self.name)
+        );
+        for (name, value) in &self.props.0 {
+            let path = format!("/{}", name);
+            value.write_kiosk(name, &path, &self.locked, 1, &mut xml);
+        }
+        xml.push_str("</channel>\n");
+        xml
+    }
+}
+
+impl Properties {
+    fn get(&self, path: &str) -> Option<&Value> {
+        let mut parts = path.split('/').filter(|part| !part.is_empty());
+        let first = self.0.get(parts.next()?)?;
+        parts.try_fold(first, |value, part| value.props.0.get(part))
+    }
+
+    fn get_mut(&mut self, path: &str) -> Option<&mut Value> {
+        let mut parts = path.split('/').filter(|part| !part.is_empty());
+        let first = self.0.get_mut(parts.next()?)?;
+        parts.try_fold(first, |value, part| value.props.0.get_mut(part))
+    }
+
+    // removes the prop and returns it with the ones under it
+    fn take(&mut self, path: &str) -> Option<Value> {
+        let (parent, name) = path.rsplit_once('/')?;
+        let props = match parent.is_empty() {
+            true => self,
+            false => &mut self.get_mut(parent)?.props,
+        };
+        props.0.remove(name)
+    }
+}
+
+impl Value {
+    fn write_kiosk(
+        &self,
+        name: &str,
+        path: &str,
+        locked: &[String],
+        depth: usize,
+        xml: &mut String,
+    ) {
+        let is_locked = locked.iter().any(|locked| locked == path);
+        let indent = "  ".repeat(depth);
+        let (r#type, value) = self.value.kiosk_type_value();
+        xml.push_str(&format!(
+            "{}<property name=\"{}\" type=\"{}\"",
+            indent,
+            xml_escape(name),
+            r#type
+        ));
+        if let Some(value) = value {
+            xml.push_str(&format!(" value=\"{}\"", xml_escape(&value)));
+        }
+        if is_locked {
+            xml.push_str(" locked=\"*\"");
+        }
+        let array = match &self.value {
+            TypedValue::Array(array) => array.as_slice(),
+            _ => &[],
+        };
+        if array.is_empty() && self.props.is_empty() {
+            xml.push_str("/>\n");
+            return;
+        }
+        xml.push_str(">\n");
+        for item in array {
+            let (r#type, value) = item.value.kiosk_type_value();
+            xml.push_str(&format!(
+                "{}  <value type=\"{}\" value=\"{}\"/>\n",
+                indent,
+                r#type,
+                xml_escape(value.as_deref().unwrap_or(""))
+            ));
+        }
+        for (name, value) in &self.props.0 {
+            let path = format!("{}/{}", path, name);
+            value.write_kiosk(name, &path, locked, depth + 1, xml);
+        }
+        xml.push_str(&format!("{}</property>\n", indent));
+    }
+}
+
+impl TypedValue {
+    fn kiosk_type_value(&self) -> (&'static str, Option<String>) {
+        match self {
+            Self::Bool(b) => ("bool", Some(b.to_string())),
+            Self::Int(n) => ("int", Some(n.to_string())),
+            Self::Uint(n) => ("uint", Some(n.to_string())),
+            Self::Double(n) => ("double", Some(n.to_string())),
+            Self::String(s) => ("string", Some(s.clone())),
+            Self::Array(_) => ("array", None),
+            Self::Empty => ("empty", None),
+        }
+    }
+}
+
+impl Xfconf {
+    // system default channels xfconfd falls back to for props users haven't
+    // set, read from the channel files in the given dir
+    pub fn read_defaults(dir: &Path) -> Result<Self> {
+        let mut xfconf = Self::default();
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(xfconf);
+            },
+            Err(err) => {
+                return Err(err).context("error reading defaults dir");
+            },
+        };
+        for entry in entries {
+            let path =
+                entry.context("error reading defaults dir entry")?.path();
+            if path.extension().map_or(true, |ext| ext != "xml") {
+                continue;
+            }
+            let channel = fs::read_to_string(&path)
+                .map_err(Error::from)
+                .and_then(|xml| Channel::from_xml(&xml))
+                .with_context(|| {
+                    format!("error reading defaults from {}", path.display())
+                })?;
+            (xfconf.channels.0).0.insert(channel.name.clone(), channel);
+        }
+        Ok(xfconf)
+    }
+
+    // drops props set to their default value, and channels left empty
+    pub fn without_defaults(&mut self, defaults: &Self) {
+        (self.channels.0).0.retain(|name, channel| {
+            if let Some(default) = (defaults.channels.0).0.get(name) {
+                channel.props.remove_defaults(&default.props);
+            }
+            !channel.props.is_empty()
+        });
+    }
+
+    // adds default props which aren't set, like xfconfd does when reading
+    pub fn fill_defaults(&mut self, defaults: &Self) {
+        for (name, default) in &(defaults.channels.0).0 {
+            match (self.channels.0).0.get_mut(name) {
+                Some(channel) => channel.props.fill_defaults(&default.props),
+                None => {
+                    let mut default = default.clone();
+                    default.locked.clear();
+                    (self.channels.0).0.insert(name.clone(), default);
+                },
+            }
+        }
+    }
+}
+
+impl Properties {
+    fn remove_defaults(&mut self, defaults: &Self) {
+        self.0.retain(|name, value| {
+            let default = match defaults.0.get(name) {
+                Some(default) => default,
+                None => return true,
+            };
+            value.props.remove_defaults(&default.props);
+            if value.value == default.value {
+                value.value = TypedValue::Empty;
+            }
+            !matches!(value.value, TypedValue::Empty) || !value.props.is_empty()
+        });
+    }
+
+    fn fill_defaults(&mut self, defaults: &Self) {
+        for (name, default) in &defaults.0 {
+            match self.0.get_mut(name) {
+                Some(value) => value.props.fill_defaults(&default.props),
+                None => {
+                    self.0.insert(name.clone(), default.clone());
+                },
+            }
+        }
+    }
+}
+
+impl Channel {
+    // reads a channel file in xfconfd's format
+    fn from_xml(xml: &str) -> Result<Self> {
+        let mut channel = None;
+        // open props, innermost last
+        let mut open: Vec<(String, Value)> = Vec::new();
+        for tag in XmlTags(xml) {
+            let tag = tag?;
+            match (tag.name, tag.kind) {
+                ("channel", XmlTagKind::Open | XmlTagKind::Empty) => {
+                    channel = Some(Self {
+                        name: tag.attr("name")?.into_owned(),
+                        props: Properties::default(),
+                        locked: Vec::new(),
+                        removed_props: Vec::new(),
+                        exclusive: false,
+                    });
+                },
+                ("property", XmlTagKind::Open | XmlTagKind::Empty) => {
+                    let name = tag.attr("name")?.into_owned();
+                    if tag.attr("locked").is_ok() {
+                        let path = open
+                            .iter()
+                            .map(|(name, _)| name.as_str())
+                            .chain(iter::once(name.as_str()))
+                            .fold(String::new(), |path, name| {
+                                path + "/" + name
+                            });
+                        channel
+                            .as_mut()
+                            .context("property outside of a channel")?
+                            .locked
+                            .push(path);
+                    }
+                    let value = Value {
+                        value: TypedValue::from_xml(&tag)?,
+                        normalize: Vec::new(),
+                        props: Properties::default(),
+                    };
+                    open.push((name, value));
+                    if tag.kind == XmlTagKind::Empty {
+                        close_xml_prop(&mut channel, &mut open)?;
+                    }
+                },
+                ("property", XmlTagKind::Close) => {
+                    close_xml_prop(&mut channel, &mut open)?;
+                },
+                ("value", XmlTagKind::Open | XmlTagKind::Empty) => {
+                    let item = TypedValue::from_xml(&tag)?;
+                    match open.last_mut() {
+                        Some((
+                            _,
+                            Value {
+                                value: TypedValue::Array(array),
+                                ..
+                            },
+                        )) => array.push(Value {
+                            value: item,
+                            normalize: Vec::new(),
+                            props: Properties::default(),
+                        }),
+                        _ => bail!("value outside of an array property"),
+                    }
+                },
+                _ => {},
+            }
+        }
+        if let Some((name, _)) = open.last() {
+            bail!("property {} is never closed", name);
+        }
+        channel.context("missing channel element")
+    }
+}
+
+// moves the innermost open prop into its parent
+fn close_xml_prop(
+    channel: &mut Option<Channel>,
+    open: &mut Vec<(String, Value)>,
+) -> Result<()> {
+    let (name, value) = open.pop().context("unexpected property end tag")?;
+    let props = match open.last_mut() {
+        Some((_, parent)) => &mut parent.props,
+        None => {
+            &mut channel
+                .as_mut()
+                .context("property outside of a channel")?
+                .props
+        },
+    };
+    props.0.insert(name, value);
+    Ok(())
+}
+
+impl TypedValue {
+    fn from_xml(tag: &XmlTag<'_>) -> Result<Self> {
+        let r#type = tag.attr("type")?;
+        let value = || tag.attr("value");
+        let parse_error = || format!("invalid {} value", r#type);
+        Ok(match &*r#type {
+            "empty" => Self::Empty,
+            "array" => Self::Array(Vec::new()),
+            "string" => Self::String(value()?.into_owned()),
+            "bool" => Self::Bool(value()?.parse().with_context(parse_error)?),
+            "int" => Self::Int(value()?.parse().with_context(parse_error)?),
+            "uint" => Self::Uint(value()?.parse().with_context(parse_error)?),
+            "double" => {
+                Self::Double(value()?.parse().with_context(parse_error)?)
+            },
+            r#type => bail!("unsupported property type {}", r#type),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum XmlTagKind {
+    Open,
+    Close,
+    // self-closing
+    Empty,
+}
+
+struct XmlTag<'a> {
+    name: &'a str,
+    kind: XmlTagKind,
+    attrs: &'a str,
+}
+
+impl<'a> XmlTag<'a> {
+    fn attr(&self, name: &str) -> Result<Cow<'a, str>> {
+        let mut rest = self.attrs;
+        while let Some(eq) = rest.find('=') {
+            let key = rest[..eq].trim();
+            let after = rest[eq + 1..].trim_start();
+            let quote =
+                after.chars().next().context("missing attribute value")?;
+            if quote != '"' && quote != '\'' {
+                bail!("unquoted value for attribute {}", key);
+            }
+            let end = after[1..]
+                .find(quote)
+                .with_context(|| format!("unterminated attribute {}", key))?;
+            if key == name {
+                return Ok(xml_unescape(&after[1..end + 1]));
+            }
+            rest = &after[end + 2..];
+        }
+        bail!("{} element is missing attribute {}", self.name, name)
+    }
+}
+
+// the element tags of an XML document, skipping text, comments and
+// declarations
+struct XmlTags<'a>(&'a str);
+
+impl<'a> Iterator for XmlTags<'a> {
+    type Item = Result<XmlTag<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let start = self.0.find('<')?;
+            let rest = &self.0[start..];
+            let (skip_end, end_len) = if rest.starts_with("<!--") {
+                ("-->", 3)
+            } else if rest.starts_with("<?") || rest.starts_with("<!") {
+                (">", 1)
+            } else {
+                break;
+            };
+            match rest.find(skip_end) {
+                Some(end) => self.0 = &rest[end + end_len..],
+                None => {
+                    self.0 = "";
+                    return Some(Err(anyhow!("unterminated XML comment")));
+                },
+            }
+        }
+        let start = self.0.find('<')?;
+        let rest = &self.0[start + 1..];
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => {
+                self.0 = "";
+                return Some(Err(anyhow!("unterminated XML tag")));
+            },
+        };
+        self.0 = &rest[end + 1..];
+        let tag = &rest[..end];
+        let (tag, kind) = if let Some(tag) = tag.strip_prefix('/') {
+            (tag, XmlTagKind::Close)
+        } else if let Some(tag) = tag.strip_suffix('/') {
+            (tag, XmlTagKind::Empty)
+        } else {
+            (tag, XmlTagKind::Open)
+        };
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        Some(Ok(XmlTag {
+            name: &tag[..name_end],
+            kind,
+            attrs: &tag[name_end..],
+        }))
+    }
+}
+
+fn xml_unescape(s: &str) -> Cow<'_, str> {
+    if !s.contains('&') {
+        return Cow::Borrowed(s);
+    }
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        unescaped.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest.find(';').map(|end| &rest[1..end]);
+        let c = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        });
+        match (c, entity) {
+            (Some(c), Some(entity)) => {
+                unescaped.push(c);
+                rest = &rest[entity.len() + 2..];
+            },
+            _ => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    unescaped.push_str(rest);
+    Cow::Owned(unescaped)
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1020,12 +3100,17 @@ mod tests {
             channel,
             Channel {
                 name: "channel".into(),
+                locked: Vec::new(),
+                removed_props: Vec::new(),
+                exclusive: false,
                 props: Properties(btreemap! {
                     "foo".into() => Value {
                         value: TypedValue::String("bar".into()),
+                        normalize: Vec::new(),
                         props: Properties(btreemap! {
                             "baz".into() => Value {
                                 value: TypedValue::Uint(42),
+                                normalize: Vec::new(),
                                 props: Default::default(),
                             },
                         }),
@@ -1034,4 +3119,458 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn deserialize_coerced_scalars() {
+        let props: Properties = serde_json::from_str(
+            r#"
+            {
+                "size": { "type": "string", "value": 24 },
+                "enabled": { "type": "bool", "value": "true" },
+                "count": { "type": "uint", "value": "3" }
+            }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            props,
+            Properties(btreemap! {
+                "size".into() => Value {
+                    value: TypedValue::String("24".into()),
+                    normalize: Vec::new(),
+                    props: Default::default(),
+                },
+                "enabled".into() => Value {
+                    value: TypedValue::Bool(true),
+                    normalize: Vec::new(),
+                    props: Default::default(),
+                },
+                "count".into() => Value {
+                    value: TypedValue::Uint(3),
+                    normalize: Vec::new(),
+                    props: Default::default(),
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn deserialize_shorthand() {
+        let props: Properties = serde_json::from_str(
+            r#"
+            {
+                "_comment": "ignored",
+                "x-note": { "also": "ignored" },
+                "size": 16,
+                "enabled": true,
+                "name": "panel",
+                "ids": [1, 2],
+                "scale": [1, 1.5],
+                "panel-1": {
+                    "props": {
+                        "length": { "type": "uint", "value": 100 }
+                    }
+                }
+            }
+            "#,
+        )
+        .unwrap();
+        let value = |value| Value {
+            value,
+            normalize: Vec::new(),
+            props: Default::default(),
+        };
+        assert_eq!(
+            props,
+            Properties(btreemap! {
+                "size".into() => value(TypedValue::Int(16)),
+                "enabled".into() => value(TypedValue::Bool(true)),
+                "name".into() => value(TypedValue::String("panel".into())),
+                "ids".into() => value(TypedValue::Array(vec![
+                    value(TypedValue::Int(1)),
+                    value(TypedValue::Int(2)),
+                ])),
+                "scale".into() => value(TypedValue::Array(vec![
+                    value(TypedValue::Double(1.0)),
+                    value(TypedValue::Double(1.5)),
+                ])),
+                "panel-1".into() => Value {
+                    value: TypedValue::Empty,
+                    normalize: Vec::new(),
+                    props: Properties(btreemap! {
+                        "length".into() => value(TypedValue::Uint(100)),
+                    }),
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn diff_normalized_strings() {
+        let normalize = [Normalize::Trim, Normalize::CollapseWhitespace];
+        let patch = TypedValuePatch::diff(
+            TypedValue::String("xfce4-terminal  --drop-down ".into()),
+            TypedValue::String("xfce4-terminal --drop-down".into()),
+            &normalize,
+        );
+        assert!(patch.is_empty());
+        let patch = TypedValuePatch::diff(
+            TypedValue::String("xfce4-terminal ".into()),
+            TypedValue::String("xfce4-terminal".into()),
+            &[],
+        );
+        assert!(!patch.is_empty());
+    }
+
+    #[test]
+    fn normalize_pipeline() {
+        let pipeline = Normalize::pipeline(
+            "xfce4-terminal",
+            "/color-foreground",
+            &[Normalize::Trim],
+        );
+        assert_eq!(pipeline, [Normalize::Lowercase, Normalize::Trim]);
+        assert_eq!(pipeline.normalize(" #FFFFFF"), "#ffffff");
+        assert!(
+            Normalize::pipeline("xfce4-terminal", "/font-name", &[]).is_empty()
+        );
+        assert!(path_matches(
+            "/backdrop/*/*/*/last-image",
+            "/backdrop/screen0/monitorHDMI-0/workspace0/last-image"
+        ));
+        assert!(!path_matches(
+            "/backdrop/*/*/*/last-image",
+            "/backdrop/screen0/last-image"
+        ));
+        assert_eq!(
+            expand_home("~/Pictures/sky.png", "/home/user"),
+            "/home/user/Pictures/sky.png"
+        );
+        assert_eq!(expand_home("~user/sky.png", "/home/user"), "~user/sky.png");
+        assert_eq!(Normalize::TrailingSlash.normalize("/tmp//"), "/tmp");
+        assert_eq!(Normalize::TrailingSlash.normalize("/"), "/");
+    }
+
+    #[test]
+    fn apply_to_old() {
+        let xfconf = |json| serde_json::from_str::<Xfconf>(json).unwrap();
+        let old = xfconf(
+            r#"
+            {
+                "channels": [{
+                    "name": "xsettings",
+                    "props": {
+                        "Net": {
+                            "props": {
+                                "ThemeName": "Greybird",
+                                "IconThemeName": "elementary"
+                            }
+                        },
+                        "Gtk": {
+                            "props": {
+                                "FontName": "Sans 10",
+                                "CursorThemeName": "Adwaita"
+                            }
+                        }
+                    }
+                }]
+            }
+            "#,
+        );
+        let new = xfconf(
+            r#"
+            {
+                "channels": [
+                    {
+                        "name": "xsettings",
+                        "props": {
+                            "Net": {
+                                "props": {
+                                    "ThemeName": "Arc",
+                                    "SoundThemeName": "freedesktop"
+                                }
+                            },
+                            "Gtk": { "props": { "FontName": "Sans 10" } }
+                        }
+                    },
+                    { "name": "thunar", "props": { "last-view": "list" } }
+                ],
+                "clear-paths": ["xsettings.Gtk.*"]
+            }
+            "#,
+        );
+        let expected = xfconf(
+            r#"
+            {
+                "channels": [
+                    {
+                        "name": "xsettings",
+                        "props": {
+                            "Net": {
+                                "props": {
+                                    "ThemeName": "Arc",
+                                    "IconThemeName": "elementary",
+                                    "SoundThemeName": "freedesktop"
+                                }
+                            },
+                            "Gtk": { "props": { "FontName": "Sans 10" } }
+                        }
+                    },
+                    { "name": "thunar", "props": { "last-view": "list" } }
+                ]
+            }
+            "#,
+        );
+        let mut state = old.clone();
+        Section::apply_to_old(XfconfPatch::diff(old, new), &mut state);
+        assert_eq!(
+            serde_json::to_value(&state).unwrap(),
+            serde_json::to_value(&expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn removed_props() {
+        let xfconf = |json| serde_json::from_str::<Xfconf>(json).unwrap();
+        let old = xfconf(
+            r#"
+            {
+                "channels": [{
+                    "name": "xfwm4",
+                    "props": {
+                        "general": {
+                            "props": { "theme": "Default", "title_font": "Sans" }
+                        }
+                    }
+                }]
+            }
+            "#,
+        );
+        let new = xfconf(
+            r#"
+            {
+                "channels": [{
+                    "name": "xfwm4",
+                    "removed-props": ["/general/title_font", "/general/unset"]
+                }]
+            }
+            "#,
+        );
+        let mut state = old.clone();
+        let patch = Section::diff(old.clone(), new).unwrap();
+        assert_eq!(SectionPatch::change_count(&patch).total(), 1);
+        Section::apply_to_old(patch, &mut state);
+        assert_eq!(
+            state.get_string("xfwm4", "/general/theme"),
+            Some("Default")
+        );
+        assert_eq!(state.get_string("xfwm4", "/general/title_font"), None);
+
+        let conflicting = xfconf(
+            r#"
+            {
+                "channels": [{
+                    "name": "xfwm4",
+                    "props": { "general": { "props": { "theme": "Arc" } } },
+                    "removed-props": ["/general"]
+                }]
+            }
+            "#,
+        );
+        assert!(Xfconf::diff(old.clone(), conflicting).is_err());
+
+        let exclusive = xfconf(
+            r#"
+            {
+                "channels": [{
+                    "name": "xfwm4",
+                    "props": { "general": { "props": { "theme": "Default" } } },
+                    "exclusive": true
+                }]
+            }
+            "#,
+        );
+        let mut state = old.clone();
+        let patch = Section::diff(old, exclusive).unwrap();
+        assert_eq!(SectionPatch::change_count(&patch).total(), 1);
+        Section::apply_to_old(patch, &mut state);
+        assert_eq!(
+            state.get_string("xfwm4", "/general/theme"),
+            Some("Default")
+        );
+        assert_eq!(state.get_string("xfwm4", "/general/title_font"), None);
+    }
+
+    #[test]
+    fn kiosk_xml() {
+        let channel: Channel = serde_json::from_str(
+            r#"
+            {
+                "name": "xsettings",
+                "props": {
+                    "Net": {
+                        "props": {
+                            "ThemeName": "Greybird",
+                            "IconThemeName": "elementary"
+                        }
+                    },
+                    "Xft": { "props": { "DPI": 96 } }
+                },
+                "locked": ["/Net/ThemeName"]
+            }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            channel.kiosk(None).kiosk_xml(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+
+<channel name="xsettings" version="1.0">
+  <property name="Net" type="empty">
+    <property name="ThemeName" type="string" value="Greybird" locked="*"/>
+  </property>
+</channel>
+"#
+        );
+    }
+
+    #[test]
+    fn kiosk_keeps_vendor_props() {
+        let dir = std::env::temp_dir()
+            .join(format!("xfce-config-kiosk-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let vendor = r#"<?xml version="1.0" encoding="UTF-8"?>
+<channel name="xfce4-panel" version="1.0">
+  <property name="panels" type="array">
+    <value type="int" value="1"/>
+  </property>
+  <property name="plugins" type="empty">
+    <property name="plugin-1" type="string" value="applicationsmenu"/>
+  </property>
+</channel>
+"#;
+        fs::write(dir.join("xfce4-panel.xml"), vendor).unwrap();
+        let xfconf = serde_json::from_value::<Xfconf>(serde_json::json!({
+            "channels": [{
+                "name": "xfce4-panel",
+                "props": { "plugins": { "props": { "plugin-2": "clock" } } },
+                "locked": ["/plugins/plugin-2"],
+            }],
+        }))
+        .unwrap();
+        xfconf.write_kiosk(&dir).unwrap();
+        let kiosk = Channel::from_xml(
+            &fs::read_to_string(dir.join("xfce4-panel.xml")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(kiosk.locked, ["/plugins/plugin-2"]);
+        assert!(kiosk.props.get("/panels").is_some());
+        assert_eq!(
+            kiosk.props.get("/plugins/plugin-1").unwrap().value,
+            TypedValue::String("applicationsmenu".to_owned())
+        );
+        assert_eq!(
+            kiosk.props.get("/plugins/plugin-2").unwrap().value,
+            TypedValue::String("clock".to_owned())
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("xfce4-panel.xml.orig")).unwrap(),
+            vendor
+        );
+
+        // the lock of the first run isn't kept
+        let xfconf = serde_json::from_value::<Xfconf>(serde_json::json!({
+            "channels": [{
+                "name": "xfce4-panel",
+                "props": { "plugins": { "props": { "plugin-3": "clock" } } },
+                "locked": ["/plugins/plugin-3"],
+            }],
+        }))
+        .unwrap();
+        xfconf.write_kiosk(&dir).unwrap();
+        let kiosk = Channel::from_xml(
+            &fs::read_to_string(dir.join("xfce4-panel.xml")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(kiosk.locked, ["/plugins/plugin-3"]);
+        assert!(kiosk.props.get("/plugins/plugin-1").is_some());
+        assert!(kiosk.props.get("/plugins/plugin-2").is_none());
+        assert_eq!(
+            fs::read_to_string(dir.join("xfce4-panel.xml.orig")).unwrap(),
+            vendor
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn apply_order() {
+        let mut props = ["autohide", "length", "mode", "length-adjust"]
+            .iter()
+            .map(|name| (name.to_string(), ()))
+            .collect::<Vec<_>>();
+        super::apply_order("xfce4-panel", &mut props);
+        assert_eq!(
+            props.into_iter().map(|(name, ())| name).collect::<Vec<_>>(),
+            ["mode", "length-adjust", "length", "autohide"]
+        );
+    }
+
+    #[test]
+    fn defaults() {
+        let defaults = Channel::from_xml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <!-- shipped defaults -->
+            <channel name="xfwm4" version="1.0">
+              <property name="general" type="empty">
+                <property name="theme" type="string" value="Default"/>
+                <property name="title_font" type="string" value="Sans &amp; Bold 9"/>
+                <property name="workspace_count" type="int" value="4"/>
+                <property name="workspace_names" type="array">
+                  <value type="string" value="1"/>
+                  <value type="string" value="2"/>
+                </property>
+              </property>
+            </channel>
+            "#,
+        )
+        .unwrap();
+        let general = &defaults.props.0["general"].props.0;
+        assert_eq!(
+            general["title_font"].value,
+            TypedValue::String("Sans & Bold 9".into())
+        );
+        assert!(matches!(
+            &general["workspace_names"].value,
+            TypedValue::Array(names) if names.len() == 2
+        ));
+        let defaults = Xfconf {
+            channels: Channels(IdMap(btreemap! {
+                "xfwm4".into() => defaults,
+            })),
+            clear_paths: Vec::new(),
+            assigned_plugins: Vec::new(),
+            desktop: None,
+        };
+
+        let mut xfconf = Xfconf::default();
+        xfconf.set_string("xfwm4", "/general/theme", "Default".into());
+        xfconf.set_int("xfwm4", "/general/workspace_count", 2);
+        xfconf.without_defaults(&defaults);
+        assert_eq!(xfconf.get_string("xfwm4", "/general/theme"), None);
+        assert_eq!(
+            xfconf.get_int("xfwm4", "/general/workspace_count"),
+            Some(2)
+        );
+
+        xfconf.fill_defaults(&defaults);
+        assert_eq!(
+            xfconf.get_string("xfwm4", "/general/theme"),
+            Some("Default")
+        );
+        assert_eq!(
+            xfconf.get_int("xfwm4", "/general/workspace_count"),
+            Some(2)
+        );
+    }
 }
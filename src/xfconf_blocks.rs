@@ -0,0 +1,151 @@
+use crate::{
+    desktop::Desktop,
+    panel::Panel,
+    panels::Panels,
+    serde::deserialize_stripped,
+    shortcuts::Shortcuts,
+    xfconf::Xfconf,
+    xfwm4::Xfwm4,
+};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+// typed config blocks which aren't sections of their own but are compiled
+// into xfconf props of the channel they cover
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct XfconfBlocks {
+    #[serde(default, deserialize_with = "deserialize_stripped")]
+    shortcuts: Option<Shortcuts>,
+    #[serde(default, deserialize_with = "deserialize_stripped")]
+    xfwm4: Option<Xfwm4>,
+    #[serde(default, deserialize_with = "deserialize_stripped")]
+    desktop: Option<Desktop>,
+    #[serde(default, deserialize_with = "deserialize_stripped")]
+    panels: Option<Panels>,
+}
+
+impl XfconfBlocks {
+    const KEYS: [&'static str; 4] = ["shortcuts", "xfwm4", "desktop", "panels"];
+
+    // the schemas of the blocks by key, next to the ones of the sections
+    pub fn schema() -> Vec<(&'static str, Value)> {
+        vec![
+            ("shortcuts", Shortcuts::schema()),
+            ("xfwm4", Xfwm4::schema()),
+            ("desktop", Desktop::schema()),
+            ("panels", Panels::schema()),
+        ]
+    }
+
+    // removes the blocks from the config so the rest can be read as usual
+    pub fn take(mut config: Value) -> Result<(Value, Self)> {
+        let mut blocks = serde_json::Map::new();
+        if let Some(config) = config.as_object_mut() {
+            for key in Self::KEYS {
+                if let Some(block) = config.remove(key) {
+                    blocks.insert(key.to_owned(), block);
+                }
+            }
+        }
+        let blocks = serde_json::from_value(Value::Object(blocks))?;
+        Ok((config, blocks))
+    }
+
+    // RC files of typed panel plugins go to the panel section
+    pub fn compile(self, xfconf: &mut Xfconf, panel: &mut Panel) -> Result<()> {
+        if let Some(shortcuts) = self.shortcuts {
+            shortcuts
+                .compile(xfconf)
+                .context("error compiling shortcuts")?;
+        }
+        if let Some(xfwm4) = self.xfwm4 {
+            xfwm4.compile(xfconf).context("error compiling xfwm4")?;
+        }
+        // after xfwm4 since it uses the workspace count
+        if let Some(desktop) = self.desktop {
+            desktop.compile(xfconf).context("error compiling desktop")?;
+        }
+        if let Some(panels) = self.panels {
+            panels
+                .compile(xfconf, panel)
+                .context("error compiling panels")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn annotated_blocks() {
+        let (_, blocks) = XfconfBlocks::take(json!({
+            "shortcuts": {
+                "_comment": "team launchers",
+                "commands": { "x-note": "vim", "<Super>t": "xfce4-terminal" },
+            },
+            "xfwm4": {
+                "x-reason": "matches the GTK theme",
+                "theme": "Greybird",
+                "workspaces": { "_comment": "one per screen", "count": 2 },
+            },
+            "desktop": {
+                "_comment": "plain backdrops",
+                "backdrops": [{
+                    "x-why": "no distractions",
+                    "monitor": "HDMI-0",
+                    "color-style": "solid",
+                }],
+            },
+            "panels": [{
+                "_comment": "dock",
+                "plugins": [{
+                    "type": "clock",
+                    "x-owner": "desktop team",
+                    "props": { "_comment": "24 hour", "digital-format": "%R" },
+                }],
+            }],
+        }))
+        .unwrap();
+        let mut xfconf = Xfconf::default();
+        blocks.compile(&mut xfconf, &mut Panel::default()).unwrap();
+        assert_eq!(
+            xfconf.get_string("xfwm4", "/general/theme"),
+            Some("Greybird")
+        );
+        assert_eq!(
+            xfconf.get_int(
+                "xfce4-desktop",
+                "/backdrop/screen0/monitorHDMI-0/workspace1/color-style"
+            ),
+            Some(0)
+        );
+        assert_eq!(
+            xfconf.get_string(
+                "xfce4-keyboard-shortcuts",
+                "/commands/custom/<Super>t"
+            ),
+            Some("xfce4-terminal")
+        );
+        assert_eq!(
+            xfconf.get_string(
+                "xfce4-keyboard-shortcuts",
+                "/commands/custom/x-note"
+            ),
+            None
+        );
+        assert_eq!(
+            xfconf
+                .get_string("xfce4-panel", "/plugins/plugin-1/digital-format"),
+            Some("%R")
+        );
+        assert_eq!(
+            xfconf.get_string("xfce4-panel", "/plugins/plugin-1/_comment"),
+            None
+        );
+    }
+}
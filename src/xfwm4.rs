@@ -0,0 +1,267 @@
+use crate::{schema, xfconf::Xfconf};
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+const CHANNEL: &str = "xfwm4";
+
+// window manager settings compiled into the general props of the xfwm4
+// channel, only the declared ones are set
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Xfwm4 {
+    #[serde(default)]
+    theme: Option<String>,
+    // title button positions, like O|HMC
+    #[serde(default)]
+    button_layout: Option<String>,
+    #[serde(default)]
+    workspaces: Workspaces,
+    #[serde(default)]
+    focus: Focus,
+    #[serde(default)]
+    snapping: Snapping,
+    #[serde(default)]
+    compositor: Compositor,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Workspaces {
+    #[serde(default)]
+    count: Option<i32>,
+    #[serde(default)]
+    names: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Focus {
+    #[serde(default)]
+    model: Option<FocusModel>,
+    #[serde(default)]
+    raise_on_focus: Option<bool>,
+    // whether new windows get focus
+    #[serde(default)]
+    focus_new: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum FocusModel {
+    Click,
+    // focus follows the mouse
+    Sloppy,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Snapping {
+    #[serde(default)]
+    to_border: Option<bool>,
+    #[serde(default)]
+    to_windows: Option<bool>,
+    // distance in pixels
+    #[serde(default)]
+    width: Option<i32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Compositor {
+    #[serde(default)]
+    enabled: Option<bool>,
+    #[serde(default)]
+    frame_shadow: Option<bool>,
+    #[serde(default)]
+    popup_shadow: Option<bool>,
+    #[serde(default)]
+    vblank_mode: Option<VblankMode>,
+    // percent
+    #[serde(default)]
+    frame_opacity: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum VblankMode {
+    Auto,
+    Glx,
+    Xpresent,
+    Off,
+}
+
+impl VblankMode {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Glx => "glx",
+            Self::Xpresent => "xpresent",
+            Self::Off => "off",
+        }
+    }
+}
+
+impl Xfwm4 {
+    pub fn schema() -> serde_json::Value {
+        let group = |props| schema::strict(schema::object(props));
+        group(vec![
+            ("theme", schema::string()),
+            ("button-layout", schema::string()),
+            (
+                "workspaces",
+                group(vec![
+                    ("count", schema::integer()),
+                    ("names", schema::array(schema::string())),
+                ]),
+            ),
+            (
+                "focus",
+                group(vec![
+                    ("model", schema::string_enum(&["click", "sloppy"])),
+                    ("raise-on-focus", schema::boolean()),
+                    ("focus-new", schema::boolean()),
+                ]),
+            ),
+            (
+                "snapping",
+                group(vec![
+                    ("to-border", schema::boolean()),
+                    ("to-windows", schema::boolean()),
+                    ("width", schema::integer()),
+                ]),
+            ),
+            (
+                "compositor",
+                group(vec![
+                    ("enabled", schema::boolean()),
+                    ("frame-shadow", schema::boolean()),
+                    ("popup-shadow", schema::boolean()),
+                    (
+                        "vblank-mode",
+                        schema::string_enum(&[
+                            "auto", "glx", "xpresent", "off",
+                        ]),
+                    ),
+                    ("frame-opacity", schema::integer()),
+                ]),
+            ),
+        ])
+    }
+
+    pub fn compile(self, xfconf: &mut Xfconf) -> Result<()> {
+        let Self {
+            theme,
+            button_layout,
+            workspaces,
+            focus,
+            snapping,
+            compositor,
+        } = self;
+        let mut set_string = |prop: &str, value: Option<String>| {
+            if let Some(value) = value {
+                xfconf.set_string(CHANNEL, &general(prop), value);
+            }
+        };
+        set_string("theme", theme);
+        set_string("button_layout", button_layout);
+        set_string(
+            "vblank_mode",
+            compositor.vblank_mode.map(|mode| mode.name().to_owned()),
+        );
+
+        if let Some(count) = workspaces.count {
+            if count < 1 {
+                bail!("xfwm4 workspace count must be at least 1");
+            }
+        }
+        if let Some(opacity) = compositor.frame_opacity {
+            if !(0..=100).contains(&opacity) {
+                bail!("xfwm4 frame opacity must be a percentage");
+            }
+        }
+        let ints = [
+            ("workspace_count", workspaces.count),
+            ("snap_width", snapping.width),
+            ("frame_opacity", compositor.frame_opacity),
+        ];
+        for (prop, value) in ints {
+            if let Some(value) = value {
+                xfconf.set_int(CHANNEL, &general(prop), value);
+            }
+        }
+
+        let bools = [
+            (
+                "click_to_focus",
+                focus.model.map(|model| matches!(model, FocusModel::Click)),
+            ),
+            ("raise_on_focus", focus.raise_on_focus),
+            ("focus_new", focus.focus_new),
+            ("snap_to_border", snapping.to_border),
+            ("snap_to_windows", snapping.to_windows),
+            ("use_compositing", compositor.enabled),
+            ("show_frame_shadow", compositor.frame_shadow),
+            ("show_popup_shadow", compositor.popup_shadow),
+        ];
+        for (prop, value) in bools {
+            if let Some(value) = value {
+                xfconf.set_bool(CHANNEL, &general(prop), value);
+            }
+        }
+
+        if let Some(names) = workspaces.names {
+            xfconf.set_string_array(
+                CHANNEL,
+                &general("workspace_names"),
+                names,
+            );
+        }
+        Ok(())
+    }
+}
+
+fn general(prop: &str) -> String {
+    format!("/general/{}", prop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn compile() {
+        let xfwm4 = serde_json::from_value::<Xfwm4>(json!({
+            "theme": "Greybird",
+            "workspaces": { "count": 2, "names": ["Main", "Web"] },
+            "focus": { "model": "sloppy" },
+            "compositor": { "enabled": true, "vblank-mode": "glx" },
+        }))
+        .unwrap();
+        let mut xfconf = Xfconf::default();
+        xfwm4.compile(&mut xfconf).unwrap();
+        assert_eq!(
+            xfconf.get_string(CHANNEL, "/general/theme"),
+            Some("Greybird")
+        );
+        assert_eq!(
+            xfconf.get_int(CHANNEL, "/general/workspace_count"),
+            Some(2)
+        );
+        assert_eq!(
+            xfconf.get_bool(CHANNEL, "/general/click_to_focus"),
+            Some(false)
+        );
+        assert_eq!(
+            xfconf.get_string(CHANNEL, "/general/vblank_mode"),
+            Some("glx")
+        );
+        assert_eq!(xfconf.get_bool(CHANNEL, "/general/snap_to_border"), None);
+
+        let xfwm4 = serde_json::from_value::<Xfwm4>(json!({
+            "compositor": { "frame-opacity": 120 },
+        }))
+        .unwrap();
+        assert!(xfwm4.compile(&mut Xfconf::default()).is_err());
+    }
+}
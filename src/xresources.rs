@@ -0,0 +1,307 @@
+use crate::{
+    external::ExternallyManaged,
+    open_file,
+    schema,
+    section::{Section, SectionPatch},
+    symlinks::{replace_file, SymlinkPolicy},
+    ChangeStats,
+    Dirs,
+    PatchRecorder,
+};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Xresources {
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "crate::serde::deserialize_unannotated"
+    )]
+    resources: BTreeMap<String, String>,
+    // run `xrdb -merge` after the file changed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    merge: bool,
+    // the lines of the existing file, comments and directives included
+    #[serde(skip)]
+    lines: Vec<String>,
+}
+
+// splits a resource line into its key and value, comments and preprocessor
+// directives are not resources
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_start();
+    if line.starts_with('!') || line.starts_with('#') {
+        return None;
+    }
+    let (key, value) = line.split_once(':')?;
+    Some((key.trim(), value.trim()))
+}
+
+// the lines with the changed resources set where they first appear, later
+// lines of the same resources removed since xrdb would apply those instead, and
+// new resources appended. comments, directives and unmanaged resources are kept
+// as they are
+fn update_lines(
+    mut changed: BTreeMap<String, String>,
+    old_lines: Vec<String>,
+) -> Vec<String> {
+    let mut written = BTreeSet::new();
+    let mut lines = Vec::new();
+    for line in old_lines {
+        let key = parse_line(&line).map(|(key, _)| key);
+        if key.map_or(false, |key| written.contains(key)) {
+            continue;
+        }
+        let new_line = key
+            .and_then(|key| changed.remove_entry(key))
+            .map(|(key, value)| {
+                let line = format!("{}: {}", key, value);
+                written.insert(key);
+                line
+            });
+        lines.push(new_line.unwrap_or(line));
+    }
+    for (key, value) in changed {
+        lines.push(format!("{}: {}", key, value));
+    }
+    lines
+}
+
+impl Section for Xresources {
+    type Patch = XresourcesPatch;
+
+    fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+
+    fn schema() -> serde_json::Value {
+        schema::object(vec![
+            ("resources", schema::map(schema::string(), true)),
+            ("merge", schema::boolean()),
+        ])
+    }
+
+    fn read(_new: &Self, dirs: &Dirs) -> Result<Self> {
+        let file = open_file(dirs.home_dir.join(".Xresources"))
+            .context("error opening Xresources file")?;
+        let mut resources = BTreeMap::new();
+        let mut lines = Vec::new();
+        if let Some(file) = file {
+            for line in io::BufReader::new(file).lines() {
+                let line = line.context("error reading Xresources file")?;
+                if let Some((key, value)) = parse_line(&line) {
+                    resources.insert(key.to_owned(), value.to_owned());
+                }
+                lines.push(line);
+            }
+        }
+        Ok(Self {
+            resources,
+            merge: false,
+            lines,
+        })
+    }
+
+    fn diff(old: Self, new: Self) -> Result<XresourcesPatch> {
+        Ok(XresourcesPatch::diff(old, new))
+    }
+
+    fn apply_to_old(patch: XresourcesPatch, old: &mut Self) {
+        old.resources.extend(patch.changed);
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct XresourcesPatch {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    changed: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    merge: bool,
+    #[serde(skip)]
+    lines: Vec<String>,
+}
+
+impl XresourcesPatch {
+    pub fn diff(mut old: Xresources, new: Xresources) -> Self {
+        let changed = new
+            .resources
+            .into_iter()
+            .filter(|(key, value)| {
+                old.resources.remove(key).as_ref() != Some(value)
+            })
+            .collect();
+        Self {
+            changed,
+            merge: new.merge,
+            lines: old.lines,
+        }
+    }
+}
+
+pub struct Applier<'a> {
+    dry_run: bool,
+    patch_recorder: &'a mut PatchRecorder,
+    home_dir: Cow<'a, Path>,
+    symlinks: SymlinkPolicy,
+    external: &'a ExternallyManaged,
+}
+
+impl<'a> Applier<'a> {
+    pub(crate) fn new(
+        dry_run: bool,
+        patch_recorder: &'a mut PatchRecorder,
+        home_dir: Cow<'a, Path>,
+        symlinks: SymlinkPolicy,
+        external: &'a ExternallyManaged,
+    ) -> Self {
+        Self {
+            dry_run,
+            patch_recorder,
+            home_dir,
+            symlinks,
+            external,
+        }
+    }
+
+    fn log(&mut self, event: PatchEvent<'_>) -> Result<()> {
+        self.patch_recorder
+            .log(&crate::PatchEvent::Xresources(event))
+    }
+
+    fn path(&self) -> PathBuf {
+        self.home_dir.join(".Xresources")
+    }
+
+    fn update_file(
+        &mut self,
+        changed: BTreeMap<String, String>,
+        old_lines: Vec<String>,
+    ) -> Result<()> {
+        if self.external.skip_write(&self.path()) {
+            return Ok(());
+        }
+        let path = self.symlinks.resolve(&self.path())?;
+        for (key, value) in &changed {
+            self.log(PatchEvent::SetResource { key, value })
+                .context("error logging set resource")?;
+        }
+        self.patch_recorder
+            .log_file_inverse(&path)
+            .context("error logging Xresources inverse")?;
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let lines = update_lines(changed, old_lines);
+        self.patch_recorder.back_up(&path)?;
+        replace_file(&path, |tmp| {
+            let mut file = fs::File::create(tmp)
+                .context("error creating temporary Xresources file")?;
+            for line in lines {
+                writeln!(file, "{}", line)
+                    .context("error writing temporary Xresources file")?;
+            }
+            Ok(())
+        })
+    }
+
+    fn merge(&mut self) -> Result<()> {
+        let path = self.path();
+        self.log(PatchEvent::Merge { path: &path })
+            .context("error logging xrdb merge")?;
+        if !self.dry_run {
+            let mut child = process::Command::new("xrdb")
+                .arg("-merge")
+                .arg(&path)
+                .spawn()
+                .context("error running xrdb")?;
+            let status = crate::deadline::wait(&mut child)
+                .context("error waiting for xrdb")?;
+            if !status.success() {
+                bail!("xrdb exited with {}", status);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum PatchEvent<'a> {
+    #[serde(rename_all = "kebab-case")]
+    SetResource { key: &'a str, value: &'a str },
+    #[serde(rename_all = "kebab-case")]
+    Merge { path: &'a Path },
+}
+
+impl XresourcesPatch {
+    pub fn apply(self, applier: &mut Applier<'_>) -> Result<()> {
+        if self.changed.is_empty() {
+            return Ok(());
+        }
+        applier.update_file(self.changed, self.lines)?;
+        if self.merge {
+            applier.merge()?;
+        }
+        Ok(())
+    }
+}
+
+impl SectionPatch for XresourcesPatch {
+    fn change_count(&self) -> ChangeStats {
+        ChangeStats::changed(self.changed.len()).in_file()
+    }
+
+    fn apply(self: Box<Self>, applier: &mut crate::Applier<'_>) -> Result<()> {
+        (*self).apply(&mut Applier::new(
+            applier.dry_run,
+            &mut applier.patch_recorder,
+            applier.dirs.home_dir.as_path().into(),
+            applier.symlinks.xresources,
+            &applier.external,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maplit::btreemap;
+
+    #[test]
+    fn duplicated_resources() {
+        let old_lines = [
+            "! colors",
+            "*foreground: #000000",
+            "XTerm*faceName: Mono",
+            "*foreground:\t#111111",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        let changed = btreemap! {
+            "*foreground".to_owned() => "#ffffff".to_owned(),
+            "*background".to_owned() => "#000000".to_owned(),
+        };
+        assert_eq!(
+            update_lines(changed, old_lines),
+            [
+                "! colors",
+                "*foreground: #ffffff",
+                "XTerm*faceName: Mono",
+                "*background: #000000",
+            ]
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::{gtk::Gtk, xfconf::Xfconf};
+
+const CHANNEL: &str = "xsettings";
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    String,
+    Int,
+    Bool,
+}
+
+// GTK settings which xfsettingsd sets from xsettings channel props, overriding
+// whatever settings.ini says moments after it's written
+const OVERRIDDEN: &[(&str, &str, Kind)] = &[
+    ("gtk-theme-name", "/Net/ThemeName", Kind::String),
+    ("gtk-icon-theme-name", "/Net/IconThemeName", Kind::String),
+    ("gtk-sound-theme-name", "/Net/SoundThemeName", Kind::String),
+    (
+        "gtk-enable-event-sounds",
+        "/Net/EnableEventSounds",
+        Kind::Bool,
+    ),
+    (
+        "gtk-enable-input-feedback-sounds",
+        "/Net/EnableInputFeedbackSounds",
+        Kind::Bool,
+    ),
+    ("gtk-cursor-blink", "/Net/CursorBlink", Kind::Bool),
+    ("gtk-cursor-blink-time", "/Net/CursorBlinkTime", Kind::Int),
+    ("gtk-double-click-time", "/Net/DoubleClickTime", Kind::Int),
+    (
+        "gtk-double-click-distance",
+        "/Net/DoubleClickDistance",
+        Kind::Int,
+    ),
+    ("gtk-dnd-drag-threshold", "/Net/DndDragThreshold", Kind::Int),
+    ("gtk-font-name", "/Gtk/FontName", Kind::String),
+    (
+        "gtk-cursor-theme-name",
+        "/Gtk/CursorThemeName",
+        Kind::String,
+    ),
+    ("gtk-cursor-theme-size", "/Gtk/CursorThemeSize", Kind::Int),
+    ("gtk-key-theme-name", "/Gtk/KeyThemeName", Kind::String),
+    ("gtk-button-images", "/Gtk/ButtonImages", Kind::Bool),
+    ("gtk-menu-images", "/Gtk/MenuImages", Kind::Bool),
+    (
+        "gtk-decoration-layout",
+        "/Gtk/DecorationLayout",
+        Kind::String,
+    ),
+    (
+        "gtk-dialogs-use-header",
+        "/Gtk/DialogsUseHeader",
+        Kind::Bool,
+    ),
+    ("gtk-xft-antialias", "/Xft/Antialias", Kind::Int),
+    ("gtk-xft-hinting", "/Xft/Hinting", Kind::Int),
+    ("gtk-xft-hintstyle", "/Xft/HintStyle", Kind::String),
+    ("gtk-xft-rgba", "/Xft/RGBA", Kind::String),
+];
+
+// GTK settings keys and the xsettings props they're overridden by
+pub fn overridden() -> impl Iterator<Item = (&'static str, &'static str)> {
+    OVERRIDDEN.iter().map(|&(key, path, _)| (key, path))
+}
+
+#[derive(Debug, PartialEq)]
+enum Setting {
+    String(String),
+    Int(i32),
+    Bool(bool),
+}
+
+impl Setting {
+    // parses a settings.ini value
+    fn parse(value: &str, kind: Kind) -> Option<Self> {
+        match kind {
+            Kind::String => Some(Self::String(value.to_owned())),
+            Kind::Int => value.parse().ok().map(Self::Int),
+            Kind::Bool => match value {
+                "true" | "1" => Some(Self::Bool(true)),
+                "false" | "0" => Some(Self::Bool(false)),
+                _ => None,
+            },
+        }
+    }
+
+    fn get(xfconf: &Xfconf, path: &str, kind: Kind) -> Option<Self> {
+        match kind {
+            Kind::String => xfconf
+                .get_string(CHANNEL, path)
+                .map(str::to_owned)
+                .map(Self::String),
+            Kind::Int => xfconf.get_int(CHANNEL, path).map(Self::Int),
+            Kind::Bool => xfconf.get_bool(CHANNEL, path).map(Self::Bool),
+        }
+    }
+
+    fn set(self, xfconf: &mut Xfconf, path: &str) {
+        match self {
+            Self::String(s) => xfconf.set_string(CHANNEL, path, s),
+            Self::Int(i) => xfconf.set_int(CHANNEL, path, i),
+            Self::Bool(b) => xfconf.set_bool(CHANNEL, path, b),
+        }
+    }
+}
+
+// warns about GTK settings xfsettingsd will override, or moves them to the
+// xsettings channel if xfconf is preferred
+pub fn check(gtk: &mut Gtk, xfconf: &mut Xfconf, prefer_xfconf: bool) {
+    for &(key, path, kind) in OVERRIDDEN {
+        let setting = match gtk.setting(key) {
+            Some(value) => Setting::parse(value, kind),
+            None => continue,
+        };
+        match Setting::get(xfconf, path, kind) {
+            Some(xfconf_setting)
+                if Some(&xfconf_setting) == setting.as_ref() => {},
+            Some(_) if prefer_xfconf => {
+                gtk.remove_setting(key);
+                crate::warn(format_args!(
+                    "dropping GTK setting {} which conflicts with xfconf {} {}",
+                    key, CHANNEL, path
+                ));
+            },
+            Some(_) => crate::warn(format_args!(
+                "GTK setting {} conflicts with xfconf {} {}, xfsettingsd will \
+                 override it",
+                key, CHANNEL, path
+            )),
+            None => match setting {
+                Some(setting) if prefer_xfconf => {
+                    gtk.remove_setting(key);
+                    setting.set(xfconf, path);
+                    crate::warn(format_args!(
+                        "moving GTK setting {} to xfconf {} {}",
+                        key, CHANNEL, path
+                    ));
+                },
+                _ => crate::warn(format_args!(
+                    "GTK setting {} will be overridden by xfsettingsd, set \
+                     xfconf {} {} instead",
+                    key, CHANNEL, path
+                )),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> (Gtk, Xfconf) {
+        let gtk = serde_json::from_value(json!({
+            "settings": {
+                "sections": {
+                    "Settings": {
+                        "gtk-theme-name": "Adwaita",
+                        "gtk-icon-theme-name": "elementary",
+                        "gtk-cursor-blink": "false",
+                        "gtk-application-prefer-dark-theme": "1",
+                    },
+                },
+            },
+        }))
+        .unwrap();
+        let xfconf = serde_json::from_value(json!({
+            "channels": [{
+                "name": "xsettings",
+                "props": {
+                    "Net": {
+                        "props": {
+                            "ThemeName": { "type": "string", "value": "Adwaita" },
+                            "IconThemeName": {
+                                "type": "string",
+                                "value": "Papirus",
+                            },
+                        },
+                    },
+                },
+            }],
+        }))
+        .unwrap();
+        (gtk, xfconf)
+    }
+
+    #[test]
+    fn warn_only() {
+        let (mut gtk, mut xfconf) = config();
+        check(&mut gtk, &mut xfconf, false);
+        assert_eq!(gtk.setting("gtk-icon-theme-name"), Some("elementary"));
+        assert_eq!(gtk.setting("gtk-cursor-blink"), Some("false"));
+        assert_eq!(xfconf.get_bool(CHANNEL, "/Net/CursorBlink"), None);
+    }
+
+    #[test]
+    fn prefer_xfconf() {
+        let (mut gtk, mut xfconf) = config();
+        check(&mut gtk, &mut xfconf, true);
+        // agreeing settings are left alone
+        assert_eq!(gtk.setting("gtk-theme-name"), Some("Adwaita"));
+        assert_eq!(gtk.setting("gtk-icon-theme-name"), None);
+        assert_eq!(
+            xfconf.get_string(CHANNEL, "/Net/IconThemeName"),
+            Some("Papirus")
+        );
+        assert_eq!(gtk.setting("gtk-cursor-blink"), None);
+        assert_eq!(xfconf.get_bool(CHANNEL, "/Net/CursorBlink"), Some(false));
+        assert_eq!(gtk.setting("gtk-application-prefer-dark-theme"), Some("1"));
+    }
+}